@@ -0,0 +1,78 @@
+//! Measures the delay between the generation of the core and accelerometer
+//! events by the kernel and their arrival at the application, and prints
+//! a histogram of the delays along with the event counters of the device.
+//!
+//! Usage: `cargo run --example latency_probe [SECONDS]`. Attach the output
+//! to reports of delayed or stuttering events.
+
+use futures_util::TryStreamExt;
+use std::time::{Duration, Instant, SystemTime};
+use xwiimote::{Channels, Device, Monitor};
+
+/// The upper bounds of the histogram buckets, in milliseconds.
+const BUCKETS: [u64; 8] = [1, 2, 5, 10, 20, 50, 100, 200];
+
+/// The nominal interval between two accelerometer reports.
+const REPORT_PERIOD: Duration = Duration::from_millis(10);
+
+fn main() -> xwiimote::Result<()> {
+    let seconds = match std::env::args().nth(1) {
+        Some(arg) => arg
+            .parse()
+            .expect("the duration must be a number of seconds"),
+        None => 10,
+    };
+    tokio_test::block_on(probe(Duration::from_secs(seconds)))
+}
+
+async fn probe(duration: Duration) -> xwiimote::Result<()> {
+    let Some(address) = Monitor::enumerate()?.try_next().await? else {
+        eprintln!("found no connected device");
+        return Ok(());
+    };
+    let device = Device::connect_async(&address, Duration::from_secs(2)).await?;
+    device.set_drop_detection(Some(REPORT_PERIOD));
+    device.open(Channels::CORE | Channels::ACCELEROMETER, false)?;
+    println!(
+        "probing {} ({}) for {}s",
+        device.device_kind()?,
+        device.uniq().unwrap_or_default(),
+        duration.as_secs()
+    );
+
+    let mut histogram = [0u64; BUCKETS.len() + 1];
+    let start = Instant::now();
+    let mut events = device.events()?;
+    while let Some((_, time)) = events.try_next().await? {
+        let delay = SystemTime::now().duration_since(time).unwrap_or_default();
+        let ix = BUCKETS
+            .iter()
+            .position(|&bound| delay < Duration::from_millis(bound))
+            .unwrap_or(BUCKETS.len());
+        histogram[ix] += 1;
+        if start.elapsed() >= duration {
+            break;
+        }
+    }
+    drop(events);
+
+    let total = histogram.iter().sum::<u64>().max(1);
+    let mut lower = 0;
+    for (ix, count) in histogram.iter().enumerate() {
+        let label = match BUCKETS.get(ix) {
+            Some(upper) => format!("{lower:>3}-{upper:<3} ms"),
+            None => format!("   >={lower:<3} ms"),
+        };
+        let bar = "#".repeat((count * 50 / total) as usize);
+        println!("{label} {count:>8} {bar}");
+        lower = BUCKETS.get(ix).copied().unwrap_or(lower);
+    }
+
+    let counters = device.event_counters();
+    println!("events read:     {}", counters.events);
+    println!("batches:         {}", counters.batches);
+    println!("largest batch:   {}", counters.max_batch);
+    println!("lost reports:    {}", counters.dropped);
+    println!("max delay:       {:?}", counters.max_delay);
+    Ok(())
+}
@@ -0,0 +1,341 @@
+//! Aggregation of the usage of a device over a session.
+//!
+//! A [`SessionAnalytics`] collector counts the presses of each button,
+//! measures how long they are held and tallies the reports of each
+//! sensor. Nothing is collected unless the application feeds the
+//! collector with events. The resulting [`SessionSummary`] works as
+//! a heatmap of the buttons for UX research, and helps diagnose faulty
+//! hardware, such as buttons that get stuck.
+//!
+//! # Examples
+//! Print a summary of the usage of a device once it disconnects.
+//! ```no_run
+//! use futures_util::TryStreamExt;
+//! use std::time::SystemTime;
+//! use xwiimote::analytics::SessionAnalytics;
+//! use xwiimote::{Channels, Device, Monitor};
+//!
+//! # tokio_test::block_on(async {
+//! # let address = Monitor::enumerate()?.try_next().await?.unwrap();
+//! let device = Device::connect(&address)?;
+//! device.open(Channels::CORE | Channels::ACCELEROMETER, false)?;
+//!
+//! let mut analytics = SessionAnalytics::new();
+//! let mut events = device.events()?;
+//! while let Some((event, time)) = events.try_next().await? {
+//!     analytics.record(&event, time);
+//! }
+//! let summary = analytics.summary(SystemTime::now());
+//! for (button, usage) in &summary.buttons {
+//!     println!("{button}: {} presses", usage.presses);
+//! }
+//! # Ok::<(), xwiimote::Error>(())
+//! # }).unwrap();
+//! ```
+
+use crate::events::{Event, KeyState};
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+/// The usage of a button recorded by a [`SessionAnalytics`].
+#[derive(Debug, Clone, Default)]
+struct ButtonUsage {
+    presses: u64,
+    repeats: u64,
+    /// The total hold time of the presses that ended.
+    total_hold: Duration,
+    longest_hold: Duration,
+    /// The time at which the button was pressed, if held down.
+    pressed_at: Option<SystemTime>,
+}
+
+impl ButtonUsage {
+    /// Ends the current press at `time`, if any.
+    fn release(&mut self, time: SystemTime) {
+        if let Some(pressed_at) = self.pressed_at.take() {
+            let held = time.duration_since(pressed_at).unwrap_or_default();
+            self.total_hold += held;
+            self.longest_hold = self.longest_hold.max(held);
+        }
+    }
+}
+
+/// Collects the usage of a device over a session; see the
+/// [module documentation](self).
+#[derive(Debug, Clone, Default)]
+pub struct SessionAnalytics {
+    /// The time of the first event, if any.
+    started: Option<SystemTime>,
+    /// The usage of each button, by name.
+    buttons: BTreeMap<String, ButtonUsage>,
+    sensors: SensorActivity,
+}
+
+impl SessionAnalytics {
+    /// Creates a collector that has recorded nothing yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an event of the device, received at `time`.
+    pub fn record(&mut self, event: &Event, time: SystemTime) {
+        self.started.get_or_insert(time);
+        if let Some((name, state)) = button(event) {
+            let usage = self.buttons.entry(name).or_default();
+            match state {
+                KeyState::Down => {
+                    // A missed release ends the previous press.
+                    usage.release(time);
+                    usage.presses += 1;
+                    usage.pressed_at = Some(time);
+                }
+                KeyState::AutoRepeat => usage.repeats += 1,
+                KeyState::Up => usage.release(time),
+            }
+            return;
+        }
+
+        let sensors = &mut self.sensors;
+        match event {
+            Event::Accelerometer { .. } => sensors.accelerometer += 1,
+            Event::Ir(sources) => {
+                sensors.ir += 1;
+                if sources.iter().any(Option::is_some) {
+                    sensors.ir_visible += 1;
+                }
+            }
+            Event::MotionPlus { .. } => sensors.motion_plus += 1,
+            #[cfg(feature = "balance-board")]
+            Event::BalanceBoard(_) => sensors.balance_board += 1,
+            #[cfg(feature = "pro-controller")]
+            Event::ProControllerMove { .. } => sensors.sticks += 1,
+            #[cfg(feature = "classic")]
+            Event::ClassicControllerMove { .. } => sensors.sticks += 1,
+            #[cfg(feature = "nunchuk")]
+            Event::NunchukMove { .. } => sensors.sticks += 1,
+            #[cfg(feature = "drums")]
+            Event::DrumsMove { .. } => sensors.sticks += 1,
+            #[cfg(feature = "guitar")]
+            Event::GuitarMove { .. } => sensors.sticks += 1,
+            Event::Dropped { count_estimate } => sensors.dropped += u64::from(*count_estimate),
+            _ => {}
+        }
+    }
+
+    /// Summarizes the usage recorded until `now`. The buttons that are
+    /// still held down count as released at `now`.
+    pub fn summary(&self, now: SystemTime) -> SessionSummary {
+        let buttons = self
+            .buttons
+            .iter()
+            .map(|(name, usage)| {
+                let mut usage = usage.clone();
+                let held_for = usage
+                    .pressed_at
+                    .map(|pressed_at| now.duration_since(pressed_at).unwrap_or_default());
+                usage.release(now);
+                let summary = ButtonSummary {
+                    presses: usage.presses,
+                    repeats: usage.repeats,
+                    total_hold: usage.total_hold,
+                    longest_hold: usage.longest_hold,
+                    held_for,
+                };
+                (name.clone(), summary)
+            })
+            .collect();
+        SessionSummary {
+            started: self.started,
+            duration: self
+                .started
+                .map(|started| now.duration_since(started).unwrap_or_default())
+                .unwrap_or_default(),
+            buttons,
+            sensors: self.sensors.clone(),
+        }
+    }
+}
+
+/// Returns the name and state of the button whose state changed
+/// according to `event`, if any.
+///
+/// The buttons of the Wii Remote are named after their [`Key`] variant,
+/// such as `A`, and those of the extensions are prefixed with the name
+/// of the extension, as in `nunchuk.C`.
+///
+/// [`Key`]: crate::events::Key
+fn button(event: &Event) -> Option<(String, KeyState)> {
+    Some(match event {
+        Event::Key(key, state) => (format!("{key:?}"), *state),
+        #[cfg(feature = "pro-controller")]
+        Event::ProControllerKey(key, state) => (format!("pro.{key:?}"), *state),
+        #[cfg(feature = "classic")]
+        Event::ClassicControllerKey(key, state) => (format!("classic.{key:?}"), *state),
+        #[cfg(feature = "nunchuk")]
+        Event::NunchukKey(key, state) => (format!("nunchuk.{key:?}"), *state),
+        #[cfg(feature = "drums")]
+        Event::DrumsKey(key, state) => (format!("drums.{key:?}"), *state),
+        #[cfg(feature = "guitar")]
+        Event::GuitarKey(key, state) => (format!("guitar.{key:?}"), *state),
+        _ => return None,
+    })
+}
+
+/// The number of reports received from each sensor during a session.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SensorActivity {
+    /// The accelerometer reports.
+    pub accelerometer: u64,
+    /// The IR camera reports.
+    pub ir: u64,
+    /// The IR camera reports in which at least one source is visible.
+    pub ir_visible: u64,
+    /// The Motion Plus gyroscope reports.
+    pub motion_plus: u64,
+    /// The Balance Board weight reports.
+    pub balance_board: u64,
+    /// The analog stick reports of the extensions.
+    pub sticks: u64,
+    /// The estimated number of reports lost before reaching the
+    /// application; see [`Event::Dropped`].
+    pub dropped: u64,
+}
+
+/// The usage of a button during a session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ButtonSummary {
+    /// The number of times the button was pressed.
+    pub presses: u64,
+    /// The number of repeat events sent while the button was held down.
+    pub repeats: u64,
+    /// The total time during which the button was held down.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_millis"))]
+    pub total_hold: Duration,
+    /// The longest time for which the button was held down at once.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_millis"))]
+    pub longest_hold: Duration,
+    /// The time for which the button has been held down at the end of
+    /// the summary, or [`None`] if it was released.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_opt_millis"))]
+    pub held_for: Option<Duration>,
+}
+
+/// A summary of the usage of a device, produced by
+/// [`SessionAnalytics::summary`].
+///
+/// With the `serde` feature, the summary serializes with the durations
+/// given in milliseconds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SessionSummary {
+    /// The time of the first recorded event, if any.
+    pub started: Option<SystemTime>,
+    /// The time elapsed since the first recorded event.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_millis"))]
+    pub duration: Duration,
+    /// The usage of each button that was pressed at least once, by name;
+    /// e.g. `A` or `nunchuk.C`.
+    pub buttons: BTreeMap<String, ButtonSummary>,
+    /// The activity of the sensors.
+    pub sensors: SensorActivity,
+}
+
+impl SessionSummary {
+    /// Lists the buttons that have been held down for at least `threshold`
+    /// at the end of the summary, which may be stuck.
+    pub fn stuck_buttons(&self, threshold: Duration) -> impl Iterator<Item = &str> {
+        self.buttons
+            .iter()
+            .filter(move |(_, usage)| usage.held_for.is_some_and(|held| held >= threshold))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl SessionSummary {
+    /// Serializes the summary in the JSON format.
+    pub fn to_json(&self) -> crate::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(feature = "serde")]
+fn serialize_millis<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
+#[cfg(feature = "serde")]
+fn serialize_opt_millis<S: serde::Serializer>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    match duration {
+        Some(duration) => serialize_millis(duration, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analytics::SessionAnalytics;
+    use crate::events::{Event, IrSource, Key, KeyState};
+    use std::time::{Duration, SystemTime};
+
+    fn at(ms: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn summarizes_buttons() {
+        let mut analytics = SessionAnalytics::new();
+        analytics.record(&Event::Key(Key::A, KeyState::Down), at(1000));
+        analytics.record(&Event::Key(Key::A, KeyState::AutoRepeat), at(1200));
+        analytics.record(&Event::Key(Key::A, KeyState::Up), at(1300));
+        analytics.record(&Event::Key(Key::A, KeyState::Down), at(2000));
+        analytics.record(&Event::Key(Key::A, KeyState::Up), at(2100));
+        analytics.record(&Event::Key(Key::Home, KeyState::Down), at(2500));
+
+        let summary = analytics.summary(at(10_000));
+        assert_eq!(summary.started, Some(at(1000)));
+        assert_eq!(summary.duration, Duration::from_secs(9));
+        let a = &summary.buttons["A"];
+        assert_eq!((a.presses, a.repeats), (2, 1));
+        assert_eq!(a.total_hold, Duration::from_millis(400));
+        assert_eq!(a.longest_hold, Duration::from_millis(300));
+        assert_eq!(a.held_for, None);
+        let home = &summary.buttons["Home"];
+        assert_eq!(home.held_for, Some(Duration::from_millis(7500)));
+        assert_eq!(home.longest_hold, Duration::from_millis(7500));
+
+        let stuck: Vec<_> = summary.stuck_buttons(Duration::from_secs(5)).collect();
+        assert_eq!(stuck, ["Home"]);
+        // The summary does not release the buttons.
+        let later = analytics.summary(at(20_000));
+        assert_eq!(later.buttons["Home"].presses, 1);
+        assert_eq!(
+            later.buttons["Home"].held_for,
+            Some(Duration::from_millis(17_500))
+        );
+    }
+
+    #[test]
+    fn counts_sensor_reports() {
+        let mut analytics = SessionAnalytics::new();
+        let visible = [Some(IrSource { x: 512, y: 384 }), None, None, None];
+        analytics.record(&Event::Accelerometer { x: 0, y: 0, z: 0 }, at(0));
+        analytics.record(&Event::Ir(visible), at(10));
+        analytics.record(&Event::Ir([None; 4]), at(20));
+        analytics.record(&Event::Dropped { count_estimate: 3 }, at(30));
+
+        let sensors = analytics.summary(at(30)).sensors;
+        assert_eq!(sensors.accelerometer, 1);
+        assert_eq!((sensors.ir, sensors.ir_visible), (2, 1));
+        assert_eq!(sensors.dropped, 3);
+        assert!(analytics.summary(at(30)).buttons.is_empty());
+    }
+}
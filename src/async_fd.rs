@@ -0,0 +1,57 @@
+use crate::reactor::{Interest, Reactor};
+use crate::Result;
+use libc::c_int;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::task::{Context, Poll};
+
+/// A non-blocking file whose readiness is monitored by the [`Reactor`].
+pub(crate) struct AsyncFd<T: AsRawFd> {
+    inner: T,
+}
+
+impl<T: AsRawFd> AsyncFd<T> {
+    const EPOLL_EVENTS: c_int = libc::EPOLLIN | libc::EPOLLOUT | libc::EPOLLRDHUP;
+
+    /// Registers a file with the reactor. The file must be in
+    /// non-blocking mode.
+    pub fn new(inner: T) -> Result<Self> {
+        Reactor::get().add_interest(&Interest::new(inner.as_raw_fd(), Self::EPOLL_EVENTS))?;
+        Ok(Self { inner })
+    }
+
+    /// Performs a non-blocking operation on the file. If the operation
+    /// would block, arranges for `wake` to be called once the file is
+    /// ready for reading (if `events` is [`libc::EPOLLIN`]) or writing
+    /// (if `events` is [`libc::EPOLLOUT`]).
+    pub fn poll_io<R>(
+        &self,
+        cx: &mut Context<'_>,
+        events: c_int,
+        mut op: impl FnMut(&T) -> io::Result<R>,
+    ) -> Poll<Result<R>> {
+        match op(&self.inner) {
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+            res => return Poll::Ready(res.map_err(Into::into)),
+        }
+        Reactor::get().set_callback(
+            Interest::new(self.inner.as_raw_fd(), events),
+            cx.waker().clone(),
+        )?;
+        // The file is edge-triggered: retry in case it became ready
+        // before the callback was set, which would not wake us.
+        match op(&self.inner) {
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Poll::Pending,
+            res => Poll::Ready(res.map_err(Into::into)),
+        }
+    }
+}
+
+impl<T: AsRawFd> Drop for AsyncFd<T> {
+    fn drop(&mut self) {
+        let interest = Interest::new(self.inner.as_raw_fd(), Self::EPOLL_EVENTS);
+        // The file is about to be closed, which removes it from the
+        // `epoll` set anyway; ignore any error.
+        let _ = Reactor::get().remove_interest(&interest);
+    }
+}
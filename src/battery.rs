@@ -0,0 +1,429 @@
+//! Smoothing of battery level readings, and notification of changes.
+
+use crate::async_fd::AsyncFd;
+use crate::netlink::{Uevent, UeventSocket};
+use crate::timer::Sleep;
+use crate::{Channels, Device, Result};
+use futures_core::Stream;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// A smoothed battery level, along with its trend.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BatteryEstimate {
+    /// The estimated battery level, as a percentage from 0 to 100%.
+    pub level: f32,
+    /// The rate at which the level decreases, in percentage points
+    /// per hour. Negative while charging.
+    ///
+    /// Only available after the level has been read at least twice.
+    pub drain_rate: Option<f32>,
+}
+
+impl BatteryEstimate {
+    /// Estimates the time until the battery is empty, if it drains.
+    ///
+    /// Returns [`None`] if the estimate is not a representable duration,
+    /// e.g. because the level is not finite.
+    pub fn time_to_empty(&self) -> Option<Duration> {
+        match self.drain_rate {
+            Some(rate) if rate > 0.0 => {
+                Duration::try_from_secs_f32(self.level / rate * 3600.0).ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Computes the exponential moving average of the battery level readings
+/// of a device, and of the rate at which the level changes.
+///
+/// The raw readings reported by the device are coarse, and they
+/// fluctuate with the load of the rumble motor and the speaker.
+#[derive(Clone, Debug)]
+pub struct BatteryEstimator {
+    /// The time over which the weight of a reading decays by a factor of _e_.
+    time_constant: Duration,
+    /// The current estimate and the time of the last reading, if any.
+    state: Option<(BatteryEstimate, Instant)>,
+}
+
+impl Default for BatteryEstimator {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_TIME_CONSTANT)
+    }
+}
+
+impl BatteryEstimator {
+    /// The default smoothing time constant.
+    pub const DEFAULT_TIME_CONSTANT: Duration = Duration::from_secs(5 * 60);
+
+    /// Creates an estimator that smooths the readings over
+    /// the given time constant.
+    pub fn new(time_constant: Duration) -> Self {
+        Self {
+            time_constant,
+            state: None,
+        }
+    }
+
+    /// Adds a battery level reading, as a percentage from 0 to 100%,
+    /// taken at the given time.
+    ///
+    /// Readings older than the previous one are ignored.
+    pub fn update(&mut self, level: u8, at: Instant) -> BatteryEstimate {
+        let level = f32::from(level.min(100));
+        let estimate = match self.state {
+            None => BatteryEstimate {
+                level,
+                drain_rate: None,
+            },
+            Some((prev, prev_at)) => {
+                let Some(elapsed) = at.checked_duration_since(prev_at) else {
+                    return prev;
+                };
+                let alpha = 1.0 - (-elapsed.as_secs_f32() / self.time_constant.as_secs_f32()).exp();
+                let new_level = prev.level + alpha * (level - prev.level);
+
+                let hours = elapsed.as_secs_f32() / 3600.0;
+                let drain_rate = if hours > 0.0 {
+                    let rate = (prev.level - new_level) / hours;
+                    Some(match prev.drain_rate {
+                        Some(prev_rate) => prev_rate + alpha * (rate - prev_rate),
+                        None => rate,
+                    })
+                } else {
+                    prev.drain_rate
+                };
+                BatteryEstimate {
+                    level: new_level,
+                    drain_rate,
+                }
+            }
+        };
+        self.state = Some((estimate, at));
+        estimate
+    }
+
+    /// Returns the current estimate, if any reading was added.
+    pub fn estimate(&self) -> Option<BatteryEstimate> {
+        self.state.map(|(estimate, _)| estimate)
+    }
+
+    /// Discards all readings, e.g. after the batteries are replaced.
+    pub fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+/// The state of the battery of a device, as produced by
+/// [`Device::battery_events`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BatteryStatus {
+    /// The battery level, as a percentage from 0 to 100%.
+    pub level: u8,
+    /// Whether the battery is charging, if the device reports it.
+    /// Only the Wii U Pro Controller has a rechargeable battery.
+    pub charging: Option<bool>,
+}
+
+impl BatteryStatus {
+    /// Extracts the status from a `change` event of a power supply,
+    /// if the event includes the battery level.
+    fn from_uevent(event: &Uevent) -> Option<Self> {
+        let level: u8 = event.property("POWER_SUPPLY_CAPACITY")?.parse().ok()?;
+        Some(Self {
+            level: level.min(100),
+            charging: event
+                .property("POWER_SUPPLY_STATUS")
+                .and_then(parse_charging),
+        })
+    }
+}
+
+/// Parses the `status` attribute of a power supply.
+fn parse_charging(status: &str) -> Option<bool> {
+    match status.trim() {
+        "Charging" => Some(true),
+        "Discharging" | "Not charging" | "Full" => Some(false),
+        _ => None, // `Unknown`
+    }
+}
+
+/// Produces the state of the battery of a device whenever it changes.
+///
+/// The `hid-wiimote` driver only queries the battery level when asked
+/// to, so the level is also read periodically in case the kernel does
+/// not announce a change.
+pub(crate) struct BatteryEvents<'d> {
+    device: &'d Device,
+    uevents: AsyncFd<UeventSocket>,
+    /// The `sysfs` directory of the battery.
+    supply: PathBuf,
+    /// Expires when the level should be read again.
+    timer: Sleep,
+    /// The last status produced, if any.
+    last: Option<BatteryStatus>,
+}
+
+impl<'d> BatteryEvents<'d> {
+    /// The interval between two readings of the battery level.
+    const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+    pub fn new(device: &'d Device, supply: &Path) -> Result<Self> {
+        Ok(Self {
+            device,
+            uevents: AsyncFd::new(UeventSocket::new()?)?,
+            supply: fs::canonicalize(supply)?,
+            timer: Sleep::new(Self::POLL_INTERVAL)?,
+            last: None,
+        })
+    }
+
+    /// Reads the current state of the battery.
+    fn read(&self) -> Result<BatteryStatus> {
+        let status = fs::read_to_string(self.supply.join("status"));
+        Ok(BatteryStatus {
+            level: self.device.battery()?.min(100),
+            charging: status.ok().as_deref().and_then(parse_charging),
+        })
+    }
+
+    /// Records the given status, and returns it if it changed.
+    fn update(&mut self, status: BatteryStatus) -> Option<BatteryStatus> {
+        (self.last.replace(status) != Some(status)).then_some(status)
+    }
+}
+
+impl Stream for BatteryEvents<'_> {
+    type Item = Result<BatteryStatus>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if this.last.is_none() {
+            // Start with the current status.
+            let status = this.read().map(|status| this.update(status).unwrap());
+            return Poll::Ready(Some(status));
+        }
+        loop {
+            let event = this.uevents.poll_io(cx, libc::EPOLLIN, |socket| {
+                socket.receive()?.ok_or(io::ErrorKind::WouldBlock.into())
+            });
+            let event = match event {
+                Poll::Ready(Ok(event)) => event,
+                Poll::Ready(Err(err)) => return Poll::Ready(Some(Err(err))),
+                Poll::Pending => break,
+            };
+            if Path::new(&event.syspath()) != this.supply {
+                continue; // an event of another device.
+            }
+            if event.action == "remove" {
+                // The device was disconnected.
+                return Poll::Ready(None);
+            }
+            let status = match BatteryStatus::from_uevent(&event) {
+                Some(status) => status,
+                None => match this.read() {
+                    Ok(status) => status,
+                    Err(err) => return Poll::Ready(Some(Err(err))),
+                },
+            };
+            if let Some(status) = this.update(status) {
+                return Poll::Ready(Some(Ok(status)));
+            }
+        }
+        loop {
+            match Pin::new(&mut this.timer).poll(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(err)) => return Poll::Ready(Some(Err(err))),
+                Poll::Pending => return Poll::Pending,
+            }
+            let res = this
+                .timer
+                .reset(Self::POLL_INTERVAL)
+                .and_then(|()| this.read());
+            match res {
+                Ok(status) => {
+                    if let Some(status) = this.update(status) {
+                        return Poll::Ready(Some(Ok(status)));
+                    }
+                }
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            }
+        }
+    }
+}
+
+/// Reduces the fidelity of the sensors of a device as its battery drains,
+/// in order to extend its runtime; see [`Device::set_battery_policy`].
+///
+/// Each step of the policy applies once the battery level drops below
+/// its threshold, and stays in effect along with the steps of higher
+/// thresholds.
+///
+/// # Examples
+/// ```
+/// use std::time::Duration;
+/// use xwiimote::battery::BatteryPolicy;
+/// use xwiimote::Channels;
+///
+/// let policy = BatteryPolicy::new()
+///     // Report at most 20 motion events per second and sensor below 30%,
+///     .below(30, Channels::empty(), Some(Duration::from_millis(50)))
+///     // and turn the IR camera off below 15%.
+///     .below(15, Channels::IR, None);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BatteryPolicy {
+    /// The threshold levels, along with the channels to close
+    /// and the minimum interval between two motion events.
+    steps: Vec<(u8, Channels, Option<Duration>)>,
+}
+
+impl BatteryPolicy {
+    /// Creates a policy that does not throttle the sensors.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a step that applies once the battery level is below `level`
+    /// percent: it closes the given `channels`, and if `coalesce` is set,
+    /// reports at most one motion event (such as an accelerometer reading)
+    /// of each kind per `coalesce` interval. The events that arrive too
+    /// soon are coalesced into the latest one, which is reported once the
+    /// interval elapses.
+    ///
+    /// The [core channel](`Channels::CORE`) is never closed, so that
+    /// the buttons keep working.
+    pub fn below(mut self, level: u8, channels: Channels, coalesce: Option<Duration>) -> Self {
+        self.steps
+            .push((level, channels.difference(Channels::CORE), coalesce));
+        self
+    }
+
+    /// Returns the channels to close and the minimum interval between
+    /// two motion events at the given battery level.
+    pub(crate) fn throttle(&self, level: u8) -> (Channels, Option<Duration>) {
+        self.steps
+            .iter()
+            .filter(|(threshold, ..)| level < *threshold)
+            .fold((Channels::empty(), None), |(closed, interval), step| {
+                (closed | step.1, interval.max(step.2))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::battery::{BatteryEstimate, BatteryEstimator, BatteryPolicy, BatteryStatus};
+    use crate::netlink::Uevent;
+    use crate::Channels;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn first_reading_is_exact() {
+        let mut estimator = BatteryEstimator::default();
+        assert_eq!(estimator.estimate(), None);
+        let estimate = estimator.update(80, Instant::now());
+        assert_eq!(estimate.level, 80.0);
+        assert_eq!(estimate.drain_rate, None);
+        assert_eq!(estimate.time_to_empty(), None);
+    }
+
+    #[test]
+    fn smooths_jumpy_readings() {
+        let mut estimator = BatteryEstimator::default();
+        let start = Instant::now();
+        estimator.update(80, start);
+        let estimate = estimator.update(60, start + Duration::from_secs(10));
+        assert!(estimate.level > 79.0 && estimate.level < 80.0);
+    }
+
+    #[test]
+    fn estimates_time_to_empty() {
+        let mut estimator = BatteryEstimator::default();
+        let start = Instant::now();
+        // The battery drains 10 points per hour.
+        for minute in 0..=360 {
+            let level = 100 - minute / 6;
+            estimator.update(level as u8, start + Duration::from_secs(60 * minute));
+        }
+        let estimate = estimator.estimate().unwrap();
+        let rate = estimate.drain_rate.unwrap();
+        assert!((rate - 10.0).abs() < 1.0, "drain rate is {rate}");
+        let hours = estimate.time_to_empty().unwrap().as_secs_f32() / 3600.0;
+        assert!((hours - 4.0).abs() < 0.5, "time to empty is {hours}h");
+    }
+
+    #[test]
+    fn rejects_unrepresentable_times_to_empty() {
+        let estimate = |level, drain_rate| BatteryEstimate {
+            level,
+            drain_rate: Some(drain_rate),
+        };
+        assert_eq!(estimate(f32::NAN, 1.0).time_to_empty(), None);
+        assert_eq!(estimate(-10.0, 1.0).time_to_empty(), None);
+        assert_eq!(estimate(50.0, f32::MIN_POSITIVE).time_to_empty(), None);
+        assert_eq!(estimate(50.0, -1.0).time_to_empty(), None);
+    }
+
+    #[test]
+    fn ignores_stale_readings() {
+        let mut estimator = BatteryEstimator::default();
+        let start = Instant::now();
+        estimator.update(50, start + Duration::from_secs(1));
+        assert_eq!(estimator.update(10, start).level, 50.0);
+    }
+
+    #[test]
+    fn parses_power_supply_events() {
+        let msg = b"change@/devices/virtual/misc/uhid/0005:057E:0330.0001/power_supply/\
+            wiimote_battery_00:1f:32:aa:bb:cc\0ACTION=change\0SUBSYSTEM=power_supply\0\
+            POWER_SUPPLY_CAPACITY=60\0POWER_SUPPLY_STATUS=Charging\0";
+        let event = Uevent::parse(msg).unwrap();
+        let status = BatteryStatus::from_uevent(&event).unwrap();
+        assert_eq!(status.level, 60);
+        assert_eq!(status.charging, Some(true));
+
+        let msg = b"change@/devices/power_supply/x\0POWER_SUPPLY_CAPACITY=20\0";
+        let event = Uevent::parse(msg).unwrap();
+        let status = BatteryStatus::from_uevent(&event).unwrap();
+        assert_eq!(status.charging, None);
+
+        let msg = b"change@/devices/power_supply/x\0POWER_SUPPLY_STATUS=Full\0";
+        assert_eq!(
+            BatteryStatus::from_uevent(&Uevent::parse(msg).unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn throttles_by_battery_level() {
+        let policy = BatteryPolicy::new()
+            .below(30, Channels::MOTION_PLUS, Some(Duration::from_millis(50)))
+            .below(
+                15,
+                Channels::CORE | Channels::IR,
+                Some(Duration::from_millis(20)),
+            );
+        assert_eq!(policy.throttle(30), (Channels::empty(), None));
+        assert_eq!(
+            policy.throttle(20),
+            (Channels::MOTION_PLUS, Some(Duration::from_millis(50)))
+        );
+        // The core channel is never closed.
+        assert_eq!(
+            policy.throttle(10),
+            (
+                Channels::MOTION_PLUS | Channels::IR,
+                Some(Duration::from_millis(50))
+            )
+        );
+        assert_eq!(BatteryPolicy::new().throttle(0), (Channels::empty(), None));
+    }
+}
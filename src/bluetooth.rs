@@ -0,0 +1,362 @@
+//! Link quality readings through the HCI sockets of the Bluetooth adapters,
+//! as done by `hcitool rssi`.
+
+use crate::async_fd::AsyncFd;
+use crate::timer::Sleep;
+use crate::{bail_if, Result, StableId};
+use std::fs;
+use std::future::{poll_fn, Future};
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+/// The protocol of the sockets that talk to an adapter directly.
+const BTPROTO_HCI: libc::c_int = 1;
+/// The channel that carries the raw HCI packets.
+const HCI_CHANNEL_RAW: u16 = 0;
+/// The socket option level and name of the packet filter.
+const SOL_HCI: libc::c_int = 0;
+const HCI_FILTER: libc::c_int = 2;
+/// Looks up a connection of an adapter; `_IOR('H', 213, int)`.
+const HCIGETCONNINFO: u32 = 0x800448d5;
+/// The type of the connections to Bluetooth Classic devices.
+const ACL_LINK: u8 = 0x01;
+
+const HCI_COMMAND_PKT: u8 = 0x01;
+const HCI_EVENT_PKT: u8 = 0x04;
+const EVT_CMD_COMPLETE: u8 = 0x0e;
+const EVT_CMD_STATUS: u8 = 0x0f;
+/// The opcode of the Read RSSI command (OGF 0x05, OCF 0x0005).
+const READ_RSSI: u16 = 0x05 << 10 | 0x0005;
+
+/// The time to wait for the adapter to answer a command.
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The address of an HCI socket; `struct sockaddr_hci`.
+#[repr(C)]
+struct SockaddrHci {
+    family: libc::sa_family_t,
+    dev: u16,
+    channel: u16,
+}
+
+/// The packets received by an HCI socket; `struct hci_filter`.
+#[repr(C)]
+struct HciFilter {
+    type_mask: u32,
+    event_mask: [u32; 2],
+    opcode: u16,
+}
+
+/// A connection of an adapter; `struct hci_conn_info`.
+#[repr(C)]
+#[derive(Default)]
+struct ConnInfo {
+    handle: u16,
+    bdaddr: [u8; 6],
+    type_: u8,
+    out: u8,
+    state: u16,
+    link_mode: u32,
+}
+
+/// The argument of [`HCIGETCONNINFO`]; `struct hci_conn_info_req`
+/// followed by the space for a single connection.
+#[repr(C)]
+#[derive(Default)]
+struct ConnInfoReq {
+    bdaddr: [u8; 6],
+    type_: u8,
+    info: ConnInfo,
+}
+
+/// Converts a Bluetooth address into the byte order used by the
+/// kernel, which is the reverse of its textual representation.
+fn bdaddr(address: &StableId) -> [u8; 6] {
+    let mut bytes = [0; 6];
+    for (byte, group) in bytes.iter_mut().rev().zip(address.as_str().split(':')) {
+        // The identifier holds six validated hexadecimal groups.
+        *byte = u8::from_str_radix(group, 16).unwrap_or_default();
+    }
+    bytes
+}
+
+/// Lists the identifiers of the Bluetooth adapters, such as 0 for `hci0`.
+fn adapters() -> io::Result<Vec<u16>> {
+    let mut ids = Vec::new();
+    for entry in fs::read_dir("/sys/class/bluetooth")? {
+        let name = entry?.file_name();
+        // The connections of an adapter are listed as `hci<id>:<handle>`.
+        if let Some(Ok(id)) = name.to_string_lossy().strip_prefix("hci").map(str::parse) {
+            ids.push(id);
+        }
+    }
+    ids.sort_unstable();
+    Ok(ids)
+}
+
+/// Extracts the RSSI from an event packet received after sending
+/// the Read RSSI command.
+///
+/// Returns [`None`] if the packet is unrelated to the command.
+fn parse_rssi_response(packet: &[u8]) -> Option<io::Result<i8>> {
+    let [HCI_EVENT_PKT, event, len, params @ ..] = packet else {
+        return None;
+    };
+    let params = params.get(..*len as usize)?;
+    let rejected = |status| {
+        Some(Err(io::Error::other(format!(
+            "the adapter rejected the command (HCI status {status:#04x})"
+        ))))
+    };
+    match (*event, params) {
+        (EVT_CMD_COMPLETE, [_, lo, hi, status, _, _, rssi, ..])
+            if u16::from_le_bytes([*lo, *hi]) == READ_RSSI =>
+        {
+            if *status != 0 {
+                return rejected(*status);
+            }
+            Some(Ok(*rssi as i8))
+        }
+        (EVT_CMD_STATUS, [status, _, lo, hi, ..])
+            if u16::from_le_bytes([*lo, *hi]) == READ_RSSI && *status != 0 =>
+        {
+            rejected(*status)
+        }
+        _ => None,
+    }
+}
+
+/// The error returned once the adapter does not answer in time.
+fn timed_out() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        "the adapter did not report the RSSI",
+    )
+}
+
+/// A raw HCI socket bound to a Bluetooth adapter, in non-blocking mode.
+struct HciSocket(OwnedFd);
+
+impl AsRawFd for HciSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl HciSocket {
+    /// Opens a socket bound to the adapter with the given identifier.
+    fn open(dev_id: u16) -> Result<Self> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_BLUETOOTH,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK,
+                BTPROTO_HCI,
+            )
+        };
+        bail_if!(fd == -1);
+        let socket = Self(unsafe { OwnedFd::from_raw_fd(fd) });
+
+        let addr = SockaddrHci {
+            family: libc::AF_BLUETOOTH as libc::sa_family_t,
+            dev: dev_id,
+            channel: HCI_CHANNEL_RAW,
+        };
+        let res_code = unsafe {
+            libc::bind(
+                fd,
+                &addr as *const _ as *const libc::sockaddr,
+                mem::size_of::<SockaddrHci>() as libc::socklen_t,
+            )
+        };
+        bail_if!(res_code == -1);
+        Ok(socket)
+    }
+
+    /// Finds the handle of the connection to the device with the given
+    /// address, if the adapter is connected to it.
+    fn connection_handle(&self, bdaddr: [u8; 6]) -> Option<u16> {
+        let mut req = ConnInfoReq {
+            bdaddr,
+            type_: ACL_LINK,
+            ..Default::default()
+        };
+        // SAFETY: the request has room for the connection information.
+        let res_code =
+            unsafe { libc::ioctl(self.0.as_raw_fd(), HCIGETCONNINFO as _, &mut req as *mut _) };
+        (res_code == 0).then_some(req.info.handle)
+    }
+
+    /// Sends the Read RSSI command for a connection, and lets only
+    /// its response through the socket.
+    fn request_rssi(&self, handle: u16) -> Result<()> {
+        let fd = self.0.as_raw_fd();
+        let filter = HciFilter {
+            type_mask: 1 << HCI_EVENT_PKT,
+            event_mask: [1 << EVT_CMD_COMPLETE | 1 << EVT_CMD_STATUS, 0],
+            opcode: READ_RSSI,
+        };
+        let res_code = unsafe {
+            libc::setsockopt(
+                fd,
+                SOL_HCI,
+                HCI_FILTER,
+                &filter as *const _ as *const libc::c_void,
+                mem::size_of::<HciFilter>() as libc::socklen_t,
+            )
+        };
+        bail_if!(res_code == -1);
+
+        let [opcode_lo, opcode_hi] = READ_RSSI.to_le_bytes();
+        let [handle_lo, handle_hi] = handle.to_le_bytes();
+        let command = [
+            HCI_COMMAND_PKT,
+            opcode_lo,
+            opcode_hi,
+            2,
+            handle_lo,
+            handle_hi,
+        ];
+        let len =
+            unsafe { libc::write(fd, command.as_ptr() as *const libc::c_void, command.len()) };
+        bail_if!(len == -1);
+        Ok(())
+    }
+
+    /// Reads a single packet, and returns the RSSI if it is the response
+    /// to the Read RSSI command. Fails with [`io::ErrorKind::WouldBlock`]
+    /// if no packet is available.
+    fn read_response(&self) -> io::Result<Option<i8>> {
+        let mut buf = [0u8; 260];
+        let len = unsafe {
+            libc::read(
+                self.0.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+            )
+        };
+        if len == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        parse_rssi_response(&buf[..len as usize]).transpose()
+    }
+
+    /// Reads the RSSI of a connection, blocking for up to [`TIMEOUT`].
+    fn read_rssi(&self, handle: u16) -> Result<i8> {
+        self.request_rssi(handle)?;
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let mut pollfd = libc::pollfd {
+                fd: self.0.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let res_code =
+                unsafe { libc::poll(&mut pollfd, 1, remaining.as_millis() as libc::c_int) };
+            if res_code == -1 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err.into());
+            } else if res_code == 0 {
+                return Err(timed_out().into());
+            }
+
+            match self.read_response() {
+                Ok(Some(rssi)) => return Ok(rssi),
+                Ok(None) => {}
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+/// Finds the adapter connected to the device with the given address,
+/// and returns a socket bound to it along with the connection handle.
+fn connection(address: &StableId) -> Result<(HciSocket, u16)> {
+    let bdaddr = bdaddr(address);
+    for dev_id in adapters()? {
+        // Skip the adapters that are down or inaccessible.
+        let Ok(socket) = HciSocket::open(dev_id) else {
+            continue;
+        };
+        if let Some(handle) = socket.connection_handle(bdaddr) {
+            return Ok((socket, handle));
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "the device is not connected to any Bluetooth adapter",
+    )
+    .into())
+}
+
+/// Reads the RSSI of the connection to the device with the given address,
+/// through the adapter that it is connected to.
+pub(crate) fn signal_strength(address: &StableId) -> Result<i8> {
+    let (socket, handle) = connection(address)?;
+    socket.read_rssi(handle)
+}
+
+/// Like [`signal_strength`], but waits for the response of the adapter
+/// on the event loop.
+pub(crate) async fn signal_strength_async(address: &StableId) -> Result<i8> {
+    let (socket, handle) = connection(address)?;
+    socket.request_rssi(handle)?;
+    let socket = AsyncFd::new(socket)?;
+    let mut timeout = Sleep::new(TIMEOUT)?;
+    poll_fn(|cx| loop {
+        match socket.poll_io(cx, libc::EPOLLIN, HciSocket::read_response) {
+            Poll::Ready(Ok(Some(rssi))) => return Poll::Ready(Ok(rssi)),
+            Poll::Ready(Ok(None)) => continue, // an unrelated packet.
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => {}
+        }
+        return match Pin::new(&mut timeout).poll(cx) {
+            Poll::Ready(Ok(())) => Poll::Ready(Err(timed_out().into())),
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+            Poll::Pending => Poll::Pending,
+        };
+    })
+    .await
+}
+#[cfg(test)]
+mod tests {
+    use crate::bluetooth::{bdaddr, parse_rssi_response, ConnInfo, ConnInfoReq};
+    use std::mem;
+
+    #[test]
+    fn reverses_bdaddr() {
+        let address = "00:1f:32:aa:bb:cc".parse().unwrap();
+        assert_eq!(bdaddr(&address), [0xcc, 0xbb, 0xaa, 0x32, 0x1f, 0x00]);
+    }
+
+    #[test]
+    fn matches_kernel_layout() {
+        assert_eq!(mem::size_of::<ConnInfo>(), 16);
+        assert_eq!(mem::size_of::<ConnInfoReq>(), 24);
+    }
+
+    #[test]
+    fn parses_rssi_response() {
+        // Command complete: 1 command allowed, opcode 0x1405, status 0,
+        // handle 0x000b, RSSI -7 dBm.
+        let packet = [0x04, 0x0e, 0x07, 0x01, 0x05, 0x14, 0x00, 0x0b, 0x00, 0xf9];
+        assert_eq!(parse_rssi_response(&packet).unwrap().unwrap(), -7);
+
+        // Command status with an error: unknown connection identifier.
+        let packet = [0x04, 0x0f, 0x04, 0x02, 0x01, 0x05, 0x14];
+        assert!(parse_rssi_response(&packet).unwrap().is_err());
+
+        // Unrelated and truncated packets are skipped.
+        let packet = [0x04, 0x0e, 0x04, 0x01, 0x03, 0x0c, 0x00];
+        assert!(parse_rssi_response(&packet).is_none());
+        assert!(parse_rssi_response(&[0x04, 0x0e, 0x07, 0x01]).is_none());
+    }
+}
@@ -0,0 +1,15 @@
+//! Bridges that forward the input of a device to other subsystems.
+//!
+//! The [`uinput`] module creates virtual keyboards, mice and gamepads,
+//! through which an application can turn the buttons and motion of
+//! a Wii Remote into input that any other program understands.
+//!
+//! With the `portal` feature, the [`portal`] module injects keyboard and
+//! pointer input through the remote desktop portal instead, and an
+//! [`InputSink`](sink::InputSink) emits it through either mechanism.
+
+#[cfg(feature = "portal")]
+pub mod portal;
+#[cfg(feature = "portal")]
+pub mod sink;
+pub mod uinput;
@@ -0,0 +1,165 @@
+//! Keyboard and pointer input through the remote desktop portal of the
+//! desktop environment.
+//!
+//! The `xdg-desktop-portal` backends of most Wayland compositors let
+//! unprivileged applications inject input in the session, once the user
+//! grants them permission. This works where `/dev/uinput` is not writable,
+//! and needs no [`VirtualDevice`](super::uinput::VirtualDevice).
+//!
+//! The portal is reached through the `zbus` crate, whose `async-io` or
+//! `tokio` feature must be enabled to choose the runtime it runs on.
+
+use crate::Result;
+use futures_core::Stream;
+use std::collections::HashMap;
+use std::future::poll_fn;
+use std::pin::pin;
+use zbus::export::serde::Serialize;
+use zbus::zvariant::{DynamicType, OwnedObjectPath, OwnedValue, Value};
+use zbus::{Connection, MatchRule, MessageStream, MessageType};
+
+/// The well-known name, object path and interface of the remote desktop
+/// portal, implemented by the `xdg-desktop-portal` backends of most
+/// Wayland compositors.
+const SERVICE: &str = "org.freedesktop.portal.Desktop";
+const PATH: &str = "/org/freedesktop/portal/desktop";
+const INTERFACE: &str = "org.freedesktop.portal.RemoteDesktop";
+
+/// The interface of the objects through which the portal answers requests.
+const REQUEST_INTERFACE: &str = "org.freedesktop.portal.Request";
+
+/// The types of devices to control: the keyboard (1) and the pointer (2).
+const DEVICE_TYPES: u32 = 1 | 2;
+
+/// The range of the Linux event codes of the mouse buttons, from
+/// `BTN_LEFT` to `BTN_TASK`, which the portal emits as pointer buttons.
+const MOUSE_BUTTONS: std::ops::RangeInclusive<u16> = 0x110..=0x117;
+
+/// The options of a portal method.
+type Options<'a> = HashMap<&'a str, Value<'a>>;
+
+/// Injects keyboard and pointer input through the remote desktop portal
+/// of the desktop environment, which works in Wayland sessions where
+/// unprivileged users cannot access `/dev/uinput`.
+pub struct RemoteDesktop {
+    /// The connection to the session bus.
+    conn: Connection,
+    /// The handle of the remote desktop session.
+    session: OwnedObjectPath,
+}
+
+impl RemoteDesktop {
+    /// Starts a remote desktop session, for which the desktop asks the
+    /// user for permission to control the keyboard and the pointer.
+    pub async fn new() -> Result<Self> {
+        let conn = Connection::session().await?;
+        let options = Options::from([
+            ("handle_token", Value::from("xwiimote_create")),
+            ("session_handle_token", Value::from("xwiimote")),
+        ]);
+        let mut results = request(&conn, "CreateSession", "xwiimote_create", &(options,)).await?;
+        // The handle is given as a string rather than as an object path.
+        let session = results
+            .remove("session_handle")
+            .ok_or_else(|| zbus::Error::Failure("the portal created no session".into()))?;
+        let session = String::try_from(session).map_err(zbus::Error::from)?;
+        let session = OwnedObjectPath::try_from(session).map_err(zbus::Error::from)?;
+
+        let options = Options::from([
+            ("handle_token", Value::from("xwiimote_select")),
+            ("types", Value::from(DEVICE_TYPES)),
+        ]);
+        request(
+            &conn,
+            "SelectDevices",
+            "xwiimote_select",
+            &(&session, options),
+        )
+        .await?;
+        let options = Options::from([("handle_token", Value::from("xwiimote_start"))]);
+        request(&conn, "Start", "xwiimote_start", &(&session, "", options)).await?;
+        Ok(Self { conn, session })
+    }
+
+    /// Presses or releases a key or a mouse button, given its Linux
+    /// event code.
+    pub async fn key(&self, code: u16, pressed: bool) -> Result<()> {
+        let method = if MOUSE_BUTTONS.contains(&code) {
+            "NotifyPointerButton"
+        } else {
+            "NotifyKeyboardKeycode"
+        };
+        let body = (&self.session, Options::new(), code as i32, pressed as u32);
+        self.notify(method, &body).await
+    }
+
+    /// Moves the pointer by the given distances, where positive values
+    /// move right and down.
+    pub async fn move_pointer(&self, dx: i32, dy: i32) -> Result<()> {
+        let body = (&self.session, Options::new(), dx as f64, dy as f64);
+        self.notify("NotifyPointerMotion", &body).await
+    }
+
+    /// Scrolls the vertical axis by `steps`, where positive values scroll up.
+    pub async fn scroll(&self, steps: i32) -> Result<()> {
+        // The portal scrolls down for positive steps.
+        let body = (&self.session, Options::new(), 0u32, -steps);
+        self.notify("NotifyPointerAxisDiscrete", &body).await
+    }
+
+    /// Calls a method of the portal that emits input in the session.
+    async fn notify<B>(&self, method: &str, body: &B) -> Result<()>
+    where
+        B: Serialize + DynamicType,
+    {
+        self.conn
+            .call_method(Some(SERVICE), PATH, Some(INTERFACE), method, body)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Calls a method of the portal that answers through a request object,
+/// and waits for the results. The body must include the `handle_token`
+/// option with the value of `token`.
+async fn request<B>(
+    conn: &Connection,
+    method: &str,
+    token: &str,
+    body: &B,
+) -> zbus::Result<HashMap<String, OwnedValue>>
+where
+    B: Serialize + DynamicType,
+{
+    // The portal derives the path of the request object from our unique
+    // name and the token; subscribe to its response before calling the
+    // method, so that it cannot be missed.
+    let sender = conn
+        .unique_name()
+        .map(|name| name.as_str().trim_start_matches(':').replace('.', "_"))
+        .unwrap_or_default();
+    let path = format!("{PATH}/request/{sender}/{token}");
+    let rule = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .interface(REQUEST_INTERFACE)?
+        .member("Response")?
+        .path(path.as_str())?
+        .build();
+    let mut responses = pin!(MessageStream::for_match_rule(rule, conn, None).await?);
+
+    conn.call_method(Some(SERVICE), PATH, Some(INTERFACE), method, body)
+        .await?;
+    let response = poll_fn(|cx| responses.as_mut().poll_next(cx))
+        .await
+        .ok_or_else(|| zbus::Error::Failure("the portal did not respond".into()))??;
+    let (code, results) = response.body::<(u32, HashMap<String, OwnedValue>)>()?;
+    match code {
+        0 => Ok(results),
+        1 => Err(zbus::Error::Failure(
+            "the user denied the remote desktop request".into(),
+        )),
+        _ => Err(zbus::Error::Failure(format!(
+            "the portal cancelled the {method} request"
+        ))),
+    }
+}
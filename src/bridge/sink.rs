@@ -0,0 +1,137 @@
+//! Keyboard and pointer input through whichever mechanism the session
+//! allows.
+//!
+//! An [`InputSink`] emits the same keys and motion through a `uinput`
+//! [`VirtualDevice`], or through the [`RemoteDesktop`] portal in Wayland
+//! sessions where unprivileged users cannot write to `/dev/uinput`.
+//! The keys are identified by their Linux event codes in both cases.
+//!
+//! Every method takes the time of the original event, e.g. of the
+//! [`Event`](crate::events::Event) that caused the input. A virtual
+//! device reports it as an `MSC_TIMESTAMP` event, which keeps the
+//! intervals between the original events; see [`VirtualDevice::sync_at`].
+//! The portal has no way to carry it.
+
+use crate::bridge::portal::RemoteDesktop;
+use crate::bridge::uinput::{VirtualDevice, VirtualDeviceBuilder, REL_WHEEL, REL_X, REL_Y};
+use crate::{Error, Result};
+use std::io;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// The mechanisms through which an [`InputSink`] injects input.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Create a virtual device through `/dev/uinput`, which needs
+    /// write access to it.
+    #[default]
+    Uinput,
+    /// Inject the input through the remote desktop portal of the desktop
+    /// environment, which asks for permission once on startup. This works
+    /// in Wayland sessions without access to `/dev/uinput`.
+    Portal,
+}
+
+impl FromStr for Backend {
+    type Err = Error;
+
+    /// Parses the name of a backend: `uinput` or `portal`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "uinput" => Ok(Self::Uinput),
+            "portal" => Ok(Self::Portal),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown input backend `{s}`"),
+            )
+            .into()),
+        }
+    }
+}
+
+/// The destination of keyboard and pointer input, created by
+/// [`InputSink::new`].
+pub enum InputSink {
+    /// A virtual device, which only emits the events it declares.
+    Uinput(VirtualDevice),
+    /// A remote desktop session, which can emit any key and motion.
+    Portal(RemoteDesktop),
+}
+
+impl InputSink {
+    /// Creates a sink of the given backend. The [`Backend::Uinput`] sink
+    /// is the device built by `builder`; the portal asks the user for
+    /// permission instead.
+    pub async fn new(backend: Backend, builder: VirtualDeviceBuilder) -> Result<Self> {
+        Ok(match backend {
+            Backend::Uinput => Self::Uinput(builder.build()?),
+            Backend::Portal => Self::Portal(RemoteDesktop::new().await?),
+        })
+    }
+
+    /// Presses or releases a key or a mouse button at `time`.
+    ///
+    /// The events of a virtual device are synchronized right away.
+    pub async fn key(&mut self, key: u16, pressed: bool, time: SystemTime) -> Result<()> {
+        match self {
+            Self::Uinput(device) => {
+                if pressed {
+                    device.press(key)?;
+                } else {
+                    device.release(key)?;
+                }
+                device.sync_at(time)
+            }
+            Self::Portal(portal) => portal.key(key, pressed).await,
+        }
+    }
+
+    /// Repeats a key that is held down, at `time`.
+    pub async fn repeat(&mut self, key: u16, time: SystemTime) -> Result<()> {
+        match self {
+            Self::Uinput(device) => {
+                device.repeat(key)?;
+                device.sync_at(time)
+            }
+            // The compositor repeats the keys held down by itself.
+            Self::Portal(_) => Ok(()),
+        }
+    }
+
+    /// Moves the mouse pointer by the given distances at `time`, where
+    /// positive values move right and down.
+    pub async fn move_pointer(&mut self, dx: i32, dy: i32, time: SystemTime) -> Result<()> {
+        match self {
+            Self::Uinput(device) => {
+                device.move_relative(REL_X, dx)?;
+                device.move_relative(REL_Y, dy)?;
+                device.sync_at(time)
+            }
+            Self::Portal(portal) => portal.move_pointer(dx, dy).await,
+        }
+    }
+
+    /// Scrolls the mouse wheel by `steps` at `time`, where positive
+    /// values scroll up.
+    pub async fn scroll(&mut self, steps: i32, time: SystemTime) -> Result<()> {
+        match self {
+            Self::Uinput(device) => {
+                device.move_relative(REL_WHEEL, steps)?;
+                device.sync_at(time)
+            }
+            Self::Portal(portal) => portal.scroll(steps).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bridge::sink::Backend;
+
+    #[test]
+    fn parses_backends() {
+        assert_eq!("uinput".parse::<Backend>().unwrap(), Backend::Uinput);
+        assert_eq!(" Portal".parse::<Backend>().unwrap(), Backend::Portal);
+        assert!("x11".parse::<Backend>().is_err());
+    }
+}
@@ -0,0 +1,994 @@
+//! Virtual input devices backed by the `uinput` kernel module.
+//!
+//! A [`VirtualDevice`] appears to the rest of the system like a physical
+//! keyboard, mouse or gamepad, whose capabilities are declared through
+//! a [`VirtualDeviceBuilder`]. The events are identified by their Linux
+//! event codes, such as those returned by [`key_code`].
+//!
+//! Creating a device requires write access to `/dev/uinput`.
+//!
+//! # Examples
+//! Press the Page Down key whenever the A button is pressed.
+//! ```no_run
+//! use futures_util::TryStreamExt;
+//! use xwiimote::bridge::uinput::{VirtualDevice, KEY_PAGEDOWN};
+//! use xwiimote::events::{Event, Key, KeyState};
+//! use xwiimote::{Channels, Device, Monitor};
+//!
+//! # tokio_test::block_on(async {
+//! let mut keyboard = VirtualDevice::keyboard("Slide clicker").build()?;
+//! let address = Monitor::enumerate()?.try_next().await?.unwrap();
+//! let device = Device::connect(&address)?;
+//! device.open(Channels::CORE, false)?;
+//!
+//! let mut events = device.events()?;
+//! while let Some((event, time)) = events.try_next().await? {
+//!     match event {
+//!         Event::Key(Key::A, KeyState::Down) => keyboard.press(KEY_PAGEDOWN)?,
+//!         Event::Key(Key::A, KeyState::Up) => keyboard.release(KEY_PAGEDOWN)?,
+//!         _ => continue,
+//!     }
+//!     keyboard.sync_at(time)?;
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! # }).unwrap();
+//! ```
+
+use crate::{bail_if, Error, Result};
+use libc::c_int;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use std::{mem, slice, thread};
+
+// Definitions from `linux/uinput.h` and `linux/input-event-codes.h`,
+// which the `libc` crate lacks.
+
+const UI_DEV_CREATE: libc::Ioctl = 0x5501;
+const UI_DEV_DESTROY: libc::Ioctl = 0x5502;
+const UI_DEV_SETUP: libc::Ioctl = 0x405c_5503;
+const UI_ABS_SETUP: libc::Ioctl = 0x401c_5504;
+const UI_SET_EVBIT: libc::Ioctl = 0x4004_5564;
+const UI_SET_KEYBIT: libc::Ioctl = 0x4004_5565;
+const UI_SET_RELBIT: libc::Ioctl = 0x4004_5566;
+const UI_SET_ABSBIT: libc::Ioctl = 0x4004_5567;
+const UI_SET_MSCBIT: libc::Ioctl = 0x4004_5568;
+
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const EV_ABS: u16 = 0x03;
+const EV_MSC: u16 = 0x04;
+const SYN_REPORT: u16 = 0;
+const MSC_TIMESTAMP: u16 = 0x05;
+
+/// The bus type of devices that exist only in software.
+pub const BUS_VIRTUAL: u16 = 0x06;
+
+// Keyboard keys.
+
+/// The `KEY_ESC` key code.
+pub const KEY_ESC: u16 = 1;
+/// The `KEY_ENTER` key code.
+pub const KEY_ENTER: u16 = 28;
+/// The `KEY_SPACE` key code.
+pub const KEY_SPACE: u16 = 57;
+/// The `KEY_HOME` key code.
+pub const KEY_HOME: u16 = 102;
+/// The `KEY_UP` key code.
+pub const KEY_UP: u16 = 103;
+/// The `KEY_PAGEUP` key code.
+pub const KEY_PAGEUP: u16 = 104;
+/// The `KEY_LEFT` key code.
+pub const KEY_LEFT: u16 = 105;
+/// The `KEY_RIGHT` key code.
+pub const KEY_RIGHT: u16 = 106;
+/// The `KEY_END` key code.
+pub const KEY_END: u16 = 107;
+/// The `KEY_DOWN` key code.
+pub const KEY_DOWN: u16 = 108;
+/// The `KEY_PAGEDOWN` key code.
+pub const KEY_PAGEDOWN: u16 = 109;
+/// The `KEY_MUTE` key code.
+pub const KEY_MUTE: u16 = 113;
+/// The `KEY_VOLUMEDOWN` key code.
+pub const KEY_VOLUMEDOWN: u16 = 114;
+/// The `KEY_VOLUMEUP` key code.
+pub const KEY_VOLUMEUP: u16 = 115;
+/// The `KEY_NEXTSONG` key code.
+pub const KEY_NEXTSONG: u16 = 163;
+/// The `KEY_PLAYPAUSE` key code.
+pub const KEY_PLAYPAUSE: u16 = 164;
+/// The `KEY_PREVIOUSSONG` key code.
+pub const KEY_PREVIOUSSONG: u16 = 165;
+/// The `KEY_BRIGHTNESSDOWN` key code.
+pub const KEY_BRIGHTNESSDOWN: u16 = 224;
+/// The `KEY_BRIGHTNESSUP` key code.
+pub const KEY_BRIGHTNESSUP: u16 = 225;
+/// The `KEY_LEFTCTRL` key code.
+pub const KEY_LEFTCTRL: u16 = 29;
+/// The `KEY_LEFTSHIFT` key code.
+pub const KEY_LEFTSHIFT: u16 = 42;
+/// The `KEY_LEFTALT` key code.
+pub const KEY_LEFTALT: u16 = 56;
+/// The `KEY_LEFTMETA` key code, i.e. the Super or Windows key.
+pub const KEY_LEFTMETA: u16 = 125;
+
+// Mouse and gamepad buttons.
+
+/// The `BTN_LEFT` button code.
+pub const BTN_LEFT: u16 = 0x110;
+/// The `BTN_RIGHT` button code.
+pub const BTN_RIGHT: u16 = 0x111;
+/// The `BTN_MIDDLE` button code.
+pub const BTN_MIDDLE: u16 = 0x112;
+/// The `BTN_SOUTH` button code, i.e. the bottom face button (`A` on
+/// an Xbox controller).
+pub const BTN_SOUTH: u16 = 0x130;
+/// The `BTN_EAST` button code.
+pub const BTN_EAST: u16 = 0x131;
+/// The `BTN_NORTH` button code.
+pub const BTN_NORTH: u16 = 0x133;
+/// The `BTN_WEST` button code.
+pub const BTN_WEST: u16 = 0x134;
+/// The `BTN_TL` button code, i.e. the left shoulder button.
+pub const BTN_TL: u16 = 0x136;
+/// The `BTN_TR` button code, i.e. the right shoulder button.
+pub const BTN_TR: u16 = 0x137;
+/// The `BTN_SELECT` button code.
+pub const BTN_SELECT: u16 = 0x13a;
+/// The `BTN_START` button code.
+pub const BTN_START: u16 = 0x13b;
+/// The `BTN_MODE` button code, i.e. the button in the center.
+pub const BTN_MODE: u16 = 0x13c;
+/// The `BTN_DPAD_UP` button code.
+pub const BTN_DPAD_UP: u16 = 0x220;
+/// The `BTN_DPAD_DOWN` button code.
+pub const BTN_DPAD_DOWN: u16 = 0x221;
+/// The `BTN_DPAD_LEFT` button code.
+pub const BTN_DPAD_LEFT: u16 = 0x222;
+/// The `BTN_DPAD_RIGHT` button code.
+pub const BTN_DPAD_RIGHT: u16 = 0x223;
+
+// Axes.
+
+/// The `REL_X` relative axis code.
+pub const REL_X: u16 = 0x00;
+/// The `REL_Y` relative axis code.
+pub const REL_Y: u16 = 0x01;
+/// The `REL_HWHEEL` relative axis code, i.e. the horizontal wheel.
+pub const REL_HWHEEL: u16 = 0x06;
+/// The `REL_WHEEL` relative axis code, i.e. the vertical wheel.
+pub const REL_WHEEL: u16 = 0x08;
+/// The `ABS_X` absolute axis code.
+pub const ABS_X: u16 = 0x00;
+/// The `ABS_Y` absolute axis code.
+pub const ABS_Y: u16 = 0x01;
+/// The `ABS_RX` absolute axis code.
+pub const ABS_RX: u16 = 0x03;
+/// The `ABS_RY` absolute axis code.
+pub const ABS_RY: u16 = 0x04;
+
+/// The keys known to [`key_code`], by their names without the `KEY_`
+/// prefix. These are also the keys of a [`VirtualDevice::keyboard`].
+const KEY_NAMES: [(&str, u16); 99] = [
+    // Media and application keys.
+    ("PLAYPAUSE", KEY_PLAYPAUSE),
+    ("NEXTSONG", KEY_NEXTSONG),
+    ("PREVIOUSSONG", KEY_PREVIOUSSONG),
+    ("STOPCD", 166),
+    ("MUTE", KEY_MUTE),
+    ("VOLUMEUP", KEY_VOLUMEUP),
+    ("VOLUMEDOWN", KEY_VOLUMEDOWN),
+    ("BRIGHTNESSUP", KEY_BRIGHTNESSUP),
+    ("BRIGHTNESSDOWN", KEY_BRIGHTNESSDOWN),
+    ("CALC", 140),
+    ("WWW", 150),
+    ("MAIL", 155),
+    ("COMPUTER", 157),
+    ("BACK", 158),
+    ("FORWARD", 159),
+    ("HOMEPAGE", 172),
+    ("SEARCH", 217),
+    // Navigation and editing keys.
+    ("UP", KEY_UP),
+    ("DOWN", KEY_DOWN),
+    ("LEFT", KEY_LEFT),
+    ("RIGHT", KEY_RIGHT),
+    ("PAGEUP", KEY_PAGEUP),
+    ("PAGEDOWN", KEY_PAGEDOWN),
+    ("HOME", KEY_HOME),
+    ("END", KEY_END),
+    ("ENTER", KEY_ENTER),
+    ("ESC", KEY_ESC),
+    ("SPACE", KEY_SPACE),
+    ("TAB", 15),
+    ("BACKSPACE", 14),
+    ("DELETE", 111),
+    ("INSERT", 110),
+    // Function keys.
+    ("F1", 59),
+    ("F2", 60),
+    ("F3", 61),
+    ("F4", 62),
+    ("F5", 63),
+    ("F6", 64),
+    ("F7", 65),
+    ("F8", 66),
+    ("F9", 67),
+    ("F10", 68),
+    ("F11", 87),
+    ("F12", 88),
+    // Letters, e.g. for application shortcuts.
+    ("A", 30),
+    ("B", 48),
+    ("C", 46),
+    ("D", 32),
+    ("E", 18),
+    ("F", 33),
+    ("G", 34),
+    ("H", 35),
+    ("I", 23),
+    ("J", 36),
+    ("K", 37),
+    ("L", 38),
+    ("M", 50),
+    ("N", 49),
+    ("O", 24),
+    ("P", 25),
+    ("Q", 16),
+    ("R", 19),
+    ("S", 31),
+    ("T", 20),
+    ("U", 22),
+    ("V", 47),
+    ("W", 17),
+    ("X", 45),
+    ("Y", 21),
+    ("Z", 44),
+    // Modifiers, e.g. for keyboard shortcuts.
+    ("LEFTCTRL", KEY_LEFTCTRL),
+    ("RIGHTCTRL", 97),
+    ("LEFTSHIFT", KEY_LEFTSHIFT),
+    ("RIGHTSHIFT", 54),
+    ("LEFTALT", KEY_LEFTALT),
+    ("RIGHTALT", 100),
+    ("LEFTMETA", KEY_LEFTMETA),
+    ("RIGHTMETA", 126),
+    // Digits and punctuation, e.g. for typed text.
+    ("1", 2),
+    ("2", 3),
+    ("3", 4),
+    ("4", 5),
+    ("5", 6),
+    ("6", 7),
+    ("7", 8),
+    ("8", 9),
+    ("9", 10),
+    ("0", 11),
+    ("MINUS", 12),
+    ("EQUAL", 13),
+    ("LEFTBRACE", 26),
+    ("RIGHTBRACE", 27),
+    ("SEMICOLON", 39),
+    ("APOSTROPHE", 40),
+    ("GRAVE", 41),
+    ("BACKSLASH", 43),
+    ("COMMA", 51),
+    ("DOT", 52),
+    ("SLASH", 53),
+];
+
+/// The characters typed by the keys of a US keyboard, without and with
+/// the Shift key held down; see [`KeyMacro::text`].
+const US_LAYOUT: [(char, char, u16); 50] = [
+    ('`', '~', 41),
+    ('1', '!', 2),
+    ('2', '@', 3),
+    ('3', '#', 4),
+    ('4', '$', 5),
+    ('5', '%', 6),
+    ('6', '^', 7),
+    ('7', '&', 8),
+    ('8', '*', 9),
+    ('9', '(', 10),
+    ('0', ')', 11),
+    ('-', '_', 12),
+    ('=', '+', 13),
+    ('q', 'Q', 16),
+    ('w', 'W', 17),
+    ('e', 'E', 18),
+    ('r', 'R', 19),
+    ('t', 'T', 20),
+    ('y', 'Y', 21),
+    ('u', 'U', 22),
+    ('i', 'I', 23),
+    ('o', 'O', 24),
+    ('p', 'P', 25),
+    ('[', '{', 26),
+    (']', '}', 27),
+    ('\\', '|', 43),
+    ('a', 'A', 30),
+    ('s', 'S', 31),
+    ('d', 'D', 32),
+    ('f', 'F', 33),
+    ('g', 'G', 34),
+    ('h', 'H', 35),
+    ('j', 'J', 36),
+    ('k', 'K', 37),
+    ('l', 'L', 38),
+    (';', ':', 39),
+    ('\'', '"', 40),
+    ('z', 'Z', 44),
+    ('x', 'X', 45),
+    ('c', 'C', 46),
+    ('v', 'V', 47),
+    ('b', 'B', 48),
+    ('n', 'N', 49),
+    ('m', 'M', 50),
+    (',', '<', 51),
+    ('.', '>', 52),
+    ('/', '?', 53),
+    (' ', ' ', KEY_SPACE),
+    ('\t', '\t', 15),
+    ('\n', '\n', KEY_ENTER),
+];
+
+/// Finds a keyboard key by its Linux input event code name, such as
+/// `KEY_NEXTSONG`. The `KEY_` prefix is optional, and the case of the
+/// name is ignored.
+///
+/// Only the media, navigation, editing, function, letter, modifier,
+/// digit and punctuation keys are known.
+pub fn key_code(name: &str) -> Option<u16> {
+    let name = name.trim().to_ascii_uppercase();
+    let name = name.strip_prefix("KEY_").unwrap_or(&name);
+    KEY_NAMES
+        .iter()
+        .find(|(other, _)| *other == name)
+        .map(|(_, code)| *code)
+}
+
+/// Returns the Linux input event code name of a keyboard key known
+/// to [`key_code`], without the `KEY_` prefix.
+pub fn key_name(code: u16) -> Option<&'static str> {
+    KEY_NAMES
+        .iter()
+        .find(|(_, other)| *other == code)
+        .map(|(name, _)| *name)
+}
+
+/// The range of values reported through an absolute axis.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AbsAxis {
+    /// The minimum value.
+    pub min: i32,
+    /// The maximum value.
+    pub max: i32,
+    /// The amount of noise in the values, which consumers may filter out.
+    pub fuzz: i32,
+    /// The size of the dead zone around the center, within which
+    /// consumers may treat the values as zero.
+    pub flat: i32,
+}
+
+impl AbsAxis {
+    /// The range of the analog sticks of a [`VirtualDevice::gamepad`].
+    pub const STICK: Self = Self {
+        min: -32768,
+        max: 32767,
+        fuzz: 16,
+        flat: 128,
+    };
+}
+
+/// Declares the identity and capabilities of a [`VirtualDevice`].
+///
+/// The kernel rejects the events that a device did not declare.
+#[derive(Clone, Debug)]
+pub struct VirtualDeviceBuilder {
+    name: String,
+    vendor: u16,
+    product: u16,
+    version: u16,
+    keys: BTreeSet<u16>,
+    relative_axes: BTreeSet<u16>,
+    absolute_axes: BTreeMap<u16, AbsAxis>,
+}
+
+impl VirtualDeviceBuilder {
+    /// Sets the USB vendor and product identifiers of the device, which
+    /// some programs use to apply device-specific settings. Both are zero
+    /// by default.
+    pub fn ids(mut self, vendor: u16, product: u16) -> Self {
+        self.vendor = vendor;
+        self.product = product;
+        self
+    }
+
+    /// Sets the version number of the device, which is zero by default.
+    pub fn version(mut self, version: u16) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Lets the device press and release the key or button with the
+    /// given code.
+    pub fn key(mut self, code: u16) -> Self {
+        self.keys.insert(code);
+        self
+    }
+
+    /// Lets the device press and release the given keys or buttons.
+    pub fn keys(mut self, codes: impl IntoIterator<Item = u16>) -> Self {
+        self.keys.extend(codes);
+        self
+    }
+
+    /// Lets the device report movements along the relative axis
+    /// with the given code, such as [`REL_WHEEL`].
+    pub fn relative_axis(mut self, code: u16) -> Self {
+        self.relative_axes.insert(code);
+        self
+    }
+
+    /// Lets the device report positions along the absolute axis
+    /// with the given code, such as [`ABS_X`], within `range`.
+    pub fn absolute_axis(mut self, code: u16, range: AbsAxis) -> Self {
+        self.absolute_axes.insert(code, range);
+        self
+    }
+
+    /// Registers the device with the kernel.
+    pub fn build(self) -> Result<VirtualDevice> {
+        if self.name.is_empty()
+            || self.name.len() >= libc::UINPUT_MAX_NAME_SIZE
+            || self.name.contains('\0')
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the device name must have between 1 and 79 bytes",
+            )
+            .into());
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+            .open("/dev/uinput")?;
+        let fd = file.as_raw_fd();
+        let set_bit = |request, bit: u16| {
+            let res_code = unsafe { libc::ioctl(fd, request, bit as c_int) };
+            bail_if!(res_code == -1);
+            Ok::<_, crate::Error>(())
+        };
+
+        set_bit(UI_SET_EVBIT, EV_MSC)?;
+        set_bit(UI_SET_MSCBIT, MSC_TIMESTAMP)?;
+        if !self.keys.is_empty() {
+            set_bit(UI_SET_EVBIT, EV_KEY)?;
+            for &code in &self.keys {
+                set_bit(UI_SET_KEYBIT, code)?;
+            }
+        }
+        if !self.relative_axes.is_empty() {
+            set_bit(UI_SET_EVBIT, EV_REL)?;
+            for &code in &self.relative_axes {
+                set_bit(UI_SET_RELBIT, code)?;
+            }
+        }
+        if !self.absolute_axes.is_empty() {
+            set_bit(UI_SET_EVBIT, EV_ABS)?;
+            for (&code, range) in &self.absolute_axes {
+                set_bit(UI_SET_ABSBIT, code)?;
+                let setup = libc::uinput_abs_setup {
+                    code,
+                    absinfo: libc::input_absinfo {
+                        value: 0,
+                        minimum: range.min,
+                        maximum: range.max,
+                        fuzz: range.fuzz,
+                        flat: range.flat,
+                        resolution: 0,
+                    },
+                };
+                let res_code = unsafe { libc::ioctl(fd, UI_ABS_SETUP, &setup) };
+                bail_if!(res_code == -1);
+            }
+        }
+
+        let mut setup: libc::uinput_setup = unsafe { mem::zeroed() };
+        setup.id = libc::input_id {
+            bustype: BUS_VIRTUAL,
+            vendor: self.vendor,
+            product: self.product,
+            version: self.version,
+        };
+        for (dst, &src) in setup.name.iter_mut().zip(self.name.as_bytes()) {
+            *dst = src as libc::c_char;
+        }
+        let res_code = unsafe { libc::ioctl(fd, UI_DEV_SETUP, &setup) };
+        bail_if!(res_code == -1);
+        let res_code = unsafe { libc::ioctl(fd, UI_DEV_CREATE) };
+        bail_if!(res_code == -1);
+        Ok(VirtualDevice { file })
+    }
+}
+
+/// An input device registered through the `uinput` kernel module.
+///
+/// The events are delivered to the consumers of the device only after
+/// a call to [`VirtualDevice::sync`], which groups them into a single
+/// report. The events carry the time at which the kernel receives them,
+/// since the module ignores the timestamps written by user space; to
+/// keep the timing of the original events, [`VirtualDevice::sync_at`]
+/// also reports their time as an `MSC_TIMESTAMP` event, which consumers
+/// such as `libinput` read to measure the intervals between reports.
+///
+/// The device is removed when dropped.
+pub struct VirtualDevice {
+    file: File,
+}
+
+impl VirtualDevice {
+    /// Starts declaring a device with the given name, which must have
+    /// between 1 and 79 bytes. The device has no capabilities yet.
+    pub fn builder(name: impl Into<String>) -> VirtualDeviceBuilder {
+        VirtualDeviceBuilder {
+            name: name.into(),
+            vendor: 0,
+            product: 0,
+            version: 0,
+            keys: BTreeSet::new(),
+            relative_axes: BTreeSet::new(),
+            absolute_axes: BTreeMap::new(),
+        }
+    }
+
+    /// Starts declaring a keyboard that can press every key known
+    /// to [`key_code`].
+    pub fn keyboard(name: impl Into<String>) -> VirtualDeviceBuilder {
+        Self::builder(name).keys(KEY_NAMES.iter().map(|(_, code)| *code))
+    }
+
+    /// Starts declaring a mouse with three buttons and two wheels.
+    pub fn mouse(name: impl Into<String>) -> VirtualDeviceBuilder {
+        Self::builder(name)
+            .keys([BTN_LEFT, BTN_RIGHT, BTN_MIDDLE])
+            .relative_axis(REL_X)
+            .relative_axis(REL_Y)
+            .relative_axis(REL_WHEEL)
+            .relative_axis(REL_HWHEEL)
+    }
+
+    /// Starts declaring a gamepad with the buttons and analog sticks of
+    /// a typical console controller, laid out as programs expect from
+    /// the kernel gamepad conventions.
+    pub fn gamepad(name: impl Into<String>) -> VirtualDeviceBuilder {
+        Self::builder(name)
+            .keys([
+                BTN_SOUTH,
+                BTN_EAST,
+                BTN_NORTH,
+                BTN_WEST,
+                BTN_TL,
+                BTN_TR,
+                BTN_SELECT,
+                BTN_START,
+                BTN_MODE,
+                BTN_DPAD_UP,
+                BTN_DPAD_DOWN,
+                BTN_DPAD_LEFT,
+                BTN_DPAD_RIGHT,
+            ])
+            .absolute_axis(ABS_X, AbsAxis::STICK)
+            .absolute_axis(ABS_Y, AbsAxis::STICK)
+            .absolute_axis(ABS_RX, AbsAxis::STICK)
+            .absolute_axis(ABS_RY, AbsAxis::STICK)
+    }
+
+    /// Presses the key or button with the given code.
+    pub fn press(&mut self, code: u16) -> Result<()> {
+        self.emit(EV_KEY, code, 1)
+    }
+
+    /// Releases the key or button with the given code.
+    pub fn release(&mut self, code: u16) -> Result<()> {
+        self.emit(EV_KEY, code, 0)
+    }
+
+    /// Reports that the key with the given code, which is pressed,
+    /// repeats as if held down on a keyboard.
+    pub fn repeat(&mut self, code: u16) -> Result<()> {
+        self.emit(EV_KEY, code, 2)
+    }
+
+    /// Moves along a relative axis by `delta`; e.g. scrolls the wheel
+    /// up by one step if the axis is [`REL_WHEEL`] and `delta` is 1.
+    pub fn move_relative(&mut self, axis: u16, delta: i32) -> Result<()> {
+        self.emit(EV_REL, axis, delta)
+    }
+
+    /// Sets the position along an absolute axis.
+    pub fn set_absolute(&mut self, axis: u16, value: i32) -> Result<()> {
+        self.emit(EV_ABS, axis, value)
+    }
+
+    /// Delivers the events emitted since the last synchronization.
+    pub fn sync(&mut self) -> Result<()> {
+        self.emit(EV_SYN, SYN_REPORT, 0)
+    }
+
+    /// Delivers the events emitted since the last synchronization, along
+    /// with the time at which the original events occurred, e.g. the
+    /// time of an [`Event`](crate::events::Event) given by the kernel.
+    pub fn sync_at(&mut self, time: SystemTime) -> Result<()> {
+        self.emit(EV_MSC, MSC_TIMESTAMP, msc_timestamp(time))?;
+        self.sync()
+    }
+
+    /// Emits a step of a macro, and delivers it right away so that the
+    /// consumers see every key change apart. Delays are ignored; see
+    /// [`VirtualDevice::play`].
+    pub fn emit_step(&mut self, step: MacroStep) -> Result<()> {
+        match step {
+            MacroStep::Press(code) => self.press(code)?,
+            MacroStep::Release(code) => self.release(code)?,
+            MacroStep::Delay(_) => return Ok(()),
+        }
+        self.sync()
+    }
+
+    /// Plays a macro, blocking the current thread during its delays.
+    ///
+    /// Asynchronous applications should rather emit the steps through
+    /// [`VirtualDevice::emit_step`], and wait through their runtime.
+    pub fn play(&mut self, key_macro: &KeyMacro) -> Result<()> {
+        for &step in key_macro.steps() {
+            match step {
+                MacroStep::Delay(duration) => thread::sleep(duration),
+                step => self.emit_step(step)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, type_: u16, code: u16, value: i32) -> Result<()> {
+        self.file.write_all(&encode_event(type_, code, value))?;
+        Ok(())
+    }
+}
+
+impl Drop for VirtualDevice {
+    fn drop(&mut self) {
+        // Closing the file also removes the device; this only makes
+        // the removal independent of other copies of the descriptor.
+        unsafe { libc::ioctl(self.file.as_raw_fd(), UI_DEV_DESTROY) };
+    }
+}
+
+/// A step of a [`KeyMacro`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MacroStep {
+    /// Press the key with the given code.
+    Press(u16),
+    /// Release the key with the given code.
+    Release(u16),
+    /// Wait before the next step.
+    Delay(Duration),
+}
+
+/// A sequence of key events emitted in response to a single action,
+/// such as a keyboard shortcut or a text typed on behalf of the user.
+///
+/// A macro can be built step by step, or parsed from a comma-separated
+/// list of steps, each of which is
+/// - a key or a combination of keys joined by `+`, given by their names
+///   as in [`key_code`], or as `ctrl`, `shift`, `alt` or `meta`;
+/// - a quoted text, which is typed as by [`KeyMacro::text`]; or
+/// - a delay in milliseconds, such as `300ms`.
+///
+/// # Examples
+/// Open a terminal and run `htop` in it.
+/// ```
+/// use std::time::Duration;
+/// use xwiimote::bridge::uinput::{KeyMacro, KEY_ENTER, KEY_LEFTALT, KEY_LEFTCTRL};
+///
+/// let key_macro: KeyMacro = r#"ctrl+alt+t, 300ms, "htop", enter"#.parse()?;
+/// let built = KeyMacro::new()
+///     .chord(&[KEY_LEFTCTRL, KEY_LEFTALT, 20])
+///     .delay(Duration::from_millis(300))
+///     .text("htop")?
+///     .tap(KEY_ENTER);
+/// assert_eq!(key_macro, built);
+/// # Ok::<(), xwiimote::Error>(())
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyMacro {
+    steps: Vec<MacroStep>,
+}
+
+impl KeyMacro {
+    /// Creates an empty macro.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends the press of a key, which stays pressed until released.
+    pub fn press(mut self, code: u16) -> Self {
+        self.steps.push(MacroStep::Press(code));
+        self
+    }
+
+    /// Appends the release of a key.
+    pub fn release(mut self, code: u16) -> Self {
+        self.steps.push(MacroStep::Release(code));
+        self
+    }
+
+    /// Appends the press and release of a key.
+    pub fn tap(self, code: u16) -> Self {
+        self.press(code).release(code)
+    }
+
+    /// Appends a combination of keys, such as Ctrl+Alt+T: the keys are
+    /// pressed in order, and then released in the reverse order.
+    pub fn chord(mut self, codes: &[u16]) -> Self {
+        self.steps
+            .extend(codes.iter().map(|&code| MacroStep::Press(code)));
+        let releases = codes.iter().rev().map(|&code| MacroStep::Release(code));
+        self.steps.extend(releases);
+        self
+    }
+
+    /// Appends a pause before the next step.
+    pub fn delay(mut self, duration: Duration) -> Self {
+        self.steps.push(MacroStep::Delay(duration));
+        self
+    }
+
+    /// Appends the keys that type the given text, assuming that the
+    /// consumers use the US keyboard layout.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if the text contains
+    /// a character that the layout cannot type.
+    pub fn text(mut self, text: &str) -> Result<Self> {
+        for c in text.chars() {
+            let Some(&(lower, _, code)) = US_LAYOUT
+                .iter()
+                .find(|&&(lower, upper, _)| c == lower || c == upper)
+            else {
+                return Err(invalid_macro(format!("cannot type `{c}`")));
+            };
+            self = if c == lower {
+                self.tap(code)
+            } else {
+                self.chord(&[KEY_LEFTSHIFT, code])
+            };
+        }
+        Ok(self)
+    }
+
+    /// Returns the steps of the macro, in order.
+    pub fn steps(&self) -> &[MacroStep] {
+        &self.steps
+    }
+
+    /// Lists the keys pressed by the macro, which the [`VirtualDevice`]
+    /// that plays it must declare.
+    pub fn keys(&self) -> impl Iterator<Item = u16> + '_ {
+        self.steps.iter().filter_map(|step| match step {
+            MacroStep::Press(code) => Some(*code),
+            _ => None,
+        })
+    }
+}
+
+fn invalid_macro(msg: String) -> Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg).into()
+}
+
+/// Splits a macro into its comma-separated steps, ignoring the commas
+/// within quoted texts.
+fn split_steps(s: &str) -> Vec<&str> {
+    let mut steps = Vec::new();
+    let (mut start, mut quoted) = (0, false);
+    for (ix, c) in s.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                steps.push(&s[start..ix]);
+                start = ix + 1;
+            }
+            _ => {}
+        }
+    }
+    steps.push(&s[start..]);
+    steps
+}
+
+impl FromStr for KeyMacro {
+    type Err = Error;
+
+    /// Parses a macro from a comma-separated list of steps; see
+    /// [`KeyMacro`] for the syntax. Empty steps are ignored.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut key_macro = Self::new();
+        for step in split_steps(s).into_iter().map(str::trim) {
+            if step.is_empty() {
+                continue;
+            } else if let Some(quoted) = step.strip_prefix('"') {
+                let text = quoted
+                    .strip_suffix('"')
+                    .ok_or_else(|| invalid_macro(format!("unterminated text `{step}`")))?;
+                key_macro = key_macro.text(text)?;
+            } else if let Some(millis) = step.strip_suffix("ms") {
+                let millis = millis
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid_macro(format!("invalid delay `{step}`")))?;
+                key_macro = key_macro.delay(Duration::from_millis(millis));
+            } else {
+                let codes = step
+                    .split('+')
+                    .map(|name| {
+                        let name = name.trim();
+                        let code = match name.to_ascii_lowercase().as_str() {
+                            "ctrl" => Some(KEY_LEFTCTRL),
+                            "shift" => Some(KEY_LEFTSHIFT),
+                            "alt" => Some(KEY_LEFTALT),
+                            "meta" | "super" => Some(KEY_LEFTMETA),
+                            _ => key_code(name),
+                        };
+                        code.ok_or_else(|| invalid_macro(format!("unknown key `{name}`")))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                key_macro = key_macro.chord(&codes);
+            }
+        }
+        Ok(key_macro)
+    }
+}
+
+/// Returns the value of an `MSC_TIMESTAMP` event for `time`: the number
+/// of microseconds since the Unix epoch, which wraps around like the
+/// hardware counters that the event usually reports. Only the differences
+/// between the values are meaningful.
+fn msc_timestamp(time: SystemTime) -> i32 {
+    let micros = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros();
+    micros as u32 as i32
+}
+
+/// Converts an event into a `struct input_event`, whose timestamp is
+/// left for the kernel to set.
+fn encode_event(type_: u16, code: u16, value: i32) -> Vec<u8> {
+    let mut event: libc::input_event = unsafe { mem::zeroed() };
+    event.type_ = type_;
+    event.code = code;
+    event.value = value;
+    let bytes = unsafe {
+        slice::from_raw_parts(
+            (&event as *const libc::input_event).cast::<u8>(),
+            mem::size_of::<libc::input_event>(),
+        )
+    };
+    bytes.to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bridge::uinput::{
+        encode_event, key_code, key_name, msc_timestamp, KeyMacro, MacroStep, VirtualDevice, ABS_X,
+        BTN_SOUTH, EV_KEY, KEY_ENTER, KEY_LEFTCTRL, KEY_LEFTSHIFT, KEY_NAMES, KEY_PAGEDOWN,
+        KEY_SPACE, REL_WHEEL,
+    };
+    use std::collections::BTreeSet;
+    use std::mem;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn finds_keys_by_name() {
+        assert_eq!(key_code("KEY_PAGEDOWN"), Some(KEY_PAGEDOWN));
+        assert_eq!(key_code(" pagedown "), Some(KEY_PAGEDOWN));
+        assert_eq!(key_code("KEY_NEXTSONG"), Some(163));
+        assert_eq!(key_code("KEY_Z"), Some(44));
+        assert_eq!(key_code("KEY_FOO"), None);
+        assert_eq!(key_name(KEY_PAGEDOWN), Some("PAGEDOWN"));
+        assert_eq!(key_name(0), None);
+
+        let codes: BTreeSet<_> = KEY_NAMES.iter().map(|(_, code)| code).collect();
+        assert_eq!(codes.len(), KEY_NAMES.len(), "duplicate key codes");
+    }
+
+    #[test]
+    fn declares_presets() {
+        let keyboard = VirtualDevice::keyboard("Keyboard");
+        assert_eq!(keyboard.keys.len(), KEY_NAMES.len());
+        assert!(keyboard.relative_axes.is_empty());
+        let mouse = VirtualDevice::mouse("Mouse").ids(0x057e, 0x0306);
+        assert!(mouse.relative_axes.contains(&REL_WHEEL));
+        assert_eq!((mouse.vendor, mouse.product), (0x057e, 0x0306));
+        let gamepad = VirtualDevice::gamepad("Gamepad").key(BTN_SOUTH);
+        assert!(gamepad.keys.contains(&BTN_SOUTH));
+        assert!(gamepad.absolute_axes.contains_key(&ABS_X));
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        for name in ["", "a\0b", &"x".repeat(80)] {
+            let err = VirtualDevice::builder(name).build().err().unwrap();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn parses_macros() {
+        let key_macro: KeyMacro = r#"ctrl+T, 50ms, "a, B", Enter"#.parse().unwrap();
+        let t = key_code("T").unwrap();
+        let expected = KeyMacro::new()
+            .chord(&[KEY_LEFTCTRL, t])
+            .delay(Duration::from_millis(50))
+            .tap(key_code("A").unwrap())
+            .tap(key_code("COMMA").unwrap())
+            .tap(KEY_SPACE)
+            .chord(&[KEY_LEFTSHIFT, key_code("B").unwrap()])
+            .tap(KEY_ENTER);
+        assert_eq!(key_macro, expected);
+        assert_eq!(
+            key_macro.steps()[..4],
+            [
+                MacroStep::Press(KEY_LEFTCTRL),
+                MacroStep::Press(t),
+                MacroStep::Release(t),
+                MacroStep::Release(KEY_LEFTCTRL)
+            ]
+        );
+        assert!(key_macro
+            .keys()
+            .all(|code| KEY_NAMES.iter().any(|(_, c)| *c == code)));
+
+        for invalid in ["ctrl+foo", "\"unterminated", "abcms", "\"é\""] {
+            let err = invalid.parse::<KeyMacro>().unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        }
+        assert_eq!("".parse::<KeyMacro>().unwrap(), KeyMacro::new());
+    }
+
+    #[test]
+    fn encodes_events() {
+        let bytes = encode_event(EV_KEY, KEY_PAGEDOWN, 1);
+        let len = mem::size_of::<libc::input_event>();
+        assert_eq!(bytes.len(), len);
+        assert_eq!(bytes[len - 8..len - 6], EV_KEY.to_ne_bytes());
+        assert_eq!(bytes[len - 6..len - 4], KEY_PAGEDOWN.to_ne_bytes());
+        assert_eq!(bytes[len - 4..], 1i32.to_ne_bytes());
+        assert!(bytes[..len - 8].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn wraps_timestamps() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_micros(u32::MAX as u64 - 4);
+        assert_eq!(msc_timestamp(time), -5);
+        let later = time + Duration::from_micros(10);
+        assert_eq!(msc_timestamp(later), 5);
+        assert_eq!(msc_timestamp(later).wrapping_sub(msc_timestamp(time)), 10);
+    }
+
+    #[test]
+    #[ignore = "requires write access to /dev/uinput"]
+    fn creates_device() -> crate::Result<()> {
+        let mut device = VirtualDevice::keyboard("xwiimote test keyboard").build()?;
+        device.press(KEY_PAGEDOWN)?;
+        device.release(KEY_PAGEDOWN)?;
+        device.sync()?;
+        let devices = std::fs::read_to_string("/proc/bus/input/devices")?;
+        assert!(devices.contains("xwiimote test keyboard"));
+        Ok(())
+    }
+}
@@ -0,0 +1,1000 @@
+//! Sharing a device between several processes.
+//!
+//! The kernel delivers the events of a device to every process that
+//! opens it, but output commands from different processes race with
+//! each other: one process may turn a light off right after another
+//! one turned it on. A [`Broker`] owns a device on behalf of a set of
+//! client processes, which connect to it through a Unix socket in order
+//! to receive its events and to send output commands that are executed
+//! in the order they arrive.
+//!
+//! # Protocol
+//! Both directions carry frames that consist of the length of the rest
+//! of the frame as a little-endian `u32`, a tag byte and a payload.
+//! Clients send the following requests:
+//!
+//! | Tag    | Request      | Payload                                    |
+//! |--------|--------------|--------------------------------------------|
+//! | `0x01` | Set an LED   | the light number (1 to 4), and 1 for on    |
+//! | `0x02` | Set rumble   | 1 to turn the motor on, or 0 to turn it off |
+//! | `0x03` | Read battery | none                                       |
+//! | `0x04` | Read an LED  | the light number (1 to 4)                  |
+//!
+//! The broker answers every request with a reply frame (tag `0x82`),
+//! in the order the requests were received. Its payload holds a status
+//! as a little-endian `i32`, which is 0 on success, an OS error number
+//! on failure, or -1 if the request was malformed; and a value byte,
+//! which is the battery level or the state of the light for the
+//! requests that read them, and 0 otherwise.
+//!
+//! Event frames (tag `0x81`) can be interleaved with the replies.
+//! Their payload is an event record in the format used by the
+//! [`recording`](crate::recording) module.
+//!
+//! A [`BrokerClient`] speaks this protocol on behalf of an application,
+//! and implements [`WiimoteDevice`] just like a [`Device`] does.
+//!
+//! # Examples
+//! Share the first connected device until it disconnects.
+//! ```no_run
+//! use futures_util::TryStreamExt;
+//! use xwiimote::broker::Broker;
+//! use xwiimote::{Channels, Device, Monitor};
+//!
+//! # tokio_test::block_on(async {
+//! let address = Monitor::enumerate()?.try_next().await?.unwrap();
+//! let path = Broker::default_path(&address.stable_id()?)?;
+//! let device = Device::connect(&address)?;
+//! device.open(Channels::CORE | Channels::ACCELEROMETER, true)?;
+//!
+//! let broker = Broker::bind(device, &path)?;
+//! println!("serving clients at {}", path.display());
+//! broker.run().await?;
+//! # Ok::<(), std::io::Error>(())
+//! # }).unwrap();
+//! ```
+//!
+//! Turn on the first light of a brokered device, from another process.
+//! ```no_run
+//! use xwiimote::broker::{Broker, BrokerClient};
+//! use xwiimote::{Led, StableId};
+//!
+//! let id: StableId = "00:1f:32:aa:bb:cc".parse()?;
+//! let client = BrokerClient::connect(Broker::default_path(&id)?)?;
+//! client.set_led(Led::One, true)?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::async_fd::AsyncFd;
+use crate::bus::Unpark;
+use crate::events::{Event, RawEvent};
+use crate::recording::{encode_record, micros_since_epoch};
+use crate::timer::Sleep;
+use crate::{Device, Error, Led, Result, StableId, WiimoteDevice};
+use futures_core::Stream;
+use num_traits::FromPrimitive;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::{self, Future};
+use std::io::{self, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::pin::{pin, Pin};
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use std::{env, fs};
+
+/// The largest frame accepted by either side.
+pub(crate) const MAX_FRAME_LEN: usize = 64 * 1024;
+
+/// The number of unsent bytes after which a client that does not
+/// read its frames is disconnected.
+const MAX_BACKLOG: usize = 1024 * 1024;
+
+/// The time to wait before accepting connections again once the
+/// process runs out of file descriptors or memory.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// What the broker does once accepting a connection fails.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum AcceptFailure {
+    /// The connection failed before it was accepted; accept the next one.
+    Skip,
+    /// The process ran out of descriptors or memory, which the clients
+    /// that disconnect may free; try again after [`ACCEPT_BACKOFF`].
+    Pause,
+    /// The listener itself is broken.
+    Fatal,
+}
+
+impl AcceptFailure {
+    fn classify(err: &Error) -> Self {
+        match err {
+            // Security modules may reject a connection with `EPERM`.
+            Error::Permission => Self::Skip,
+            _ => match err.raw_os_error() {
+                Some(libc::ECONNABORTED | libc::EPROTO | libc::EINTR) => Self::Skip,
+                Some(libc::EMFILE | libc::ENFILE | libc::ENOBUFS | libc::ENOMEM) => Self::Pause,
+                _ => Self::Fatal,
+            },
+        }
+    }
+}
+
+/// A command sent by a client to the broker.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Request {
+    SetLed(Led, bool),
+    SetRumble(bool),
+    Battery,
+    Led(Led),
+}
+
+impl Request {
+    const SET_LED: u8 = 0x01;
+    const SET_RUMBLE: u8 = 0x02;
+    const BATTERY: u8 = 0x03;
+    const LED: u8 = 0x04;
+
+    /// Appends the frame of the request to `buf`.
+    pub fn encode(self, buf: &mut Vec<u8>) {
+        let (tag, payload) = match self {
+            Self::SetLed(light, enabled) => (Self::SET_LED, vec![light as u8, enabled as u8]),
+            Self::SetRumble(enabled) => (Self::SET_RUMBLE, vec![enabled as u8]),
+            Self::Battery => (Self::BATTERY, vec![]),
+            Self::Led(light) => (Self::LED, vec![light as u8]),
+        };
+        encode_frame(tag, &payload, buf);
+    }
+
+    /// Parses a frame sent by a client.
+    pub fn decode(tag: u8, payload: &[u8]) -> Result<Self> {
+        let light = |number: u8| {
+            Led::from_u8(number).ok_or_else(|| invalid_frame("invalid LED light number"))
+        };
+        match (tag, payload) {
+            (Self::SET_LED, &[number, enabled]) => Ok(Self::SetLed(light(number)?, enabled != 0)),
+            (Self::SET_RUMBLE, &[enabled]) => Ok(Self::SetRumble(enabled != 0)),
+            (Self::BATTERY, &[]) => Ok(Self::Battery),
+            (Self::LED, &[number]) => Ok(Self::Led(light(number)?)),
+            _ => Err(invalid_frame("unknown request")),
+        }
+    }
+
+    /// Executes the request on a device, and returns the value read.
+    fn execute(self, device: &Device) -> Result<u8> {
+        match self {
+            Self::SetLed(light, enabled) => device.set_led(light, enabled).map(|_| 0),
+            Self::SetRumble(enabled) => device.rumble(enabled).map(|_| 0),
+            Self::Battery => device.battery(),
+            Self::Led(light) => device.led(light).map(u8::from),
+        }
+    }
+}
+
+/// A frame sent by the broker to its clients.
+#[derive(Debug)]
+pub(crate) enum Response {
+    Event(Event, SystemTime),
+    Reply(Result<u8>),
+}
+
+impl Response {
+    const EVENT: u8 = 0x81;
+    const REPLY: u8 = 0x82;
+
+    /// The status of a reply to a malformed request.
+    const MALFORMED: i32 = -1;
+
+    /// Appends the frame of the response to `buf`.
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        let mut payload = Vec::new();
+        let tag = match self {
+            Self::Event(event, time) => {
+                encode_record(event, micros_since_epoch(*time), &mut payload);
+                Self::EVENT
+            }
+            Self::Reply(result) => {
+                let (status, value) = match result {
+                    Ok(value) => (0, *value),
+                    // The client classifies the error code again.
+                    Err(Error::Disconnected) => (libc::ENODEV, 0),
+                    Err(Error::Permission) => (libc::EACCES, 0),
+                    Err(err) => (err.raw_os_error().unwrap_or(Self::MALFORMED), 0),
+                };
+                payload.extend_from_slice(&status.to_le_bytes());
+                payload.push(value);
+                Self::REPLY
+            }
+        };
+        encode_frame(tag, &payload, buf);
+    }
+
+    /// Parses a frame sent by the broker. Event frames that hold
+    /// an event this library does not know are reported as [`None`].
+    pub fn decode(tag: u8, payload: &[u8]) -> Result<Option<Self>> {
+        match (tag, payload) {
+            (Self::EVENT, _) => {
+                let mut pos = 0;
+                let (_, raw) = crate::recording::decode_record(payload, &mut pos)?;
+                // The record holds a valid event type and the payload of
+                // the type, just like the recordings replayed from a file.
+                let event = Event::parse(unsafe { RawEvent::new(&raw) });
+                Ok(event.map(|(event, time)| Self::Event(event, time)))
+            }
+            (Self::REPLY, &[s0, s1, s2, s3, value]) => {
+                let result = match i32::from_le_bytes([s0, s1, s2, s3]) {
+                    0 => Ok(value),
+                    Self::MALFORMED => Err(invalid_frame("the broker rejected the request")),
+                    errno => Err(io::Error::from_raw_os_error(errno).into()),
+                };
+                Ok(Some(Self::Reply(result)))
+            }
+            _ => Err(invalid_frame("unknown response")),
+        }
+    }
+}
+
+/// Appends a frame with the given tag and payload to `buf`.
+fn encode_frame(tag: u8, payload: &[u8], buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&(payload.len() as u32 + 1).to_le_bytes());
+    buf.push(tag);
+    buf.extend_from_slice(payload);
+}
+
+/// Removes the first complete frame from `buf`, and returns its tag
+/// and payload.
+pub(crate) fn next_frame(buf: &mut Vec<u8>) -> Result<Option<(u8, Vec<u8>)>> {
+    let Some(len) = buf.get(..4) else {
+        return Ok(None);
+    };
+    let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+    if len == 0 || len > MAX_FRAME_LEN {
+        return Err(invalid_frame("invalid frame length"));
+    }
+    if buf.len() < 4 + len {
+        return Ok(None);
+    }
+    let frame: Vec<u8> = buf.drain(..4 + len).skip(4).collect();
+    Ok(Some((frame[0], frame[1..].to_vec())))
+}
+
+fn invalid_frame(msg: &str) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+/// A process connected to a [`Broker`].
+struct Client {
+    stream: AsyncFd<UnixStream>,
+    /// The received bytes that do not form a complete frame yet.
+    input: Vec<u8>,
+    /// The frames that are yet to be sent.
+    output: Vec<u8>,
+    closed: bool,
+}
+
+impl Client {
+    fn new(stream: UnixStream) -> Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream: AsyncFd::new(stream)?,
+            input: Vec::new(),
+            output: Vec::new(),
+            closed: false,
+        })
+    }
+
+    /// Queues a frame for sending, and disconnects the client if it
+    /// has fallen too far behind.
+    fn send(&mut self, frame: &[u8]) {
+        if self.output.len() + frame.len() > MAX_BACKLOG {
+            self.closed = true;
+        } else {
+            self.output.extend_from_slice(frame);
+        }
+    }
+}
+
+/// Owns a device and serves it to the processes that connect to
+/// a Unix socket. See the [module documentation](self) for details.
+pub struct Broker {
+    device: Device,
+    listener: AsyncFd<UnixListener>,
+    path: PathBuf,
+}
+
+impl Broker {
+    /// Listens for clients at the Unix socket `path`.
+    ///
+    /// The events forwarded to clients are those of the channels
+    /// opened on `device`. The [core channel](crate::Channels::CORE) is
+    /// opened in writable mode, if not already open, so that clients
+    /// can toggle the rumble motor.
+    ///
+    /// A socket file left behind by a broker that exited is replaced,
+    /// but binding fails if another broker is listening at `path`.
+    pub fn bind(device: Device, path: impl AsRef<Path>) -> Result<Self> {
+        device.ensure_core_open()?;
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let listener = match UnixListener::bind(path) {
+            Err(err)
+                if err.kind() == io::ErrorKind::AddrInUse && UnixStream::connect(path).is_err() =>
+            {
+                fs::remove_file(path)?;
+                UnixListener::bind(path)?
+            }
+            res => res?,
+        };
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            device,
+            listener: AsyncFd::new(listener)?,
+            path: path.to_owned(),
+        })
+    }
+
+    /// Returns the conventional socket path of the broker that owns
+    /// the device with the given identifier, which is located in
+    /// the `xwiimote` subdirectory of `$XDG_RUNTIME_DIR`.
+    pub fn default_path(id: &StableId) -> Result<PathBuf> {
+        let runtime_dir = env::var_os("XDG_RUNTIME_DIR")
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "XDG_RUNTIME_DIR is not set"))?;
+        Ok(PathBuf::from(runtime_dir)
+            .join("xwiimote")
+            .join(format!("{id}.sock")))
+    }
+
+    /// Returns the path of the socket.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the device served by the broker.
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// Serves clients until the device disconnects.
+    ///
+    /// Clients that send malformed frames, or that do not keep up with
+    /// the events of the device, are disconnected without affecting
+    /// the others. Connections that fail before they are accepted are
+    /// skipped, and once the process runs out of file descriptors, the
+    /// broker stops accepting connections for a moment.
+    pub async fn run(&self) -> Result<()> {
+        let mut events = pin!(self.device.events()?);
+        let mut clients: Vec<Client> = Vec::new();
+        let mut frame = Vec::new();
+        // Expires once the broker should accept connections again.
+        let mut paused: Option<Sleep> = None;
+        future::poll_fn(|cx| {
+            // Accept the pending connections, unless paused.
+            if let Some(timer) = &mut paused {
+                if Pin::new(timer).poll(cx)?.is_ready() {
+                    paused = None;
+                }
+            }
+            while paused.is_none() {
+                let Poll::Ready(res) = self.listener.poll_io(cx, libc::EPOLLIN, |l| l.accept())
+                else {
+                    break;
+                };
+                let err = match res {
+                    Ok((stream, _)) => {
+                        clients.extend(Client::new(stream).ok());
+                        continue;
+                    }
+                    Err(err) => err,
+                };
+                match AcceptFailure::classify(&err) {
+                    AcceptFailure::Skip => {}
+                    AcceptFailure::Pause => {
+                        let mut timer = Sleep::new(ACCEPT_BACKOFF)?;
+                        if Pin::new(&mut timer).poll(cx)?.is_pending() {
+                            paused = Some(timer);
+                        }
+                    }
+                    AcceptFailure::Fatal => return Poll::Ready(Err(err)),
+                }
+            }
+
+            // Forward the received events.
+            while let Poll::Ready(res) = events.as_mut().poll_next(cx) {
+                let Some((event, time)) = res.transpose()? else {
+                    return Poll::Ready(Ok(()));
+                };
+                frame.clear();
+                Response::Event(event, time).encode(&mut frame);
+                for client in &mut clients {
+                    client.send(&frame);
+                }
+            }
+
+            for client in &mut clients {
+                // Execute the requests, in order.
+                let mut chunk = [0; 4096];
+                while !client.closed {
+                    let read = client
+                        .stream
+                        .poll_io(cx, libc::EPOLLIN, |s| (&*s).read(&mut chunk));
+                    match read {
+                        Poll::Ready(Ok(0) | Err(_)) => client.closed = true,
+                        Poll::Ready(Ok(n)) => client.input.extend_from_slice(&chunk[..n]),
+                        Poll::Pending => break,
+                    }
+                }
+                loop {
+                    let reply = match next_frame(&mut client.input) {
+                        Ok(Some((tag, payload))) => Request::decode(tag, &payload)
+                            .and_then(|request| request.execute(&self.device)),
+                        Ok(None) => break,
+                        Err(_) => {
+                            client.closed = true;
+                            break;
+                        }
+                    };
+                    frame.clear();
+                    Response::Reply(reply).encode(&mut frame);
+                    client.send(&frame);
+                }
+
+                // Send the queued frames.
+                while !client.closed && !client.output.is_empty() {
+                    let written = client
+                        .stream
+                        .poll_io(cx, libc::EPOLLOUT, |s| (&*s).write(&client.output));
+                    match written {
+                        Poll::Ready(Ok(n)) => drop(client.output.drain(..n)),
+                        Poll::Ready(Err(_)) => client.closed = true,
+                        Poll::Pending => break,
+                    }
+                }
+            }
+            clients.retain(|client| !client.closed);
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+impl Drop for Broker {
+    fn drop(&mut self) {
+        // Another broker may have replaced the socket already.
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// The state of a [`BrokerClient`] that changes as frames arrive.
+#[derive(Default)]
+struct ClientState {
+    /// The received bytes that do not form a complete frame yet.
+    input: Vec<u8>,
+    /// The events received while waiting for a reply.
+    events: VecDeque<(Event, SystemTime)>,
+    /// The replies to the request in flight, once received.
+    replies: VecDeque<Result<u8>>,
+    /// Was a request sent that did not complete yet?
+    in_flight: bool,
+    /// The number of replies to discard, since their requests
+    /// were cancelled.
+    abandoned: usize,
+    /// The task of the request in flight, which waits for its reply
+    /// even if the stream of events reads it.
+    requester: Option<Waker>,
+    /// The tasks of the requests that wait for the one in flight.
+    waiters: Vec<Waker>,
+    /// Has the broker closed the connection?
+    closed: bool,
+}
+
+impl ClientState {
+    /// Moves the complete frames of the input to the queues of events
+    /// and replies.
+    fn read_frames(&mut self) -> Result<()> {
+        while let Some((tag, payload)) = next_frame(&mut self.input)? {
+            match Response::decode(tag, &payload)? {
+                Some(Response::Event(event, time)) => self.events.push_back((event, time)),
+                Some(Response::Reply(_)) if self.abandoned > 0 => self.abandoned -= 1,
+                Some(Response::Reply(reply)) if self.in_flight => {
+                    self.replies.push_back(reply);
+                    self.wake_requester();
+                }
+                Some(Response::Reply(_)) => return Err(invalid_frame("unexpected reply")),
+                None => {} // the broker knows an event we don't
+            }
+        }
+        Ok(())
+    }
+
+    fn wake_requester(&mut self) {
+        if let Some(waker) = self.requester.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// A connection to a [`Broker`], which forwards output commands
+/// to the device it owns and receives its events.
+///
+/// The output operations block until the broker replies; their `_async`
+/// variants wait on the event loop instead. Events that arrive in the
+/// meantime are kept until the stream returned by
+/// [`BrokerClient::events`] produces them.
+pub struct BrokerClient {
+    stream: AsyncFd<UnixStream>,
+    state: RefCell<ClientState>,
+}
+
+impl BrokerClient {
+    /// Connects to the broker listening at the Unix socket `path`, which
+    /// is usually obtained through [`Broker::default_path`].
+    pub fn connect(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(UnixStream::connect(path)?)
+    }
+
+    fn new(stream: UnixStream) -> Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream: AsyncFd::new(stream)?,
+            state: RefCell::default(),
+        })
+    }
+
+    /// Sends a request to the broker and blocks until it replies.
+    ///
+    /// Fails with [`io::ErrorKind::WouldBlock`] if an asynchronous
+    /// request is in flight, which this thread could not complete.
+    fn request(&self, request: Request) -> Result<u8> {
+        if self.state.borrow().in_flight {
+            return Err(
+                io::Error::new(io::ErrorKind::WouldBlock, "another request is in flight").into(),
+            );
+        }
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut reply = pin!(self.request_async(request));
+        loop {
+            match reply.as_mut().poll(&mut cx) {
+                Poll::Ready(res) => return res,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    /// Sends a request to the broker once the previous requests
+    /// complete, and waits for its reply on the event loop.
+    fn request_async(&self, request: Request) -> PendingRequest<'_> {
+        let mut frame = Vec::new();
+        request.encode(&mut frame);
+        PendingRequest {
+            client: self,
+            frame,
+            written: None,
+        }
+    }
+
+    /// Reads the available bytes, and queues the complete frames.
+    fn poll_read(&self, state: &mut ClientState, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let mut chunk = [0; 4096];
+        let read = self
+            .stream
+            .poll_io(cx, libc::EPOLLIN, |s| (&*s).read(&mut chunk));
+        Poll::Ready(match read {
+            Poll::Ready(Ok(0)) => {
+                state.closed = true;
+                state.wake_requester();
+                Ok(())
+            }
+            Poll::Ready(Ok(n)) => {
+                state.input.extend_from_slice(&chunk[..n]);
+                state.read_frames()
+            }
+            Poll::Ready(Err(err)) => Err(err),
+            Poll::Pending => return Poll::Pending,
+        })
+    }
+
+    /// Reads the current state of an LED light.
+    pub fn led(&self, light: Led) -> Result<bool> {
+        self.request(Request::Led(light))
+            .map(|enabled| enabled != 0)
+    }
+
+    /// Like [`BrokerClient::led`], but waits on the event loop.
+    pub async fn led_async(&self, light: Led) -> Result<bool> {
+        self.request_async(Request::Led(light))
+            .await
+            .map(|enabled| enabled != 0)
+    }
+
+    /// Changes the state of an LED light.
+    pub fn set_led(&self, light: Led, enabled: bool) -> Result<()> {
+        self.request(Request::SetLed(light, enabled)).map(|_| ())
+    }
+
+    /// Like [`BrokerClient::set_led`], but waits on the event loop.
+    pub async fn set_led_async(&self, light: Led, enabled: bool) -> Result<()> {
+        self.request_async(Request::SetLed(light, enabled))
+            .await
+            .map(|_| ())
+    }
+
+    /// Toggles the rumble motor.
+    pub fn set_rumble(&self, enabled: bool) -> Result<()> {
+        self.request(Request::SetRumble(enabled)).map(|_| ())
+    }
+
+    /// Like [`BrokerClient::set_rumble`], but waits on the event loop.
+    pub async fn set_rumble_async(&self, enabled: bool) -> Result<()> {
+        self.request_async(Request::SetRumble(enabled))
+            .await
+            .map(|_| ())
+    }
+
+    /// Reads the current battery level, as a percentage from 0 to 100%.
+    pub fn battery(&self) -> Result<u8> {
+        self.request(Request::Battery)
+    }
+
+    /// Like [`BrokerClient::battery`], but waits on the event loop.
+    pub async fn battery_async(&self) -> Result<u8> {
+        self.request_async(Request::Battery).await
+    }
+
+    /// Returns a stream that produces the events forwarded by the broker.
+    ///
+    /// The events of the channels opened by the broker are received
+    /// from the moment the client connects. The stream ends once the
+    /// broker closes the connection, e.g. because the device disconnected.
+    pub fn events(&self) -> BrokerEvents<'_> {
+        BrokerEvents { client: self }
+    }
+}
+
+/// A request sent to a broker, which completes with its reply.
+///
+/// A client has at most one request in flight, since the broker
+/// replies in order; the others wait for it to complete.
+struct PendingRequest<'a> {
+    client: &'a BrokerClient,
+    frame: Vec<u8>,
+    /// The number of bytes of the frame sent so far, while the request
+    /// is in flight.
+    written: Option<usize>,
+}
+
+impl PendingRequest<'_> {
+    fn poll_reply(&mut self, state: &mut ClientState, cx: &mut Context<'_>) -> Poll<Result<u8>> {
+        if state.closed && self.written.is_none() {
+            return Poll::Ready(Err(Error::Disconnected));
+        }
+        let written = match &mut self.written {
+            Some(written) => written,
+            None if state.in_flight => {
+                state.waiters.push(cx.waker().clone());
+                return Poll::Pending;
+            }
+            None => {
+                state.in_flight = true;
+                self.written.insert(0)
+            }
+        };
+        while *written < self.frame.len() {
+            let frame = &self.frame[*written..];
+            match self
+                .client
+                .stream
+                .poll_io(cx, libc::EPOLLOUT, |s| (&*s).write(frame))
+            {
+                Poll::Ready(Ok(n)) => *written += n,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        loop {
+            if let Some(reply) = state.replies.pop_front() {
+                self.complete(state, true);
+                return Poll::Ready(reply);
+            }
+            if state.closed {
+                return Poll::Ready(Err(Error::Disconnected));
+            }
+            // The stream of events may read the reply first.
+            state.requester = Some(cx.waker().clone());
+            match self.client.poll_read(state, cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    /// Lets the next request through, once this one is answered or
+    /// cancelled.
+    fn complete(&mut self, state: &mut ClientState, answered: bool) {
+        let Some(written) = self.written.take() else {
+            return;
+        };
+        if !answered {
+            if written < self.frame.len() {
+                // The rest of the frame would be taken for another one.
+                state.closed = true;
+            } else if state.replies.pop_front().is_none() {
+                state.abandoned += 1;
+            }
+        }
+        state.in_flight = false;
+        state.requester = None;
+        for waker in state.waiters.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+impl Future for PendingRequest<'_> {
+    type Output = Result<u8>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut state = this.client.state.borrow_mut();
+        let res = this.poll_reply(&mut state, cx);
+        if res.is_ready() {
+            this.complete(&mut state, false);
+        }
+        res
+    }
+}
+
+impl Drop for PendingRequest<'_> {
+    fn drop(&mut self) {
+        if self.written.is_some() {
+            let mut state = self.client.state.borrow_mut();
+            self.complete(&mut state, false);
+        }
+    }
+}
+
+impl WiimoteDevice for BrokerClient {
+    fn led(&self, light: Led) -> Result<bool> {
+        BrokerClient::led(self, light)
+    }
+
+    fn set_led(&self, light: Led, enabled: bool) -> Result<()> {
+        BrokerClient::set_led(self, light, enabled)
+    }
+
+    fn set_rumble(&self, enabled: bool) -> Result<()> {
+        BrokerClient::set_rumble(self, enabled)
+    }
+
+    fn battery(&self) -> Result<u8> {
+        BrokerClient::battery(self)
+    }
+
+    fn events(&self) -> Result<impl Stream<Item = Result<(Event, SystemTime)>> + '_> {
+        Ok(BrokerClient::events(self))
+    }
+}
+
+/// The stream returned by [`BrokerClient::events`].
+pub struct BrokerEvents<'a> {
+    client: &'a BrokerClient,
+}
+
+impl Stream for BrokerEvents<'_> {
+    type Item = Result<(Event, SystemTime)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let client = self.client;
+        let mut state = client.state.borrow_mut();
+        loop {
+            if let Some(event) = state.events.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            if state.closed {
+                return Poll::Ready(None);
+            }
+            // The replies are queued for the requests that await them.
+            match client.poll_read(&mut state, cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(err)) => return Poll::Ready(Some(Err(err))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::broker::{next_frame, AcceptFailure, BrokerClient, Request, Response};
+    use crate::events::{Event, Key, KeyState};
+    use crate::{Error, Led};
+    use futures_util::StreamExt;
+    use std::future::Future;
+    use std::io::{self, Read, Write};
+    use std::os::unix::net::UnixStream;
+    use std::pin::pin;
+    use std::task::{Context, Waker};
+    use std::thread;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn round_trips_requests() {
+        let requests = [
+            Request::SetLed(Led::Three, true),
+            Request::SetRumble(false),
+            Request::Battery,
+            Request::Led(Led::One),
+        ];
+        let mut buf = Vec::new();
+        for request in requests {
+            request.encode(&mut buf);
+        }
+        // Frames may arrive in pieces.
+        let mut received = buf.split_off(5);
+        assert_eq!(next_frame(&mut buf).unwrap(), None);
+        buf.append(&mut received);
+
+        let mut decoded = Vec::new();
+        while let Some((tag, payload)) = next_frame(&mut buf).unwrap() {
+            decoded.push(Request::decode(tag, &payload).unwrap());
+        }
+        assert_eq!(decoded, requests);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn round_trips_responses() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_micros(1_234_567);
+        let mut buf = Vec::new();
+        Response::Event(Event::Key(Key::A, KeyState::Down), time).encode(&mut buf);
+        Response::Reply(Ok(87)).encode(&mut buf);
+        Response::Reply(Err(Error::Disconnected)).encode(&mut buf);
+        Response::Reply(Err(io::Error::from_raw_os_error(libc::EIO).into())).encode(&mut buf);
+
+        let mut next = || {
+            let (tag, payload) = next_frame(&mut buf).unwrap().unwrap();
+            Response::decode(tag, &payload).unwrap().unwrap()
+        };
+        match next() {
+            Response::Event(Event::Key(Key::A, KeyState::Down), t) => assert_eq!(t, time),
+            other => panic!("unexpected response {other:?}"),
+        }
+        assert!(matches!(next(), Response::Reply(Ok(87))));
+        assert!(matches!(next(), Response::Reply(Err(Error::Disconnected))));
+        match next() {
+            Response::Reply(Err(err)) => assert_eq!(err.raw_os_error(), Some(libc::EIO)),
+            other => panic!("unexpected response {other:?}"),
+        }
+    }
+
+    #[test]
+    fn survives_accept_failures() {
+        let failure = |code| AcceptFailure::classify(&io::Error::from_raw_os_error(code).into());
+        assert_eq!(failure(libc::ECONNABORTED), AcceptFailure::Skip);
+        assert_eq!(failure(libc::EPERM), AcceptFailure::Skip);
+        assert_eq!(failure(libc::EMFILE), AcceptFailure::Pause);
+        assert_eq!(failure(libc::ENFILE), AcceptFailure::Pause);
+        assert_eq!(failure(libc::EBADF), AcceptFailure::Fatal);
+    }
+
+    #[test]
+    fn rejects_malformed_frames() {
+        assert!(Request::decode(0x01, &[7, 1]).is_err());
+        assert!(Request::decode(0x03, &[0]).is_err());
+        assert!(Request::decode(0x42, &[]).is_err());
+        let mut huge = (u32::MAX).to_le_bytes().to_vec();
+        assert!(next_frame(&mut huge).is_err());
+    }
+
+    #[test]
+    fn client_keeps_events_received_before_a_reply() {
+        let (local, mut remote) = UnixStream::pair().unwrap();
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1);
+        let broker = thread::spawn(move || {
+            let mut buf = vec![0; 5];
+            remote.read_exact(&mut buf).unwrap();
+            let (tag, payload) = next_frame(&mut buf).unwrap().unwrap();
+            assert_eq!(Request::decode(tag, &payload).unwrap(), Request::Battery);
+
+            let mut frames = Vec::new();
+            Response::Event(Event::Key(Key::B, KeyState::Up), time).encode(&mut frames);
+            Response::Reply(Ok(42)).encode(&mut frames);
+            remote.write_all(&frames).unwrap();
+        });
+
+        let client = BrokerClient::new(local).unwrap();
+        assert_eq!(client.battery().unwrap(), 42);
+        broker.join().unwrap();
+
+        futures_executor::block_on(async {
+            let mut events = client.events();
+            match events.next().await {
+                Some(Ok((Event::Key(Key::B, KeyState::Up), t))) => assert_eq!(t, time),
+                other => panic!("unexpected event {other:?}"),
+            }
+            // The broker closed the connection.
+            assert!(events.next().await.is_none());
+        });
+        assert_eq!(
+            client.led(Led::One).unwrap_err().kind(),
+            io::ErrorKind::NotConnected
+        );
+    }
+
+    #[test]
+    fn client_discards_replies_of_cancelled_requests() {
+        let (local, mut remote) = UnixStream::pair().unwrap();
+        let broker = thread::spawn(move || {
+            let mut buf = vec![0; 10];
+            remote.read_exact(&mut buf).unwrap();
+            let mut frames = Vec::new();
+            Response::Reply(Ok(7)).encode(&mut frames);
+            Response::Reply(Ok(42)).encode(&mut frames);
+            remote.write_all(&frames).unwrap();
+        });
+
+        let client = BrokerClient::new(local).unwrap();
+        // The request is sent, and then dropped before the reply arrives.
+        {
+            let request = pin!(client.request_async(Request::Battery));
+            let mut cx = Context::from_waker(Waker::noop());
+            assert!(request.poll(&mut cx).is_pending());
+        }
+        assert_eq!(client.battery().unwrap(), 42);
+        broker.join().unwrap();
+    }
+
+    #[test]
+    fn client_survives_requests_dropped_at_every_await_point() {
+        let (local, mut remote) = UnixStream::pair().unwrap();
+        let client = BrokerClient::new(local).unwrap();
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut frame = Vec::new();
+        Request::Battery.encode(&mut frame);
+        let mut received = vec![0; frame.len()];
+
+        // Dropped before it is polled, so it is never sent.
+        drop(client.request_async(Request::Battery));
+
+        // Dropped while waiting for the request in flight.
+        let mut first = pin!(client.request_async(Request::Battery));
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+        {
+            let second = pin!(client.request_async(Request::Battery));
+            assert!(second.poll(&mut cx).is_pending());
+        }
+        remote.read_exact(&mut received).unwrap();
+        assert_eq!(received, frame);
+        let mut reply = Vec::new();
+        Response::Reply(Ok(7)).encode(&mut reply);
+        remote.write_all(&reply).unwrap();
+        assert_eq!(futures_executor::block_on(first).unwrap(), 7);
+
+        // Dropped while waiting for its reply.
+        {
+            let third = pin!(client.request_async(Request::Battery));
+            assert!(third.poll(&mut cx).is_pending());
+        }
+        let mut last = pin!(client.request_async(Request::Battery));
+        assert!(last.as_mut().poll(&mut cx).is_pending());
+        let mut replies = Vec::new();
+        Response::Reply(Ok(13)).encode(&mut replies);
+        Response::Reply(Ok(42)).encode(&mut replies);
+        remote.write_all(&replies).unwrap();
+        assert_eq!(futures_executor::block_on(last).unwrap(), 42);
+
+        // Only the first, third and last requests were sent.
+        let mut rest = vec![0; 2 * frame.len()];
+        remote.read_exact(&mut rest).unwrap();
+        assert_eq!(rest, [frame.clone(), frame].concat());
+        remote.set_nonblocking(true).unwrap();
+        let err = remote.read(&mut rest).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    }
+}
@@ -0,0 +1,245 @@
+use crate::supervisor::Backoff;
+use crate::timer::sleep;
+use crate::{Address, Channels, Device, Error, MotionPlusNormalization, OpenRetry, Result};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The options for connecting to devices, which can be reused for
+/// any number of them; see [`Monitor::devices`].
+///
+/// [`Monitor::devices`]: crate::Monitor::devices
+#[derive(Clone, Debug)]
+pub struct ConnectOptions {
+    connect_timeout: Duration,
+    backoff: Backoff,
+    channels: Channels,
+    writable: bool,
+    watch: bool,
+    open_retry: Option<OpenRetry>,
+    mp_normalization: Option<MotionPlusNormalization>,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(2),
+            backoff: Backoff {
+                max_retries: Some(0),
+                ..Backoff::default()
+            },
+            channels: Channels::empty(),
+            writable: false,
+            watch: true,
+            open_retry: None,
+            mp_normalization: None,
+        }
+    }
+}
+
+impl ConnectOptions {
+    /// Creates the default connection options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the time to wait for the kernel to set up the device, and
+    /// then the interfaces of the channels to open; see
+    /// [`Device::connect_async`] and [`Device::open_async`].
+    ///
+    /// Defaults to 2 seconds.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Sets the policy for retrying the whole connection process,
+    /// including opening the channels, after a failure.
+    ///
+    /// By default, the first failure is returned.
+    pub fn retry(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets the channels to open once connected, and whether to open
+    /// them in writable mode; see [`Device::open_async`].
+    ///
+    /// By default, no channel is opened.
+    pub fn channels(mut self, channels: Channels, writable: bool) -> Self {
+        self.channels = channels;
+        self.writable = writable;
+        self
+    }
+
+    /// Sets whether to watch the device for hot-plug events, such as
+    /// extensions being plugged in and the device being removed.
+    ///
+    /// Without watching, the event streams do not end once the device
+    /// is disconnected. Enabled by default.
+    pub fn watch(mut self, watch: bool) -> Self {
+        self.watch = watch;
+        self
+    }
+
+    /// Sets the policy for retrying to open channels that are not
+    /// available yet in later calls to [`Device::open`]; see
+    /// [`Device::set_open_retry`].
+    ///
+    /// Disabled by default.
+    pub fn open_retry(mut self, retry: Option<OpenRetry>) -> Self {
+        self.open_retry = retry;
+        self
+    }
+
+    /// Sets the Motion Plus sensor normalization values of the device;
+    /// see [`Device::set_mp_normalization`].
+    ///
+    /// By default, the values are left unchanged.
+    pub fn mp_normalization(mut self, values: MotionPlusNormalization) -> Self {
+        self.mp_normalization = Some(values);
+        self
+    }
+
+    /// Connects to the device at `address` and opens the configured
+    /// channels.
+    ///
+    /// Fails with the last error once the retry policy is exhausted,
+    /// or immediately on [`Error::Permission`].
+    pub async fn connect(&self, address: &Address) -> Result<Device> {
+        let mut failures = 0;
+        loop {
+            let err = match self.try_connect(address).await {
+                Ok(device) => return Ok(device),
+                Err(err @ Error::Permission) => return Err(err),
+                Err(err) => err,
+            };
+            failures += 1;
+            if self.backoff.exhausted(failures) {
+                return Err(err);
+            }
+            // The jitter only needs to differ between processes.
+            let random = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0.0, |time| time.subsec_nanos() as f64 / 1e9);
+            sleep(self.backoff.delay(failures, random)).await?;
+        }
+    }
+
+    /// Makes a single connection attempt.
+    async fn try_connect(&self, address: &Address) -> Result<Device> {
+        let device = Device::connect_ready(address, self.connect_timeout, self.watch).await?;
+        device.set_open_retry(self.open_retry);
+        if let Some(values) = &self.mp_normalization {
+            device.set_mp_normalization(values)?;
+        }
+        if !self.channels.is_empty() {
+            device
+                .open_async(self.channels, self.writable, self.connect_timeout)
+                .await?;
+        }
+        Ok(device)
+    }
+}
+
+/// Configures the connection to a [`Device`], which is then established
+/// in one call by [`DeviceBuilder::build`].
+///
+/// # Examples
+/// ```
+/// use std::time::Duration;
+/// use xwiimote::supervisor::Backoff;
+/// use xwiimote::{Channels, Device, Monitor};
+/// use futures_util::TryStreamExt;
+///
+/// # let _ = async {
+/// let address = Monitor::enumerate()?.try_next().await?.unwrap();
+/// let device = Device::builder(&address)
+///     .connect_timeout(Duration::from_secs(5))
+///     .retry(Backoff {
+///         max_retries: Some(3),
+///         ..Backoff::default()
+///     })
+///     .channels(Channels::CORE | Channels::ACCELEROMETER, true)
+///     .build()
+///     .await?;
+/// # Ok::<(), xwiimote::Error>(())
+/// # };
+/// ```
+#[derive(Clone, Debug)]
+pub struct DeviceBuilder {
+    address: Address,
+    options: ConnectOptions,
+}
+
+impl DeviceBuilder {
+    pub(crate) fn new(address: &Address) -> Self {
+        Self {
+            address: address.clone(),
+            options: ConnectOptions::default(),
+        }
+    }
+
+    /// See [`ConnectOptions::connect_timeout`].
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.options = self.options.connect_timeout(timeout);
+        self
+    }
+
+    /// See [`ConnectOptions::retry`].
+    pub fn retry(mut self, backoff: Backoff) -> Self {
+        self.options = self.options.retry(backoff);
+        self
+    }
+
+    /// See [`ConnectOptions::channels`].
+    pub fn channels(mut self, channels: Channels, writable: bool) -> Self {
+        self.options = self.options.channels(channels, writable);
+        self
+    }
+
+    /// See [`ConnectOptions::watch`].
+    pub fn watch(mut self, watch: bool) -> Self {
+        self.options = self.options.watch(watch);
+        self
+    }
+
+    /// See [`ConnectOptions::open_retry`].
+    pub fn open_retry(mut self, retry: Option<OpenRetry>) -> Self {
+        self.options = self.options.open_retry(retry);
+        self
+    }
+
+    /// See [`ConnectOptions::mp_normalization`].
+    pub fn mp_normalization(mut self, values: MotionPlusNormalization) -> Self {
+        self.options = self.options.mp_normalization(values);
+        self
+    }
+
+    /// Connects to the device and opens the configured channels;
+    /// see [`ConnectOptions::connect`].
+    pub async fn build(self) -> Result<Device> {
+        self.options.connect(&self.address).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Address, Channels, Device};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    #[test]
+    fn configures_connection() {
+        let address = Address::from(PathBuf::from("/sys/bus/hid/devices/0005:057E:0306.0001"));
+        let builder = Device::builder(&address)
+            .connect_timeout(Duration::from_millis(500))
+            .channels(Channels::CORE, true)
+            .watch(false);
+        assert_eq!(builder.address, address);
+        let options = builder.options;
+        assert_eq!(options.connect_timeout, Duration::from_millis(500));
+        assert_eq!((options.channels, options.writable), (Channels::CORE, true));
+        assert!(!options.watch);
+        // No retries by default.
+        assert!(options.backoff.exhausted(1));
+    }
+}
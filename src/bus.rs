@@ -0,0 +1,277 @@
+//! Distribution of the events of a device to several consumers.
+//!
+//! Every event of a device is produced once, so only one stream can
+//! sensibly read them. An [`EventBus`] reads the events on a dedicated
+//! thread and hands a copy to each of its [`Subscription`]s, so that
+//! e.g. the user interface, a logger and the game logic can consume them
+//! independently, at their own pace.
+//!
+//! # Examples
+//! Log every event, while the game reacts only to the key presses.
+//! ```no_run
+//! use futures_util::TryStreamExt;
+//! use xwiimote::bus::EventBus;
+//! use xwiimote::events::{Event, KeyState};
+//! use xwiimote::{Device, Monitor};
+//!
+//! # tokio_test::block_on(async {
+//! # let address = Monitor::enumerate()?.try_next().await?.unwrap();
+//! let bus = EventBus::new(Device::connect(&address)?)?;
+//! let mut log = bus.subscribe();
+//! let mut presses = bus.subscribe_filtered(16, |event| {
+//!     matches!(event, Event::Key(_, KeyState::Down))
+//! });
+//! std::thread::spawn(move || {
+//!     futures_executor::block_on(async move {
+//!         while let Ok(Some((event, time))) = log.try_next().await {
+//!             println!("{time:?}: {event:?}");
+//!         }
+//!     })
+//! });
+//! while let Some((event, _)) = presses.try_next().await? {
+//!     println!("pressed: {event:?}");
+//! }
+//! # Ok::<(), xwiimote::Error>(())
+//! # }).unwrap();
+//! ```
+
+use crate::events::{Event, EventStream};
+use crate::observer::{Broadcast, Queue};
+use crate::{lock, Device, Result};
+use futures_core::Stream;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, JoinHandle, Thread};
+use std::time::SystemTime;
+
+/// The state shared by a bus and its reader thread.
+#[derive(Default)]
+struct Shared {
+    /// Relays the events to the subscriptions.
+    broadcast: Broadcast,
+    /// Should the reader thread exit?
+    stopped: AtomicBool,
+}
+
+/// Wakes the thread that waits for a future, such as the reader
+/// thread of a bus.
+pub(crate) struct Unpark(pub(crate) Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Reads the events of a device on a dedicated thread, and distributes
+/// them to any number of subscriptions.
+///
+/// The bus owns the device, which remains available through
+/// [`EventBus::device`] to control its outputs and channels. The bus
+/// stops once the device disconnects or an error occurs; the error is
+/// produced by every subscription after the preceding events. Dropping
+/// the bus stops it too, and closes the device.
+pub struct EventBus {
+    device: Arc<Device>,
+    shared: Arc<Shared>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl EventBus {
+    /// The number of events buffered by the subscriptions created
+    /// with [`EventBus::subscribe`].
+    pub const DEFAULT_CAPACITY: usize = Broadcast::CAPACITY;
+
+    /// Starts reading the events of a device.
+    ///
+    /// Only the events of the channels that are open are received;
+    /// see [`Device::open`].
+    pub fn new(device: Device) -> Result<Self> {
+        let device = Arc::new(device);
+        let events = EventStream::owned(Arc::clone(&device))?;
+        let shared = Arc::new(Shared::default());
+        let reader = thread::Builder::new()
+            .name("xwiimote-bus".to_owned())
+            .spawn({
+                let shared = Arc::clone(&shared);
+                move || read(events, &shared)
+            })?;
+        Ok(Self {
+            device,
+            shared,
+            reader: Some(reader),
+        })
+    }
+
+    /// Returns the device whose events are distributed.
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// Creates a subscription that receives every event from now on,
+    /// and buffers up to [`EventBus::DEFAULT_CAPACITY`] events.
+    pub fn subscribe(&self) -> Subscription {
+        self.subscribe_filtered(Self::DEFAULT_CAPACITY, |_| true)
+    }
+
+    /// Creates a subscription that receives the events accepted by
+    /// `filter` from now on, and buffers up to `capacity` of them.
+    ///
+    /// The filter runs on the reader thread, so it should be quick.
+    /// If the subscription falls behind, the oldest events are discarded;
+    /// see [`Subscription::missed`].
+    pub fn subscribe_filtered(
+        &self,
+        capacity: usize,
+        filter: impl Fn(&Event) -> bool + Send + 'static,
+    ) -> Subscription {
+        Subscription::new(self.shared.broadcast.subscribe(capacity, Box::new(filter)))
+    }
+}
+
+impl Drop for EventBus {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::Release);
+        if let Some(reader) = self.reader.take() {
+            reader.thread().unpark();
+            // Wait for the thread to release its handle on the device.
+            let _ = reader.join();
+        }
+    }
+}
+
+/// Runs the reader thread of a bus.
+fn read(mut events: EventStream<'static>, shared: &Shared) {
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let error = loop {
+        if shared.stopped.load(Ordering::Acquire) {
+            break None;
+        }
+        match Pin::new(&mut events).poll_next(&mut cx) {
+            Poll::Ready(Some(Ok(event))) => shared.broadcast.send(event),
+            Poll::Ready(Some(Err(err))) => break Some(err),
+            Poll::Ready(None) => break None,
+            Poll::Pending => thread::park(),
+        }
+    };
+    shared.broadcast.close(error);
+}
+
+/// A stream of the events distributed by an [`EventBus`], created by
+/// [`EventBus::subscribe`] or [`EventBus::subscribe_filtered`].
+///
+/// The stream ends once the bus stops, after producing the error that
+/// stopped it, if any.
+pub struct Subscription {
+    queue: Arc<Mutex<Queue>>,
+}
+
+impl Subscription {
+    /// Creates a stream of the events received by `queue`.
+    pub(crate) fn new(queue: Arc<Mutex<Queue>>) -> Self {
+        Self { queue }
+    }
+
+    /// Returns the number of events discarded so far because the
+    /// buffer of the subscription was full.
+    pub fn missed(&self) -> u64 {
+        lock(&self.queue).missed()
+    }
+}
+
+impl Stream for Subscription {
+    type Item = Result<(Event, SystemTime)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        lock(&self.queue).poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Subscription;
+    use crate::events::{Event, Key, KeyState};
+    use crate::observer::{Broadcast, Filter};
+    use crate::Error;
+    use futures_core::Stream;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+    use std::time::SystemTime;
+
+    fn subscribe(broadcast: &Broadcast, capacity: usize, filter: Filter) -> Subscription {
+        Subscription::new(broadcast.subscribe(capacity, filter))
+    }
+
+    fn poll(subscription: &mut Subscription) -> Poll<Option<Result<Event, Error>>> {
+        let mut cx = Context::from_waker(Waker::noop());
+        Pin::new(subscription)
+            .poll_next(&mut cx)
+            .map(|item| item.map(|item| item.map(|(event, _)| event)))
+    }
+
+    #[test]
+    fn filters_events_per_subscription() {
+        let broadcast = Broadcast::default();
+        let mut all = subscribe(&broadcast, 8, Box::new(|_| true));
+        let mut keys = subscribe(
+            &broadcast,
+            8,
+            Box::new(|event| matches!(event, Event::Key(..))),
+        );
+        broadcast.send((Event::Other, SystemTime::now()));
+        broadcast.send((Event::Key(Key::A, KeyState::Down), SystemTime::now()));
+
+        assert!(matches!(
+            poll(&mut all),
+            Poll::Ready(Some(Ok(Event::Other)))
+        ));
+        assert!(matches!(
+            poll(&mut all),
+            Poll::Ready(Some(Ok(Event::Key(..))))
+        ));
+        assert!(matches!(
+            poll(&mut keys),
+            Poll::Ready(Some(Ok(Event::Key(..))))
+        ));
+        assert!(poll(&mut keys).is_pending());
+    }
+
+    #[test]
+    fn discards_old_events() {
+        let broadcast = Broadcast::default();
+        let mut events = subscribe(&broadcast, 4, Box::new(|_| true));
+        for _ in 0..10 {
+            broadcast.send((Event::Other, SystemTime::now()));
+        }
+        assert_eq!(events.missed(), 6);
+        let mut n_received = 0;
+        while let Poll::Ready(Some(_)) = poll(&mut events) {
+            n_received += 1;
+        }
+        assert_eq!(n_received, 4);
+    }
+
+    #[test]
+    fn reports_the_error_to_every_subscription() {
+        let broadcast = Broadcast::default();
+        let mut first = subscribe(&broadcast, 8, Box::new(|_| true));
+        let mut second = subscribe(&broadcast, 8, Box::new(|_| true));
+        broadcast.send((Event::Other, SystemTime::now()));
+        broadcast.close(Some(Error::Disconnected));
+
+        for events in [&mut first, &mut second] {
+            assert!(matches!(poll(events), Poll::Ready(Some(Ok(Event::Other)))));
+            assert!(matches!(
+                poll(events),
+                Poll::Ready(Some(Err(Error::Disconnected)))
+            ));
+            assert!(matches!(poll(events), Poll::Ready(None)));
+        }
+        // Late subscriptions end right away.
+        let mut late = subscribe(&broadcast, 8, Box::new(|_| true));
+        assert!(matches!(poll(&mut late), Poll::Ready(None)));
+    }
+}
@@ -0,0 +1,447 @@
+//! Typed markers for the channels that can be opened on a [`Device`].
+//!
+//! Each marker type implements [`Channel`], which associates the channel
+//! with the type of events it produces. Opening a channel through
+//! [`Device::open_typed`] returns a stream whose items are restricted
+//! to that type, so that matching on events from a different channel
+//! becomes a compile-time error.
+//!
+//! # Examples
+//! Print the positions of the IR sources tracked by the camera.
+//! ```
+//! use futures_util::TryStreamExt;
+//! use xwiimote::channels::Ir;
+//! use xwiimote::{Address, Device};
+//!
+//! # let _ = async {
+//! # let address = Address::from(std::path::PathBuf::new());
+//! let device = Device::connect(&address)?;
+//! let mut sources = device.open_typed::<Ir>()?;
+//! while let Some((sources, _time)) = sources.try_next().await? {
+//!     println!("{sources:?}");
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! # };
+//! ```
+
+#[cfg(feature = "classic")]
+use crate::events::ClassicControllerKey;
+#[cfg(feature = "drums")]
+use crate::events::DrumsKey;
+#[cfg(feature = "guitar")]
+use crate::events::GuitarKey;
+#[cfg(feature = "nunchuk")]
+use crate::events::NunchukKey;
+#[cfg(feature = "pro-controller")]
+use crate::events::ProControllerKey;
+use crate::events::{Event, EventStream, IrSource, Key, KeyState, MAX_IR_SOURCES};
+#[cfg(doc)]
+use crate::Device;
+use crate::{Channels, Result};
+use futures_core::Stream;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+
+pub use xwiimote_util::Acceleration;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// A channel whose events are of a single, statically known type.
+///
+/// This trait is sealed and cannot be implemented outside this crate.
+pub trait Channel: sealed::Sealed + 'static {
+    /// The channel represented by this type.
+    const CHANNELS: Channels;
+
+    /// The type of the events received through this channel.
+    type Event;
+
+    /// Extracts the channel-specific payload of an event,
+    /// if it was received through this channel.
+    fn filter(event: Event) -> Option<Self::Event>;
+}
+
+/// Defines a marker type for a channel.
+macro_rules! channel {
+    ($(#[$attr:meta])* $name:ident, $channels:expr, $event:ty, |$ev:ident| $filter:expr) => {
+        $(#[$attr])*
+        #[derive(Copy, Clone, Debug)]
+        pub enum $name {}
+
+        impl sealed::Sealed for $name {}
+
+        impl Channel for $name {
+            const CHANNELS: Channels = $channels;
+            type Event = $event;
+
+            fn filter($ev: Event) -> Option<Self::Event> {
+                $filter
+            }
+        }
+    };
+}
+
+/// The rotational speed reported by the Motion Plus gyroscope.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RotationSpeed {
+    /// The x-axis rotational speed.
+    pub x: i32,
+    /// The y-axis rotational speed.
+    pub y: i32,
+    /// The z-axis rotational speed.
+    pub z: i32,
+}
+
+/// The position of two analog sticks.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Sticks {
+    /// The left analog stick absolute x-axis position.
+    pub left_x: i32,
+    /// The left analog stick absolute y-axis position.
+    pub left_y: i32,
+    /// The right analog stick absolute x-axis position.
+    pub right_x: i32,
+    /// The right analog stick absolute y-axis position.
+    pub right_y: i32,
+}
+
+channel!(
+    /// The [core channel](`Channels::CORE`), which reports Wii Remote key events.
+    Core,
+    Channels::CORE,
+    (Key, KeyState),
+    |event| match event {
+        Event::Key(key, state) => Some((key, state)),
+        _ => None,
+    }
+);
+
+channel!(
+    /// The [accelerometer channel](`Channels::ACCELEROMETER`).
+    Accelerometer,
+    Channels::ACCELEROMETER,
+    Acceleration,
+    |event| match event {
+        Event::Accelerometer { x, y, z } => Some(Acceleration { x, y, z }),
+        _ => None,
+    }
+);
+
+channel!(
+    /// The [IR camera channel](`Channels::IR`).
+    Ir,
+    Channels::IR,
+    [Option<IrSource>; MAX_IR_SOURCES],
+    |event| match event {
+        Event::Ir(sources) => Some(sources),
+        _ => None,
+    }
+);
+
+channel!(
+    /// The [Motion Plus channel](`Channels::MOTION_PLUS`).
+    MotionPlus,
+    Channels::MOTION_PLUS,
+    RotationSpeed,
+    |event| match event {
+        Event::MotionPlus { x, y, z } => Some(RotationSpeed { x, y, z }),
+        _ => None,
+    }
+);
+
+#[cfg(feature = "balance-board")]
+channel!(
+    /// The [Balance Board channel](`Channels::BALANCE_BOARD`), which
+    /// reports the weights measured by the four sensors of the board.
+    BalanceBoard,
+    Channels::BALANCE_BOARD,
+    [i32; 4],
+    |event| match event {
+        Event::BalanceBoard(weights) => Some(weights),
+        _ => None,
+    }
+);
+
+/// An event received through the [`Nunchuk`] channel.
+#[cfg(feature = "nunchuk")]
+#[derive(Copy, Clone, Debug)]
+pub enum NunchukEvent {
+    /// The state of a key changed.
+    Key(NunchukKey, KeyState),
+    /// The analog stick moved, or the acceleration changed.
+    Move {
+        /// The x-axis absolute position.
+        x: i32,
+        /// The y-axis absolute position.
+        y: i32,
+        /// The x-axis acceleration.
+        x_acceleration: i32,
+        /// The y-axis acceleration.
+        y_acceleration: i32,
+    },
+}
+
+#[cfg(feature = "nunchuk")]
+channel!(
+    /// The [Nunchuk channel](`Channels::NUNCHUK`).
+    Nunchuk,
+    Channels::NUNCHUK,
+    NunchukEvent,
+    |event| match event {
+        Event::NunchukKey(key, state) => Some(NunchukEvent::Key(key, state)),
+        Event::NunchukMove {
+            x,
+            y,
+            x_acceleration,
+            y_acceleration,
+        } => Some(NunchukEvent::Move {
+            x,
+            y,
+            x_acceleration,
+            y_acceleration,
+        }),
+        _ => None,
+    }
+);
+
+/// An event received through the [`ClassicController`] channel.
+#[cfg(feature = "classic")]
+#[derive(Copy, Clone, Debug)]
+pub enum ClassicControllerEvent {
+    /// The state of a key changed.
+    Key(ClassicControllerKey, KeyState),
+    /// An analog stick or trigger moved.
+    Move {
+        /// The position of the analog sticks.
+        sticks: Sticks,
+        /// The TL trigger absolute position, ranging from 0 to
+        /// [`TRIGGER_MAX`](crate::ranges::TRIGGER_MAX).
+        left_trigger: u8,
+        /// The TR trigger absolute position, ranging from 0 to
+        /// [`TRIGGER_MAX`](crate::ranges::TRIGGER_MAX).
+        right_trigger: u8,
+    },
+}
+
+#[cfg(feature = "classic")]
+channel!(
+    /// The [Classic controller channel](`Channels::CLASSIC_CONTROLLER`).
+    ClassicController,
+    Channels::CLASSIC_CONTROLLER,
+    ClassicControllerEvent,
+    |event| match event {
+        Event::ClassicControllerKey(key, state) => Some(ClassicControllerEvent::Key(key, state)),
+        Event::ClassicControllerMove {
+            left_x,
+            left_y,
+            right_x,
+            right_y,
+            left_trigger,
+            right_trigger,
+        } => Some(ClassicControllerEvent::Move {
+            sticks: Sticks {
+                left_x,
+                left_y,
+                right_x,
+                right_y,
+            },
+            left_trigger,
+            right_trigger,
+        }),
+        _ => None,
+    }
+);
+
+/// An event received through the [`ProController`] channel.
+#[cfg(feature = "pro-controller")]
+#[derive(Copy, Clone, Debug)]
+pub enum ProControllerEvent {
+    /// The state of a key changed.
+    Key(ProControllerKey, KeyState),
+    /// An analog stick moved.
+    Move(Sticks),
+}
+
+#[cfg(feature = "pro-controller")]
+channel!(
+    /// The [Wii U Pro controller channel](`Channels::PRO_CONTROLLER`).
+    ProController,
+    Channels::PRO_CONTROLLER,
+    ProControllerEvent,
+    |event| match event {
+        Event::ProControllerKey(key, state) => Some(ProControllerEvent::Key(key, state)),
+        Event::ProControllerMove {
+            left_x,
+            left_y,
+            right_x,
+            right_y,
+        } => Some(ProControllerEvent::Move(Sticks {
+            left_x,
+            left_y,
+            right_x,
+            right_y,
+        })),
+        _ => None,
+    }
+);
+
+/// An event received through the [`Drums`] channel.
+#[cfg(feature = "drums")]
+#[derive(Copy, Clone, Debug)]
+pub enum DrumsEvent {
+    /// The state of a key changed.
+    Key(DrumsKey, KeyState),
+    /// The analog stick moved, or the pressure on the pads
+    /// or pedals changed.
+    Move(DrumsMove),
+}
+
+/// The state of the analog stick, pads and pedals of a drums controller.
+///
+/// See [`Event::DrumsMove`] for the meaning of each value.
+#[cfg(feature = "drums")]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct DrumsMove {
+    /// The x-axis analog stick position.
+    pub x: i32,
+    /// The y-axis analog stick position.
+    pub y: i32,
+    /// The pressure on the left cymbal.
+    pub cymbal_left: i32,
+    /// The pressure on the right cymbal.
+    pub cymbal_right: i32,
+    /// The pressure on the left tom-tom.
+    pub tom_left: i32,
+    /// The pressure on the right tom-tom.
+    pub tom_right: i32,
+    /// The pressure on the far-right tom-tom.
+    pub tom_far_right: i32,
+    /// The pressure on the bass pedal.
+    pub bass: i32,
+    /// The position of the hi-hat pedal.
+    pub hi_hat: i32,
+}
+
+#[cfg(feature = "drums")]
+impl DrumsMove {
+    /// Checks whether the bass pedal is pressed.
+    pub fn bass_pressed(&self) -> bool {
+        self.bass != 0
+    }
+}
+
+#[cfg(feature = "drums")]
+channel!(
+    /// The [drums channel](`Channels::DRUMS`).
+    Drums,
+    Channels::DRUMS,
+    DrumsEvent,
+    |event| match event {
+        Event::DrumsKey(key, state) => Some(DrumsEvent::Key(key, state)),
+        Event::DrumsMove {
+            x,
+            y,
+            cymbal_left,
+            cymbal_right,
+            tom_left,
+            tom_right,
+            tom_far_right,
+            bass,
+            hi_hat,
+        } => Some(DrumsEvent::Move(DrumsMove {
+            x,
+            y,
+            cymbal_left,
+            cymbal_right,
+            tom_left,
+            tom_right,
+            tom_far_right,
+            bass,
+            hi_hat,
+        })),
+        _ => None,
+    }
+);
+
+/// An event received through the [`Guitar`] channel.
+#[cfg(feature = "guitar")]
+#[derive(Copy, Clone, Debug)]
+pub enum GuitarEvent {
+    /// The state of a key changed.
+    Key(GuitarKey, KeyState),
+    /// The analog stick, the whammy bar or the fret bar moved.
+    Move {
+        /// The x-axis analog stick position.
+        x: i32,
+        /// The y-axis analog stick position.
+        y: i32,
+        /// The whammy bar position.
+        whammy_bar: i32,
+        /// The touch slider position, if touched.
+        fret_bar: Option<i32>,
+    },
+}
+
+#[cfg(feature = "guitar")]
+channel!(
+    /// The [guitar channel](`Channels::GUITAR`).
+    Guitar,
+    Channels::GUITAR,
+    GuitarEvent,
+    |event| match event {
+        Event::GuitarKey(key, state) => Some(GuitarEvent::Key(key, state)),
+        Event::GuitarMove {
+            x,
+            y,
+            whammy_bar,
+            fret_bar,
+        } => Some(GuitarEvent::Move {
+            x,
+            y,
+            whammy_bar,
+            fret_bar,
+        }),
+        _ => None,
+    }
+);
+
+/// Streams the events received through a single channel.
+///
+/// Events from other channels are discarded.
+pub(crate) struct TypedEventStream<'d, C> {
+    inner: EventStream<'d>,
+    // Unlike `C`, this type is always `Unpin`.
+    channel: PhantomData<fn() -> C>,
+}
+
+impl<'d, C: Channel> TypedEventStream<'d, C> {
+    /// Restricts the given stream to the events of channel `C`.
+    pub fn new(inner: EventStream<'d>) -> Self {
+        Self {
+            inner,
+            channel: PhantomData,
+        }
+    }
+}
+
+impl<C: Channel> Stream for TypedEventStream<'_, C> {
+    type Item = Result<(C::Event, SystemTime)>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            return match Pin::new(&mut self.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok((event, time)))) => match C::filter(event) {
+                    Some(event) => Poll::Ready(Some(Ok((event, time)))),
+                    None => continue, // event from another channel
+                },
+                Poll::Ready(Some(Err(err))) => Poll::Ready(Some(Err(err))),
+                Poll::Ready(None) => Poll::Ready(None),
+                Poll::Pending => Poll::Pending,
+            };
+        }
+    }
+}
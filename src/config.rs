@@ -0,0 +1,205 @@
+//! Persistence of per-device user preferences.
+
+use crate::{Address, Device, Result, StableId};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A function that applies the stored configuration of a device.
+type LoadHook = Box<dyn FnMut(&mut Device, &[u8]) -> Result<()>>;
+
+/// Associates user data, such as key mappings, calibrations or
+/// preferred LED patterns, with the unique identifier of a device.
+///
+/// The data of each device is stored in a separate file in a directory.
+/// Devices are identified by their [`StableId`], which is derived from
+/// their Bluetooth address and persists across reconnections, unlike
+/// their [`Address`].
+///
+/// # Examples
+/// ```
+/// use xwiimote::config::DeviceConfigStore;
+/// use xwiimote::{Led, Monitor};
+/// use futures_util::TryStreamExt;
+///
+/// # let _ = async {
+/// let mut store = DeviceConfigStore::new("/tmp/wiimotes");
+/// // Light up the preferred LED of every device once connected.
+/// store.on_load(|device, data| {
+///     let led = if data == b"2" { Led::Two } else { Led::One };
+///     device.set_led(led, true)
+/// });
+///
+/// let mut monitor = Monitor::enumerate()?;
+/// while let Some(address) = monitor.try_next().await? {
+///     let _device = store.connect(&address)?;
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// # };
+/// ```
+pub struct DeviceConfigStore {
+    dir: PathBuf,
+    hooks: Vec<LoadHook>,
+}
+
+impl DeviceConfigStore {
+    /// Creates a store that keeps its files in `dir`.
+    ///
+    /// The directory is created when the first configuration is saved.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Creates a store in the `xwiimote` subdirectory of the user's
+    /// configuration directory, as given by `$XDG_CONFIG_HOME`
+    /// (or `$HOME/.config` if not set).
+    pub fn user() -> Result<Self> {
+        let base = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => match std::env::var_os("HOME") {
+                Some(home) => Path::new(&home).join(".config"),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "cannot determine the configuration directory",
+                    )
+                    .into())
+                }
+            },
+        };
+        Ok(Self::new(base.join("xwiimote")))
+    }
+
+    /// Returns the directory that holds the stored configurations.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Adds a function that is called with the stored configuration
+    /// of every device connected through [`DeviceConfigStore::connect`].
+    ///
+    /// Hooks are not called for devices without a stored configuration.
+    pub fn on_load(&mut self, hook: impl FnMut(&mut Device, &[u8]) -> Result<()> + 'static) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    /// Connects to the device at the given address, and passes its stored
+    /// configuration, if any, to the functions added with
+    /// [`DeviceConfigStore::on_load`].
+    pub fn connect(&mut self, address: &Address) -> Result<Device> {
+        let mut device = Device::connect(address)?;
+        if let Some(data) = self.load_bytes(&address.stable_id()?)? {
+            for hook in &mut self.hooks {
+                hook(&mut device, &data)?;
+            }
+        }
+        Ok(device)
+    }
+
+    /// Reads the configuration stored for the device with the given
+    /// identifier, if any.
+    pub fn load_bytes(&self, id: &StableId) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.path(id)) {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Replaces the configuration stored for the device with the given
+    /// identifier.
+    pub fn save_bytes(&self, id: &StableId, data: &[u8]) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        // Write to a temporary file first, so that readers never
+        // observe a partially written configuration.
+        let path = self.path(id);
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, data)?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    /// Deletes the configuration stored for the device with the given
+    /// identifier, if any.
+    pub fn remove(&self, id: &StableId) -> Result<()> {
+        match fs::remove_file(self.path(id)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the path of the file that holds the configuration
+    /// of a device.
+    fn path(&self, id: &StableId) -> PathBuf {
+        // The identifier only has hexadecimal digits and colons, which
+        // can appear in file names.
+        self.dir.join(format!("{id}.conf"))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl DeviceConfigStore {
+    /// Adds a function that is called with the deserialized configuration
+    /// of every device connected through [`DeviceConfigStore::connect`].
+    ///
+    /// The configurations are stored in the JSON format.
+    pub fn on_load_with<T: serde::de::DeserializeOwned>(
+        &mut self,
+        mut hook: impl FnMut(&mut Device, T) -> Result<()> + 'static,
+    ) {
+        self.on_load(move |device, data| hook(device, serde_json::from_slice(data)?));
+    }
+
+    /// Reads and deserializes the configuration stored for the device
+    /// with the given identifier, if any.
+    pub fn load<T: serde::de::DeserializeOwned>(&self, id: &StableId) -> Result<Option<T>> {
+        match self.load_bytes(id)? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Serializes and replaces the configuration stored for the device
+    /// with the given identifier.
+    pub fn save<T: serde::Serialize>(&self, id: &StableId, config: &T) -> Result<()> {
+        self.save_bytes(id, &serde_json::to_vec_pretty(config)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::DeviceConfigStore;
+    use crate::{Result, StableId};
+
+    #[test]
+    fn names_files_by_normalized_id() -> Result<()> {
+        let store = DeviceConfigStore::new("/store");
+        let id: StableId = "00:1F:32:AA:BB:CC".parse()?;
+        assert_eq!(
+            store.path(&id).to_str(),
+            Some("/store/00:1f:32:aa:bb:cc.conf")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn round_trips_data() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("xwiimote-config-{}", std::process::id()));
+        let store = DeviceConfigStore::new(&dir);
+        let id: StableId = "00:1f:32:aa:bb:cc".parse()?;
+        assert_eq!(store.load_bytes(&id)?, None);
+
+        store.save_bytes(&id, b"first")?;
+        store.save_bytes(&id, b"second")?;
+        assert_eq!(store.load_bytes(&id)?.as_deref(), Some(&b"second"[..]));
+
+        store.remove(&id)?;
+        store.remove(&id)?;
+        assert_eq!(store.load_bytes(&id)?, None);
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,250 @@
+//! Detection of other processes that consume the input of a device.
+//!
+//! Besides the interface used by this library, the kernel exposes every
+//! Wii Remote through a set of `evdev` nodes, which desktop environments
+//! treat like any other input device: a Wayland compositor may turn the
+//! D-pad into arrow key presses, and a process that *grabs* a node
+//! receives its events exclusively. Either case makes the input of an
+//! application look duplicated or missing. [`check`] finds the processes
+//! that have the nodes of a device open, and the nodes that are grabbed.
+//!
+//! # Examples
+//! Explain why a device seems to misbehave.
+//! ```no_run
+//! use futures_util::TryStreamExt;
+//! use xwiimote::{conflict, Monitor};
+//!
+//! # tokio_test::block_on(async {
+//! let address = Monitor::enumerate()?.try_next().await?.unwrap();
+//! for node in conflict::check(&address)? {
+//!     if node.is_conflicting() {
+//!         eprintln!("{node}");
+//!     }
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! # }).unwrap();
+//! ```
+
+use crate::{Address, Result};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+/// The `EVIOCGRAB` request, which grabs (if the argument is 1) or releases
+/// (if 0) an `evdev` node for the exclusive use of the caller.
+pub(crate) const EVIOCGRAB: libc::Ioctl = 0x4004_4590;
+
+/// A process that has an `evdev` node of a device open.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Consumer {
+    /// The process identifier.
+    pub pid: u32,
+    /// The name of the executable of the process, such as `kwin_wayland`.
+    pub command: String,
+}
+
+/// The state of an `evdev` node of a device.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvdevNode {
+    /// The path of the node, such as `/dev/input/event12`.
+    pub path: PathBuf,
+    /// The name of the input interface, such as `Nintendo Wii Remote`.
+    pub name: String,
+    /// Has another process grabbed the node? [`None`] if the node could
+    /// not be opened to find out, usually for lack of permissions.
+    pub grabbed: Option<bool>,
+    /// The other processes that have the node open.
+    ///
+    /// Only the processes whose open files can be inspected are listed,
+    /// which excludes those of other users unless running as root.
+    pub consumers: Vec<Consumer>,
+}
+
+impl EvdevNode {
+    /// Returns `true` if the node is grabbed or used by another process.
+    pub fn is_conflicting(&self) -> bool {
+        self.grabbed == Some(true) || !self.consumers.is_empty()
+    }
+}
+
+impl fmt::Display for EvdevNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.path.display(), self.name)?;
+        if self.grabbed == Some(true) {
+            write!(
+                f,
+                ": grabbed by another process, which receives its events exclusively"
+            )?;
+        } else if !self.consumers.is_empty() {
+            write!(f, ": also read by")?;
+            for (ix, consumer) in self.consumers.iter().enumerate() {
+                let sep = if ix == 0 { " " } else { ", " };
+                write!(f, "{sep}{} (pid {})", consumer.command, consumer.pid)?;
+            }
+            write!(f, ", which may act on its input too")?;
+        }
+        Ok(())
+    }
+}
+
+/// Inspects the `evdev` nodes of the device at the given address.
+///
+/// Whether a node is grabbed is found by grabbing it briefly; another
+/// process may miss the events generated in the meantime. The nodes
+/// opened by the current process, including those opened by the
+/// `xwiimote` library itself, are not reported as conflicts, but the
+/// nodes grabbed through [`Device::grab_evdev`](crate::Device::grab_evdev)
+/// are reported as grabbed.
+pub fn check(address: &Address) -> Result<Vec<EvdevNode>> {
+    let nodes = address.evdev_nodes()?;
+    let paths: Vec<_> = nodes.iter().map(|(path, _)| path.clone()).collect();
+    let consumers = find_consumers(Path::new("/proc"), &paths)?;
+    Ok(nodes
+        .into_iter()
+        .zip(consumers)
+        .map(|((path, name), consumers)| EvdevNode {
+            grabbed: is_grabbed(&path),
+            path,
+            name,
+            consumers,
+        })
+        .collect())
+}
+
+/// Finds out whether another process grabbed an `evdev` node, by trying
+/// to grab it. Returns [`None`] if the node cannot be opened.
+fn is_grabbed(node: &Path) -> Option<bool> {
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(node)
+        .ok()?;
+    let res_code = unsafe { libc::ioctl(file.as_raw_fd(), EVIOCGRAB, 1 as libc::c_int) };
+    if res_code == 0 {
+        unsafe { libc::ioctl(file.as_raw_fd(), EVIOCGRAB, 0 as libc::c_int) };
+        Some(false)
+    } else {
+        (io::Error::last_os_error().raw_os_error() == Some(libc::EBUSY)).then_some(true)
+    }
+}
+
+/// Lists the processes, other than the current one, that have each of
+/// the given files open, by inspecting the `fd` directory of every
+/// process in `proc_dir`.
+fn find_consumers(proc_dir: &Path, files: &[PathBuf]) -> Result<Vec<Vec<Consumer>>> {
+    let mut consumers = vec![Vec::new(); files.len()];
+    let own_pid = std::process::id();
+    for entry in fs::read_dir(proc_dir)? {
+        let entry = entry?;
+        let Some(pid) = entry.file_name().to_str().and_then(|pid| pid.parse().ok()) else {
+            continue;
+        };
+        if pid == own_pid {
+            continue;
+        }
+        // The process may have exited, or belong to another user.
+        let Ok(fds) = fs::read_dir(entry.path().join("fd")) else {
+            continue;
+        };
+        let mut open = vec![false; files.len()];
+        for fd in fds.flatten() {
+            if let Ok(target) = fs::read_link(fd.path()) {
+                if let Some(ix) = files.iter().position(|file| *file == target) {
+                    open[ix] = true;
+                }
+            }
+        }
+        if !open.contains(&true) {
+            continue;
+        }
+        let command = fs::read_to_string(entry.path().join("comm")).unwrap_or_default();
+        for (ix, _) in open.iter().enumerate().filter(|(_, open)| **open) {
+            consumers[ix].push(Consumer {
+                pid,
+                command: command.trim().to_owned(),
+            });
+        }
+    }
+    for list in &mut consumers {
+        list.sort_by_key(|consumer| consumer.pid);
+    }
+    Ok(consumers)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::conflict::{find_consumers, Consumer, EvdevNode};
+    use crate::Result;
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use std::path::PathBuf;
+
+    #[test]
+    fn finds_consumers() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("xwiimote-proc-{}", std::process::id()));
+        let nodes = [
+            PathBuf::from("/dev/input/event7"),
+            PathBuf::from("/dev/input/event8"),
+        ];
+        for (pid, comm, files) in [
+            (
+                1200,
+                "kwin_wayland\n",
+                &["/dev/input/event7", "/dev/null"][..],
+            ),
+            (
+                900,
+                "game\n",
+                &["/dev/input/event7", "/dev/input/event8"][..],
+            ),
+            (77, "bash\n", &["/dev/tty1"][..]),
+        ] {
+            let fd_dir = dir.join(pid.to_string()).join("fd");
+            fs::create_dir_all(&fd_dir)?;
+            fs::write(dir.join(pid.to_string()).join("comm"), comm)?;
+            for (fd, file) in files.iter().enumerate() {
+                symlink(file, fd_dir.join(fd.to_string()))?;
+            }
+        }
+        fs::create_dir_all(dir.join("self"))?;
+
+        let consumers = find_consumers(&dir, &nodes)?;
+        fs::remove_dir_all(dir)?;
+        let consumer = |pid, command: &str| Consumer {
+            pid,
+            command: command.to_owned(),
+        };
+        assert_eq!(
+            consumers,
+            [
+                vec![consumer(900, "game"), consumer(1200, "kwin_wayland")],
+                vec![consumer(900, "game")],
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn describes_conflicts() {
+        let mut node = EvdevNode {
+            path: PathBuf::from("/dev/input/event7"),
+            name: "Nintendo Wii Remote".to_owned(),
+            grabbed: Some(false),
+            consumers: Vec::new(),
+        };
+        assert!(!node.is_conflicting());
+        node.consumers.push(Consumer {
+            pid: 1200,
+            command: "kwin_wayland".to_owned(),
+        });
+        assert!(node.is_conflicting());
+        assert_eq!(
+            node.to_string(),
+            "/dev/input/event7 (Nintendo Wii Remote): also read by \
+             kwin_wayland (pid 1200), which may act on its input too"
+        );
+    }
+}
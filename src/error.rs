@@ -0,0 +1,261 @@
+use crate::Channels;
+use std::{fmt, io};
+
+/// The error type of the operations of this crate.
+///
+/// The failure modes that applications commonly handle get their own
+/// variant; all other failures are reported as [`Error::Io`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The device was disconnected, e.g. because its battery ran out or it
+    /// moved out of range. The [`Device`](crate::Device) must be dropped,
+    /// and a new one connected once the device is found again.
+    Disconnected,
+    /// The operation requires the given channels, which are not open or
+    /// could not be opened because they are not available; e.g. the
+    /// Nunchuk channel while no Nunchuk is plugged in.
+    ChannelClosed(Channels),
+    /// The given open channels produced no events for the timeout of the
+    /// [`Watchdog`](crate::Watchdog) of the device, which could not revive
+    /// them. Reconnecting to the device usually does.
+    Stalled(Channels),
+    /// The process lacks the permissions to access a device file.
+    /// See the `udev` rules shipped with the `xwiimote` package.
+    Permission,
+    /// Reading an event from the device failed with an error code that
+    /// none of the other variants describe; e.g. `ENODEV` is reported as
+    /// [`Error::Disconnected`].
+    ///
+    /// The raw code should be included in bug reports.
+    Dispatch {
+        /// The class of the failure.
+        reason: DispatchFailure,
+        /// The positive OS error code.
+        code: i32,
+    },
+    /// Any other failure of an I/O operation.
+    Io(io::Error),
+}
+
+/// The known causes of an [`Error::Dispatch`] error.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DispatchFailure {
+    /// The kernel failed to exchange reports with the device (`EIO`),
+    /// e.g. because of Bluetooth interference.
+    Transport,
+    /// The file descriptor of the device is in a bad state (`EBADFD`),
+    /// e.g. because the device was closed by another thread.
+    BadDescriptor,
+    /// An error code that the `xwiimote` library does not document.
+    Unexpected,
+}
+
+impl Error {
+    /// Returns the corresponding [`io::ErrorKind`] of the error.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            Self::Disconnected => io::ErrorKind::NotConnected,
+            Self::ChannelClosed(_) => io::ErrorKind::NotFound,
+            Self::Stalled(_) => io::ErrorKind::TimedOut,
+            Self::Permission => io::ErrorKind::PermissionDenied,
+            Self::Dispatch { code, .. } => io::Error::from_raw_os_error(*code).kind(),
+            Self::Io(err) => err.kind(),
+        }
+    }
+
+    /// Returns the OS error code of an [`Error::Dispatch`] or
+    /// [`Error::Io`] error, if any.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match self {
+            Self::Dispatch { code, .. } => Some(*code),
+            Self::Io(err) => err.raw_os_error(),
+            _ => None,
+        }
+    }
+
+    /// Converts the error of an operation on the given channels, which
+    /// the kernel reports as `ENODEV` if a channel is not available or
+    /// not open. The device is considered disconnected otherwise.
+    pub(crate) fn from_channel_op(err: io::Error, channels: Channels, open: Channels) -> Self {
+        let missing = channels.difference(open);
+        if err.raw_os_error() == Some(libc::ENODEV) && !missing.is_empty() {
+            Self::ChannelClosed(missing)
+        } else {
+            err.into()
+        }
+    }
+
+    /// Creates an equivalent error, to report it to several consumers.
+    ///
+    /// An [`Error::Io`] error keeps its OS error code or, failing that,
+    /// its kind and message.
+    pub(crate) fn duplicate(&self) -> Self {
+        match self {
+            Self::Disconnected => Self::Disconnected,
+            Self::ChannelClosed(channels) => Self::ChannelClosed(*channels),
+            Self::Stalled(channels) => Self::Stalled(*channels),
+            Self::Permission => Self::Permission,
+            Self::Dispatch { reason, code } => Self::Dispatch {
+                reason: *reason,
+                code: *code,
+            },
+            Self::Io(err) => Self::Io(match err.raw_os_error() {
+                Some(code) => io::Error::from_raw_os_error(code),
+                None => io::Error::new(err.kind(), err.to_string()),
+            }),
+        }
+    }
+
+    /// Converts the negative error code returned by `xwii_iface_dispatch`.
+    ///
+    /// The codes without a [`DispatchFailure`] are classified like any
+    /// other I/O error.
+    pub(crate) fn from_dispatch(res_code: i32) -> Self {
+        let code = -res_code;
+        let reason = match code {
+            libc::EIO => DispatchFailure::Transport,
+            libc::EBADFD => DispatchFailure::BadDescriptor,
+            _ => match io::Error::from_raw_os_error(code).into() {
+                Self::Io(_) => DispatchFailure::Unexpected,
+                err => return err,
+            },
+        };
+        Self::Dispatch { reason, code }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Disconnected => write!(f, "the device is disconnected"),
+            Self::ChannelClosed(channels) => write!(f, "channels not open: {channels:?}"),
+            Self::Stalled(channels) => write!(f, "channels stopped reporting: {channels:?}"),
+            Self::Permission => write!(f, "permission denied to access the device"),
+            Self::Dispatch { reason, code } => {
+                let err = io::Error::from_raw_os_error(*code);
+                write!(f, "failed to read an event ({reason:?}): {err}")
+            }
+            Self::Io(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    /// Classifies an I/O error: the kernel reports `ENODEV` or `ESHUTDOWN`
+    /// once a device is removed, and `EACCES` or `EPERM` on permission
+    /// problems.
+    fn from(err: io::Error) -> Self {
+        match err.raw_os_error() {
+            Some(libc::ENODEV | libc::ESHUTDOWN) => Self::Disconnected,
+            _ if err.kind() == io::ErrorKind::PermissionDenied => Self::Permission,
+            _ => Self::Io(err),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(err) => err,
+            err => io::Error::new(err.kind(), err),
+        }
+    }
+}
+
+impl From<std::ffi::NulError> for Error {
+    fn from(err: std::ffi::NulError) -> Self {
+        Self::Io(err.into())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Io(err.into())
+    }
+}
+
+#[cfg(feature = "portal")]
+impl From<zbus::Error> for Error {
+    fn from(err: zbus::Error) -> Self {
+        Self::Io(io::Error::other(err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::DispatchFailure;
+    use crate::{Channels, Error};
+    use std::io;
+
+    #[test]
+    fn classifies_io_errors() {
+        let err = |code| Error::from(io::Error::from_raw_os_error(code));
+        assert!(matches!(err(libc::ENODEV), Error::Disconnected));
+        assert!(matches!(err(libc::EACCES), Error::Permission));
+        assert!(matches!(err(libc::EIO), Error::Io(_)));
+        assert_eq!(err(libc::EIO).raw_os_error(), Some(libc::EIO));
+
+        // The device is still there, but the channel is not.
+        let nodev = || io::Error::from_raw_os_error(libc::ENODEV);
+        let open = Channels::CORE;
+        let err = Error::from_channel_op(nodev(), Channels::CORE | Channels::NUNCHUK, open);
+        assert!(matches!(err, Error::ChannelClosed(channels) if channels == Channels::NUNCHUK));
+        let err = Error::from_channel_op(nodev(), Channels::CORE, open);
+        assert!(matches!(err, Error::Disconnected));
+
+        let err = io::Error::from(Error::Permission);
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn classifies_dispatch_errors() {
+        assert!(matches!(
+            Error::from_dispatch(-libc::ENODEV),
+            Error::Disconnected
+        ));
+        assert!(matches!(
+            Error::from_dispatch(-libc::ESHUTDOWN),
+            Error::Disconnected
+        ));
+        assert!(matches!(
+            Error::from_dispatch(-libc::EACCES),
+            Error::Permission
+        ));
+        let err = Error::from_dispatch(-libc::EIO);
+        assert!(matches!(
+            err,
+            Error::Dispatch {
+                reason: DispatchFailure::Transport,
+                code: libc::EIO
+            }
+        ));
+        assert_eq!(err.raw_os_error(), Some(libc::EIO));
+        assert!(matches!(
+            Error::from_dispatch(-libc::EBADFD),
+            Error::Dispatch {
+                reason: DispatchFailure::BadDescriptor,
+                ..
+            }
+        ));
+        let err = Error::from_dispatch(-libc::EPROTO);
+        assert!(matches!(
+            err,
+            Error::Dispatch {
+                reason: DispatchFailure::Unexpected,
+                code: libc::EPROTO
+            }
+        ));
+    }
+}
@@ -1,16 +1,24 @@
+use crate::battery::BatteryPolicy;
+use crate::frame::CoordinateFrame;
 use crate::reactor::{Interest, Reactor};
-#[cfg(doc)]
-use crate::Channels;
-use crate::{Device, Result};
+use crate::timer::Sleep;
+use crate::{lock, Channels, Device, Error, ExtensionKind, Result, Watchdog, WatchdogAction};
 use futures_core::Stream;
 use libc::c_int;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
+use std::future::Future;
+use std::ops::Deref;
 use std::pin::Pin;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::thread;
 use std::time::{Duration, SystemTime};
-use std::{io, mem};
-use xwiimote_sys::{xwii_event, xwii_iface_dispatch, xwii_iface_get_fd, XWII_EVENT_GONE};
+use xwiimote_sys::{
+    timeval, xwii_event, xwii_event_abs, xwii_event_key, xwii_event_union, xwii_iface_get_fd,
+    XWII_EVENT_GONE, XWII_EVENT_NUM,
+};
 
 // Keys.
 
@@ -69,6 +77,7 @@ macro_rules! regular_controller_key_enum {
     };
 }
 
+#[cfg(any(feature = "classic", feature = "pro-controller"))]
 macro_rules! gamepad_key_enum {
     ($doc:expr, $name:ident {$($body:tt)*}) => {
         regular_controller_key_enum!{
@@ -102,6 +111,7 @@ regular_controller_key_enum!(
     }
 );
 
+#[cfg(feature = "pro-controller")]
 gamepad_key_enum!(
     "The keys of a Wii U Pro controller",
     ProControllerKey {
@@ -116,10 +126,12 @@ gamepad_key_enum!(
     }
 );
 
+#[cfg(feature = "classic")]
 gamepad_key_enum!("The keys of a Classic controller", ClassicControllerKey {});
 
 /// The keys of a Nunchuk.
 // This is the only extension that doesn't have the + and - buttons.
+#[cfg(feature = "nunchuk")]
 #[repr(u32)]
 #[derive(Copy, Clone, Debug, FromPrimitive)]
 pub enum NunchukKey {
@@ -129,8 +141,10 @@ pub enum NunchukKey {
     Z = xwiimote_sys::XWII_KEY_Z,
 }
 
+#[cfg(feature = "drums")]
 key_enum!("The keys of a drums controller.", DrumsKey {});
 
+#[cfg(feature = "guitar")]
 key_enum!("The keys of a guitar controller.",
     GuitarKey {
         /// The StarPower/Home button.
@@ -164,7 +178,9 @@ pub enum KeyState {
 
 // Event kinds
 
-const MAX_IR_SOURCES: usize = 4;
+/// The maximum number of IR sources tracked by the IR camera, i.e. the
+/// length of the array reported in [`Event::Ir`].
+pub const MAX_IR_SOURCES: usize = 4;
 
 /// An IR source detected by the IR camera, as reported in [`Event::Ir`].
 #[derive(Copy, Clone, Debug)]
@@ -178,17 +194,15 @@ pub struct IrSource {
 impl IrSource {
     /// Parses the IR source data from the given event.
     ///
-    /// # Safety
-    /// Assumes `raw` points to an event of type [`xwiimote_sys::XWII_EVENT_IR`].
-    unsafe fn parse(raw: &xwii_event) -> [Option<IrSource>; MAX_IR_SOURCES] {
-        // See `xwii_event_ir_is_valid`, which we cannot use since `bindgen`
-        // does not expose functions declared with `static inline`.
-        const MISSING_SOURCE: i32 = 1023;
+    /// The sources missing from the payload of `raw` are reported
+    /// as [`None`].
+    fn parse(raw: RawEvent) -> [Option<IrSource>; MAX_IR_SOURCES] {
         let mut sources: [Option<_>; MAX_IR_SOURCES] = Default::default();
 
-        for (ix, pos) in raw.v.abs.iter().take(MAX_IR_SOURCES).enumerate() {
+        for (ix, source) in sources.iter_mut().enumerate() {
+            let Some(pos) = raw.abs(ix) else { break };
             if pos.x != MISSING_SOURCE && pos.y != MISSING_SOURCE {
-                sources[ix] = Some(IrSource { x: pos.x, y: pos.y })
+                *source = Some(IrSource { x: pos.x, y: pos.y })
             }
         }
         sources
@@ -222,6 +236,7 @@ pub enum Event {
     ///
     /// Received only if [`Channels::IR`] is open.
     Ir([Option<IrSource>; MAX_IR_SOURCES]),
+    #[cfg(feature = "balance-board")]
     /// Provides Balance Board weight data. Four sensors report
     /// data for each of the edges of the board.
     ///
@@ -238,10 +253,12 @@ pub enum Event {
         /// The z-axis rotational speed.
         z: i32,
     },
+    #[cfg(feature = "pro-controller")]
     /// The state of a Wii U Pro controller key changed.
     ///
     /// Received only if [`Channels::PRO_CONTROLLER`] is open.
     ProControllerKey(ProControllerKey, KeyState),
+    #[cfg(feature = "pro-controller")]
     /// Reports the movement of an analog stick from
     /// a Wii U Pro controller.
     ///
@@ -261,11 +278,32 @@ pub enum Event {
     ///
     /// No payload is provided, hence the application should check
     /// what changed by examining the [`Device`] manually.
+    ///
+    /// See [`Device::set_watch_debounce`] to filter out the bursts
+    /// of these events caused by loose extension connectors.
     Other,
+    /// Some motion reports were lost before reaching the application,
+    /// e.g. because it did not read them fast enough or the Bluetooth
+    /// link is congested. Reported at the time of the first report
+    /// received after the gap.
+    ///
+    /// Received only if enabled with [`Device::set_drop_detection`].
+    Dropped {
+        /// The estimated number of lost reports.
+        count_estimate: u32,
+    },
+    /// The auto-reopen policy of the device opened the given channels
+    /// again, after they became available. Reported right after the
+    /// [`Event::Other`] that revealed the change.
+    ///
+    /// Received only if enabled with [`Device::set_auto_reopen`].
+    Reopened(Channels),
+    #[cfg(feature = "classic")]
     /// The state of a Classic controller key changed.
     ///
     /// Received only if [`Channels::CLASSIC_CONTROLLER`] is open.
     ClassicControllerKey(ClassicControllerKey, KeyState),
+    #[cfg(feature = "classic")]
     /// Reports the movement of an analog stick from
     /// a Classic controller.
     ///
@@ -279,21 +317,25 @@ pub enum Event {
         right_x: i32,
         /// The right analog stick y-axis absolute position.
         right_y: i32,
-        /// The TL trigger absolute position, ranging from 0 to 63.
+        /// The TL trigger absolute position, ranging from 0 to
+        /// [`TRIGGER_MAX`](crate::ranges::TRIGGER_MAX).
         ///
         /// Many controller do not have analog controllers, in
-        /// which case this value is either 0 or 63.
+        /// which case this value is either 0 or `TRIGGER_MAX`.
         left_trigger: u8,
-        /// The TR trigger absolute position, ranging from 0 to 63.
+        /// The TR trigger absolute position, ranging from 0 to
+        /// [`TRIGGER_MAX`](crate::ranges::TRIGGER_MAX).
         ///
         /// Many controller do not have analog controllers, in
-        /// which case this value is either 0 or 63.
+        /// which case this value is either 0 or `TRIGGER_MAX`.
         right_trigger: u8,
     },
+    #[cfg(feature = "nunchuk")]
     /// The state of a Nunchuk key changed.
     ///
     /// Received only if [`Channels::NUNCHUK`] is open.
     NunchukKey(NunchukKey, KeyState),
+    #[cfg(feature = "nunchuk")]
     /// Reports the movement of an analog stick from a Nunchuk.
     ///
     /// Received only if [`Channels::NUNCHUK`] is open.
@@ -307,20 +349,47 @@ pub enum Event {
         /// The y-axis acceleration.
         y_acceleration: i32,
     },
+    #[cfg(feature = "drums")]
     /// The state of a drums controller key changed.
     ///
     /// Received only if [`Channels::DRUMS`] is open.
     DrumsKey(DrumsKey, KeyState),
-    /// Reports the movement of an analog stick from a
-    /// drums controller.
+    #[cfg(feature = "drums")]
+    /// Reports the movement of the analog stick and the pressure
+    /// on the pads and pedals of a drums controller.
+    ///
+    /// The pressure values are zero while a pad or pedal is released.
     ///
     /// Received only if [`Channels::DRUMS`] is open.
-    // todo: figure out how many drums, and how to report pressure.
-    DrumsMove {},
+    DrumsMove {
+        /// The x-axis analog stick position.
+        x: i32,
+        /// The y-axis analog stick position.
+        y: i32,
+        /// The pressure on the left cymbal.
+        cymbal_left: i32,
+        /// The pressure on the right cymbal.
+        cymbal_right: i32,
+        /// The pressure on the left tom-tom.
+        tom_left: i32,
+        /// The pressure on the right tom-tom.
+        tom_right: i32,
+        /// The pressure on the far-right tom-tom.
+        tom_far_right: i32,
+        /// The pressure on the bass pedal, which is non-zero
+        /// while the pedal is pressed.
+        bass: i32,
+        /// The position of the hi-hat pedal. Unlike the bass pedal,
+        /// the hi-hat pedal is continuous: intermediate values
+        /// represent a partially open hi-hat.
+        hi_hat: i32,
+    },
+    #[cfg(feature = "guitar")]
     /// The state of a guitar controller key changed.
     ///
     /// Received only if [`Channels::GUITAR`] is open.
     GuitarKey(GuitarKey, KeyState),
+    #[cfg(feature = "guitar")]
     /// Reports the movement of an analog stick, the whammy bar,
     /// or the fret bar from a guitar controller.
     ///
@@ -332,30 +401,172 @@ pub enum Event {
         y: i32,
         /// The whammy bar position.
         whammy_bar: i32,
-        /// The fret bar absolute position.
-        fret_bar: i32,
+        /// The position of the finger on the touch slider of the neck
+        /// (also known as the fret bar), or [`None`] if the slider
+        /// is not touched.
+        ///
+        /// Guitars without a touch slider always report [`None`].
+        fret_bar: Option<i32>,
     },
 }
 
+/// The raw type of [`Event::Dropped`] events. It lets recordings keep
+/// track of the holes in the data.
+///
+/// The synthetic types are stored in recordings and sent through the
+/// broker protocol, so they are fixed and lie well past the types
+/// defined by the `xwiimote` library, which may add new ones. They
+/// still fit in the 16 bits of a recorded type.
+pub(crate) const XWII_EVENT_DROPPED: u32 = 0xff00;
+
+/// The raw type of [`Event::Reopened`] events, which carry the bits
+/// of the channels in the first position.
+pub(crate) const XWII_EVENT_REOPENED: u32 = 0xff01;
+
+/// The coordinate reported for the IR sources that the camera
+/// does not see.
+///
+/// See `xwii_event_ir_is_valid`, which we cannot use since `bindgen`
+/// does not expose functions declared with `static inline`.
+const MISSING_SOURCE: i32 = 1023;
+
+/// The fret bar position reported while the touch slider
+/// of a guitar is not touched.
+#[cfg(feature = "guitar")]
+const FRET_BAR_UNTOUCHED: i32 = 0x0f;
+
+/// A raw event, with accessors that check that the payload field they
+/// read is the one used by the type of the event.
+#[derive(Copy, Clone)]
+pub(crate) struct RawEvent<'a> {
+    raw: &'a xwii_event,
+}
+
+impl<'a> RawEvent<'a> {
+    /// Wraps a raw event.
+    ///
+    /// # Safety
+    /// The `key` and `abs` fields of the payload of `raw` must be fully
+    /// initialized, as in the events returned by
+    /// [`xwii_iface_dispatch`](xwiimote_sys::xwii_iface_dispatch)
+    /// and [`Event::to_raw`], or those created by `xwii_event::default`.
+    pub unsafe fn new(raw: &'a xwii_event) -> Self {
+        Self { raw }
+    }
+
+    /// Returns the type of the event.
+    pub fn type_(&self) -> u32 {
+        self.raw.type_
+    }
+
+    /// Returns the time at which the kernel generated the event, or
+    /// [`None`] if it cannot be represented.
+    pub fn time(&self) -> Option<SystemTime> {
+        // Rust does not provide a way to create a `SystemTime` directly.
+        let since_epoch = Duration::from_secs(self.raw.time.tv_sec.max(0) as u64)
+            + Duration::from_micros(self.raw.time.tv_usec.max(0) as u64);
+        SystemTime::UNIX_EPOCH.checked_add(since_epoch)
+    }
+
+    /// Returns the key payload, or [`None`] if the event is not a key event.
+    pub fn key(&self) -> Option<xwii_event_key> {
+        use xwiimote_sys::*;
+        let is_key = matches!(
+            self.raw.type_,
+            XWII_EVENT_KEY
+                | XWII_EVENT_PRO_CONTROLLER_KEY
+                | XWII_EVENT_CLASSIC_CONTROLLER_KEY
+                | XWII_EVENT_NUNCHUK_KEY
+                | XWII_EVENT_DRUMS_KEY
+                | XWII_EVENT_GUITAR_KEY
+        );
+        if !is_key {
+            return None;
+        }
+        // SAFETY: the field is initialized, and it is the one used by key events.
+        Some(unsafe { self.raw.v.key })
+    }
+
+    /// Returns the position at index `ix` of the payload, or [`None`] if
+    /// the type of the event does not carry that many positions.
+    pub fn abs(&self, ix: usize) -> Option<xwii_event_abs> {
+        if ix >= Self::abs_len(self.raw.type_) {
+            return None;
+        }
+        // SAFETY: the field is initialized, and it is the one used
+        // by the event types that carry positions.
+        Some(unsafe { self.raw.v.abs[ix] })
+    }
+
+    /// Returns the whole payload as positions, whatever the type
+    /// of the event; the payload of key events overlaps the first one.
+    pub fn payload(&self) -> [xwii_event_abs; 8] {
+        // SAFETY: the field is initialized, and it has no invalid values.
+        unsafe { self.raw.v.abs }
+    }
+
+    /// Returns the number of positions carried by events of the given type.
+    fn abs_len(type_: u32) -> usize {
+        use xwiimote_sys::*;
+        match type_ {
+            XWII_EVENT_ACCEL
+            | XWII_EVENT_MOTION_PLUS
+            | XWII_EVENT_DROPPED
+            | XWII_EVENT_REOPENED => 1,
+            XWII_EVENT_PRO_CONTROLLER_MOVE | XWII_EVENT_NUNCHUK_MOVE => 2,
+            XWII_EVENT_CLASSIC_CONTROLLER_MOVE | XWII_EVENT_GUITAR_MOVE => 3,
+            XWII_EVENT_IR | XWII_EVENT_BALANCE_BOARD => 4,
+            XWII_EVENT_DRUMS_MOVE => XWII_DRUMS_ABS_NUM as usize,
+            _ => 0,
+        }
+    }
+}
+
+impl Event {
+    /// Returns the IR sources reported by an [`Event::Ir`] event, along
+    /// with their indices in its array; the missing sources are skipped.
+    ///
+    /// Returns an empty iterator for the other events.
+    ///
+    /// # Examples
+    /// ```
+    /// use xwiimote::events::{Event, IrSource};
+    ///
+    /// let source = IrSource { x: 512, y: 384 };
+    /// let event = Event::Ir([None, Some(source), None, None]);
+    /// let sources: Vec<_> = event.ir_sources().map(|(ix, _)| ix).collect();
+    /// assert_eq!(sources, [1]);
+    /// ```
+    pub fn ir_sources(&self) -> impl Iterator<Item = (usize, IrSource)> {
+        let sources = match self {
+            Event::Ir(sources) => *sources,
+            _ => [None; MAX_IR_SOURCES],
+        };
+        sources
+            .into_iter()
+            .enumerate()
+            .filter_map(|(ix, source)| Some((ix, source?)))
+    }
+}
+
 impl Event {
     /// Parses an event.
     ///
     /// # Returns
-    /// The parsed event and the time at which the kernel generated the event.
-    ///
-    /// # Safety
-    /// Assumes that `raw` is an object returned by [`xwii_iface_dispatch`].
-    unsafe fn parse(raw: &xwii_event) -> (Self, SystemTime) {
-        // Rust does not provide a way to create a `SystemTime` directly.
-        let since_epoch = Duration::new(raw.time.tv_sec as u64, raw.time.tv_usec as u32 * 1000);
-        let time = SystemTime::UNIX_EPOCH + since_epoch;
-        let event = match raw.type_ {
+    /// The parsed event and the time at which the kernel generated the event,
+    /// or [`None`] if the event originates from an extension whose support
+    /// was disabled at compile time, or if the event is malformed (e.g. it
+    /// has an unknown type or key code). This function never panics, so that
+    /// a single unexpected event cannot take down the whole application.
+    pub(crate) fn parse(raw: RawEvent) -> Option<(Self, SystemTime)> {
+        let time = raw.time()?;
+        let event = match raw.type_() {
             xwiimote_sys::XWII_EVENT_KEY => {
-                let (key, state) = Self::parse_key(raw);
+                let (key, state) = Self::parse_key(raw)?;
                 Event::Key(key, state)
             }
             xwiimote_sys::XWII_EVENT_ACCEL => {
-                let acc = raw.v.abs[0];
+                let acc = raw.abs(0)?;
                 Event::Accelerometer {
                     x: acc.x,
                     y: acc.y,
@@ -363,103 +574,831 @@ impl Event {
                 }
             }
             xwiimote_sys::XWII_EVENT_IR => Event::Ir(IrSource::parse(raw)),
+            #[cfg(feature = "balance-board")]
             xwiimote_sys::XWII_EVENT_BALANCE_BOARD => {
-                let weights = raw.v.abs;
-                Event::BalanceBoard([weights[0].x, weights[1].x, weights[2].x, weights[3].x])
+                let weight = |ix| raw.abs(ix).map(|pos| pos.x);
+                Event::BalanceBoard([weight(0)?, weight(1)?, weight(2)?, weight(3)?])
             }
             xwiimote_sys::XWII_EVENT_MOTION_PLUS => {
-                let rot_speed = raw.v.abs[0];
+                let rot_speed = raw.abs(0)?;
                 Event::MotionPlus {
                     x: rot_speed.x,
                     y: rot_speed.y,
                     z: rot_speed.z,
                 }
             }
+            #[cfg(feature = "pro-controller")]
             xwiimote_sys::XWII_EVENT_PRO_CONTROLLER_KEY => {
-                let (key, state) = Self::parse_key(raw);
+                let (key, state) = Self::parse_key(raw)?;
                 Event::ProControllerKey(key, state)
             }
+            #[cfg(feature = "pro-controller")]
             xwiimote_sys::XWII_EVENT_PRO_CONTROLLER_MOVE => {
-                let pos = raw.v.abs;
+                let (left, right) = (raw.abs(0)?, raw.abs(1)?);
                 Event::ProControllerMove {
-                    left_x: pos[0].x,
-                    left_y: pos[0].y,
-                    right_x: pos[1].x,
-                    right_y: pos[1].y,
+                    left_x: left.x,
+                    left_y: left.y,
+                    right_x: right.x,
+                    right_y: right.y,
                 }
             }
             xwiimote_sys::XWII_EVENT_WATCH => Event::Other,
+            XWII_EVENT_DROPPED => Event::Dropped {
+                count_estimate: raw.abs(0)?.x.try_into().ok()?,
+            },
+            XWII_EVENT_REOPENED => {
+                Event::Reopened(Channels::from_bits_truncate(raw.abs(0)?.x as u32))
+            }
+            #[cfg(feature = "classic")]
             xwiimote_sys::XWII_EVENT_CLASSIC_CONTROLLER_KEY => {
-                let (key, state) = Self::parse_key(raw);
+                let (key, state) = Self::parse_key(raw)?;
                 Event::ClassicControllerKey(key, state)
             }
+            #[cfg(feature = "classic")]
             xwiimote_sys::XWII_EVENT_CLASSIC_CONTROLLER_MOVE => {
-                let pos = raw.v.abs;
+                let (left, right, triggers) = (raw.abs(0)?, raw.abs(1)?, raw.abs(2)?);
                 Event::ClassicControllerMove {
-                    left_x: pos[0].x,
-                    left_y: pos[0].y,
-                    right_x: pos[1].x,
-                    right_y: pos[1].y,
-                    left_trigger: pos[2].x as u8,
-                    right_trigger: pos[2].y as u8,
+                    left_x: left.x,
+                    left_y: left.y,
+                    right_x: right.x,
+                    right_y: right.y,
+                    left_trigger: triggers.x as u8,
+                    right_trigger: triggers.y as u8,
                 }
             }
+            #[cfg(feature = "nunchuk")]
             xwiimote_sys::XWII_EVENT_NUNCHUK_KEY => {
-                let (key, state) = Self::parse_key(raw);
+                let (key, state) = Self::parse_key(raw)?;
                 Event::NunchukKey(key, state)
             }
+            #[cfg(feature = "nunchuk")]
             xwiimote_sys::XWII_EVENT_NUNCHUK_MOVE => {
-                let values = raw.v.abs;
+                let (stick, acc) = (raw.abs(0)?, raw.abs(1)?);
                 Event::NunchukMove {
-                    x: values[0].x,
-                    y: values[0].y,
-                    x_acceleration: values[1].x,
-                    y_acceleration: values[1].y,
+                    x: stick.x,
+                    y: stick.y,
+                    x_acceleration: acc.x,
+                    y_acceleration: acc.y,
                 }
             }
+            #[cfg(feature = "drums")]
             xwiimote_sys::XWII_EVENT_DRUMS_KEY => {
-                let (key, state) = Self::parse_key(raw);
+                let (key, state) = Self::parse_key(raw)?;
                 Event::DrumsKey(key, state)
             }
-            xwiimote_sys::XWII_EVENT_DRUMS_MOVE => todo!(),
+            #[cfg(feature = "drums")]
+            xwiimote_sys::XWII_EVENT_DRUMS_MOVE => {
+                use xwiimote_sys::{
+                    XWII_DRUMS_ABS_BASS, XWII_DRUMS_ABS_CYMBAL_LEFT, XWII_DRUMS_ABS_CYMBAL_RIGHT,
+                    XWII_DRUMS_ABS_HI_HAT, XWII_DRUMS_ABS_PAD, XWII_DRUMS_ABS_TOM_FAR_RIGHT,
+                    XWII_DRUMS_ABS_TOM_LEFT, XWII_DRUMS_ABS_TOM_RIGHT,
+                };
+                let pad = raw.abs(XWII_DRUMS_ABS_PAD as usize)?;
+                let pressure = |ix| raw.abs(ix as usize).map(|pos| pos.x);
+                Event::DrumsMove {
+                    x: pad.x,
+                    y: pad.y,
+                    cymbal_left: pressure(XWII_DRUMS_ABS_CYMBAL_LEFT)?,
+                    cymbal_right: pressure(XWII_DRUMS_ABS_CYMBAL_RIGHT)?,
+                    tom_left: pressure(XWII_DRUMS_ABS_TOM_LEFT)?,
+                    tom_right: pressure(XWII_DRUMS_ABS_TOM_RIGHT)?,
+                    tom_far_right: pressure(XWII_DRUMS_ABS_TOM_FAR_RIGHT)?,
+                    bass: pressure(XWII_DRUMS_ABS_BASS)?,
+                    hi_hat: pressure(XWII_DRUMS_ABS_HI_HAT)?,
+                }
+            }
+            #[cfg(feature = "guitar")]
             xwiimote_sys::XWII_EVENT_GUITAR_KEY => {
-                let (key, state) = Self::parse_key(raw);
+                let (key, state) = Self::parse_key(raw)?;
                 Event::GuitarKey(key, state)
             }
-            // Handled by `EventStream`.
-            XWII_EVENT_GONE => panic!("unexpected removal event"),
-            type_id => panic!("unexpected event type: {type_id}"),
+            #[cfg(feature = "guitar")]
+            xwiimote_sys::XWII_EVENT_GUITAR_MOVE => {
+                let (stick, whammy_bar, fret_bar) = (raw.abs(0)?, raw.abs(1)?, raw.abs(2)?);
+                Event::GuitarMove {
+                    x: stick.x,
+                    y: stick.y,
+                    whammy_bar: whammy_bar.x,
+                    fret_bar: Some(fret_bar.x).filter(|&pos| pos != FRET_BAR_UNTOUCHED),
+                }
+            }
+            // The support for these extensions is disabled; ignore their events.
+            #[allow(unreachable_patterns)]
+            xwiimote_sys::XWII_EVENT_BALANCE_BOARD
+            | xwiimote_sys::XWII_EVENT_PRO_CONTROLLER_KEY
+            | xwiimote_sys::XWII_EVENT_PRO_CONTROLLER_MOVE
+            | xwiimote_sys::XWII_EVENT_CLASSIC_CONTROLLER_KEY
+            | xwiimote_sys::XWII_EVENT_CLASSIC_CONTROLLER_MOVE
+            | xwiimote_sys::XWII_EVENT_NUNCHUK_KEY
+            | xwiimote_sys::XWII_EVENT_NUNCHUK_MOVE
+            | xwiimote_sys::XWII_EVENT_DRUMS_KEY
+            | xwiimote_sys::XWII_EVENT_DRUMS_MOVE
+            | xwiimote_sys::XWII_EVENT_GUITAR_KEY
+            | xwiimote_sys::XWII_EVENT_GUITAR_MOVE => return None,
+            // Removal events are handled by `EventStream`; other types
+            // were added by newer versions of the library.
+            _ => return None,
         };
-        (event, time)
+        Some((event, time))
     }
 
     /// Parses the key payload of a raw event.
     ///
-    /// # Safety
-    /// Assumes that `raw` is an object returned by [`xwii_iface_dispatch`]
-    /// whose payload type is [`xwii_event_key`].
-    unsafe fn parse_key<T: FromPrimitive>(raw: &xwii_event) -> (T, KeyState) {
-        let data = raw.v.key;
-        let key =
-            T::from_u32(data.code).unwrap_or_else(|| panic!("unknown key code {}", data.code));
-        let state = KeyState::from_u32(data.state)
-            .unwrap_or_else(|| panic!("unknown key state {}", data.state));
-        (key, state)
+    /// Returns [`None`] if the event is not a key event, or if the key
+    /// code or state is unknown.
+    fn parse_key<T: FromPrimitive>(raw: RawEvent) -> Option<(T, KeyState)> {
+        let data = raw.key()?;
+        Some((T::from_u32(data.code)?, KeyState::from_u32(data.state)?))
+    }
+}
+
+impl Event {
+    /// Converts the event back into the raw representation
+    /// read by [`Event::parse`].
+    pub(crate) fn to_raw(self, time: SystemTime) -> xwii_event {
+        let since_epoch = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let time = timeval {
+            tv_sec: since_epoch.as_secs() as _,
+            tv_usec: since_epoch.subsec_micros() as _,
+        };
+        let mut abs = [xwii_event_abs::default(); 8];
+        let pos = |x, y, z| xwii_event_abs { x, y, z };
+        let key = |code: u32, state: KeyState| {
+            // Zero the rest of the payload, which overlaps the positions.
+            let mut payload = xwii_event_union::default();
+            payload.key = xwii_event_key {
+                code,
+                state: state as u32,
+            };
+            payload
+        };
+        let (type_, payload) = match self {
+            Event::Key(code, state) => (xwiimote_sys::XWII_EVENT_KEY, key(code as u32, state)),
+            Event::Accelerometer { x, y, z } => {
+                abs[0] = pos(x, y, z);
+                (xwiimote_sys::XWII_EVENT_ACCEL, xwii_event_union { abs })
+            }
+            Event::Ir(sources) => {
+                for (ix, source) in sources.iter().enumerate() {
+                    abs[ix] = match source {
+                        Some(source) => pos(source.x, source.y, 0),
+                        None => pos(MISSING_SOURCE, MISSING_SOURCE, 0),
+                    };
+                }
+                (xwiimote_sys::XWII_EVENT_IR, xwii_event_union { abs })
+            }
+            #[cfg(feature = "balance-board")]
+            Event::BalanceBoard(weights) => {
+                for (ix, weight) in weights.into_iter().enumerate() {
+                    abs[ix] = pos(weight, 0, 0);
+                }
+                (
+                    xwiimote_sys::XWII_EVENT_BALANCE_BOARD,
+                    xwii_event_union { abs },
+                )
+            }
+            Event::MotionPlus { x, y, z } => {
+                abs[0] = pos(x, y, z);
+                (
+                    xwiimote_sys::XWII_EVENT_MOTION_PLUS,
+                    xwii_event_union { abs },
+                )
+            }
+            #[cfg(feature = "pro-controller")]
+            Event::ProControllerKey(code, state) => (
+                xwiimote_sys::XWII_EVENT_PRO_CONTROLLER_KEY,
+                key(code as u32, state),
+            ),
+            #[cfg(feature = "pro-controller")]
+            Event::ProControllerMove {
+                left_x,
+                left_y,
+                right_x,
+                right_y,
+            } => {
+                abs[0] = pos(left_x, left_y, 0);
+                abs[1] = pos(right_x, right_y, 0);
+                (
+                    xwiimote_sys::XWII_EVENT_PRO_CONTROLLER_MOVE,
+                    xwii_event_union { abs },
+                )
+            }
+            Event::Other => (xwiimote_sys::XWII_EVENT_WATCH, Default::default()),
+            Event::Dropped { count_estimate } => {
+                abs[0] = pos(count_estimate.min(i32::MAX as u32) as i32, 0, 0);
+                (XWII_EVENT_DROPPED, xwii_event_union { abs })
+            }
+            Event::Reopened(channels) => {
+                abs[0] = pos(channels.bits() as i32, 0, 0);
+                (XWII_EVENT_REOPENED, xwii_event_union { abs })
+            }
+            #[cfg(feature = "classic")]
+            Event::ClassicControllerKey(code, state) => (
+                xwiimote_sys::XWII_EVENT_CLASSIC_CONTROLLER_KEY,
+                key(code as u32, state),
+            ),
+            #[cfg(feature = "classic")]
+            Event::ClassicControllerMove {
+                left_x,
+                left_y,
+                right_x,
+                right_y,
+                left_trigger,
+                right_trigger,
+            } => {
+                abs[0] = pos(left_x, left_y, 0);
+                abs[1] = pos(right_x, right_y, 0);
+                abs[2] = pos(left_trigger as i32, right_trigger as i32, 0);
+                (
+                    xwiimote_sys::XWII_EVENT_CLASSIC_CONTROLLER_MOVE,
+                    xwii_event_union { abs },
+                )
+            }
+            #[cfg(feature = "nunchuk")]
+            Event::NunchukKey(code, state) => (
+                xwiimote_sys::XWII_EVENT_NUNCHUK_KEY,
+                key(code as u32, state),
+            ),
+            #[cfg(feature = "nunchuk")]
+            Event::NunchukMove {
+                x,
+                y,
+                x_acceleration,
+                y_acceleration,
+            } => {
+                abs[0] = pos(x, y, 0);
+                abs[1] = pos(x_acceleration, y_acceleration, 0);
+                (
+                    xwiimote_sys::XWII_EVENT_NUNCHUK_MOVE,
+                    xwii_event_union { abs },
+                )
+            }
+            #[cfg(feature = "drums")]
+            Event::DrumsKey(code, state) => {
+                (xwiimote_sys::XWII_EVENT_DRUMS_KEY, key(code as u32, state))
+            }
+            #[cfg(feature = "drums")]
+            Event::DrumsMove {
+                x,
+                y,
+                cymbal_left,
+                cymbal_right,
+                tom_left,
+                tom_right,
+                tom_far_right,
+                bass,
+                hi_hat,
+            } => {
+                use xwiimote_sys::{
+                    XWII_DRUMS_ABS_BASS, XWII_DRUMS_ABS_CYMBAL_LEFT, XWII_DRUMS_ABS_CYMBAL_RIGHT,
+                    XWII_DRUMS_ABS_HI_HAT, XWII_DRUMS_ABS_PAD, XWII_DRUMS_ABS_TOM_FAR_RIGHT,
+                    XWII_DRUMS_ABS_TOM_LEFT, XWII_DRUMS_ABS_TOM_RIGHT,
+                };
+                abs[XWII_DRUMS_ABS_PAD as usize] = pos(x, y, 0);
+                for (ix, pressure) in [
+                    (XWII_DRUMS_ABS_CYMBAL_LEFT, cymbal_left),
+                    (XWII_DRUMS_ABS_CYMBAL_RIGHT, cymbal_right),
+                    (XWII_DRUMS_ABS_TOM_LEFT, tom_left),
+                    (XWII_DRUMS_ABS_TOM_RIGHT, tom_right),
+                    (XWII_DRUMS_ABS_TOM_FAR_RIGHT, tom_far_right),
+                    (XWII_DRUMS_ABS_BASS, bass),
+                    (XWII_DRUMS_ABS_HI_HAT, hi_hat),
+                ] {
+                    abs[ix as usize] = pos(pressure, 0, 0);
+                }
+                (
+                    xwiimote_sys::XWII_EVENT_DRUMS_MOVE,
+                    xwii_event_union { abs },
+                )
+            }
+            #[cfg(feature = "guitar")]
+            Event::GuitarKey(code, state) => {
+                (xwiimote_sys::XWII_EVENT_GUITAR_KEY, key(code as u32, state))
+            }
+            #[cfg(feature = "guitar")]
+            Event::GuitarMove {
+                x,
+                y,
+                whammy_bar,
+                fret_bar,
+            } => {
+                abs[0] = pos(x, y, 0);
+                abs[1] = pos(whammy_bar, 0, 0);
+                abs[2] = pos(fret_bar.unwrap_or(FRET_BAR_UNTOUCHED), 0, 0);
+                (
+                    xwiimote_sys::XWII_EVENT_GUITAR_MOVE,
+                    xwii_event_union { abs },
+                )
+            }
+        };
+        xwii_event {
+            time,
+            type_,
+            v: payload,
+        }
+    }
+}
+
+/// Counts the events that the streams of a [`Device`] read from the
+/// kernel, in order to diagnose delayed events.
+///
+/// See [`Device::event_counters`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventCounters {
+    /// The number of events read.
+    pub events: u64,
+    /// The number of times the streams drained the event queue of
+    /// the kernel, i.e. read events until none was left.
+    pub batches: u64,
+    /// The largest number of events read in a single batch. Large
+    /// batches mean that the events queued up in the kernel, i.e.
+    /// the application did not poll the stream often enough.
+    pub max_batch: u64,
+    /// The estimated number of lost reports; only counted if drop
+    /// detection is enabled (see [`Device::set_drop_detection`]).
+    pub dropped: u64,
+    /// The longest time between the generation of an event by the
+    /// kernel and its reading by a stream.
+    pub max_delay: Duration,
+    /// The number of times a [`Watchdog`] reopened stalled channels.
+    pub reopens: u64,
+}
+
+impl EventCounters {
+    /// Records an event generated at `time`, which was read now.
+    fn record(&mut self, time: SystemTime, batch: u64) {
+        self.events += 1;
+        self.max_batch = self.max_batch.max(batch);
+        let delay = SystemTime::now().duration_since(time).unwrap_or_default();
+        self.max_delay = self.max_delay.max(delay);
     }
 }
 
+/// The rates of the events received through each channel of a [`Device`]
+/// over the last few seconds.
+///
+/// A sensor channel that is open but produces no events is likely stuck,
+/// and reopening it may revive it; the rate of the core channel is zero
+/// while no button is pressed, though. See [`Device::channel_stats`].
+#[derive(Clone, Debug, Default)]
+pub struct ChannelStats {
+    meters: Vec<(Channels, RateMeter)>,
+}
+
+/// Counts the events of a channel per second, in a ring of buckets.
+#[derive(Copy, Clone, Debug, Default)]
+struct RateMeter {
+    /// The number of events received in each second, and the time
+    /// of that second in seconds since the Unix epoch.
+    buckets: [(u32, u64); ChannelStats::WINDOW_SECS as usize],
+    /// The time of the last event.
+    last: Option<SystemTime>,
+}
+
+impl ChannelStats {
+    /// The length of the sliding window over which rates are computed.
+    pub const WINDOW: Duration = Duration::from_secs(Self::WINDOW_SECS);
+    const WINDOW_SECS: u64 = 5;
+
+    /// Returns the number of events per second received through `channel`
+    /// during the last [`ChannelStats::WINDOW`].
+    pub fn rate(&self, channel: Channels) -> f32 {
+        self.rate_at(channel, SystemTime::now())
+    }
+
+    /// Returns the time of the last event received through `channel`,
+    /// if any.
+    pub fn last_event(&self, channel: Channels) -> Option<SystemTime> {
+        self.meter(channel).and_then(|meter| meter.last)
+    }
+
+    /// Returns those of the given channels that produced no events during
+    /// the last [`ChannelStats::WINDOW`].
+    pub fn idle(&self, channels: Channels) -> Channels {
+        let now = SystemTime::now();
+        channels
+            .iter()
+            .filter(|&channel| self.rate_at(channel, now) == 0.0)
+            .collect()
+    }
+
+    fn meter(&self, channel: Channels) -> Option<&RateMeter> {
+        self.meters
+            .iter()
+            .find(|(other, _)| *other == channel)
+            .map(|(_, meter)| meter)
+    }
+
+    /// Computes the rate over the complete seconds of the window
+    /// that ends at `now`.
+    fn rate_at(&self, channel: Channels, now: SystemTime) -> f32 {
+        let Some(meter) = self.meter(channel) else {
+            return 0.0;
+        };
+        let now = unix_secs(now);
+        let first = now.saturating_sub(Self::WINDOW_SECS);
+        let count: u32 = meter
+            .buckets
+            .iter()
+            .filter(|(_, sec)| (first..now).contains(sec))
+            .map(|(count, _)| count)
+            .sum();
+        count as f32 / Self::WINDOW_SECS as f32
+    }
+
+    /// Records an event of the given raw type, generated at `time`.
+    pub(crate) fn record(&mut self, type_: u32, time: SystemTime) {
+        let Some(channel) = event_channel(type_) else {
+            return;
+        };
+        let ix = match self.meters.iter().position(|(other, _)| *other == channel) {
+            Some(ix) => ix,
+            None => {
+                self.meters.push((channel, RateMeter::default()));
+                self.meters.len() - 1
+            }
+        };
+        let meter = &mut self.meters[ix].1;
+        let sec = unix_secs(time);
+        let bucket = &mut meter.buckets[(sec % Self::WINDOW_SECS) as usize];
+        if bucket.1 != sec {
+            *bucket = (0, sec);
+        }
+        bucket.0 += 1;
+        meter.last = meter.last.max(Some(time));
+    }
+}
+
+/// Returns the number of whole seconds since the Unix epoch.
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Returns the channel through which events of the given raw type
+/// are received.
+fn event_channel(type_: u32) -> Option<Channels> {
+    use xwiimote_sys::*;
+    Some(match type_ {
+        XWII_EVENT_KEY => Channels::CORE,
+        XWII_EVENT_ACCEL => Channels::ACCELEROMETER,
+        XWII_EVENT_IR => Channels::IR,
+        XWII_EVENT_BALANCE_BOARD => Channels::BALANCE_BOARD,
+        XWII_EVENT_MOTION_PLUS => Channels::MOTION_PLUS,
+        XWII_EVENT_PRO_CONTROLLER_KEY | XWII_EVENT_PRO_CONTROLLER_MOVE => Channels::PRO_CONTROLLER,
+        XWII_EVENT_CLASSIC_CONTROLLER_KEY | XWII_EVENT_CLASSIC_CONTROLLER_MOVE => {
+            Channels::CLASSIC_CONTROLLER
+        }
+        XWII_EVENT_NUNCHUK_KEY | XWII_EVENT_NUNCHUK_MOVE => Channels::NUNCHUK,
+        XWII_EVENT_DRUMS_KEY | XWII_EVENT_DRUMS_MOVE => Channels::DRUMS,
+        XWII_EVENT_GUITAR_KEY | XWII_EVENT_GUITAR_MOVE => Channels::GUITAR,
+        _ => return None,
+    })
+}
+
 /// Watches for events from a [`Device`].
 ///
 /// The kinds of streamed events depend on the open channels with
 /// the device. See the description of each [`EventKind`] variant
 /// for the channels needed to receive events of a certain kind.
 pub(crate) struct EventStream<'d> {
-    device: &'d Device,
+    device: DeviceRef<'d>,
     /// Raw buffer for incoming events.
     last_event: xwii_event,
     /// Whether the `epoll` interest is currently registered.
     /// Used to prevent a double-close when dropping the stream.
     have_interest: bool,
+    /// Delays the watch events, if enabled.
+    debounce: Option<Debounce>,
+    /// Detects the lost motion reports, if enabled.
+    drops: Option<DropDetector>,
+    /// The frame in which the sensor data is reported.
+    frame: CoordinateFrame,
+    /// An event to produce before reading the next one, if any.
+    pending: Option<(Event, SystemTime)>,
+    /// The number of events read since the kernel queue was last empty.
+    batch: u64,
+    /// Applies the battery policy of the device, if any.
+    throttle: Option<Throttle>,
+    /// Checks the watched channels for stalls, if enabled.
+    watchdog: Option<WatchdogTimer>,
+    /// Tries to open the channels of the auto-reopen policy again,
+    /// if the last attempt failed.
+    reopen_retry: Option<ReopenRetry>,
+}
+
+/// A device that an [`EventStream`] borrows or owns.
+enum DeviceRef<'d> {
+    Borrowed(&'d Device),
+    Owned(Arc<Device>),
+}
+
+impl Deref for DeviceRef<'_> {
+    type Target = Device;
+
+    fn deref(&self) -> &Device {
+        match self {
+            DeviceRef::Borrowed(device) => device,
+            DeviceRef::Owned(device) => device,
+        }
+    }
+}
+
+/// A pending retry of the auto-reopen policy of an event stream.
+struct ReopenRetry {
+    /// Expires when the channels should be tried again.
+    timer: Sleep,
+    /// The number of attempts left after the next one.
+    retries: u32,
+    interval: Duration,
+    /// The time of the watch event that triggered the first attempt.
+    time: SystemTime,
+}
+
+/// The state of the [`Watchdog`] of an event stream.
+struct WatchdogTimer {
+    watchdog: Watchdog,
+    /// Expires when the channels should be checked again.
+    timer: Sleep,
+    /// The time from which the channels without events are considered
+    /// stalled, i.e. when the stream started or the channels were reopened.
+    since: SystemTime,
+}
+
+impl WatchdogTimer {
+    fn new(watchdog: Watchdog) -> Result<Self> {
+        Ok(Self {
+            timer: Sleep::new(Self::check_interval(&watchdog))?,
+            watchdog,
+            since: SystemTime::now(),
+        })
+    }
+
+    /// Checks the channels twice per timeout.
+    fn check_interval(watchdog: &Watchdog) -> Duration {
+        (watchdog.timeout / 2).max(Duration::from_millis(10))
+    }
+
+    /// Returns the watched channels among `open` that produced
+    /// no events for the timeout, as of `now`.
+    fn stalled(&self, open: Channels, stats: &ChannelStats, now: SystemTime) -> Channels {
+        let watched = self.watchdog.channels.difference(Channels::CORE) & open;
+        watched
+            .iter()
+            .filter(|&channel| {
+                let last = stats
+                    .last_event(channel)
+                    .map_or(self.since, |last| last.max(self.since));
+                now.duration_since(last)
+                    .is_ok_and(|idle| idle >= self.watchdog.timeout)
+            })
+            .collect()
+    }
+}
+
+/// Delays the watch events ([`Event::Other`]) until the set of available
+/// channels stays unchanged for a given time, and drops them if the set
+/// returns to its previous state.
+struct Debounce {
+    window: Duration,
+    /// The channels that were available when the last watch event
+    /// was reported.
+    reported: Channels,
+    /// The timer that expires once the availability is stable,
+    /// and the time of the last watch event, if a change is pending.
+    pending: Option<(Sleep, SystemTime)>,
+}
+
+/// Estimates the number of motion reports lost between two consecutive
+/// events of the same kind.
+struct DropDetector {
+    /// The nominal interval between two reports.
+    period: Duration,
+    /// The time of the last motion event of each raw type.
+    last: [Option<SystemTime>; XWII_EVENT_NUM as usize],
+}
+
+impl DropDetector {
+    /// The smallest number of missing periods that is reported as a gap.
+    const MIN_MISSING: f64 = 3.0;
+
+    fn new(period: Duration) -> Self {
+        Self {
+            period,
+            last: [None; XWII_EVENT_NUM as usize],
+        }
+    }
+
+    /// Records an event of the given raw type, and returns the estimated
+    /// number of reports lost since the previous event of that type.
+    fn check(&mut self, type_: u32, time: SystemTime) -> Option<u32> {
+        use xwiimote_sys::XWII_EVENT_WATCH;
+        if type_ == XWII_EVENT_WATCH {
+            // Another channel may have been opened or closed in the meantime.
+            self.last = [None; XWII_EVENT_NUM as usize];
+            return None;
+        }
+        if !is_motion(type_) {
+            // Key events are only sent when the state of a key changes.
+            return None;
+        }
+        let last = self.last.get_mut(type_ as usize)?.replace(time)?;
+        let gap = time.duration_since(last).ok()?;
+        let periods = (gap.as_secs_f64() / self.period.as_secs_f64()).round();
+        (periods >= Self::MIN_MISSING).then(|| (periods - 1.0).min(u32::MAX as f64) as u32)
+    }
+}
+
+/// Checks whether an event of the given raw type reports the readings
+/// of a sensor, which the device sends periodically.
+fn is_motion(type_: u32) -> bool {
+    use xwiimote_sys::*;
+    matches!(
+        type_,
+        XWII_EVENT_ACCEL
+            | XWII_EVENT_IR
+            | XWII_EVENT_MOTION_PLUS
+            | XWII_EVENT_BALANCE_BOARD
+            | XWII_EVENT_NUNCHUK_MOVE
+            | XWII_EVENT_CLASSIC_CONTROLLER_MOVE
+            | XWII_EVENT_PRO_CONTROLLER_MOVE
+            | XWII_EVENT_DRUMS_MOVE
+            | XWII_EVENT_GUITAR_MOVE
+    )
+}
+
+/// Applies a [`BatteryPolicy`] to the events of a device.
+struct Throttle {
+    policy: BatteryPolicy,
+    /// The time of the last battery reading, if any.
+    checked: Option<SystemTime>,
+    /// Receives the battery level from the thread that reads it,
+    /// while a reading is in progress.
+    reading: Option<Receiver<Result<u8>>>,
+    /// The minimum interval between two motion events of the same kind.
+    coalesce: Option<Duration>,
+    /// The time of the last reported motion event of each raw type.
+    last: [Option<SystemTime>; XWII_EVENT_NUM as usize],
+    /// The latest motion event of each raw type that arrived too soon
+    /// after the last reported one, if any.
+    held: [Option<(Event, SystemTime)>; XWII_EVENT_NUM as usize],
+    /// Expires once the first held event is due, if any.
+    timer: Option<Sleep>,
+}
+
+impl Throttle {
+    /// The interval between two readings of the battery level.
+    const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+    fn new(policy: BatteryPolicy) -> Self {
+        Self {
+            policy,
+            checked: None,
+            reading: None,
+            coalesce: None,
+            last: [None; XWII_EVENT_NUM as usize],
+            held: [None; XWII_EVENT_NUM as usize],
+            timer: None,
+        }
+    }
+
+    /// Records an event of the given raw type, and returns it if it
+    /// should be reported now.
+    ///
+    /// Reads the battery level of `device` now and then, and closes
+    /// the channels that the policy turns off at that level.
+    fn check(
+        &mut self,
+        device: &Device,
+        type_: u32,
+        event: (Event, SystemTime),
+    ) -> Result<Option<(Event, SystemTime)>> {
+        self.read_battery(device, event.1);
+        self.coalesce(type_, event)
+    }
+
+    /// Applies the last battery reading, and starts the next one once
+    /// it is due.
+    ///
+    /// The level is read on a separate thread, since the `sysfs` read
+    /// may block while the device disconnects.
+    fn read_battery(&mut self, device: &Device, time: SystemTime) {
+        if let Some(reading) = &self.reading {
+            match reading.try_recv() {
+                Ok(Ok(level)) => {
+                    let (closed, coalesce) = self.policy.throttle(level);
+                    self.coalesce = coalesce;
+                    let open = device.get_open() & closed;
+                    if !open.is_empty() {
+                        device.close_throttled(open);
+                    }
+                }
+                Err(TryRecvError::Empty) => return,
+                // The battery level cannot be read while the device
+                // disconnects; the stream reports the disconnection
+                // soon anyway.
+                Ok(Err(_)) | Err(TryRecvError::Disconnected) => {}
+            }
+            self.reading = None;
+        }
+
+        let due = self.checked.is_none_or(|checked| {
+            time.duration_since(checked)
+                .is_ok_and(|elapsed| elapsed >= Self::CHECK_INTERVAL)
+        });
+        if due {
+            self.checked = Some(time);
+            let observer = device.observer();
+            let (level, reading) = mpsc::channel();
+            let spawned = thread::Builder::new()
+                .name("xwiimote-battery".to_owned())
+                .spawn(move || {
+                    let _ = level.send(observer.battery());
+                });
+            self.reading = spawned.ok().map(|_| reading);
+        }
+    }
+
+    /// Holds back the motion events that arrive sooner than the coalesce
+    /// interval after the last reported event of the same type, keeping
+    /// only the latest one until the interval elapses.
+    fn coalesce(
+        &mut self,
+        type_: u32,
+        event: (Event, SystemTime),
+    ) -> Result<Option<(Event, SystemTime)>> {
+        let Some(interval) = self.coalesce.filter(|_| is_motion(type_)) else {
+            return Ok(Some(event));
+        };
+        let ix = type_ as usize;
+        let Some(last) = self.last.get_mut(ix) else {
+            return Ok(Some(event));
+        };
+        match *last {
+            Some(prev) if event.1.duration_since(prev).is_ok_and(|gap| gap < interval) => {
+                self.held[ix] = Some(event);
+                self.rearm()?;
+                Ok(None)
+            }
+            _ => {
+                // The newer event supersedes the held one, if any.
+                *last = Some(event.1);
+                self.held[ix] = None;
+                Ok(Some(event))
+            }
+        }
+    }
+
+    /// Returns the time at which the event held for the given raw type
+    /// is due, if any.
+    fn due(&self, ix: usize) -> Option<SystemTime> {
+        self.held[ix]?;
+        let interval = self.coalesce.unwrap_or_default();
+        self.last[ix].map(|prev| prev + interval)
+    }
+
+    /// Arms the timer to expire once the first held event is due.
+    fn rearm(&mut self) -> Result<()> {
+        let Some(due) = (0..self.held.len()).filter_map(|ix| self.due(ix)).min() else {
+            self.timer = None;
+            return Ok(());
+        };
+        let wait = due
+            .duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO);
+        match &mut self.timer {
+            Some(timer) => timer.reset(wait),
+            None => {
+                self.timer = Some(Sleep::new(wait)?);
+                Ok(())
+            }
+        }
+    }
+
+    /// Produces a held event once it is due; otherwise arranges for
+    /// `wake` to be called once the first one is.
+    fn poll_held(&mut self, cx: &mut Context<'_>) -> Result<Option<(Event, SystemTime)>> {
+        let Some(timer) = &mut self.timer else {
+            return Ok(None);
+        };
+        if Pin::new(timer).poll(cx)?.is_pending() {
+            return Ok(None);
+        }
+        let now = SystemTime::now();
+        let released = (0..self.held.len())
+            .filter_map(|ix| self.due(ix).map(|due| (due, ix)))
+            .filter(|(due, _)| *due <= now)
+            .min()
+            .and_then(|(due, ix)| {
+                // Keep the pace of the reported events.
+                self.last[ix] = Some(due);
+                self.held[ix].take()
+            });
+        self.rearm()?;
+        Ok(released)
+    }
 }
 
 impl<'d> EventStream<'d> {
@@ -467,24 +1406,202 @@ impl<'d> EventStream<'d> {
 
     /// Creates a new stream over the events from the device.
     pub fn new(device: &'d Device) -> Result<Self> {
+        Self::with_device(DeviceRef::Borrowed(device))
+    }
+
+    fn with_device(device: DeviceRef<'d>) -> Result<Self> {
         // Watch the fd descriptor for read availability to avoid busy-waiting.
-        let fd = unsafe { xwii_iface_get_fd(device.handle) };
+        let fd = device.with_handle(|handle| unsafe { xwii_iface_get_fd(handle) });
         let interest = Interest::new(fd, Self::EPOLL_EVENTS);
         Reactor::get().add_interest(&interest)?;
 
+        let debounce = lock(&device.watch_debounce).map(|window| Debounce {
+            window,
+            reported: device.available(),
+            pending: None,
+        });
+        let drops = lock(&device.drop_detection).map(DropDetector::new);
+        let frame = device.coordinate_frame();
+        let throttle = lock(&device.battery_policy).clone().map(Throttle::new);
+        let watchdog = lock(&device.watchdog).map(WatchdogTimer::new).transpose()?;
         Ok(Self {
             device,
             last_event: Default::default(),
             have_interest: true,
+            debounce,
+            drops,
+            frame,
+            pending: None,
+            batch: 0,
+            throttle,
+            watchdog,
+            reopen_retry: None,
         })
     }
 
+    /// Records a watch event received at the given time, restarting
+    /// the debounce window.
+    ///
+    /// Returns `false` if debouncing is disabled.
+    fn delay_watch_event(&mut self, time: SystemTime) -> Result<bool> {
+        let Some(debounce) = &mut self.debounce else {
+            return Ok(false);
+        };
+        match &mut debounce.pending {
+            Some((sleep, last_time)) => {
+                sleep.reset(debounce.window)?;
+                *last_time = time;
+            }
+            None => debounce.pending = Some((Sleep::new(debounce.window)?, time)),
+        }
+        Ok(true)
+    }
+
+    /// Checks whether the debounce window of a pending watch event
+    /// elapsed, and returns the event if the availability changed.
+    ///
+    /// Otherwise arranges for `wake` to be called once the window elapses.
+    fn poll_debounced(&mut self, cx: &mut Context<'_>) -> Result<Option<(Event, SystemTime)>> {
+        let Some(debounce) = &mut self.debounce else {
+            return Ok(None);
+        };
+        let Some((sleep, time)) = &mut debounce.pending else {
+            return Ok(None);
+        };
+        match Pin::new(sleep).poll(cx) {
+            Poll::Ready(result) => {
+                result?;
+                let time = *time;
+                debounce.pending = None;
+                let available = self.device.available();
+                if available != debounce.reported {
+                    debounce.reported = available;
+                    return Ok(Some((Event::Other, time)));
+                }
+                // The extension bounced back to its previous state.
+                Ok(None)
+            }
+            Poll::Pending => Ok(None),
+        }
+    }
+
+    /// Opens the channels of the auto-reopen policy of the device that
+    /// became available, after a watch event reported at `time`, and
+    /// queues the notification of those that were opened.
+    ///
+    /// On failure, the channels are tried again by [`Self::poll_reopen`].
+    fn reopen_available(&mut self, time: SystemTime) -> Result<()> {
+        match self.device.reopen_available() {
+            Ok(channels) => {
+                self.reopen_retry = None;
+                if !channels.is_empty() {
+                    self.pending = Some((Event::Reopened(channels), time));
+                }
+            }
+            Err(_) => {
+                let retry = lock(&self.device.open_retry).unwrap_or_default();
+                self.reopen_retry = Some(ReopenRetry {
+                    timer: Sleep::new(retry.interval)?,
+                    retries: retry.retries,
+                    interval: retry.interval,
+                    time,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Tries to open the channels of the auto-reopen policy again if the
+    /// retry timer expired, and returns the notification of those that
+    /// were opened. Otherwise arranges for `wake` to be called once the
+    /// timer expires.
+    fn poll_reopen(&mut self, cx: &mut Context<'_>) -> Result<Option<(Event, SystemTime)>> {
+        loop {
+            let Some(retry) = &mut self.reopen_retry else {
+                return Ok(None);
+            };
+            if Pin::new(&mut retry.timer).poll(cx)?.is_pending() {
+                return Ok(None);
+            }
+            // The interfaces may have appeared without a watch event yet.
+            self.device.refresh_interfaces();
+            match self.device.reopen_available() {
+                Ok(channels) => {
+                    let time = retry.time;
+                    self.reopen_retry = None;
+                    return Ok((!channels.is_empty()).then_some((Event::Reopened(channels), time)));
+                }
+                Err(_) if retry.retries > 0 => {
+                    retry.retries -= 1;
+                    retry.timer.reset(retry.interval)?;
+                }
+                Err(_) => {
+                    // Give up until the next watch event.
+                    self.reopen_retry = None;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    /// Reports a watch event received at the given time, unless it is
+    /// delayed by the debounce window, and opens the channels of the
+    /// auto-reopen policy that became available.
+    fn watch_event(&mut self, time: SystemTime) -> Option<Result<(Event, SystemTime)>> {
+        match self.delay_watch_event(time) {
+            // Report the event once the debounce window elapses.
+            Ok(true) => None,
+            Ok(false) => {
+                self.device.broadcast.send((Event::Other, time));
+                Some(self.reopen_available(time).map(|()| (Event::Other, time)))
+            }
+            Err(err) => Some(Err(err)),
+        }
+    }
+
+    /// Reacts to the channels that stalled, if the check of the watchdog
+    /// is due; otherwise arranges for `wake` to be called once it is.
+    fn poll_watchdog(&mut self, cx: &mut Context<'_>) -> Result<()> {
+        let Some(state) = &mut self.watchdog else {
+            return Ok(());
+        };
+        while Pin::new(&mut state.timer).poll(cx).is_ready() {
+            state
+                .timer
+                .reset(WatchdogTimer::check_interval(&state.watchdog))?;
+            let now = SystemTime::now();
+            let open = self.device.get_open();
+            let stalled = state.stalled(open, &lock(&self.device.stats), now);
+            if stalled.is_empty() {
+                continue;
+            }
+            if state.watchdog.action == WatchdogAction::Reconnect
+                || self.device.reopen_stalled(stalled).is_err()
+            {
+                return Err(Error::Stalled(stalled));
+            }
+            state.since = now;
+        }
+        Ok(())
+    }
+
+    /// Produces a motion event held back by the battery policy once it
+    /// is due, if any.
+    fn poll_held(&mut self, cx: &mut Context<'_>) -> Result<Option<(Event, SystemTime)>> {
+        match &mut self.throttle {
+            Some(throttle) => throttle.poll_held(cx),
+            None => Ok(None),
+        }
+    }
+
     /// Removes interest for the [`Device`] file events.
     fn remove_interest(&mut self) -> Result<()> {
         if self.have_interest {
             self.have_interest = false;
 
-            let fd = unsafe { xwii_iface_get_fd(self.device.handle) };
+            let fd = self
+                .device
+                .with_handle(|handle| unsafe { xwii_iface_get_fd(handle) });
             let interest = Interest::new(fd, Self::EPOLL_EVENTS);
             Reactor::get().remove_interest(&interest)
         } else {
@@ -501,46 +1618,593 @@ impl Stream for EventStream<'_> {
             // We stop reading events once a disconnect event is received.
             return Poll::Ready(None);
         }
-
-        // Attempt to read a single incoming event.
-        let res_code = unsafe {
-            xwii_iface_dispatch(
-                self.device.handle,
-                &mut self.last_event,
-                mem::size_of::<xwii_event>(),
-            )
-        };
-
-        const PENDING: c_int = -libc::EAGAIN;
-        let result = match res_code {
-            0 => {
-                if self.last_event.type_ == XWII_EVENT_GONE {
-                    // We were watching for hot-plug events, and the device
-                    // was closed. No more events are coming.
-                    self.remove_interest().err().map(Err)
-                } else {
-                    let event = unsafe { Event::parse(&self.last_event) };
-                    Some(Ok(event))
+        match self.pending.take() {
+            // A watch event that revealed a gap in the motion reports.
+            Some((Event::Other, time)) => {
+                if let Some(res) = self.watch_event(time) {
+                    return Poll::Ready(Some(res));
                 }
             }
-            PENDING => {
-                // No event is available, arrange for `wake` to be called once
-                // an event is available.
-                let fd = unsafe { xwii_iface_get_fd(self.device.handle) };
-                let interest = Interest::new(fd, Self::EPOLL_EVENTS);
-                Reactor::get().set_callback(interest, cx.waker().clone());
-                return Poll::Pending;
+            Some(event) => {
+                self.device.broadcast.send(event);
+                return Poll::Ready(Some(Ok(event)));
             }
-            // Failure, perhaps the device was disconnected.
-            _ => Some(Err(io::Error::last_os_error())),
-        };
-        Poll::Ready(result)
+            None => {}
+        }
+        if let Err(err) = self.poll_watchdog(cx) {
+            return Poll::Ready(Some(Err(err)));
+        }
+
+        match self.poll_debounced(cx) {
+            Ok(Some(event)) => {
+                self.device.broadcast.send(event);
+                return Poll::Ready(Some(self.reopen_available(event.1).map(|()| event)));
+            }
+            Ok(None) => {}
+            Err(err) => return Poll::Ready(Some(Err(err))),
+        }
+        match self.poll_reopen(cx) {
+            Ok(Some(event)) => {
+                self.device.broadcast.send(event);
+                return Poll::Ready(Some(Ok(event)));
+            }
+            Ok(None) => {}
+            Err(err) => return Poll::Ready(Some(Err(err))),
+        }
+        match self.poll_held(cx) {
+            Ok(Some(event)) => {
+                self.device.broadcast.send(event);
+                return Poll::Ready(Some(Ok(event)));
+            }
+            Ok(None) => {}
+            Err(err) => return Poll::Ready(Some(Err(err))),
+        }
+
+        loop {
+            // Attempt to read a single incoming event.
+            let this = &mut *self;
+            let res_code = this.device.dispatch(&mut this.last_event);
+
+            const PENDING: c_int = -libc::EAGAIN;
+            let result = match res_code {
+                0 => {
+                    if self.last_event.type_ == XWII_EVENT_GONE {
+                        // We were watching for hot-plug events, and the device
+                        // was closed. No more events are coming.
+                        self.device.broadcast.close(None);
+                        self.remove_interest().err().map(Err)
+                    } else {
+                        // SAFETY: the event was filled by `xwii_iface_dispatch`.
+                        let event = Event::parse(unsafe { RawEvent::new(&self.last_event) })
+                            .map(|(event, time)| (self.frame.event(event), time));
+                        let type_ = self.last_event.type_;
+                        if let Some((_, time)) = event {
+                            self.batch += 1;
+                            self.device.update_counters(|c| c.record(time, self.batch));
+                            lock(&self.device.stats).record(type_, time);
+                        }
+                        let dropped = match (&mut self.drops, event) {
+                            (Some(drops), Some((_, time))) => drops
+                                .check(type_, time)
+                                .map(|count_estimate| (Event::Dropped { count_estimate }, time)),
+                            _ => None,
+                        };
+                        let this = &mut *self;
+                        let event = match (&mut this.throttle, event) {
+                            (Some(throttle), Some(event)) => {
+                                match throttle.check(&this.device, type_, event) {
+                                    Ok(event) => event,
+                                    Err(err) => return Poll::Ready(Some(Err(err))),
+                                }
+                            }
+                            _ => event,
+                        };
+                        if let Some(dropped) = dropped {
+                            if let (Event::Dropped { count_estimate }, _) = dropped {
+                                let count = u64::from(count_estimate);
+                                self.device.update_counters(|c| c.dropped += count);
+                            }
+                            // Report the gap first, and then the event that revealed it
+                            // unless it was discarded.
+                            self.pending = event;
+                            self.device.broadcast.send(dropped);
+                            return Poll::Ready(Some(Ok(dropped)));
+                        }
+                        match event {
+                            Some((Event::Other, time)) => match self.watch_event(time) {
+                                Some(res) => Some(res),
+                                None => continue,
+                            },
+                            Some(event) => {
+                                self.device.broadcast.send(event);
+                                Some(Ok(event))
+                            }
+                            None => continue, // unsupported event, read the next one
+                        }
+                    }
+                }
+                PENDING => {
+                    if self.batch > 0 {
+                        self.batch = 0;
+                        self.device.update_counters(|c| c.batches += 1);
+                    }
+                    // No event is available, arrange for `wake` to be called once
+                    // an event is available.
+                    let fd = self
+                        .device
+                        .with_handle(|handle| unsafe { xwii_iface_get_fd(handle) });
+                    let interest = Interest::new(fd, Self::EPOLL_EVENTS);
+                    Reactor::get().set_callback(interest, cx.waker().clone())?;
+                    // Also wake up once the pending watch event, if any,
+                    // should be reported.
+                    match self.poll_debounced(cx) {
+                        Ok(Some(event)) => {
+                            self.device.broadcast.send(event);
+                            return Poll::Ready(Some(
+                                self.reopen_available(event.1).map(|()| event),
+                            ));
+                        }
+                        Ok(None) => {}
+                        Err(err) => return Poll::Ready(Some(Err(err))),
+                    }
+                    // Likewise for the motion events held back since.
+                    return match self.poll_held(cx) {
+                        Ok(Some(event)) => {
+                            self.device.broadcast.send(event);
+                            Poll::Ready(Some(Ok(event)))
+                        }
+                        Ok(None) => Poll::Pending,
+                        Err(err) => Poll::Ready(Some(Err(err))),
+                    };
+                }
+                // Failure, perhaps the device was disconnected.
+                _ => Some(Err(Error::from_dispatch(res_code))),
+            };
+            return Poll::Ready(result);
+        }
+    }
+}
+
+impl EventStream<'static> {
+    /// Creates a new stream over the events from the device, which
+    /// is kept alive by the stream.
+    pub fn owned(device: Arc<Device>) -> Result<Self> {
+        Self::with_device(DeviceRef::Owned(device))
     }
 }
 
 impl Drop for EventStream<'_> {
     fn drop(&mut self) {
-        self.remove_interest()
-            .expect("failed to remove interest for device fd");
+        // The stream may be dropped while unwinding, or after the device
+        // file was closed (which removes it from the `epoll` set already).
+        // Panicking would abort the process in the first case, and there
+        // is nothing to clean up in the second; ignore any error.
+        let _ = self.remove_interest();
+    }
+}
+
+/// A stream that owns a [`Device`] and produces its events, including
+/// the time at which the kernel generated them.
+///
+/// Unlike the stream returned by [`Device::events`], this stream has no
+/// lifetime parameter and can be moved into a spawned task. It is created
+/// by [`Device::into_events`], and the device is closed once the stream
+/// is dropped.
+pub struct OwnedEvents {
+    stream: EventStream<'static>,
+}
+
+impl OwnedEvents {
+    pub(crate) fn new(device: Device) -> Result<Self> {
+        Ok(Self {
+            stream: EventStream::owned(Arc::new(device))?,
+        })
+    }
+
+    /// Returns the device whose events are produced, e.g. to turn on
+    /// its LED lights or rumble motor from the task that owns the stream.
+    pub fn device(&self) -> &Device {
+        &self.stream.device
+    }
+}
+
+impl Stream for OwnedEvents {
+    type Item = Result<(Event, SystemTime)>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.stream).poll_next(cx)
+    }
+}
+
+/// Produces the type of the extension plugged to a device whenever
+/// a hot-plug event reveals that it changed.
+pub(crate) struct ExtensionChanges<S, F> {
+    events: S,
+    /// The last known extension type.
+    last: ExtensionKind,
+    /// Queries the current extension type.
+    query: F,
+}
+
+impl<S, F> ExtensionChanges<S, F>
+where
+    S: Stream<Item = Result<(Event, SystemTime)>> + Unpin,
+    F: FnMut() -> Result<ExtensionKind> + Unpin,
+{
+    pub fn new(events: S, current: ExtensionKind, query: F) -> Self {
+        Self {
+            events,
+            last: current,
+            query,
+        }
+    }
+}
+
+impl<S, F> Stream for ExtensionChanges<S, F>
+where
+    S: Stream<Item = Result<(Event, SystemTime)>> + Unpin,
+    F: FnMut() -> Result<ExtensionKind> + Unpin,
+{
+    type Item = Result<ExtensionKind>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            match Pin::new(&mut this.events).poll_next(cx) {
+                Poll::Ready(Some(Ok((Event::Other, _)))) => {
+                    let kind = match (this.query)() {
+                        Ok(kind) => kind,
+                        Err(err) => return Poll::Ready(Some(Err(err))),
+                    };
+                    // A watch event may also report a change in the channels
+                    // of the same extension, e.g. once it is initialized.
+                    if kind != this.last {
+                        this.last = kind.clone();
+                        return Poll::Ready(Some(Ok(kind)));
+                    }
+                }
+                Poll::Ready(Some(Ok(_))) => {}
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Numbers the events of a stream, counting the reports that were
+/// lost as if they had been received.
+///
+/// Each event is paired with a sequence number that starts at 0 and
+/// increases by one with every event. An [`Event::Dropped`] event gets
+/// the number of the first lost report, and the numbering resumes after
+/// the estimated number of lost reports; data-logging applications can
+/// thus store the numbers along with the events and spot the holes later.
+///
+/// # Examples
+/// ```no_run
+/// use futures_util::TryStreamExt;
+/// use std::time::Duration;
+/// use xwiimote::events::sequence;
+/// use xwiimote::{Channels, Device, Monitor};
+///
+/// # tokio_test::block_on(async {
+/// # let address = Monitor::enumerate()?.try_next().await?.unwrap();
+/// let device = Device::connect(&address)?;
+/// device.set_drop_detection(Some(Duration::from_millis(10)));
+/// device.open(Channels::CORE | Channels::ACCELEROMETER, false)?;
+///
+/// let mut events = sequence(device.events()?);
+/// while let Some((seq, event, time)) = events.try_next().await? {
+///     println!("#{seq} at {time:?}: {event:?}");
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// # }).unwrap();
+/// ```
+pub fn sequence<S>(events: S) -> Sequenced<S>
+where
+    S: Stream<Item = Result<(Event, SystemTime)>> + Unpin,
+{
+    Sequenced { events, next: 0 }
+}
+
+/// The stream returned by [`sequence`].
+///
+/// Errors from the underlying stream are passed through, and do not
+/// consume a sequence number.
+pub struct Sequenced<S> {
+    events: S,
+    /// The number of the next event.
+    next: u64,
+}
+
+impl<S> Stream for Sequenced<S>
+where
+    S: Stream<Item = Result<(Event, SystemTime)>> + Unpin,
+{
+    type Item = Result<(u64, Event, SystemTime)>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        match Pin::new(&mut this.events).poll_next(cx) {
+            Poll::Ready(Some(Ok((event, time)))) => {
+                let seq = this.next;
+                let count = match event {
+                    Event::Dropped { count_estimate } => count_estimate as u64,
+                    _ => 1,
+                };
+                this.next = seq.saturating_add(count);
+                Poll::Ready(Some(Ok((seq, event, time))))
+            }
+            Poll::Ready(Some(Err(err))) => Poll::Ready(Some(Err(err))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::battery::BatteryPolicy;
+    use crate::events::{
+        sequence, ChannelStats, DropDetector, Event, EventCounters, ExtensionChanges, IrSource,
+        Key, KeyState, OwnedEvents, RawEvent, Throttle, WatchdogTimer, XWII_EVENT_DROPPED,
+        XWII_EVENT_REOPENED,
+    };
+    use crate::{Channels, ExtensionKind, Watchdog};
+    use std::task::{Context, Waker};
+    use std::thread;
+    use std::time::{Duration, SystemTime};
+    use xwiimote_sys::{xwii_event, XWII_EVENT_ACCEL, XWII_EVENT_KEY, XWII_EVENT_NUM};
+
+    #[test]
+    fn owned_events_can_be_sent() {
+        fn assert_static<T: Send + 'static>() {}
+        assert_static::<OwnedEvents>();
+    }
+
+    #[test]
+    fn parses_converted_events() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456);
+        let raw = Event::Key(Key::Home, KeyState::AutoRepeat).to_raw(time);
+        let parsed = Event::parse(unsafe { RawEvent::new(&raw) });
+        assert!(matches!(
+            parsed,
+            Some((Event::Key(Key::Home, KeyState::AutoRepeat), parsed_time)) if parsed_time == time
+        ));
+    }
+
+    #[test]
+    fn converts_reopened_events() {
+        let raw = Event::Reopened(Channels::NUNCHUK).to_raw(SystemTime::UNIX_EPOCH);
+        let parsed = Event::parse(unsafe { RawEvent::new(&raw) });
+        assert!(matches!(
+            parsed,
+            Some((Event::Reopened(channels), _)) if channels == Channels::NUNCHUK
+        ));
+    }
+
+    #[test]
+    fn lists_ir_sources() {
+        let source = |x| Some(IrSource { x, y: 0 });
+        let event = Event::Ir([source(1), None, None, source(4)]);
+        let sources: Vec<_> = event.ir_sources().map(|(ix, s)| (ix, s.x)).collect();
+        assert_eq!(sources, [(0, 1), (3, 4)]);
+        assert_eq!(Event::Other.ir_sources().count(), 0);
+    }
+
+    #[test]
+    fn checks_the_payload_of_raw_events() {
+        use xwiimote_sys::{XWII_EVENT_ACCEL, XWII_EVENT_DRUMS_MOVE, XWII_EVENT_WATCH};
+        let raw = Event::Key(Key::A, KeyState::Down).to_raw(SystemTime::UNIX_EPOCH);
+        let key = unsafe { RawEvent::new(&raw) };
+        assert!(key.key().is_some_and(|data| data.code == Key::A as u32));
+        assert!(key.abs(0).is_none());
+
+        let mut raw = xwii_event {
+            type_: XWII_EVENT_ACCEL,
+            ..Default::default()
+        };
+        assert!(unsafe { RawEvent::new(&raw) }.key().is_none());
+        assert!(unsafe { RawEvent::new(&raw) }.abs(0).is_some());
+        assert!(unsafe { RawEvent::new(&raw) }.abs(1).is_none());
+        raw.type_ = XWII_EVENT_DRUMS_MOVE;
+        assert!(unsafe { RawEvent::new(&raw) }.abs(7).is_some());
+        raw.type_ = XWII_EVENT_WATCH;
+        assert!(unsafe { RawEvent::new(&raw) }.abs(0).is_none());
+    }
+
+    #[test]
+    fn rejects_malformed_events_without_panicking() {
+        let mut raw = xwii_event {
+            type_: XWII_EVENT_KEY,
+            ..Default::default()
+        };
+        raw.v.key.code = u32::MAX;
+        assert!(Event::parse(unsafe { RawEvent::new(&raw) }).is_none());
+
+        // Feed pseudo-random payloads and timestamps of every event type.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut random = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let synthetic = [XWII_EVENT_DROPPED, XWII_EVENT_REOPENED];
+        for type_ in (0..XWII_EVENT_NUM + 4).chain(synthetic) {
+            for _ in 0..256 {
+                let mut raw = xwii_event {
+                    type_,
+                    ..Default::default()
+                };
+                raw.time.tv_sec = random() as _;
+                raw.time.tv_usec = random() as _;
+                let mut abs = unsafe { raw.v.abs };
+                for pos in &mut abs {
+                    (pos.x, pos.y, pos.z) = (random() as i32, random() as i32, random() as i32);
+                }
+                raw.v.abs = abs;
+                let _ = Event::parse(unsafe { RawEvent::new(&raw) });
+            }
+        }
+    }
+
+    #[test]
+    fn estimates_dropped_reports() {
+        use xwiimote_sys::{XWII_EVENT_ACCEL, XWII_EVENT_KEY, XWII_EVENT_WATCH};
+        let mut detector = DropDetector::new(Duration::from_millis(10));
+        let at = |ms| SystemTime::UNIX_EPOCH + Duration::from_millis(ms);
+        assert_eq!(detector.check(XWII_EVENT_ACCEL, at(0)), None);
+        // Reports often arrive in pairs.
+        assert_eq!(detector.check(XWII_EVENT_ACCEL, at(20)), None);
+        assert_eq!(detector.check(XWII_EVENT_ACCEL, at(71)), Some(4));
+        // Key events are not periodic.
+        assert_eq!(detector.check(XWII_EVENT_KEY, at(80)), None);
+        assert_eq!(detector.check(XWII_EVENT_KEY, at(500)), None);
+        // The channels may have changed.
+        assert_eq!(detector.check(XWII_EVENT_WATCH, at(600)), None);
+        assert_eq!(detector.check(XWII_EVENT_ACCEL, at(700)), None);
+    }
+
+    #[test]
+    fn numbers_events_across_gaps() {
+        use futures_util::{stream, TryStreamExt};
+        let time = SystemTime::UNIX_EPOCH;
+        let raw = Event::Dropped { count_estimate: 5 }.to_raw(time);
+        let (dropped, _) = Event::parse(unsafe { RawEvent::new(&raw) }).unwrap();
+        assert!(matches!(dropped, Event::Dropped { count_estimate: 5 }));
+
+        let events = [Event::Other, dropped, Event::Other, Event::Other];
+        let numbered = futures_executor::block_on(
+            sequence(stream::iter(events.map(|event| Ok((event, time))))).try_collect::<Vec<_>>(),
+        )
+        .unwrap();
+        let seqs: Vec<_> = numbered.iter().map(|(seq, ..)| *seq).collect();
+        assert_eq!(seqs, [0, 1, 6, 7]);
+    }
+
+    #[test]
+    fn reports_extension_changes() {
+        use futures_util::{stream, TryStreamExt};
+        let time = SystemTime::UNIX_EPOCH;
+        let key = Event::Key(Key::A, KeyState::Down);
+        let events = [Event::Other, key, Event::Other, Event::Other, Event::Other];
+        let stream = stream::iter(events.map(|event| Ok((event, time))));
+        let mut kinds = [
+            ExtensionKind::Nunchuk,
+            ExtensionKind::Nunchuk,
+            ExtensionKind::None,
+            ExtensionKind::MotionPlus,
+        ]
+        .into_iter();
+        let changes = ExtensionChanges::new(stream, ExtensionKind::None, move || {
+            Ok(kinds.next().unwrap())
+        });
+        let changes = futures_executor::block_on(changes.try_collect::<Vec<_>>()).unwrap();
+        assert_eq!(
+            changes,
+            [
+                ExtensionKind::Nunchuk,
+                ExtensionKind::None,
+                ExtensionKind::MotionPlus
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_delayed_events() {
+        let mut counters = EventCounters::default();
+        let now = SystemTime::now();
+        counters.record(now - Duration::from_millis(40), 1);
+        counters.record(now, 2);
+        assert_eq!(counters.events, 2);
+        assert_eq!(counters.max_batch, 2);
+        assert!(counters.max_delay >= Duration::from_millis(40));
+        // Timestamps in the future do not count as delays.
+        counters.record(now + Duration::from_secs(60), 1);
+        assert!(counters.max_delay < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn coalesces_to_the_latest_motion_event() {
+        let mut throttle = Throttle::new(BatteryPolicy::new());
+        throttle.coalesce = Some(Duration::from_millis(20));
+        let accel = |x, time| (Event::Accelerometer { x, y: 0, z: 0 }, time);
+        let x = |event: Option<(Event, SystemTime)>| match event {
+            Some((Event::Accelerometer { x, .. }, _)) => Some(x),
+            _ => None,
+        };
+        let now = SystemTime::now();
+        let event = throttle.coalesce(XWII_EVENT_ACCEL, accel(1, now));
+        assert_eq!(x(event.unwrap()), Some(1));
+        for (value, delay) in [(2, 5), (3, 10)] {
+            let time = now + Duration::from_millis(delay);
+            assert!(throttle
+                .coalesce(XWII_EVENT_ACCEL, accel(value, time))
+                .unwrap()
+                .is_none());
+        }
+        // Other events are not held back.
+        let key = (Event::Key(Key::A, KeyState::Down), now);
+        assert!(throttle.coalesce(XWII_EVENT_KEY, key).unwrap().is_some());
+
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(throttle.poll_held(&mut cx).unwrap().is_none());
+        thread::sleep(Duration::from_millis(40));
+        assert_eq!(x(throttle.poll_held(&mut cx).unwrap()), Some(3));
+        assert!(throttle.poll_held(&mut cx).unwrap().is_none());
+        assert!(throttle.timer.is_none());
+    }
+
+    #[test]
+    fn computes_channel_rates() {
+        use xwiimote_sys::{XWII_EVENT_ACCEL, XWII_EVENT_IR, XWII_EVENT_WATCH};
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut stats = ChannelStats::default();
+        // 100 accelerometer reports per second for 10 seconds.
+        for ix in 0..1000 {
+            stats.record(XWII_EVENT_ACCEL, start + Duration::from_millis(10 * ix));
+        }
+        stats.record(XWII_EVENT_IR, start);
+        stats.record(XWII_EVENT_WATCH, start);
+
+        let end = start + Duration::from_secs(10);
+        assert_eq!(stats.rate_at(Channels::ACCELEROMETER, end), 100.0);
+        // The IR camera stopped reporting long ago.
+        assert_eq!(stats.rate_at(Channels::IR, end), 0.0);
+        assert_eq!(stats.last_event(Channels::IR), Some(start));
+        assert_eq!(stats.rate_at(Channels::NUNCHUK, end), 0.0);
+        let later = end + ChannelStats::WINDOW;
+        assert_eq!(stats.rate_at(Channels::ACCELEROMETER, later), 0.0);
+    }
+
+    #[test]
+    fn detects_stalled_channels() -> crate::Result<()> {
+        use xwiimote_sys::{XWII_EVENT_ACCEL, XWII_EVENT_KEY};
+        let mut state = WatchdogTimer::new(Watchdog {
+            timeout: Duration::from_secs(2),
+            channels: Channels::all(),
+            ..Watchdog::default()
+        })?;
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        state.since = start;
+        let mut stats = ChannelStats::default();
+        stats.record(XWII_EVENT_ACCEL, start + Duration::from_secs(5));
+        stats.record(XWII_EVENT_KEY, start);
+
+        let open = Channels::CORE | Channels::ACCELEROMETER | Channels::IR;
+        let at = |secs| start + Duration::from_secs(secs);
+        assert_eq!(state.stalled(open, &stats, at(1)), Channels::empty());
+        // The IR camera never reported, and the core channel is not watched.
+        assert_eq!(state.stalled(open, &stats, at(6)), Channels::IR);
+        assert_eq!(
+            state.stalled(open, &stats, at(8)),
+            Channels::ACCELEROMETER | Channels::IR
+        );
+        // The channels were just reopened.
+        state.since = at(8);
+        assert_eq!(state.stalled(open, &stats, at(9)), Channels::empty());
+        Ok(())
     }
 }
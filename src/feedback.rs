@@ -0,0 +1,557 @@
+//! Combined light and rumble patterns that signal common situations,
+//! and animations of the LED lights.
+
+use crate::timer::Sleep;
+use crate::{Channels, Device, Error, Leds, Result};
+use futures_sink::Sink;
+use std::cell::RefCell;
+use std::future::{poll_fn, Future};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+/// A step of a [`FeedbackCue`], during which the LED lights and
+/// the rumble motor keep the same state.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CueStep {
+    /// The LED lights that are on.
+    pub leds: Leds,
+    /// Whether the rumble motor is on.
+    pub rumble: bool,
+    /// How long the step lasts.
+    pub duration: Duration,
+}
+
+/// A sequence of LED light and rumble states that share a timeline,
+/// played by [`Device::play_cue`](crate::Device::play_cue).
+///
+/// The predefined cues give applications a consistent way of telling
+/// the user that a device was assigned a player number, that its
+/// battery is low, or that an error occurred.
+///
+/// # Examples
+/// A custom cue that sweeps the lights while rumbling briefly.
+/// ```
+/// use std::time::Duration;
+/// use xwiimote::feedback::FeedbackCue;
+/// use xwiimote::Leds;
+///
+/// let step = Duration::from_millis(100);
+/// let cue = FeedbackCue::new()
+///     .step(Leds::ONE, true, step)
+///     .step(Leds::TWO, false, step)
+///     .step(Leds::THREE, false, step)
+///     .step(Leds::FOUR, false, step);
+/// assert_eq!(cue.duration(), Duration::from_millis(400));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FeedbackCue {
+    steps: Vec<CueStep>,
+}
+
+impl FeedbackCue {
+    /// Creates an empty cue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a step to the cue.
+    pub fn step(mut self, leds: Leds, rumble: bool, duration: Duration) -> Self {
+        self.steps.push(CueStep {
+            leds,
+            rumble,
+            duration,
+        });
+        self
+    }
+
+    /// Returns the steps of the cue, in playing order.
+    pub fn steps(&self) -> &[CueStep] {
+        &self.steps
+    }
+
+    /// Returns the time it takes to play the cue.
+    pub fn duration(&self) -> Duration {
+        self.steps.iter().map(|step| step.duration).sum()
+    }
+
+    /// Blinks the light of a player number three times, rumbling
+    /// along with the first blink.
+    ///
+    /// # Panics
+    /// Panics if `player` is not in the range from 1 to 4.
+    pub fn player_found(player: u8) -> Self {
+        assert!((1..=4).contains(&player), "invalid player number {player}");
+        let leds = Leds::from_bits_truncate(1 << (player - 1));
+        let (on, off) = (Duration::from_millis(200), Duration::from_millis(150));
+        Self::new()
+            .step(leds, true, on)
+            .step(Leds::empty(), false, off)
+            .step(leds, false, on)
+            .step(Leds::empty(), false, off)
+            .step(leds, false, on)
+    }
+
+    /// Slowly blinks the leftmost light twice, with a short rumble.
+    pub fn low_battery() -> Self {
+        let pulse = Duration::from_millis(100);
+        Self::new()
+            .step(Leds::ONE, true, pulse)
+            .step(Leds::ONE, false, Duration::from_millis(500))
+            .step(Leds::empty(), false, Duration::from_millis(400))
+            .step(Leds::ONE, false, Duration::from_millis(600))
+    }
+
+    /// Flashes all the lights quickly three times, rumbling with each flash.
+    pub fn error() -> Self {
+        let (on, off) = (Duration::from_millis(120), Duration::from_millis(80));
+        let mut cue = Self::new();
+        for _ in 0..3 {
+            cue = cue
+                .step(Leds::all(), true, on)
+                .step(Leds::empty(), false, off);
+        }
+        cue
+    }
+}
+
+/// A pattern of LED lights, shown by an [`LedAnimator`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LedPattern {
+    /// Turns the given lights on for `on`, and then off for `off`.
+    Blink {
+        /// The lights that blink.
+        leds: Leds,
+        /// How long the lights stay on.
+        on: Duration,
+        /// How long the lights stay off.
+        off: Duration,
+    },
+    /// Moves a single light from left to right, keeping it in each
+    /// position for `step`.
+    Chase {
+        /// How long the light stays in each position.
+        step: Duration,
+    },
+    /// Shows a level from 0 to 100 as a bar of lights that grows
+    /// from the left, e.g. the battery level.
+    Meter {
+        /// The level to show, as a percentage.
+        level: u8,
+    },
+    /// Shows each set of lights for the given duration, in order.
+    Frames(Vec<(Leds, Duration)>),
+}
+
+impl LedPattern {
+    /// Returns the frames of the pattern, and whether they repeat.
+    fn frames(&self) -> (Vec<(Leds, Duration)>, bool) {
+        match self {
+            Self::Blink { leds, on, off } => (vec![(*leds, *on), (Leds::empty(), *off)], true),
+            Self::Chase { step } => {
+                let lights = [Leds::ONE, Leds::TWO, Leds::THREE, Leds::FOUR];
+                (lights.map(|leds| (leds, *step)).to_vec(), true)
+            }
+            Self::Meter { level } => {
+                // Round up, so that any non-zero level lights something.
+                let count = (u32::from(*level).min(100) * 4).div_ceil(100);
+                let leds = Leds::from_bits_truncate((1 << count) - 1);
+                (vec![(leds, Duration::ZERO)], false)
+            }
+            Self::Frames(frames) => (frames.clone(), true),
+        }
+    }
+
+    /// Returns the frames of the pattern and whether they repeat, or fails
+    /// if the pattern repeats without ever waiting, which would keep the
+    /// animation from yielding.
+    fn checked_frames(&self) -> Result<(Vec<(Leds, Duration)>, bool)> {
+        let (frames, repeat) = self.frames();
+        if repeat && frames.iter().all(|(_, duration)| duration.is_zero()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a repeating LED pattern needs a frame of non-zero duration",
+            )
+            .into());
+        }
+        Ok((frames, repeat))
+    }
+}
+
+/// Shows [`LedPattern`]s on the lights of a device, taking care
+/// of the timing.
+///
+/// The animation runs while the future returned by [`LedAnimator::run`]
+/// is polled, typically alongside the event stream of the device.
+/// The pattern can be changed at any time from the same thread.
+///
+/// # Examples
+/// ```
+/// use std::time::Duration;
+/// use xwiimote::feedback::{LedAnimator, LedPattern};
+/// use xwiimote::{Device, Leds};
+///
+/// # fn example(device: &Device) -> xwiimote::Result<()> {
+/// let animator = LedAnimator::new(device);
+/// animator.set_pattern(Some(LedPattern::Blink {
+///     leds: Leds::all(),
+///     on: Duration::from_millis(300),
+///     off: Duration::from_millis(700),
+/// }))?;
+/// // Poll `animator.run()` along with the events of the device,
+/// // e.g. with `futures::join!` or `tokio::select!`.
+/// # Ok(())
+/// # }
+/// ```
+pub struct LedAnimator<'d> {
+    device: &'d Device,
+    state: RefCell<AnimatorState>,
+}
+
+/// The current pattern of an [`LedAnimator`], and its progress.
+#[derive(Default)]
+struct AnimatorState {
+    frames: Vec<(Leds, Duration)>,
+    repeat: bool,
+    /// The index of the next frame to show.
+    next: usize,
+    /// Whether the pattern changed since the animation last ran.
+    changed: bool,
+    /// Wakes up the animation once the pattern changes.
+    waker: Option<Waker>,
+}
+
+impl<'d> LedAnimator<'d> {
+    /// Creates an animator for the lights of `device`, with no pattern.
+    pub fn new(device: &'d Device) -> Self {
+        Self {
+            device,
+            state: RefCell::default(),
+        }
+    }
+
+    /// Starts showing the given pattern from its first frame, or stops
+    /// the animation if `pattern` is [`None`]; the lights then keep
+    /// their current state.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if the pattern repeats
+    /// but none of its frames lasts any time, e.g. a [`LedPattern::Chase`]
+    /// with a zero `step`. The current pattern keeps running then.
+    pub fn set_pattern(&self, pattern: Option<LedPattern>) -> Result<()> {
+        let frames = pattern.map_or_else(|| Ok(Default::default()), |p| p.checked_frames())?;
+        let mut state = self.state.borrow_mut();
+        (state.frames, state.repeat) = frames;
+        state.next = 0;
+        state.changed = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /// Runs the animation, updating the lights as the frames of the
+    /// pattern elapse.
+    ///
+    /// The future only completes if the lights cannot be set, e.g.
+    /// because the device was disconnected.
+    pub async fn run(&self) -> Result<()> {
+        let mut timer: Option<Sleep> = None;
+        poll_fn(|cx| loop {
+            let mut state = self.state.borrow_mut();
+            if state.changed {
+                state.changed = false;
+                timer = None;
+            }
+            if let Some(sleep) = &mut timer {
+                match Pin::new(sleep).poll(cx) {
+                    Poll::Ready(Ok(())) => {}
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                    Poll::Pending => {
+                        state.waker = Some(cx.waker().clone());
+                        return Poll::Pending;
+                    }
+                }
+            }
+            let Some(&(leds, duration)) = state.frames.get(state.next) else {
+                // Wait for another pattern.
+                timer = None;
+                state.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            };
+            state.next += 1;
+            if state.repeat && state.next == state.frames.len() {
+                state.next = 0;
+            }
+            drop(state);
+
+            if let Err(err) = self.device.set_leds(leds) {
+                return Poll::Ready(Err(err));
+            }
+            if duration.is_zero() {
+                continue;
+            }
+            let res = match &mut timer {
+                Some(sleep) => sleep.reset(duration),
+                None => Sleep::new(duration).map(|sleep| timer = Some(sleep)),
+            };
+            if let Err(err) = res {
+                return Poll::Ready(Err(err));
+            }
+        })
+        .await
+    }
+}
+
+/// A command sent to a [`RumbleSink`].
+///
+/// A command is ignored while the rumble motor is controlled by a command
+/// of a higher priority: a pulse that has not ended yet, or a command that
+/// turned the motor on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RumbleCommand {
+    /// Turns the motor on until another command turns it off.
+    On {
+        /// The priority of the command.
+        priority: u8,
+    },
+    /// Turns the motor off.
+    Off {
+        /// The priority of the command.
+        priority: u8,
+    },
+    /// Turns the motor on for `duration`.
+    Pulse {
+        /// How long the motor stays on.
+        duration: Duration,
+        /// The priority of the command.
+        priority: u8,
+    },
+}
+
+impl RumbleCommand {
+    /// Returns the priority of the command.
+    pub fn priority(&self) -> u8 {
+        match *self {
+            Self::On { priority } | Self::Off { priority } | Self::Pulse { priority, .. } => {
+                priority
+            }
+        }
+    }
+}
+
+/// The command that controls the motor of a [`RumbleSink`].
+#[derive(Copy, Clone, Debug)]
+struct ActiveCommand {
+    priority: u8,
+    /// The end of the pulse, if the command is a pulse.
+    until: Option<Instant>,
+}
+
+/// Checks whether `command` can take control of the motor from
+/// the `active` command at time `now`.
+fn overrides(active: Option<ActiveCommand>, command: &RumbleCommand, now: Instant) -> bool {
+    active.is_none_or(|active| {
+        active.until.is_some_and(|until| until <= now) || command.priority() >= active.priority
+    })
+}
+
+/// Plays [`RumbleCommand`]s, e.g. the force-feedback effects of a game,
+/// on the rumble motor of a device; see [`Device::rumble_sink`].
+///
+/// The commands are written in order, each once the sink is flushed,
+/// and pulses end in the background. If the kernel closed the core
+/// channel, e.g. after the device was reset, the sink opens it again
+/// in writable mode. Closing the sink turns the motor off.
+///
+/// # Examples
+/// ```
+/// use futures_util::SinkExt;
+/// use std::time::Duration;
+/// use xwiimote::feedback::RumbleCommand;
+/// use xwiimote::Device;
+///
+/// # async fn example(device: &Device) -> xwiimote::Result<()> {
+/// let mut rumble = device.rumble_sink()?;
+/// let hit = RumbleCommand::Pulse {
+///     duration: Duration::from_millis(150),
+///     priority: 1,
+/// };
+/// rumble.send(hit).await?;
+/// // The motor keeps rumbling for the pulse.
+/// rumble.send(RumbleCommand::Off { priority: 0 }).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RumbleSink<'d> {
+    device: &'d Device,
+    /// The command waiting to be written.
+    pending: Option<RumbleCommand>,
+    active: Option<ActiveCommand>,
+}
+
+impl<'d> RumbleSink<'d> {
+    /// Creates a sink for the rumble motor of `device`, whose core
+    /// channel must be open in writable mode.
+    pub(crate) fn new(device: &'d Device) -> Self {
+        Self {
+            device,
+            pending: None,
+            active: None,
+        }
+    }
+
+    /// Writes a command, unless a command of a higher priority
+    /// controls the motor.
+    fn apply(&mut self, command: RumbleCommand) -> Result<()> {
+        let now = Instant::now();
+        if !overrides(self.active, &command, now) {
+            return Ok(());
+        }
+        if !self.device.get_open().contains(Channels::CORE) {
+            self.reopen()?;
+        }
+        match self.write(command) {
+            // The kernel may close the channel between the check and the write.
+            Err(Error::ChannelClosed(channels)) if channels.contains(Channels::CORE) => {
+                self.reopen()?;
+                self.write(command)?;
+            }
+            res => res?,
+        }
+        self.active = match command {
+            RumbleCommand::Off { .. } => None,
+            RumbleCommand::On { priority } => Some(ActiveCommand {
+                priority,
+                until: None,
+            }),
+            RumbleCommand::Pulse { duration, priority } => Some(ActiveCommand {
+                priority,
+                until: Some(now + duration),
+            }),
+        };
+        Ok(())
+    }
+
+    fn write(&mut self, command: RumbleCommand) -> Result<()> {
+        match command {
+            RumbleCommand::On { .. } => self.device.rumble(true),
+            RumbleCommand::Off { .. } => self.device.rumble(false),
+            RumbleCommand::Pulse { duration, .. } => self.device.rumble_pulse(duration),
+        }
+    }
+
+    /// Opens the core channel again in writable mode.
+    fn reopen(&mut self) -> Result<()> {
+        self.device.close(Channels::CORE)?;
+        self.device.open(Channels::CORE, true)
+    }
+}
+
+impl Sink<RumbleCommand> for RumbleSink<'_> {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.poll_flush(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, command: RumbleCommand) -> Result<()> {
+        self.get_mut().pending = Some(command);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let sink = self.get_mut();
+        match sink.pending.take() {
+            Some(command) => Poll::Ready(sink.apply(command)),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if let Err(err) = std::task::ready!(self.as_mut().poll_flush(cx)) {
+            return Poll::Ready(Err(err));
+        }
+        let sink = self.get_mut();
+        sink.active = None;
+        Poll::Ready(sink.device.rumble(false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::feedback::{overrides, ActiveCommand, FeedbackCue, LedPattern, RumbleCommand};
+    use crate::Leds;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn predefined_cues_end_with_the_motor_off() {
+        for cue in [
+            FeedbackCue::player_found(3),
+            FeedbackCue::low_battery(),
+            FeedbackCue::error(),
+        ] {
+            assert!(!cue.steps().last().unwrap().rumble);
+            assert!(cue.duration() < Duration::from_secs(2));
+        }
+    }
+
+    #[test]
+    fn player_cue_lights_the_player_number() {
+        let cue = FeedbackCue::player_found(3);
+        assert_eq!(cue.steps()[0].leds, Leds::THREE);
+        assert_eq!(cue.duration(), Duration::from_millis(900));
+    }
+
+    #[test]
+    fn expands_led_patterns() {
+        let step = Duration::from_millis(100);
+        let (frames, repeat) = LedPattern::Chase { step }.frames();
+        assert!(repeat);
+        assert_eq!(frames[1], (Leds::TWO, step));
+        assert_eq!(frames.len(), 4);
+
+        let meter = |level| LedPattern::Meter { level }.frames();
+        assert_eq!(meter(0), (vec![(Leds::empty(), Duration::ZERO)], false));
+        assert_eq!(meter(1).0[0].0, Leds::ONE);
+        assert_eq!(meter(50).0[0].0, Leds::ONE | Leds::TWO);
+        assert_eq!(meter(100).0[0].0, Leds::all());
+    }
+
+    #[test]
+    fn rejects_patterns_that_never_wait() {
+        let step = Duration::ZERO;
+        let err = LedPattern::Chase { step }.checked_frames().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        let frames = vec![(Leds::ONE, step), (Leds::TWO, Duration::from_millis(10))];
+        assert!(LedPattern::Frames(frames).checked_frames().is_ok());
+        // A pattern shown once ends after its last frame.
+        assert!(LedPattern::Meter { level: 50 }.checked_frames().is_ok());
+    }
+
+    #[test]
+    fn prioritizes_rumble_commands() {
+        let now = Instant::now();
+        let on = |priority| RumbleCommand::On { priority };
+        let off = |priority| RumbleCommand::Off { priority };
+        assert!(overrides(None, &off(0), now));
+
+        let held = Some(ActiveCommand {
+            priority: 2,
+            until: None,
+        });
+        assert!(!overrides(held, &off(1), now));
+        assert!(overrides(held, &off(2), now));
+        assert!(overrides(held, &on(3), now));
+
+        // A pulse only holds the motor until it ends.
+        let pulse = Some(ActiveCommand {
+            priority: 2,
+            until: Some(now + Duration::from_millis(100)),
+        });
+        assert!(!overrides(pulse, &on(1), now));
+        assert!(overrides(pulse, &on(1), now + Duration::from_millis(100)));
+    }
+}
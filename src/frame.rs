@@ -0,0 +1,184 @@
+//! Coordinate frame conventions for motion and IR data.
+//!
+//! The events produced by a [`Device`](crate::Device) report sensor data
+//! in the *device frame*, which is right-handed and has its y-axis pointing
+//! up. Many applications instead work in screen space, where the y-axis
+//! points down. A [`CoordinateFrame`] converts the accelerometer, Motion Plus
+//! and IR camera data of an [`Event`] into the frame chosen by the
+//! application, so that the same convention is applied to every sensor.
+//! The event streams of a device apply the frame set with
+//! [`Device::set_coordinate_frame`](crate::Device::set_coordinate_frame).
+
+use crate::events::{Event, IrSource};
+use crate::ranges::IR_HEIGHT;
+
+/// The orientation of the axes in a three-dimensional frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Handedness {
+    /// The z-axis is the cross product of the x- and y-axes.
+    #[default]
+    Right,
+    /// The z-axis points opposite to the cross product of the x- and y-axes.
+    Left,
+}
+
+/// The direction of the y-axis.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum YAxis {
+    /// The y-axis points up, as in mathematical conventions.
+    #[default]
+    Up,
+    /// The y-axis points down, as in screen coordinates.
+    Down,
+}
+
+/// A coordinate frame in which sensor data is expressed.
+///
+/// Every conversion into a frame is its own inverse, so it also converts
+/// data expressed in the frame back into the device frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CoordinateFrame {
+    /// The handedness of the frame.
+    pub handedness: Handedness,
+    /// The direction of the y-axis.
+    pub y_axis: YAxis,
+}
+
+impl CoordinateFrame {
+    /// The frame in which the device reports its data.
+    pub const DEVICE: Self = Self {
+        handedness: Handedness::Right,
+        y_axis: YAxis::Up,
+    };
+
+    /// The screen-space frame: the y-axis points down and the z-axis
+    /// points into the screen, which keeps the frame right-handed.
+    pub const SCREEN: Self = Self {
+        handedness: Handedness::Right,
+        y_axis: YAxis::Down,
+    };
+
+    /// Returns the signs by which the components of a vector in
+    /// the device frame are multiplied to express it in this frame.
+    fn signs(&self) -> (i32, i32, i32) {
+        let y = match self.y_axis {
+            YAxis::Up => 1,
+            YAxis::Down => -1,
+        };
+        // Flipping the y-axis alone changes the handedness of the frame.
+        // Flip the z-axis too, unless a change of handedness is desired.
+        let z = match self.handedness {
+            Handedness::Right => y,
+            Handedness::Left => -y,
+        };
+        (1, y, z)
+    }
+
+    /// Converts a vector (such as an acceleration) from the device frame
+    /// into this frame.
+    pub fn vector(&self, (x, y, z): (i32, i32, i32)) -> (i32, i32, i32) {
+        let (sx, sy, sz) = self.signs();
+        (sx * x, sy * y, sz * z)
+    }
+
+    /// Converts an angular velocity from the device frame into this frame.
+    ///
+    /// Rotational speeds are pseudovectors, hence they change sign
+    /// whenever the conversion changes the handedness of the frame.
+    pub fn angular_velocity(&self, v: (i32, i32, i32)) -> (i32, i32, i32) {
+        let (x, y, z) = self.vector(v);
+        match self.handedness {
+            Handedness::Right => (x, y, z),
+            Handedness::Left => (-x, -y, -z),
+        }
+    }
+
+    /// Converts the position of an IR source from the device frame
+    /// into this frame.
+    ///
+    /// The position remains within the bounds of the camera image,
+    /// that is, from (0, 0) to ([`IR_WIDTH`](crate::ranges::IR_WIDTH),
+    /// [`IR_HEIGHT`]).
+    pub fn ir_source(&self, source: IrSource) -> IrSource {
+        match self.y_axis {
+            YAxis::Up => source,
+            YAxis::Down => IrSource {
+                x: source.x,
+                y: IR_HEIGHT - 1 - source.y,
+            },
+        }
+    }
+
+    /// Converts the motion and IR data of an event from the device frame
+    /// into this frame. Other events are returned unchanged.
+    pub fn event(&self, event: Event) -> Event {
+        match event {
+            Event::Accelerometer { x, y, z } => {
+                let (x, y, z) = self.vector((x, y, z));
+                Event::Accelerometer { x, y, z }
+            }
+            Event::MotionPlus { x, y, z } => {
+                let (x, y, z) = self.angular_velocity((x, y, z));
+                Event::MotionPlus { x, y, z }
+            }
+            Event::Ir(sources) => Event::Ir(sources.map(|s| s.map(|s| self.ir_source(s)))),
+            #[cfg(feature = "nunchuk")]
+            Event::NunchukMove {
+                x,
+                y,
+                x_acceleration,
+                y_acceleration,
+            } => {
+                let (x_acceleration, y_acceleration, _) =
+                    self.vector((x_acceleration, y_acceleration, 0));
+                Event::NunchukMove {
+                    x,
+                    y,
+                    x_acceleration,
+                    y_acceleration,
+                }
+            }
+            event => event,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::IrSource;
+    use crate::frame::{CoordinateFrame, Handedness, YAxis};
+
+    #[test]
+    fn device_frame_is_identity() {
+        let frame = CoordinateFrame::DEVICE;
+        assert_eq!(frame.vector((1, 2, 3)), (1, 2, 3));
+        assert_eq!(frame.angular_velocity((1, 2, 3)), (1, 2, 3));
+    }
+
+    #[test]
+    fn screen_frame_stays_right_handed() {
+        let frame = CoordinateFrame::SCREEN;
+        assert_eq!(frame.vector((1, 2, 3)), (1, -2, -3));
+        // A proper rotation transforms pseudovectors like vectors.
+        assert_eq!(frame.angular_velocity((1, 2, 3)), (1, -2, -3));
+    }
+
+    #[test]
+    fn left_handed_frame_flips_pseudovectors() {
+        let frame = CoordinateFrame {
+            handedness: Handedness::Left,
+            y_axis: YAxis::Down,
+        };
+        assert_eq!(frame.vector((1, 2, 3)), (1, -2, 3));
+        assert_eq!(frame.angular_velocity((1, 2, 3)), (-1, 2, -3));
+    }
+
+    #[test]
+    fn ir_sources_stay_in_bounds() {
+        let frame = CoordinateFrame::SCREEN;
+        let source = frame.ir_source(IrSource { x: 10, y: 0 });
+        assert_eq!((source.x, source.y), (10, 767));
+        let source = frame.ir_source(source);
+        assert_eq!((source.x, source.y), (10, 0));
+    }
+}
@@ -0,0 +1,414 @@
+//! Recognition of user-defined motions.
+//!
+//! A [`Gesture`] is a named motion, described by a few examples of the
+//! accelerometer readings taken while the user performed it. Examples
+//! are captured with a [`GestureCapture`], usually while a button is
+//! held down. A [`GestureSet`] later compares new captures against the
+//! examples of every gesture through dynamic time warping, which
+//! tolerates motions that are performed faster or slower than the
+//! examples. The set can be saved as bytes, e.g. with
+//! [`DeviceConfigStore::save_bytes`](crate::config::DeviceConfigStore::save_bytes).
+//!
+//! # Examples
+//! Recognize a motion performed while the B button is held down.
+//! ```
+//! use futures_util::TryStreamExt;
+//! use xwiimote::events::{Event, Key, KeyState};
+//! use xwiimote::gesture::{GestureCapture, GestureSet};
+//! use xwiimote::{Address, Channels, Device};
+//!
+//! # let _ = async {
+//! # let address = Address::from(std::path::PathBuf::new());
+//! # let saved = Vec::new();
+//! let gestures = GestureSet::from_bytes(&saved)?;
+//! let device = Device::connect(&address)?;
+//! device.open(Channels::CORE | Channels::ACCELEROMETER, false)?;
+//!
+//! let mut capture = GestureCapture::default();
+//! let mut events = device.events()?;
+//! while let Some((event, _)) = events.try_next().await? {
+//!     match event {
+//!         Event::Key(Key::B, KeyState::Down) => capture.begin(),
+//!         Event::Key(Key::B, KeyState::Up) => {
+//!             let samples = capture.end().unwrap_or_default();
+//!             if let Some(found) = gestures.recognize(&samples) {
+//!                 println!("performed {}", found.name);
+//!             }
+//!         }
+//!         Event::Accelerometer { x, y, z } => capture.push([x, y, z]),
+//!         _ => {}
+//!     }
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! # };
+//! ```
+
+use crate::orientation::GRAVITY;
+use crate::recording::take;
+use crate::{Error, Result};
+use std::io;
+
+// Serialized layout, with every integer in little-endian byte order:
+//
+// - The `MAGIC` bytes and the number of gestures (`u32`).
+// - For each gesture, the length of its name (`u16`), the name in UTF-8,
+//   and the number of examples (`u32`).
+// - For each example, the number of samples (`u32`) followed by the `x`,
+//   `y` and `z` values of each sample (`i32`).
+
+/// The bytes at the start of every serialized gesture set.
+const MAGIC: &[u8; 8] = b"XWIIGES1";
+
+/// An accelerometer reading, with the `x`, `y` and `z` components.
+pub type Sample = [i32; 3];
+
+/// A named motion, together with the examples that describe it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Gesture {
+    name: String,
+    examples: Vec<Vec<Sample>>,
+}
+
+impl Gesture {
+    /// Creates a gesture without examples.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            examples: Vec::new(),
+        }
+    }
+
+    /// Returns the name of the gesture.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the examples of the gesture.
+    pub fn examples(&self) -> &[Vec<Sample>] {
+        &self.examples
+    }
+
+    /// Adds an example of the gesture, as returned by [`GestureCapture::end`].
+    ///
+    /// Three to five examples are usually enough; more examples make
+    /// the recognition more tolerant, but also slower.
+    pub fn add_example(&mut self, samples: Vec<Sample>) {
+        self.examples.push(samples);
+    }
+
+    /// Removes every example of the gesture.
+    pub fn clear_examples(&mut self) {
+        self.examples.clear();
+    }
+}
+
+/// A gesture recognized by [`GestureSet::recognize`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Match<'a> {
+    /// The name of the recognized gesture.
+    pub name: &'a str,
+    /// The distance between the performed motion and the closest
+    /// example of the gesture, which is 0 for identical motions.
+    pub distance: f32,
+}
+
+/// A collection of gestures that are recognized together.
+#[derive(Clone, Debug)]
+pub struct GestureSet {
+    gestures: Vec<Gesture>,
+    threshold: f32,
+}
+
+impl Default for GestureSet {
+    fn default() -> Self {
+        Self {
+            gestures: Vec::new(),
+            threshold: Self::DEFAULT_THRESHOLD,
+        }
+    }
+}
+
+impl GestureSet {
+    /// The default largest distance at which a motion is recognized.
+    pub const DEFAULT_THRESHOLD: f32 = 0.3;
+
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the gestures of the set.
+    pub fn gestures(&self) -> &[Gesture] {
+        &self.gestures
+    }
+
+    /// Returns the gesture with the given name, if any.
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Gesture> {
+        self.gestures
+            .iter_mut()
+            .find(|gesture| gesture.name == name)
+    }
+
+    /// Adds a gesture, replacing the one with the same name, if any.
+    pub fn insert(&mut self, gesture: Gesture) {
+        match self.get_mut(&gesture.name) {
+            Some(existing) => *existing = gesture,
+            None => self.gestures.push(gesture),
+        }
+    }
+
+    /// Removes the gesture with the given name, and returns it.
+    pub fn remove(&mut self, name: &str) -> Option<Gesture> {
+        let ix = self.gestures.iter().position(|g| g.name == name)?;
+        Some(self.gestures.remove(ix))
+    }
+
+    /// Sets the largest distance at which a motion is recognized as
+    /// a gesture. Lower values reject more of the sloppy motions, but
+    /// also more of the intended ones.
+    ///
+    /// The distance is the average difference between the aligned
+    /// accelerometer readings, in multiples of the standard gravity.
+    /// Defaults to [`GestureSet::DEFAULT_THRESHOLD`].
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    /// Finds the gesture whose examples are the closest to the given
+    /// motion, if the distance to them is within the threshold.
+    pub fn recognize(&self, samples: &[Sample]) -> Option<Match<'_>> {
+        self.gestures
+            .iter()
+            .filter_map(|gesture| {
+                let distance = gesture
+                    .examples
+                    .iter()
+                    .map(|example| dtw_distance(samples, example))
+                    .min_by(f32::total_cmp)?;
+                Some(Match {
+                    name: &gesture.name,
+                    distance,
+                })
+            })
+            .filter(|found| found.distance <= self.threshold)
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+
+    /// Serializes the gestures of the set. The threshold is not included.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = MAGIC.to_vec();
+        buf.extend_from_slice(&(self.gestures.len() as u32).to_le_bytes());
+        for gesture in &self.gestures {
+            buf.extend_from_slice(&(gesture.name.len() as u16).to_le_bytes());
+            buf.extend_from_slice(gesture.name.as_bytes());
+            buf.extend_from_slice(&(gesture.examples.len() as u32).to_le_bytes());
+            for example in &gesture.examples {
+                buf.extend_from_slice(&(example.len() as u32).to_le_bytes());
+                for value in example.iter().flatten() {
+                    buf.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+        buf
+    }
+
+    /// Deserializes the gestures returned by [`GestureSet::to_bytes`].
+    /// An empty slice yields an empty set.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut set = Self::default();
+        if data.is_empty() {
+            return Ok(set);
+        }
+        let mut pos = 0;
+        if &take::<8>(data, &mut pos)? != MAGIC {
+            return Err(invalid_data("not a gesture set"));
+        }
+        let read_len = |pos: &mut usize| -> Result<usize> {
+            Ok(u32::from_le_bytes(take(data, pos)?) as usize)
+        };
+        for _ in 0..read_len(&mut pos)? {
+            let name_len = u16::from_le_bytes(take(data, &mut pos)?) as usize;
+            let name = data
+                .get(pos..pos + name_len)
+                .and_then(|name| std::str::from_utf8(name).ok())
+                .ok_or_else(|| invalid_data("invalid gesture name"))?;
+            pos += name_len;
+            let mut gesture = Gesture::new(name);
+            for _ in 0..read_len(&mut pos)? {
+                let n_samples = read_len(&mut pos)?;
+                // Do not trust the length before the data is known to exist.
+                if data.len() - pos < n_samples * 12 {
+                    return Err(invalid_data("the gesture set is truncated"));
+                }
+                let mut example = Vec::with_capacity(n_samples);
+                for _ in 0..n_samples {
+                    let mut sample = [0; 3];
+                    for value in &mut sample {
+                        *value = i32::from_le_bytes(take(data, &mut pos)?);
+                    }
+                    example.push(sample);
+                }
+                gesture.add_example(example);
+            }
+            set.insert(gesture);
+        }
+        Ok(set)
+    }
+}
+
+fn invalid_data(msg: &str) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+/// Collects the accelerometer readings of a single motion.
+#[derive(Clone, Debug, Default)]
+pub struct GestureCapture {
+    /// The readings of the motion in progress, if any.
+    samples: Option<Vec<Sample>>,
+}
+
+impl GestureCapture {
+    /// The fewest readings that make up a motion. Shorter captures,
+    /// such as those of an accidental button press, are discarded.
+    pub const MIN_SAMPLES: usize = 10;
+
+    /// The most readings that make up a motion, which bounds the time
+    /// taken by the recognition. Later readings are ignored.
+    pub const MAX_SAMPLES: usize = 400;
+
+    /// Starts capturing a motion, discarding the one in progress, if any.
+    pub fn begin(&mut self) {
+        self.samples = Some(Vec::new());
+    }
+
+    /// Returns `true` if a motion is being captured.
+    pub fn is_capturing(&self) -> bool {
+        self.samples.is_some()
+    }
+
+    /// Adds an accelerometer reading to the motion in progress, if any.
+    pub fn push(&mut self, sample: Sample) {
+        if let Some(samples) = &mut self.samples {
+            if samples.len() < Self::MAX_SAMPLES {
+                samples.push(sample);
+            }
+        }
+    }
+
+    /// Stops capturing, and returns the readings of the motion unless
+    /// there are too few of them.
+    pub fn end(&mut self) -> Option<Vec<Sample>> {
+        self.samples
+            .take()
+            .filter(|samples| samples.len() >= Self::MIN_SAMPLES)
+    }
+}
+
+/// Computes the dynamic time warping distance between two motions,
+/// which is the average difference between the aligned readings.
+fn dtw_distance(a: &[Sample], b: &[Sample]) -> f32 {
+    if a.is_empty() || b.is_empty() {
+        return f32::INFINITY;
+    }
+    let diff = |p: &Sample, q: &Sample| {
+        // The readings may span the whole range of `i32`.
+        let [dx, dy, dz] = [0, 1, 2].map(|i| (i64::from(p[i]) - i64::from(q[i])) as f32);
+        (dx * dx + dy * dy + dz * dz).sqrt() / GRAVITY
+    };
+    // Keep two rows of the cost matrix. Each cell holds the cost of
+    // the best alignment of the prefixes, and the length of its path.
+    let mut prev = vec![(f32::INFINITY, 0); b.len() + 1];
+    let mut row = prev.clone();
+    prev[0] = (0.0, 0);
+    for p in a {
+        row[0] = (f32::INFINITY, 0);
+        for (j, q) in b.iter().enumerate() {
+            let best = [prev[j], prev[j + 1], row[j]]
+                .into_iter()
+                .min_by(|x, y| x.0.total_cmp(&y.0))
+                .unwrap_or((f32::INFINITY, 0));
+            row[j + 1] = (best.0 + diff(p, q), best.1 + 1);
+        }
+        std::mem::swap(&mut prev, &mut row);
+    }
+    let (cost, len) = prev[b.len()];
+    cost / len as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gesture::{dtw_distance, Gesture, GestureCapture, GestureSet, Sample};
+    use std::f32::consts::TAU;
+
+    /// A motion along one axis, performed over `n` readings.
+    fn swing(axis: usize, n: usize) -> Vec<Sample> {
+        (0..n)
+            .map(|i| {
+                let mut sample = [0, 0, 100];
+                sample[axis] += (200.0 * (i as f32 / n as f32 * TAU).sin()) as i32;
+                sample
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tolerates_different_speeds() {
+        assert_eq!(dtw_distance(&swing(0, 40), &swing(0, 40)), 0.0);
+        let slow = dtw_distance(&swing(0, 40), &swing(0, 60));
+        let other = dtw_distance(&swing(0, 40), &swing(1, 40));
+        assert!(slow < 0.2 && other > 0.5, "{slow} vs {other}");
+    }
+
+    #[test]
+    fn handles_extreme_readings() {
+        let distance = dtw_distance(&[[i32::MAX; 3]], &[[i32::MIN; 3]]);
+        assert!(distance.is_finite() && distance > 0.0);
+    }
+
+    #[test]
+    fn recognizes_the_closest_gesture() {
+        let mut set = GestureSet::new();
+        for (name, axis) in [("sideways", 0), ("up", 1)] {
+            let mut gesture = Gesture::new(name);
+            gesture.add_example(swing(axis, 30));
+            gesture.add_example(swing(axis, 50));
+            set.insert(gesture);
+        }
+        assert_eq!(set.recognize(&swing(1, 40)).unwrap().name, "up");
+        assert_eq!(set.recognize(&swing(0, 45)).unwrap().name, "sideways");
+        // A motion unlike any example is not recognized.
+        assert_eq!(set.recognize(&swing(2, 40)), None);
+        assert_eq!(set.recognize(&[]), None);
+    }
+
+    #[test]
+    fn round_trips_gesture_sets() {
+        let mut set = GestureSet::new();
+        let mut gesture = Gesture::new("círculo");
+        gesture.add_example(swing(0, 12));
+        set.insert(gesture);
+        set.insert(Gesture::new("empty"));
+
+        let bytes = set.to_bytes();
+        let decoded = GestureSet::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.gestures(), set.gestures());
+        assert!(GestureSet::from_bytes(&[]).unwrap().gestures().is_empty());
+        assert!(GestureSet::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(GestureSet::from_bytes(b"XWIIREC1").is_err());
+    }
+
+    #[test]
+    fn discards_short_captures() {
+        let mut capture = GestureCapture::default();
+        capture.push([1, 2, 3]);
+        assert!(!capture.is_capturing());
+        capture.begin();
+        capture.push([1, 2, 3]);
+        assert_eq!(capture.end(), None);
+        capture.begin();
+        for sample in swing(0, 20) {
+            capture.push(sample);
+        }
+        assert_eq!(capture.end().map(|samples| samples.len()), Some(20));
+    }
+}
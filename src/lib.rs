@@ -4,6 +4,7 @@
 //! # Examples
 //! Connect to the first Wii Remote found and print its battery level.
 //! ```
+//! use std::time::Duration;
 //! use xwiimote::{Device, Monitor};
 //! use futures_util::TryStreamExt;
 //!
@@ -13,7 +14,7 @@
 //! match monitor.try_next().await {
 //!     Ok(Some(address)) => {
 //!         // Connect to the Wii Remote specified by `address`.
-//!         let device = Device::connect(&address)?;
+//!         let device = Device::connect_async(&address, Duration::from_secs(2)).await?;
 //!         let level = device.battery()?;
 //!         println!("the battery level is {}%", level);
 //!     }
@@ -32,7 +33,7 @@
 //! # let _ = async { // the `while` loop runs indefinitely.
 //! let mut monitor = Monitor::discover()?;
 //! while let Ok(Some(address)) = monitor.try_next().await {
-//!     println!("found device at {address:?}");
+//!     println!("found device at {address}");
 //! }
 //! # Ok::<(), std::io::Error>(())
 //! # };
@@ -41,31 +42,99 @@
 //!
 //! [xwiimote]: https://github.com/xwiimote/xwiimote
 
-use crate::events::{Event, EventStream};
-use crate::reactor::{Interest, Reactor};
+use crate::async_fd::AsyncFd;
+use crate::battery::{
+    BatteryEstimate, BatteryEstimator, BatteryEvents, BatteryPolicy, BatteryStatus,
+};
+use crate::channels::{Channel, TypedEventStream};
+use crate::events::{
+    ChannelStats, Event, EventCounters, EventStream, ExtensionChanges, OwnedEvents,
+};
+use crate::feedback::{FeedbackCue, RumbleSink};
+use crate::frame::CoordinateFrame;
+use crate::netlink::UeventSocket;
+use crate::observer::{Broadcast, Observer};
+use crate::output::{OutputQueue, Pwm, SharedHandle};
+use crate::reactor::Reactor;
+use crate::self_test::SelfTestReport;
+use crate::split::{DeviceControl, DeviceEvents};
+use crate::supervisor::Backoff;
+use crate::timer::Sleep;
 use bitflags::bitflags;
 use futures_core::Stream;
 use libc::{c_int, c_uint};
 use num_derive::FromPrimitive;
+use std::collections::{HashMap, VecDeque};
 use std::ffi::{CStr, CString, OsStr};
-use std::os::fd::RawFd;
+use std::fmt;
+use std::fs;
+use std::future::{poll_fn, Future};
+use std::io;
+use std::mem;
 use std::os::unix::ffi::OsStrExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::ptr;
-use std::task::{Context, Poll};
-use std::time::{Duration, SystemTime};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::Poll;
+use std::time::{Duration, Instant, SystemTime};
 use xwiimote_sys::{
-    xwii_iface, xwii_iface_available, xwii_iface_close, xwii_iface_get_battery,
-    xwii_iface_get_devtype, xwii_iface_get_extension, xwii_iface_get_led,
-    xwii_iface_get_mp_normalization, xwii_iface_new, xwii_iface_open, xwii_iface_opened,
-    xwii_iface_rumble, xwii_iface_set_led, xwii_iface_set_mp_normalization, xwii_iface_unref,
-    xwii_iface_watch, xwii_monitor, xwii_monitor_get_fd, xwii_monitor_new, xwii_monitor_poll,
-    xwii_monitor_unref, XWII_IFACE_WRITABLE,
+    xwii_event, xwii_iface, xwii_iface_available, xwii_iface_close, xwii_iface_dispatch,
+    xwii_iface_get_battery, xwii_iface_get_devtype, xwii_iface_get_extension, xwii_iface_get_fd,
+    xwii_iface_get_led, xwii_iface_get_mp_normalization, xwii_iface_new, xwii_iface_open,
+    xwii_iface_opened, xwii_iface_rumble, xwii_iface_set_led, xwii_iface_set_mp_normalization,
+    xwii_iface_unref, xwii_iface_watch, XWII_IFACE_WRITABLE,
 };
 
+pub mod analytics;
+mod async_fd;
+pub mod battery;
+#[cfg(feature = "bluetooth")]
+mod bluetooth;
+#[cfg(feature = "uinput")]
+pub mod bridge;
+pub mod broker;
+mod builder;
+pub mod bus;
+pub mod channels;
+pub mod config;
+pub mod conflict;
+mod error;
 pub mod events;
-pub(crate) mod reactor;
+pub mod feedback;
+pub mod frame;
+pub mod gesture;
+#[cfg(feature = "glib")]
+pub mod main_loop;
+pub mod merge;
+mod monitor;
+mod netlink;
+pub mod observer;
+mod output;
+pub mod pointer;
+pub mod reactor;
+pub mod recording;
+pub mod self_test;
+pub mod session;
+pub mod split;
+pub mod supervisor;
+pub mod switch;
+mod timer;
+#[cfg(feature = "uhid")]
+pub mod uhid;
+
+#[cfg(feature = "balance-board")]
+pub use xwiimote_util::balance;
+pub use xwiimote_util::{filter, orientation, ranges};
+
+pub use builder::{ConnectOptions, DeviceBuilder};
+pub use error::{DispatchFailure, Error};
+pub use monitor::{
+    Backend, DeviceEvent, Devices, Discovered, DiscoveredDevice, DiscoveredDevices, Discoveries,
+    HotplugEvents, Monitor, MonitorBuilder,
+};
 
 // FFI and libc utilities.
 
@@ -74,7 +143,7 @@ pub(crate) mod reactor;
 macro_rules! bail_if {
     ($e:expr) => {
         if $e {
-            return Err(std::io::Error::last_os_error());
+            return Err(std::io::Error::last_os_error().into());
         }
     };
 }
@@ -95,20 +164,158 @@ fn to_rust_str(str: &CStr) -> String {
     str.to_string_lossy().into_owned()
 }
 
+/// Lists the file descriptors watched by an `epoll` descriptor, given
+/// the contents of its `/proc/self/fdinfo` entry.
+fn epoll_targets(fdinfo: &str) -> impl Iterator<Item = c_int> + '_ {
+    fdinfo.lines().filter_map(|line| {
+        let fd = line.strip_prefix("tfd:")?.split_whitespace().next()?;
+        fd.parse().ok()
+    })
+}
+
 /// The main result type used by this crate.
-pub type Result<T> = std::io::Result<T>;
+pub type Result<T> = std::result::Result<T, Error>;
 
 /// A Wii Remote device address.
-#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// The address is the `sysfs` directory of the HID device, such as
+/// `/sys/bus/hid/devices/0005:057E:0306.0001`, and is displayed as such.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Address(PathBuf);
 
 impl Address {
     /// Converts the path given as a C string into a device address.
+    // Only the monitor of the `xwiimote` library produces raw paths.
+    #[cfg_attr(feature = "udev", allow(dead_code))]
     fn from_raw(path_str: &CStr) -> Self {
         let path_str = OsStr::from_bytes(path_str.to_bytes()).to_os_string();
         Self(PathBuf::from(path_str))
     }
 
+    /// Finds the connected device with the given Bluetooth address
+    /// (e.g. `00:1F:32:AA:BB:CC`), as stored in configuration files or
+    /// shown by `bluetoothctl`. Case is ignored.
+    ///
+    /// The device must be connected, since its `sysfs` path is only
+    /// assigned then; use [`Monitor::resolve`] to wait for it instead.
+    pub fn from_mac(mac: &str) -> Result<Self> {
+        let id: StableId = mac.parse()?;
+        Self::find_by_id(Path::new("/sys/bus/hid/devices"), &id)
+    }
+
+    /// Finds the device with the given stable identifier among the
+    /// HID devices listed in `devices_dir`.
+    fn find_by_id(devices_dir: &Path, id: &StableId) -> Result<Self> {
+        for entry in fs::read_dir(devices_dir)? {
+            let address = Self(entry?.path());
+            // Skip the devices that do not report an address, or
+            // disconnected in the meantime.
+            if address.stable_id().ok().as_ref() == Some(id) {
+                return Ok(Self(fs::canonicalize(address.0)?));
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no connected device has the Bluetooth address `{id}`"),
+        )
+        .into())
+    }
+
+    /// Reads the unique identifier of the device, which is its
+    /// Bluetooth address (e.g. `00:1f:32:aa:bb:cc`).
+    ///
+    /// Unlike the address itself, the identifier stays the same across
+    /// reconnections; see also [`Address::stable_id`].
+    pub fn uniq(&self) -> Result<String> {
+        self.info()?
+            .uniq()
+            .filter(|uniq| !uniq.is_empty())
+            .map(str::to_owned)
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "device has no unique identifier").into()
+            })
+    }
+
+    /// Reads the `udev` properties of the device.
+    pub fn info(&self) -> Result<DeviceInfo> {
+        let uevent = fs::read_to_string(self.0.join("uevent"))?;
+        Ok(DeviceInfo::parse(&uevent))
+    }
+
+    /// Looks up the device in the `udev` database, which gives access
+    /// to every property of the device, including those set by the
+    /// `udev` rules (e.g. `ID_SEAT`), unlike [`Address::info`].
+    #[cfg(feature = "udev")]
+    pub fn udev_device(&self) -> Result<udev::Device> {
+        Ok(udev::Device::from_syspath(&self.0)?)
+    }
+
+    /// Returns an identifier of the device that stays the same across
+    /// reconnections and reboots, unlike the address itself.
+    ///
+    /// Unlike [`Address::uniq`], the identifier is the validated and
+    /// normalized Bluetooth address, which can be passed to
+    /// [`Address::from_mac`].
+    pub fn stable_id(&self) -> Result<StableId> {
+        self.uniq()?.parse()
+    }
+
+    /// Finds the `sysfs` directory of the battery, which the kernel
+    /// names `wiimote_battery_<bluetooth address>`.
+    fn power_supply_dir(&self) -> Result<PathBuf> {
+        let entry = fs::read_dir(self.0.join("power_supply"))?.next();
+        match entry {
+            Some(entry) => Ok(entry?.path()),
+            None => {
+                Err(io::Error::new(io::ErrorKind::NotFound, "the battery does not exist").into())
+            }
+        }
+    }
+
+    /// Finds the `sysfs` directory of an LED light. The kernel names
+    /// these directories `<hid id>:blue:p<n>`, where `n` starts at 0.
+    fn led_dir(&self, light: Led) -> Result<PathBuf> {
+        let suffix = format!(":blue:p{}", light as u32 - Led::One as u32);
+        for entry in fs::read_dir(self.0.join("leds"))? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().ends_with(&suffix) {
+                return Ok(entry.path());
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound, "the LED light does not exist").into())
+    }
+
+    /// Lists the `evdev` nodes that the kernel created for the device, one
+    /// for each of its input interfaces, along with the names of the
+    /// interfaces (e.g. `Nintendo Wii Remote Accelerometer`).
+    fn evdev_nodes(&self) -> Result<Vec<(PathBuf, String)>> {
+        let mut nodes = Vec::new();
+        for input in fs::read_dir(self.0.join("input"))? {
+            let input = input?.path();
+            let name = fs::read_to_string(input.join("name")).unwrap_or_default();
+            for entry in fs::read_dir(&input)? {
+                let file_name = entry?.file_name();
+                if file_name.to_string_lossy().starts_with("event") {
+                    let node = PathBuf::from("/dev/input").join(file_name);
+                    nodes.push((node, name.trim().to_owned()));
+                }
+            }
+        }
+        nodes.sort();
+        Ok(nodes)
+    }
+
+    /// Finds the `hidraw` node that the kernel created for the device.
+    fn hidraw_node(&self) -> Result<PathBuf> {
+        for entry in fs::read_dir(self.0.join("hidraw"))? {
+            let file_name = entry?.file_name();
+            if file_name.to_string_lossy().starts_with("hidraw") {
+                return Ok(PathBuf::from("/dev").join(file_name));
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound, "the hidraw node does not exist").into())
+    }
+
     fn to_c_string(&self) -> CString {
         let slice = self.0.as_os_str().as_bytes();
         CString::new(slice).expect("path contains an internal null byte")
@@ -123,115 +330,318 @@ impl From<PathBuf> for Address {
     }
 }
 
-// Device monitoring (enumeration and discovery).
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.display().fmt(f)
+    }
+}
+
+impl FromStr for Address {
+    type Err = Error;
+
+    /// Parses the path to a Wii Remote HID device, such as the one
+    /// displayed by an [`Address`]. A bare HID identifier such as
+    /// `0005:057E:0306.0001` refers to the device of that name under
+    /// the `/sys/bus/hid/devices` directory.
+    ///
+    /// Use [`Address::from_mac`] to find a device by its Bluetooth
+    /// address instead.
+    fn from_str(s: &str) -> Result<Self> {
+        if s.is_empty() || s.contains('\0') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("`{s}` is not a device path"),
+            )
+            .into());
+        }
+        let path = if s.contains('/') {
+            PathBuf::from(s)
+        } else {
+            Path::new("/sys/bus/hid/devices").join(s)
+        };
+        Ok(Self(path))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Address {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serde::Serialize::serialize(&self.0, serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Address {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        <PathBuf as serde::Deserialize>::deserialize(deserializer).map(Self)
+    }
+}
+
+/// The `udev` properties of a Wii Remote, obtained through [`Address::info`].
+///
+/// They help correlate a device with the entries listed by other tools,
+/// such as `bluetoothctl` or `libinput`, e.g. when the input of a device
+/// seems to be duplicated or missing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceInfo {
+    properties: HashMap<String, String>,
+}
+
+impl DeviceInfo {
+    /// Parses the contents of a `uevent` attribute, which holds
+    /// a `KEY=VALUE` property per line.
+    fn parse(uevent: &str) -> Self {
+        let properties = uevent
+            .lines()
+            .filter_map(|line| {
+                let (key, value) = line.split_once('=')?;
+                Some((key.to_owned(), value.to_owned()))
+            })
+            .collect();
+        Self { properties }
+    }
+
+    /// Returns the name of the kernel driver bound to the device,
+    /// which is `wiimote` unless another driver claimed it.
+    pub fn driver(&self) -> Option<&str> {
+        self.property("DRIVER")
+    }
+
+    /// Returns the name reported by the device, such as
+    /// `Nintendo RVL-CNT-01` for a Wii Remote.
+    pub fn name(&self) -> Option<&str> {
+        self.property("HID_NAME")
+    }
+
+    /// Returns the physical location of the device, which is the
+    /// Bluetooth address of the adapter it is connected to.
+    pub fn phys(&self) -> Option<&str> {
+        self.property("HID_PHYS")
+    }
+
+    /// Returns the Bluetooth address of the device. See also
+    /// [`Address::stable_id`].
+    pub fn uniq(&self) -> Option<&str> {
+        self.property("HID_UNIQ")
+    }
+
+    /// Returns the value of any other property, such as `HID_ID`
+    /// or `MODALIAS`, if present.
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(String::as_str)
+    }
+}
 
-/// Enumerates the addresses of connected Wii Remotes and optionally streams
-/// device addresses as new devices are discovered. The same address may
-/// be produced multiple times.
+/// The state of a device at a given time, obtained through [`Device::info`].
 ///
-/// When discovery mode is disabled, the stream returns [`None`]
-/// once the addresses of all connected devices have been produced.
+/// It gathers the details shown by device listings and diagnostics,
+/// which would otherwise require a call (and error check) each.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceSnapshot {
+    /// The type of the device.
+    pub kind: DeviceKind,
+    /// The type of the extension plugged to the device.
+    pub extension: ExtensionKind,
+    /// The battery level as a percentage from 0 to 100%, if it
+    /// could be read.
+    pub battery: Option<u8>,
+    /// The channels that are open.
+    pub open_channels: Channels,
+    /// The channels that can be opened, including those that are open.
+    pub available_channels: Channels,
+    /// The LED lights that are turned on.
+    pub leds: Leds,
+    /// The Bluetooth address of the device, if it reports one;
+    /// see [`Address::uniq`].
+    pub uniq: Option<String>,
+    /// The directory of the device in the `sysfs` filesystem.
+    pub sysfs_path: PathBuf,
+}
+
+/// An identifier of a Wii Remote that persists across reconnections
+/// and reboots, obtained through [`Address::stable_id`].
 ///
-/// A monitor should be dropped when no longer needed in order to avoid
-/// needlessly polling the system for new devices.
-pub struct Monitor {
-    handle: *mut xwii_monitor,
-    /// The file descriptor used by the monitor referenced by `handle`.
-    /// Only present in discovery mode in order to monitor for hot-plug events.
-    mon_fd: Option<RawFd>,
-    /// Have we produced all the connected devices already?
-    enumerated: bool,
-}
-
-impl Monitor {
-    const HOTPLUG_EVENTS: c_int = libc::EPOLLIN | libc::EPOLLHUP | libc::EPOLLPRI;
-
-    fn new(discover: bool) -> Result<Self> {
-        // Create a monitor based on udevd events.
-        let handle = unsafe { xwii_monitor_new(discover, false) };
-        bail_if!(handle.is_null());
-
-        Ok(Self {
-            handle,
-            mon_fd: discover.then(|| unsafe { xwii_monitor_get_fd(handle, false) }),
-            enumerated: false,
-        })
+/// The `sysfs` path of a device changes every time it connects, so
+/// configurations that refer to a particular device should store its
+/// stable identifier instead, which is derived from its Bluetooth address.
+/// Use [`Monitor::resolve`] to find the current address of the device.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StableId(String);
+
+impl StableId {
+    /// Returns the identifier as a string, which is the Bluetooth
+    /// address of the device in lowercase (e.g. `00:1f:32:aa:bb:cc`).
+    pub fn as_str(&self) -> &str {
+        &self.0
     }
+}
 
-    /// Creates a monitor that streams the addresses of all connected devices.
-    pub fn enumerate() -> Result<Self> {
-        Self::new(false)
+impl fmt::Display for StableId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
     }
+}
+
+impl FromStr for StableId {
+    type Err = Error;
 
-    /// Creates a monitor that first streams the addresses of all connected
-    /// devices and then listens for hot-plugged devices, producing
-    /// their addresses as they are found.
-    pub fn discover() -> Result<Self> {
-        Self::new(true)
+    /// Parses an identifier from a Bluetooth address, such as the
+    /// one returned by [`StableId::as_str`]. Case is ignored.
+    fn from_str(s: &str) -> Result<Self> {
+        let groups: Vec<_> = s.split(':').collect();
+        let valid = groups.len() == 6
+            && groups
+                .iter()
+                .all(|group| group.len() == 2 && group.chars().all(|c| c.is_ascii_hexdigit()));
+        if !valid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("`{s}` is not a Bluetooth address"),
+            )
+            .into());
+        }
+        Ok(Self(s.to_ascii_lowercase()))
     }
 }
 
-impl Stream for Monitor {
-    type Item = Result<Address>;
+/// The type of a device, as reported by [`Device::device_kind`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DeviceKind {
+    /// The original Wii Remote (`gen10`).
+    WiiRemote,
+    /// The Wii Remote Plus, which has a built-in Motion Plus (`gen20`).
+    WiiRemotePlus,
+    /// The Wii Balance Board (`balanceboard`).
+    BalanceBoard,
+    /// The Wii U Pro Controller (`procontroller`).
+    ProController,
+    /// A device type that the `xwiimote` library does not know,
+    /// or does not know yet, with its raw identifier.
+    Unknown(String),
+}
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let raw_path = if self.enumerated {
-            // At this point every connected device has already been produced.
-            // If `self.mon_fd` is present, we should now discover new devices.
-            // Otherwise the enumeration process is complete.
-            let mon_fd = match self.mon_fd {
-                Some(fd) => fd,
-                None => return Poll::Ready(None),
-            };
+impl DeviceKind {
+    /// Returns the raw identifier of the device type, such as `gen10`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::WiiRemote => "gen10",
+            Self::WiiRemotePlus => "gen20",
+            Self::BalanceBoard => "balanceboard",
+            Self::ProController => "procontroller",
+            Self::Unknown(raw) => raw,
+        }
+    }
+}
 
-            let raw_path = unsafe { xwii_monitor_poll(self.handle) };
-            if raw_path.is_null() {
-                // No new device is available; arrange for `wake` to be called
-                // once a new device is found.
-                let interest = Interest::new(mon_fd, Self::HOTPLUG_EVENTS);
-                Reactor::get().set_callback(interest, cx.waker().clone());
-                return Poll::Pending;
-            }
-            raw_path
-        } else {
-            // Enumerate the next connected device, if any.
-            // This process requires no blocking; read directly.
-            let raw_path = unsafe { xwii_monitor_poll(self.handle) };
-            if raw_path.is_null() {
-                // We just read the first `null` device address;
-                // the enumeration phase is complete.
-                self.enumerated = true;
-                return if let Some(mon_fd) = self.mon_fd {
-                    // Listen for hot-plug events on the monitor descriptor.
-                    let interest = Interest::new(mon_fd, Self::HOTPLUG_EVENTS);
-                    Reactor::get().add_interest(&interest)?;
-                    // Poll again to return the first discovered device.
-                    self.poll_next(cx)
-                } else {
-                    Poll::Ready(None)
-                };
-            }
-            raw_path
-        };
+impl fmt::Display for DeviceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DeviceKind {
+    type Err = std::convert::Infallible;
 
-        // Convert the raw path into an address and free the original string.
-        let slice = unsafe { CStr::from_ptr(raw_path) };
-        let address = Address::from_raw(slice);
-        unsafe { free_str(raw_path) };
-        Poll::Ready(Some(Ok(address)))
+    /// Parses a raw identifier, as returned by [`Device::kind`].
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "gen10" => Self::WiiRemote,
+            "gen20" => Self::WiiRemotePlus,
+            "balanceboard" => Self::BalanceBoard,
+            "procontroller" => Self::ProController,
+            other => Self::Unknown(other.to_owned()),
+        })
     }
 }
 
-impl Drop for Monitor {
-    fn drop(&mut self) {
-        if let Some(mon_fd) = self.mon_fd {
-            let interest = Interest::new(mon_fd, Self::HOTPLUG_EVENTS);
-            Reactor::get()
-                .remove_interest(&interest)
-                .expect("failed to remove interest for monitor fd");
+/// The type of the extension plugged to a device, as reported by
+/// [`Device::extension_kind`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ExtensionKind {
+    /// No extension is plugged (`none`).
+    None,
+    /// The Nunchuk (`nunchuk`).
+    Nunchuk,
+    /// The Classic Controller or the Classic Controller Pro (`classic`).
+    ClassicController,
+    /// The Motion Plus, with no extension plugged to its passthrough port
+    /// (`motionplus`).
+    MotionPlus,
+    /// The Guitar Hero guitar (`guitar`).
+    Guitar,
+    /// The Guitar Hero drums (`drums`).
+    Drums,
+    /// The built-in extension of the Wii Balance Board (`balanceboard`).
+    BalanceBoard,
+    /// The built-in extension of the Wii U Pro Controller (`procontroller`).
+    ProController,
+    /// An extension that the kernel driver does not recognize, or that
+    /// this library does not know yet, with its raw identifier.
+    Unknown(String),
+}
+
+impl ExtensionKind {
+    /// Returns the raw identifier of the extension type, such as `nunchuk`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::None => "none",
+            Self::Nunchuk => "nunchuk",
+            Self::ClassicController => "classic",
+            Self::MotionPlus => "motionplus",
+            Self::Guitar => "guitar",
+            Self::Drums => "drums",
+            Self::BalanceBoard => "balanceboard",
+            Self::ProController => "procontroller",
+            Self::Unknown(raw) => raw,
+        }
+    }
+
+    /// Determines the extension plugged to a device of the given type from
+    /// its raw identifier and the channels available on the device.
+    ///
+    /// The Motion Plus channel of a Wii Remote Plus belongs to its built-in
+    /// Motion Plus, which is not an extension.
+    fn detect(raw: &str, device: &DeviceKind, available: Channels) -> Self {
+        let kind = raw.parse().unwrap();
+        if kind == Self::None
+            && *device != DeviceKind::WiiRemotePlus
+            && available.contains(Channels::MOTION_PLUS)
+        {
+            return Self::MotionPlus;
         }
-        // Decrements ref-count to zero. This closes `self.fd`, if set.
-        unsafe { xwii_monitor_unref(self.handle) };
+        kind
+    }
+}
+
+impl fmt::Display for ExtensionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ExtensionKind {
+    type Err = std::convert::Infallible;
+
+    /// Parses a raw identifier, as returned by [`Device::extension`].
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "none" => Self::None,
+            "nunchuk" => Self::Nunchuk,
+            "classic" => Self::ClassicController,
+            "motionplus" => Self::MotionPlus,
+            "guitar" => Self::Guitar,
+            "drums" => Self::Drums,
+            "balanceboard" => Self::BalanceBoard,
+            "procontroller" => Self::ProController,
+            other => Self::Unknown(other.to_owned()),
+        })
     }
 }
 
@@ -242,6 +652,7 @@ bitflags! {
     ///
     /// The `xwiimote` library uses the term "interface" to refer
     /// to this concept.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
     pub struct Channels: c_uint {
         // todo: improve docs
         /// Primary channel.
@@ -257,9 +668,9 @@ bitflags! {
         /// Classic controller channel.
         const CLASSIC_CONTROLLER = xwiimote_sys::XWII_IFACE_CLASSIC_CONTROLLER;
         /// Balance board channel.
-        const BALANCE_BOARD = xwiimote_sys::XWII_IFACE_PRO_CONTROLLER;
+        const BALANCE_BOARD = xwiimote_sys::XWII_IFACE_BALANCE_BOARD;
         /// ProController channel.
-        const PRO_CONTROLLER = xwiimote_sys::XWII_IFACE_DRUMS;
+        const PRO_CONTROLLER = xwiimote_sys::XWII_IFACE_PRO_CONTROLLER;
         /// Drums channel.
         const DRUMS = xwiimote_sys::XWII_IFACE_DRUMS;
         /// Guitar channel.
@@ -284,9 +695,98 @@ pub struct MotionPlusNormalization {
     pub factor: i32,
 }
 
+/// Determines how [`Device::open`] retries opening channels that
+/// are not available yet.
+///
+/// Right after an extension is plugged in, the kernel may report
+/// that its channel does not exist (`ENODEV`) for a few milliseconds.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct OpenRetry {
+    /// The maximum number of retries after the first attempt.
+    pub retries: u32,
+    /// The time to wait between two attempts.
+    pub interval: Duration,
+}
+
+impl Default for OpenRetry {
+    fn default() -> Self {
+        Self {
+            retries: 5,
+            interval: Duration::from_millis(20),
+        }
+    }
+}
+
+/// Detects the open channels that stop producing events while the
+/// device stays connected; see [`Device::set_watchdog`].
+///
+/// Some channels only report changes: the IR camera while it sees no
+/// sources, and the buttons and sticks of the Classic controller, the
+/// Pro controller, the guitar and the drums while nobody touches them.
+/// A watchdog takes them for stalled after `timeout`, so they are not
+/// watched by default. The default watches the channels that sense
+/// motion or weight, which report continuously because of sensor noise,
+/// and tolerates the short pauses of a weak Bluetooth link.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Watchdog {
+    /// The time without events after which a channel is considered stalled.
+    pub timeout: Duration,
+    /// The channels to watch. The [core channel](`Channels::CORE`) is
+    /// never watched, since it only reports button presses.
+    pub channels: Channels,
+    /// What to do once a channel stalls.
+    pub action: WatchdogAction,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            channels: Channels::ACCELEROMETER
+                | Channels::MOTION_PLUS
+                | Channels::NUNCHUK
+                | Channels::BALANCE_BOARD,
+            action: WatchdogAction::Reopen,
+        }
+    }
+}
+
+/// The ways in which a [`Watchdog`] deals with stalled channels.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum WatchdogAction {
+    /// Closes the stalled channels and opens them again in read-only
+    /// mode. If they cannot be opened, the event streams fail with
+    /// [`Error::Stalled`].
+    Reopen,
+    /// Makes the event streams fail with [`Error::Stalled`], so that
+    /// the application reconnects to the device; e.g. a
+    /// [`Supervisor`](supervisor::Supervisor) does so automatically.
+    Reconnect,
+}
+
+/// Caps the time for which the rumble motor runs continuously, to keep
+/// it from overheating and from draining the batteries; see
+/// [`Device::set_rumble_limit`].
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct RumbleLimit {
+    /// The time after which the motor is turned off.
+    pub max_on: Duration,
+    /// The time for which the motor stays off afterwards.
+    pub cooldown: Duration,
+}
+
+impl Default for RumbleLimit {
+    fn default() -> Self {
+        Self {
+            max_on: Duration::from_secs(10),
+            cooldown: Duration::from_secs(5),
+        }
+    }
+}
+
 /// The Wii Remote LED lights.
 #[repr(u32)]
-#[derive(Copy, Clone, Debug, FromPrimitive)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive)]
 pub enum Led {
     /// The leftmost light.
     One = xwiimote_sys::XWII_LED1,
@@ -298,41 +798,253 @@ pub enum Led {
     Four = xwiimote_sys::XWII_LED4,
 }
 
+impl Led {
+    /// Every light, from left to right.
+    const ALL: [Self; 4] = [Self::One, Self::Two, Self::Three, Self::Four];
+}
+
+bitflags! {
+    /// A set of LED lights, e.g. those that are turned on.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    pub struct Leds: u8 {
+        /// The leftmost light.
+        const ONE = 1 << 0;
+        /// The mid-left light.
+        const TWO = 1 << 1;
+        /// The mid-right light.
+        const THREE = 1 << 2;
+        /// The rightmost light.
+        const FOUR = 1 << 3;
+    }
+}
+
+impl From<Led> for Leds {
+    fn from(light: Led) -> Self {
+        Self::from_bits_truncate(1 << (light as u32 - Led::One as u32))
+    }
+}
+
+/// The contents of the `trigger` attribute of an LED light, which lists
+/// the available triggers and encloses the current one in brackets.
+struct LedTriggers {
+    available: Vec<String>,
+    current: Option<String>,
+}
+
+impl LedTriggers {
+    fn parse(attr: &str) -> Self {
+        let mut current = None;
+        let available = attr
+            .split_whitespace()
+            .map(
+                |name| match name.strip_prefix('[').and_then(|n| n.strip_suffix(']')) {
+                    Some(name) => {
+                        current = Some(name.to_owned());
+                        name.to_owned()
+                    }
+                    None => name.to_owned(),
+                },
+            )
+            .collect();
+        Self { available, current }
+    }
+}
+
+/// The queue of the output operations of a device, and the handle
+/// used by its thread.
+type Output = (OutputQueue, Arc<SharedHandle>);
+
 /// A connected Wii Remote.
+///
+/// All the methods take `&self`, so a device can be shared between tasks
+/// and threads, e.g. one that processes the events and another one that
+/// controls the lights and the rumble motor.
 pub struct Device {
     handle: *mut xwii_iface,
+    /// Serializes the calls on `handle`, which the `xwiimote` library
+    /// does not synchronize; see [`Device::with_handle`].
+    handle_lock: Arc<Mutex<()>>,
+    /// The address of the device in the `sysfs` filesystem.
+    address: Address,
     /// Is the [core channel](`Channels::CORE`) open in writable mode?
     ///
     /// Operations like toggling the rumble motor require this channel
     /// to be open in order to function.
-    core_open: bool,
+    core_open: AtomicBool,
+    /// The channels that were last opened in writable mode, which
+    /// the auto-reopen policy opens in writable mode again.
+    writable: Mutex<Channels>,
+    /// The retry policy of [`Device::open`], if enabled.
+    open_retry: Mutex<Option<OpenRetry>>,
+    /// Smooths the battery level readings.
+    battery: Mutex<BatteryEstimator>,
+    /// Relays the received events to the observers of the device.
+    broadcast: Arc<Broadcast>,
+    /// The time for which the channel availability must be stable
+    /// before a watch event is reported, if set.
+    watch_debounce: Mutex<Option<Duration>>,
+    /// The nominal interval between two motion reports, if the streams
+    /// should detect the reports that were lost.
+    drop_detection: Mutex<Option<Duration>>,
+    /// The frame in which the streams report the sensor data.
+    frame: Mutex<CoordinateFrame>,
+    /// Throttles the sensors as the battery drains, if set.
+    battery_policy: Mutex<Option<BatteryPolicy>>,
+    /// Detects the stalled channels, if set.
+    watchdog: Mutex<Option<Watchdog>>,
+    /// The channels to open again whenever they become available.
+    auto_reopen: Mutex<Channels>,
+    /// The last state written to each LED light, if any.
+    cached_leds: Mutex<[Option<bool>; 4]>,
+    /// The period of the rumble intensity modulation.
+    pwm_period: Mutex<Duration>,
+    /// The number of the last rumble pattern started, which is the only
+    /// one that controls the motor.
+    rumble_owner: AtomicU64,
+    /// The cap on the continuous operation of the rumble motor, if any.
+    rumble_limit: Mutex<Option<RumbleLimit>>,
+    /// Counts the events read by the streams of the device.
+    counters: Mutex<EventCounters>,
+    /// Measures the rate of the events received through each channel.
+    stats: Mutex<ChannelStats>,
+    /// Should the `evdev` nodes of the open channels be grabbed?
+    evdev_grab: AtomicBool,
+    /// The events read while refreshing the interfaces of the handle,
+    /// which the event streams produce before reading new ones.
+    stash: Mutex<VecDeque<xwii_event>>,
+    /// Executes the output operations, if a timeout is set.
+    ///
+    /// The lock is held while an operation runs, which serializes
+    /// the output operations of all threads.
+    output: Mutex<Option<Output>>,
+}
+
+// The calls on the device handle are serialized by `handle_lock`, which
+// the output thread also takes before writing the LED and rumble outputs.
+unsafe impl Send for Device {}
+unsafe impl Sync for Device {}
+
+/// Locks `mutex`, ignoring the panics of its previous holders; the state
+/// of the device remains consistent after every single update.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
 impl Device {
     /// Connects to the Wii Remote specified by `address`.
+    ///
+    /// This function blocks the calling thread for 100 ms, since opening
+    /// a device file right after it is discovered results in a "Transport
+    /// is not connected" error. Async code should prefer
+    /// [`Device::connect_async`], which waits until the device is ready.
     pub fn connect(address: &Address) -> Result<Self> {
-        let path = address.to_c_string();
-
-        // Opening a device file immediately after being discovered results
-        // in a "Transport is not connected" error. This delays the operation,
-        // but it isn't ideal (since the delay is arbitrary).
         std::thread::sleep(Duration::from_millis(100));
+        Self::new(address, true)
+    }
+
+    /// Connects to the Wii Remote specified by `address`, retrying with
+    /// an exponential backoff until the kernel has set up the device.
+    ///
+    /// The device is ready once at least one of its channels is available.
+    /// Fails with the last error if the device is not ready within
+    /// `timeout`, or immediately if the error is not transient
+    /// (e.g. [`Error::Permission`]).
+    ///
+    /// The retries wait on the event loop, so the executor thread
+    /// is never blocked.
+    pub async fn connect_async(address: &Address, timeout: Duration) -> Result<Self> {
+        Self::connect_ready(address, timeout, true).await
+    }
+
+    /// Configures the connection to the Wii Remote specified by `address`;
+    /// see [`DeviceBuilder`].
+    pub fn builder(address: &Address) -> DeviceBuilder {
+        DeviceBuilder::new(address)
+    }
+
+    /// Implements [`Device::connect_async`], watching the device
+    /// for hot-plug events if `watch` is set.
+    pub(crate) async fn connect_ready(
+        address: &Address,
+        timeout: Duration,
+        watch: bool,
+    ) -> Result<Self> {
+        let deadline = Instant::now() + timeout;
+        let backoff = Backoff {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(200),
+            multiplier: 2.0,
+            jitter: 0.0,
+            max_retries: None,
+        };
+        let mut failures = 0;
+        loop {
+            let err = match Self::new(address, watch) {
+                Ok(device) if !device.available().is_empty() => return Ok(device),
+                // The kernel creates the `evdev` nodes of the channels
+                // shortly after the device itself. The handle only looks
+                // for them once created, so a new one is needed.
+                Ok(_) => io::Error::new(io::ErrorKind::TimedOut, "the device is not ready").into(),
+                Err(err) if Self::is_transient(&err) => err,
+                Err(err) => return Err(err),
+            };
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(err);
+            }
+            failures += 1;
+            timer::sleep(backoff.delay(failures, 0.0).min(deadline - now)).await?;
+        }
+    }
+
+    /// Checks whether a connection attempt failed because the device
+    /// was discovered before the kernel finished setting it up.
+    fn is_transient(err: &Error) -> bool {
+        matches!(err, Error::Disconnected)
+            || matches!(err.raw_os_error(), Some(libc::ENOTCONN | libc::ENOENT))
+    }
 
+    /// Creates the handle of the device at `address`, and watches
+    /// the device for hot-plug events if `watch` is set.
+    fn new(address: &Address, watch: bool) -> Result<Self> {
+        let path = address.to_c_string();
         let mut handle = ptr::null_mut();
         let res_code = unsafe { xwii_iface_new(&mut handle, path.as_ptr()) };
         bail_if!(res_code != 0);
 
-        // Watch the device for hot-plug events. Otherwise the `xwii_iface_dispatch`
-        // function does not report events of type `XWII_EVENT_GONE`,
-        // which we need in order to tell the reactor to remove interest
-        // from the device file.
-        let res_code = unsafe { xwii_iface_watch(handle, true) };
-        bail_if!(res_code != 0);
-
-        Ok(Self {
+        let device = Self {
             handle,
-            core_open: false,
-        })
+            handle_lock: Arc::default(),
+            address: address.clone(),
+            core_open: AtomicBool::new(false),
+            writable: Mutex::new(Channels::empty()),
+            open_retry: Mutex::default(),
+            battery: Mutex::default(),
+            broadcast: Arc::default(),
+            watch_debounce: Mutex::default(),
+            drop_detection: Mutex::default(),
+            frame: Mutex::default(),
+            battery_policy: Mutex::default(),
+            watchdog: Mutex::default(),
+            auto_reopen: Mutex::new(Channels::empty()),
+            cached_leds: Mutex::default(),
+            pwm_period: Mutex::new(Duration::from_millis(40)),
+            rumble_owner: AtomicU64::new(0),
+            rumble_limit: Mutex::new(None),
+            counters: Mutex::default(),
+            stats: Mutex::default(),
+            evdev_grab: AtomicBool::new(false),
+            stash: Mutex::default(),
+            output: Mutex::default(),
+        };
+        // Without watching the device, the `xwii_iface_dispatch` function
+        // does not report events of type `XWII_EVENT_GONE`, which we need
+        // in order to tell the reactor to remove interest from the device file.
+        if watch {
+            let res_code = device.with_handle(|handle| unsafe { xwii_iface_watch(handle, true) });
+            bail_if!(res_code != 0);
+        }
+        Ok(device)
     }
 
     // Channels.
@@ -345,24 +1057,323 @@ impl Device {
     ///
     /// A channel may be closed automatically if an extension is unplugged
     /// or on error conditions.
-    pub fn open(&mut self, channels: Channels, writable: bool) -> Result<()> {
+    ///
+    /// If a retry policy is set with [`Device::set_open_retry`], the
+    /// function blocks and tries again while the kernel reports that
+    /// a channel does not exist.
+    pub fn open(&self, channels: Channels, writable: bool) -> Result<()> {
         let mut ifaces = channels.bits();
         if writable {
             ifaces |= XWII_IFACE_WRITABLE;
         }
-        let res_code = unsafe { xwii_iface_open(self.handle, ifaces) };
-        bail_if!(res_code != 0);
+        self.sync_output()?;
+        let open_retry = *lock(&self.open_retry);
+        let mut retries = open_retry.map_or(0, |retry| retry.retries);
+        while let Err(err) = self.open_ifaces(ifaces) {
+            match (open_retry, err.raw_os_error()) {
+                // Channels that opened successfully are ignored on the next try.
+                (Some(retry), Some(libc::ENODEV)) if retries > 0 => {
+                    retries -= 1;
+                    std::thread::sleep(retry.interval);
+                }
+                _ => return Err(Error::from_channel_op(err, channels, self.get_open())),
+            }
+        }
+        self.opened(channels, writable)
+    }
+
+    /// Opens the given channels like [`Device::open`], but waits while
+    /// the kernel has not created their interfaces yet, e.g. right after
+    /// the device or an extension is plugged in.
+    ///
+    /// The function watches the kernel events of the device, and tries
+    /// again whenever an interface is added, until `timeout` elapses.
+    /// The executor thread is never blocked, and the retry policy set
+    /// with [`Device::set_open_retry`] is not used.
+    ///
+    /// The handle only learns about the new interfaces if the device is
+    /// [watched](`ConnectOptions::watch`) for hot-plug events, as it is
+    /// by default. The events read meanwhile are kept for the event streams.
+    pub async fn open_async(
+        &self,
+        channels: Channels,
+        writable: bool,
+        timeout: Duration,
+    ) -> Result<()> {
+        let mut ifaces = channels.bits();
+        if writable {
+            ifaces |= XWII_IFACE_WRITABLE;
+        }
+        let deadline = Instant::now() + timeout;
+        // Subscribe before the first attempt, so that no event is missed.
+        let uevents = AsyncFd::new(UeventSocket::new()?)?;
+        let syspath = fs::canonicalize(&self.address.0)?;
+        self.sync_output()?;
+        while let Err(err) = self.open_ifaces(ifaces) {
+            let now = Instant::now();
+            if err.raw_os_error() != Some(libc::ENODEV) || now >= deadline {
+                return Err(Error::from_channel_op(err, channels, self.get_open()));
+            }
+            Self::wait_uevent(&uevents, &syspath, deadline - now).await?;
+            self.refresh_interfaces();
+        }
+        self.opened(channels, writable)
+    }
+
+    /// Waits until the given channels are available, e.g. right after an
+    /// extension is plugged in, and then opens them like [`Device::open`].
+    ///
+    /// The kernel reports an extension slightly before its interface is
+    /// ready, so the attempts that fail with `EAGAIN` or `ENODEV` are
+    /// retried too. If the channels cannot be opened before `timeout`
+    /// elapses, the last error is returned; [`Error::ChannelClosed`] lists
+    /// the channels that are still not available. Like
+    /// [`Device::open_async`], the executor thread is never blocked, and
+    /// the handle only sees the new interfaces if the device is watched.
+    pub async fn open_when_available(
+        &self,
+        channels: Channels,
+        writable: bool,
+        timeout: Duration,
+    ) -> Result<()> {
+        let mut ifaces = channels.bits();
+        if writable {
+            ifaces |= XWII_IFACE_WRITABLE;
+        }
+        let deadline = Instant::now() + timeout;
+        // Subscribe before the first check, so that no event is missed.
+        let uevents = AsyncFd::new(UeventSocket::new()?)?;
+        let syspath = fs::canonicalize(&self.address.0)?;
+        self.sync_output()?;
+        loop {
+            self.refresh_interfaces();
+            let missing = channels.difference(self.available());
+            let err = if missing.is_empty() {
+                match self.open_ifaces(ifaces) {
+                    Ok(()) => break,
+                    Err(err) if matches!(err.raw_os_error(), Some(libc::EAGAIN | libc::ENODEV)) => {
+                        Error::from_channel_op(err, channels, self.get_open())
+                    }
+                    Err(err) => return Err(Error::from_channel_op(err, channels, self.get_open())),
+                }
+            } else {
+                Error::ChannelClosed(missing)
+            };
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(err);
+            }
+            Self::wait_uevent(&uevents, &syspath, deadline - now).await?;
+        }
+        self.opened(channels, writable)
+    }
+
+    /// Opens the given interfaces, including the `XWII_IFACE_WRITABLE` flag.
+    fn open_ifaces(&self, ifaces: c_uint) -> io::Result<()> {
+        self.with_handle(|handle| {
+            let res_code = unsafe { xwii_iface_open(handle, ifaces) };
+            (res_code == 0)
+                .then_some(())
+                .ok_or_else(io::Error::last_os_error)
+        })
+    }
+
+    /// Waits until the kernel reports an event of the device with the given
+    /// `syspath`, or until `timeout` elapses.
+    ///
+    /// Waits for [`Device::OPEN_POLL_INTERVAL`] at most, in case an event
+    /// was lost; the caller should check the state of the device again.
+    async fn wait_uevent(
+        uevents: &AsyncFd<UeventSocket>,
+        syspath: &Path,
+        timeout: Duration,
+    ) -> Result<()> {
+        let mut timer = Sleep::new(timeout.min(Self::OPEN_POLL_INTERVAL))?;
+        poll_fn(|cx| {
+            if let Poll::Ready(res) = Pin::new(&mut timer).poll(cx) {
+                return Poll::Ready(res);
+            }
+            loop {
+                let event = uevents.poll_io(cx, libc::EPOLLIN, |socket| {
+                    socket.receive()?.ok_or(io::ErrorKind::WouldBlock.into())
+                });
+                match event {
+                    Poll::Ready(Ok(event)) if Path::new(&event.syspath()).starts_with(syspath) => {
+                        return Poll::Ready(Ok(()));
+                    }
+                    Poll::Ready(Ok(_)) => {} // an event of another device.
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+        })
+        .await
+    }
+
+    /// Processes the pending hot-plug events of the handle, so that it sees
+    /// the interfaces that the kernel created or removed since.
+    ///
+    /// The `xwiimote` library only looks for the interfaces of a device
+    /// when the handle is created, and when it dispatches a watch event.
+    /// The events read along the way are stashed for the event streams,
+    /// which are woken up. Does nothing if the device is not watched.
+    fn refresh_interfaces(&self) {
+        // Keep the streams from reading newer events in the meantime.
+        let mut stash = lock(&self.stash);
+        let len = stash.len();
+        let mut event = xwii_event::default();
+        while self.with_handle(|handle| unsafe {
+            xwii_iface_dispatch(handle, &mut event, mem::size_of::<xwii_event>())
+        }) == 0
+        {
+            // The streams report the errors when they read again.
+            if stash.len() == Self::MAX_STASHED_EVENTS {
+                stash.pop_front();
+            }
+            stash.push_back(event);
+        }
+        if stash.len() != len {
+            drop(stash);
+            let fd = self.with_handle(|handle| unsafe { xwii_iface_get_fd(handle) });
+            Reactor::get().wake_waiters(fd);
+        }
+    }
+
+    /// The maximum number of events stashed by [`Device::refresh_interfaces`];
+    /// the oldest ones are discarded if no stream reads them.
+    const MAX_STASHED_EVENTS: usize = 1024;
+
+    /// Reads the next event of the device into `event`, starting with those
+    /// stashed by [`Device::refresh_interfaces`]. Returns the result code
+    /// of `xwii_iface_dispatch`.
+    pub(crate) fn dispatch(&self, event: &mut xwii_event) -> c_int {
+        let mut stash = lock(&self.stash);
+        match stash.pop_front() {
+            Some(stashed) => {
+                *event = stashed;
+                0
+            }
+            None => self.with_handle(|handle| unsafe {
+                xwii_iface_dispatch(handle, event, mem::size_of::<xwii_event>())
+            }),
+        }
+    }
 
+    /// The maximum time between two attempts of [`Device::open_async`]
+    /// and [`Device::open_when_available`].
+    const OPEN_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+    /// Updates the state of the device after the given channels are opened.
+    fn opened(&self, channels: Channels, writable: bool) -> Result<()> {
         if channels.contains(Channels::CORE) && writable {
-            self.core_open = true;
+            self.core_open.store(true, Ordering::Relaxed);
+        }
+        let mut writable_channels = lock(&self.writable);
+        if writable {
+            writable_channels.insert(channels);
+        } else {
+            writable_channels.remove(channels);
+        }
+        drop(writable_channels);
+        if self.evdev_grab.load(Ordering::Relaxed) {
+            self.set_evdev_grab(true)?;
+        }
+        Ok(())
+    }
+
+    /// Watches the open channels for stalls, or disables the watchdog
+    /// if `watchdog` is [`None`].
+    ///
+    /// The event streams of the device check the time of the last event
+    /// of each watched channel (see [`Device::channel_stats`]), and react
+    /// to those that produced no events for the timeout of the watchdog.
+    /// Only affects the streams created afterwards.
+    ///
+    /// Disabled by default.
+    pub fn set_watchdog(&self, watchdog: Option<Watchdog>) {
+        *lock(&self.watchdog) = watchdog;
+    }
+
+    /// Closes the given channels and opens them again in read-only mode,
+    /// on behalf of a [`Watchdog`].
+    pub(crate) fn reopen_stalled(&self, channels: Channels) -> Result<()> {
+        let channels = channels.difference(Channels::CORE);
+        self.sync_output()?;
+        let res = self.with_handle(|handle| {
+            unsafe { xwii_iface_close(handle, channels.bits()) };
+            let res_code = unsafe { xwii_iface_open(handle, channels.bits()) };
+            (res_code == 0)
+                .then_some(())
+                .ok_or_else(io::Error::last_os_error)
+        });
+        if let Err(err) = res {
+            return Err(Error::from_channel_op(err, channels, self.get_open()));
+        }
+        self.update_counters(|c| c.reopens += 1);
+        if self.evdev_grab.load(Ordering::Relaxed) {
+            self.set_evdev_grab(true)?;
         }
         Ok(())
     }
 
+    /// Opens the given channels whenever they become available again,
+    /// e.g. when an extension is plugged back in; or disables this policy
+    /// if `channels` is empty. A channel is opened in writable mode if it
+    /// was last opened in that mode, and in read-only mode otherwise.
+    ///
+    /// The event streams of the device check the channels after reporting
+    /// each [`Event::Other`], which happens once the connection settles
+    /// if [`Device::set_watch_debounce`] is set, and report an
+    /// [`Event::Reopened`] with the channels they opened. If an interface
+    /// is not ready yet, the channels are tried again according to the
+    /// policy of [`Device::set_open_retry`], or [`OpenRetry::default`]
+    /// if none is set, and after the next watch event.
+    /// The channels that are open already are not affected.
+    ///
+    /// Disabled by default.
+    pub fn set_auto_reopen(&self, channels: Channels) {
+        *lock(&self.auto_reopen) = channels;
+    }
+
+    /// Opens the channels of the auto-reopen policy that are available
+    /// but not open, on behalf of an event stream.
+    ///
+    /// Returns the channels that were opened. Fails if some of them
+    /// could not be opened, e.g. because their interfaces are not ready.
+    pub(crate) fn reopen_available(&self) -> Result<Channels> {
+        let wanted = *lock(&self.auto_reopen);
+        let channels = wanted
+            .intersection(self.available())
+            .difference(self.get_open());
+        if channels.is_empty() {
+            return Ok(channels);
+        }
+        self.sync_output()?;
+        let writable = channels.intersection(*lock(&self.writable));
+        if !writable.is_empty() {
+            self.open_ifaces(writable.bits() | XWII_IFACE_WRITABLE)?;
+            self.opened(writable, true)?;
+        }
+        let read_only = channels.difference(writable);
+        if !read_only.is_empty() {
+            self.open_ifaces(read_only.bits())?;
+            self.opened(read_only, false)?;
+        }
+        Ok(channels)
+    }
+
+    /// Sets the policy for retrying [`Device::open`] when a channel is
+    /// not available yet, or disables retries if `retry` is [`None`].
+    ///
+    /// Disabled by default.
+    pub fn set_open_retry(&self, retry: Option<OpenRetry>) {
+        *lock(&self.open_retry) = retry;
+    }
+
     /// Open the [core channel](`Channels::CORE`) in writable mode,
     /// if not already open.
-    fn ensure_core_open(&mut self) -> Result<()> {
-        if !self.core_open {
+    fn ensure_core_open(&self) -> Result<()> {
+        if !self.core_open.load(Ordering::Relaxed) {
             self.open(Channels::CORE, true)?
         }
         Ok(())
@@ -371,17 +1382,19 @@ impl Device {
     /// Closes the given channels.
     ///
     /// If a channel is already closed, it is ignored.
-    pub fn close(&mut self, channels: Channels) -> Result<()> {
+    pub fn close(&self, channels: Channels) -> Result<()> {
+        self.sync_output()?;
         if channels.contains(Channels::CORE) {
-            self.core_open = false;
+            self.core_open.store(false, Ordering::Relaxed);
         }
-        unsafe { xwii_iface_close(self.handle, channels.bits()) };
+        self.with_handle(|handle| unsafe { xwii_iface_close(handle, channels.bits()) });
         Ok(())
     }
 
     /// Lists the currently open channels.
     pub fn get_open(&self) -> Channels {
-        Channels::from_bits(unsafe { xwii_iface_opened(self.handle) }).unwrap()
+        Channels::from_bits(self.with_handle(|handle| unsafe { xwii_iface_opened(handle) }))
+            .unwrap()
     }
 
     /// Lists the channels that can be opened, including those
@@ -391,12 +1404,182 @@ impl Device {
     /// to the device. Conversely, it becomes unavailable when the extension
     /// is disconnected.
     pub fn available(&self) -> Channels {
-        Channels::from_bits(unsafe { xwii_iface_available(self.handle) }).unwrap()
+        Channels::from_bits(self.with_handle(|handle| unsafe { xwii_iface_available(handle) }))
+            .unwrap()
     }
 
-    // Events.
-
-    /// Returns an stream that produces events received from the device,
+    /// Delays the reporting of extension hot-plug events ([`Event::Other`])
+    /// until the set of available channels has not changed for `window`,
+    /// or reports them immediately if `window` is [`None`].
+    ///
+    /// Worn extension connectors may make contact intermittently when
+    /// moved, which causes bursts of plug and unplug events. With a window
+    /// of a few hundred milliseconds, a single event is reported once the
+    /// connection settles, and none at all if the extension ends up in
+    /// its original state. Only affects the streams created afterwards.
+    ///
+    /// Disabled by default.
+    pub fn set_watch_debounce(&self, window: Option<Duration>) {
+        *lock(&self.watch_debounce) = window;
+    }
+
+    /// Reports an [`Event::Dropped`] whenever the time between two
+    /// consecutive motion events of the same kind (e.g. two accelerometer
+    /// readings) suggests that reports were lost, given the nominal
+    /// `period` between reports; or disables the detection if `period`
+    /// is [`None`].
+    ///
+    /// The kernel does not tell when it discards events, so the number
+    /// of lost reports is estimated from the gaps in the timestamps.
+    /// A Wii Remote sends a report every 10 milliseconds or so, but
+    /// they often arrive in pairs; gaps shorter than three periods are
+    /// thus ignored. Only affects the streams created afterwards.
+    ///
+    /// Disabled by default.
+    pub fn set_drop_detection(&self, period: Option<Duration>) {
+        *lock(&self.drop_detection) = period;
+    }
+
+    /// Sets the frame in which the streams report the accelerometer,
+    /// Motion Plus and IR camera data; see [`CoordinateFrame`].
+    ///
+    /// Helpers that take raw readings, such as
+    /// [`Tilt::from_acceleration`](orientation::Tilt::from_acceleration),
+    /// expect them in the device frame, into which
+    /// [`CoordinateFrame::event`] converts them back. Likewise,
+    /// an [`Arbiter`](pointer::Arbiter) must be told the frame of the
+    /// events it receives. Only affects the streams created afterwards.
+    ///
+    /// Defaults to [`CoordinateFrame::DEVICE`].
+    pub fn set_coordinate_frame(&self, frame: CoordinateFrame) {
+        *lock(&self.frame) = frame;
+    }
+
+    /// Returns the frame in which the streams report the sensor data.
+    pub fn coordinate_frame(&self) -> CoordinateFrame {
+        *lock(&self.frame)
+    }
+
+    /// Returns the counters of the events read by the streams of the
+    /// device since it was connected, or since the counters were reset.
+    pub fn event_counters(&self) -> EventCounters {
+        *lock(&self.counters)
+    }
+
+    /// Returns the rates of the events received through each channel by
+    /// the streams of the device, e.g. to detect a stuck sensor.
+    pub fn channel_stats(&self) -> ChannelStats {
+        lock(&self.stats).clone()
+    }
+
+    /// Resets the counters returned by [`Device::event_counters`].
+    pub fn reset_event_counters(&self) {
+        *lock(&self.counters) = EventCounters::default();
+    }
+
+    /// Applies `f` to the event counters.
+    pub(crate) fn update_counters(&self, f: impl FnOnce(&mut EventCounters)) {
+        f(&mut lock(&self.counters));
+    }
+
+    /// Reduces the fidelity of the sensors according to `policy` as the
+    /// battery drains, or disables the throttling if `policy` is [`None`].
+    ///
+    /// The streams of the device read the battery level once per minute,
+    /// close the channels that the policy turns off at that level, and
+    /// coalesce the motion events that arrive too soon. The closed channels
+    /// are not reopened if the level rises again, e.g. while charging.
+    /// Only affects the streams created afterwards.
+    ///
+    /// Disabled by default.
+    pub fn set_battery_policy(&self, policy: Option<BatteryPolicy>) {
+        *lock(&self.battery_policy) = policy;
+    }
+
+    /// Closes the channels turned off by the battery policy, which
+    /// never include the core channel.
+    pub(crate) fn close_throttled(&self, channels: Channels) {
+        // Wait for the pending output operations, which may need them.
+        let _ = self.sync_output();
+        self.with_handle(|handle| unsafe { xwii_iface_close(handle, channels.bits()) });
+    }
+
+    /// Grabs the `evdev` nodes of the open channels, so that the rest of
+    /// the system stops receiving their events; e.g. the desktop no longer
+    /// interprets the D-pad as arrow keys while a game uses the device.
+    ///
+    /// The channels opened afterwards are grabbed too, until
+    /// [`Device::ungrab_evdev`] is called or the device is dropped.
+    /// Fails with `EBUSY` if another process grabbed a node already;
+    /// see the [`conflict`] module to find out which one.
+    pub fn grab_evdev(&self) -> Result<()> {
+        self.evdev_grab.store(true, Ordering::Relaxed);
+        self.set_evdev_grab(true)
+    }
+
+    /// Releases the `evdev` nodes grabbed by [`Device::grab_evdev`], so
+    /// that the rest of the system receives their events again.
+    pub fn ungrab_evdev(&self) -> Result<()> {
+        self.evdev_grab.store(false, Ordering::Relaxed);
+        self.set_evdev_grab(false)
+    }
+
+    /// Returns the `hidraw` node of the device (e.g. `/dev/hidraw3`), which
+    /// exchanges raw HID reports with the device.
+    pub fn hidraw_node(&self) -> Result<PathBuf> {
+        self.address.hidraw_node()
+    }
+
+    /// Lists the `evdev` nodes of the device (e.g. `/dev/input/event12`),
+    /// one for each of its input interfaces, such as the core buttons or
+    /// the accelerometer.
+    ///
+    /// Other programs, such as desktop environments, may read these nodes
+    /// too; see [`Device::grab_evdev`] to keep them from reacting to the
+    /// input of the device, and [`conflict::check`] to find them.
+    pub fn event_nodes(&self) -> Result<Vec<PathBuf>> {
+        let nodes = self.address.evdev_nodes()?;
+        Ok(nodes.into_iter().map(|(node, _)| node).collect())
+    }
+
+    /// Grabs or releases the `evdev` nodes of the device.
+    ///
+    /// A grab only lets the open file that holds it receive the events of
+    /// a node, so it must be held by the files that the `xwiimote` library
+    /// opened for the channels. The library watches them through its
+    /// `epoll` descriptor, whose targets the kernel lists in `fdinfo`;
+    /// the handle is locked meanwhile, so that they stay open.
+    fn set_evdev_grab(&self, grab: bool) -> Result<()> {
+        let nodes: Vec<_> = self
+            .address
+            .evdev_nodes()?
+            .into_iter()
+            .map(|(node, _)| node)
+            .collect();
+        self.with_handle(|handle| {
+            let epoll_fd = unsafe { xwii_iface_get_fd(handle) };
+            let fdinfo = fs::read_to_string(format!("/proc/self/fdinfo/{epoll_fd}"))?;
+            for fd in epoll_targets(&fdinfo) {
+                // The library also watches for hot-plug events.
+                match fs::read_link(format!("/proc/self/fd/{fd}")) {
+                    Ok(target) if nodes.contains(&target) => {}
+                    _ => continue,
+                }
+                // Grabbing a node twice fails, even through the same file.
+                // Releasing a node that is not grabbed fails too; ignore it.
+                unsafe { libc::ioctl(fd, conflict::EVIOCGRAB, 0 as c_int) };
+                if grab {
+                    let res_code = unsafe { libc::ioctl(fd, conflict::EVIOCGRAB, 1 as c_int) };
+                    bail_if!(res_code != 0);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    // Events.
+
+    /// Returns an stream that produces events received from the device,
     /// including the time at which the kernel generated them.
     ///
     /// Most event types are received only if the appropriate channels
@@ -405,20 +1588,240 @@ impl Device {
         EventStream::new(self)
     }
 
+    /// Turns the device into a stream that produces its events, including
+    /// the time at which the kernel generated them.
+    ///
+    /// The stream owns the device, so it can be moved into a spawned task;
+    /// [`OwnedEvents::device`] gives access to the device from there.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use futures_util::TryStreamExt;
+    /// use xwiimote::events::{Event, KeyState};
+    /// use xwiimote::{Device, Monitor};
+    ///
+    /// # tokio_test::block_on(async {
+    /// # let address = Monitor::enumerate()?.try_next().await?.unwrap();
+    /// let mut events = Device::connect(&address)?.into_events()?;
+    /// // The stream can outlive the function that created it.
+    /// let reader = std::thread::spawn(move || {
+    ///     futures_executor::block_on(async move {
+    ///         while let Some((event, _)) = events.try_next().await? {
+    ///             if let Event::Key(_, state) = event {
+    ///                 events.device().set_rumble(matches!(state, KeyState::Down))?;
+    ///             }
+    ///         }
+    ///         Ok::<_, xwiimote::Error>(())
+    ///     })
+    /// });
+    /// reader.join().unwrap()?;
+    /// # Ok::<(), xwiimote::Error>(())
+    /// # }).unwrap();
+    /// ```
+    pub fn into_events(self) -> Result<OwnedEvents> {
+        OwnedEvents::new(self)
+    }
+
+    /// Splits the device into an event half and a control half, so that
+    /// one task can read the events while another one manages the LED
+    /// lights, the rumble motor and the open channels.
+    ///
+    /// Both halves borrow the device, which may be used directly as well.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use futures_util::future::try_join;
+    /// use futures_util::TryStreamExt;
+    /// use std::time::Duration;
+    /// use xwiimote::events::{Event, KeyState};
+    /// use xwiimote::{Device, Led, Monitor};
+    ///
+    /// # tokio_test::block_on(async {
+    /// # let address = Monitor::enumerate()?.try_next().await?.unwrap();
+    /// let device = Device::connect(&address)?;
+    /// let (mut events, control) = device.split()?;
+    ///
+    /// let keys = async {
+    ///     while let Some((event, _)) = events.try_next().await? {
+    ///         if let Event::Key(key, KeyState::Down) = event {
+    ///             println!("{key:?} pressed");
+    ///         }
+    ///     }
+    ///     Ok::<_, xwiimote::Error>(())
+    /// };
+    /// let lights = async {
+    ///     for light in [Led::One, Led::Two, Led::Three, Led::Four] {
+    ///         control.set_led(light, true)?;
+    ///         control.rumble_for(Duration::from_millis(100)).await?;
+    ///     }
+    ///     Ok::<_, xwiimote::Error>(())
+    /// };
+    /// try_join(keys, lights).await?;
+    /// # Ok::<(), xwiimote::Error>(())
+    /// # }).unwrap();
+    /// ```
+    pub fn split(&self) -> Result<(DeviceEvents<'_>, DeviceControl<'_>)> {
+        Ok((DeviceEvents::new(self)?, DeviceControl::new(self)))
+    }
+
+    /// Opens the channel `C` and returns a stream that produces the events
+    /// received through it, including the time at which the kernel
+    /// generated them.
+    ///
+    /// The [core channel](`Channels::CORE`) is opened in writable mode
+    /// so that out-of-band actions such as [`Device::set_rumble`] keep
+    /// working; every other channel is opened in read-only mode.
+    /// See the [`channels`] module for the list of available channels.
+    pub fn open_typed<C: Channel>(
+        &self,
+    ) -> Result<impl Stream<Item = Result<(C::Event, SystemTime)>> + '_> {
+        self.open(C::CHANNELS, C::CHANNELS == Channels::CORE)?;
+        Ok(TypedEventStream::<C>::new(EventStream::new(self)?))
+    }
+
+    /// Returns a stream that produces the type of the extension plugged
+    /// to the device whenever it changes, i.e. when an extension is
+    /// plugged or unplugged.
+    ///
+    /// The device must be watched for hot-plug events; see
+    /// [`Device::connect`]. Like an [`Observer`], this stream receives
+    /// copies of the hot-plug events read by the other streams of the
+    /// device, so one of them (e.g. [`Device::events`]) must be polled
+    /// for it to make progress. The stream ends once the device
+    /// disconnects.
+    pub fn extension_changes(&self) -> Result<impl Stream<Item = Result<ExtensionKind>> + '_> {
+        let hotplug = self.broadcast.subscribe(
+            Broadcast::CAPACITY,
+            Box::new(|event| matches!(event, Event::Other)),
+        );
+        Ok(ExtensionChanges::new(
+            bus::Subscription::new(hotplug),
+            self.extension_kind()?,
+            || self.extension_kind(),
+        ))
+    }
+
+    /// Returns a read-only handle to the device, which can query its
+    /// state and receive copies of the events produced by the streams
+    /// of this device.
+    ///
+    /// Unlike a [`Device`], observers do not keep the device connected.
+    pub fn observer(&self) -> Observer {
+        Observer::new(self.address.clone(), Arc::clone(&self.broadcast))
+    }
+
     // Out-of-band actions (which don't require any open channel to work).
 
     /// Reads the current state of an LED light.
     pub fn led(&self, light: Led) -> Result<bool> {
         let mut enabled = false;
-        let res_code = unsafe { xwii_iface_get_led(self.handle, light as c_uint, &mut enabled) };
+        let res_code = self.with_handle(|handle| unsafe {
+            xwii_iface_get_led(handle, light as c_uint, &mut enabled)
+        });
         bail_if!(res_code != 0);
         Ok(enabled)
     }
 
     /// Changes the state of an LED light.
     pub fn set_led(&self, light: Led, enabled: bool) -> Result<()> {
-        let res_code = unsafe { xwii_iface_set_led(self.handle, light as c_uint, enabled) };
-        bail_if!(res_code != 0);
+        self.run_output(move |handle| {
+            let res_code = unsafe { xwii_iface_set_led(handle, light as c_uint, enabled) };
+            bail_if!(res_code != 0);
+            Ok(())
+        })?;
+        self.cache_led(light, Some(enabled));
+        Ok(())
+    }
+
+    /// Reads the set of LED lights that are turned on.
+    pub fn leds(&self) -> Result<Leds> {
+        let mut leds = Leds::empty();
+        for light in Led::ALL {
+            leds.set(light.into(), self.led(light)?);
+        }
+        Ok(leds)
+    }
+
+    /// Turns on the given LED lights, and turns off the others.
+    ///
+    /// The kernel driver sends a report to the device for every light that
+    /// changes, so the lights known to be in the right state (see
+    /// [`Device::cached_leds`]) are not written again. The remaining
+    /// writes are issued back to back.
+    pub fn set_leds(&self, leds: Leds) -> Result<()> {
+        let cached = self.cached_leds();
+        let changes: Vec<_> = Led::ALL
+            .into_iter()
+            .zip(cached)
+            .map(|(light, cached)| (light, leds.contains(light.into()), cached))
+            .filter(|&(_, enabled, cached)| cached != Some(enabled))
+            .map(|(light, enabled, _)| (light, enabled))
+            .collect();
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let writes = changes.clone();
+        let res = self.run_output(move |handle| {
+            for (light, enabled) in writes {
+                let res_code = unsafe { xwii_iface_set_led(handle, light as c_uint, enabled) };
+                bail_if!(res_code != 0);
+            }
+            Ok(())
+        });
+        for (light, enabled) in changes {
+            // Some of the writes may have failed.
+            let state = res.is_ok().then_some(enabled);
+            self.cache_led(light, state);
+        }
+        res
+    }
+
+    /// Returns the state of each LED light, from [`Led::One`] to [`Led::Four`],
+    /// as last written through [`Device::set_led`].
+    ///
+    /// Unlike [`Device::led`], this function does not query the device.
+    /// Code that updates a few lights while other parts of the application
+    /// control the rest (e.g. a battery indicator beside an animation)
+    /// can compute the new state from the cached one, instead of reading
+    /// the lights back and racing with the other writers.
+    ///
+    /// The state of a light is [`None`] if it was never written through
+    /// this device handle, or if a kernel trigger was bound to it since.
+    pub fn cached_leds(&self) -> [Option<bool>; 4] {
+        *lock(&self.cached_leds)
+    }
+
+    fn cache_led(&self, light: Led, enabled: Option<bool>) {
+        lock(&self.cached_leds)[light as usize - Led::One as usize] = enabled;
+    }
+
+    /// Returns the name of the kernel trigger that controls an LED light,
+    /// or `none` if the light is controlled manually.
+    pub fn led_trigger(&self, light: Led) -> Result<String> {
+        let triggers = fs::read_to_string(self.address.led_dir(light)?.join("trigger"))?;
+        LedTriggers::parse(&triggers).current.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "no LED trigger is selected").into()
+        })
+    }
+
+    /// Lists the names of the kernel triggers that can control
+    /// an LED light, such as `battery-charging` or `timer`.
+    pub fn available_led_triggers(&self, light: Led) -> Result<Vec<String>> {
+        let triggers = fs::read_to_string(self.address.led_dir(light)?.join("trigger"))?;
+        Ok(LedTriggers::parse(&triggers).available)
+    }
+
+    /// Binds an LED light to a kernel trigger, which then turns the
+    /// light on and off without any intervention from user space.
+    /// The `none` trigger returns the control of the light to
+    /// [`Device::set_led`].
+    ///
+    /// This requires write access to the `sysfs` attributes of
+    /// the light, which usually belong to the root user.
+    pub fn set_led_trigger(&self, light: Led, trigger: &str) -> Result<()> {
+        fs::write(self.address.led_dir(light)?.join("trigger"), trigger)?;
+        // The trigger may change the state of the light at any time.
+        self.cache_led(light, None);
         Ok(())
     }
 
@@ -429,15 +1832,59 @@ impl Device {
     /// means the battery is fully charged.
     pub fn battery(&self) -> Result<u8> {
         let mut level = 0;
-        let res_code = unsafe { xwii_iface_get_battery(self.handle, &mut level) };
+        let res_code =
+            self.with_handle(|handle| unsafe { xwii_iface_get_battery(handle, &mut level) });
         bail_if!(res_code != 0);
         Ok(level)
     }
 
-    /// Returns the device type identifier.
+    /// Reads the current battery level and returns its smoothed value,
+    /// along with the trend of the previous readings.
+    ///
+    /// The function should be called periodically, e.g. once per minute,
+    /// in order to estimate the rate at which the battery drains.
+    pub fn battery_estimate(&self) -> Result<BatteryEstimate> {
+        let level = self.battery()?;
+        Ok(lock(&self.battery).update(level, Instant::now()))
+    }
+
+    /// Returns a stream that produces the state of the battery, first
+    /// when polled and then whenever it changes.
+    ///
+    /// The stream watches the events that the kernel sends for the
+    /// power supply of the device, and also reads the battery level once
+    /// per minute. It ends once the device is disconnected.
+    pub fn battery_events(&self) -> Result<impl Stream<Item = Result<BatteryStatus>> + '_> {
+        BatteryEvents::new(self, &self.address.power_supply_dir()?)
+    }
+
+    /// Reads the strength of the Bluetooth connection to the device,
+    /// as the RSSI reported by the adapter it is connected to.
+    ///
+    /// The RSSI is measured in dB relative to the golden receive power
+    /// range of the adapter: 0 means that the signal is in that range, and
+    /// negative values (down to about -80) mean that it is weaker. The
+    /// reading requires the adapter to accept raw HCI commands from the
+    /// process, like `hcitool rssi` does.
+    #[cfg(feature = "bluetooth")]
+    pub fn signal_strength(&self) -> Result<i8> {
+        bluetooth::signal_strength(&self.address.stable_id()?)
+    }
+
+    /// Like [`Device::signal_strength`], but waits for the adapter
+    /// to answer on the event loop, so the executor thread is never
+    /// blocked.
+    #[cfg(feature = "bluetooth")]
+    pub async fn signal_strength_async(&self) -> Result<i8> {
+        bluetooth::signal_strength_async(&self.address.stable_id()?).await
+    }
+
+    /// Returns the device type identifier; see [`Device::device_kind`]
+    /// for a typed alternative.
     pub fn kind(&self) -> Result<String> {
         let mut raw_kind = ptr::null_mut();
-        let res_code = unsafe { xwii_iface_get_devtype(self.handle, &mut raw_kind) };
+        let res_code =
+            self.with_handle(|handle| unsafe { xwii_iface_get_devtype(handle, &mut raw_kind) });
         bail_if!(res_code != 0);
 
         let kind = to_rust_str(unsafe { CStr::from_ptr(raw_kind) });
@@ -445,10 +1892,23 @@ impl Device {
         Ok(kind)
     }
 
-    /// Returns the current extension type identifier.
+    /// Returns the type of the device.
+    pub fn device_kind(&self) -> Result<DeviceKind> {
+        Ok(self.kind()?.parse().unwrap())
+    }
+
+    /// Reads the unique identifier of the device, which is its
+    /// Bluetooth address; see [`Address::uniq`].
+    pub fn uniq(&self) -> Result<String> {
+        self.address.uniq()
+    }
+
+    /// Returns the current extension type identifier; see
+    /// [`Device::extension_kind`] for a typed alternative.
     pub fn extension(&self) -> Result<String> {
         let mut raw_ext_kind = ptr::null_mut();
-        let res_code = unsafe { xwii_iface_get_extension(self.handle, &mut raw_ext_kind) };
+        let res_code = self
+            .with_handle(|handle| unsafe { xwii_iface_get_extension(handle, &mut raw_ext_kind) });
         bail_if!(res_code != 0);
 
         let ext_kind = to_rust_str(unsafe { CStr::from_ptr(raw_ext_kind) });
@@ -456,53 +1916,790 @@ impl Device {
         Ok(ext_kind)
     }
 
+    /// Returns the type of the extension currently plugged to the device.
+    ///
+    /// The kernel driver reports the Motion Plus apart from the other
+    /// extensions; it is returned as [`ExtensionKind::MotionPlus`] only
+    /// if no other extension is plugged and its channel is available.
+    /// The Motion Plus built into a Wii Remote Plus is not an extension.
+    pub fn extension_kind(&self) -> Result<ExtensionKind> {
+        Ok(ExtensionKind::detect(
+            &self.extension()?,
+            &self.device_kind()?,
+            self.available(),
+        ))
+    }
+
+    /// Reads the state of the device in a single call.
+    ///
+    /// The battery level and the unique identifier are [`None`] if they
+    /// cannot be read, since some devices lack them; other failures are
+    /// reported as errors. See [`Address::info`] for the `udev`
+    /// properties of the device.
+    pub fn info(&self) -> Result<DeviceSnapshot> {
+        Ok(DeviceSnapshot {
+            kind: self.device_kind()?,
+            extension: self.extension_kind()?,
+            battery: self.battery().ok(),
+            open_channels: self.get_open(),
+            available_channels: self.available(),
+            leds: self.leds()?,
+            uniq: self.uniq().ok(),
+            sysfs_path: self.address.0.clone(),
+        })
+    }
+
     /// Toggles the rumble motor.
     ///
     /// If the [core channel][core] is closed, it is opened in writable mode.
+    /// Turning the motor on fails while the
+    /// [rumble limit](`Device::set_rumble_limit`) cools down.
     ///
     /// [core]: `Channels::CORE`
-    pub fn set_rumble(&mut self, enabled: bool) -> Result<()> {
+    pub fn set_rumble(&self, enabled: bool) -> Result<()> {
         self.ensure_core_open()?;
-        let res_code = unsafe { xwii_iface_rumble(self.handle, enabled) };
-        bail_if!(res_code != 0); // the channel might have been closed by the kernel
+        self.rumble(enabled)
+    }
+
+    /// Emulates a rumble motor of variable intensity, from 0 (off)
+    /// to 1 (always on), by turning the motor on for that fraction
+    /// of every [PWM period](`Device::set_rumble_pwm_period`).
+    ///
+    /// The modulation runs on the output thread, which is started if
+    /// no [output timeout](`Device::set_output_timeout`) is set. Changing
+    /// the intensity keeps the current cycle, so force-feedback bridges
+    /// can forward every magnitude update. Other rumble operations stop
+    /// the modulation.
+    ///
+    /// If the [core channel][core] is closed, it is opened in writable mode.
+    ///
+    /// [core]: `Channels::CORE`
+    pub fn set_rumble_intensity(&self, intensity: f32) -> Result<()> {
+        self.ensure_core_open()?;
+        // This also maps NaN to 0.
+        let intensity = intensity.clamp(0.0, 1.0).max(0.0);
+        let period = self.rumble_pwm_period();
+        let on = period.mul_f32(intensity);
+        let off = period.saturating_sub(on);
+        if on.is_zero() || off.is_zero() {
+            return self.rumble(!on.is_zero());
+        }
+        let output = self.output_queue()?;
+        let (queue, handle) = output.as_ref().unwrap();
+        let handle = Arc::clone(handle);
+        let pwm = Pwm::new(on, off, move |enabled| {
+            handle.with(|handle| write_rumble(handle, enabled))
+        });
+        queue.set_pwm(Some(pwm))
+    }
+
+    /// Sets the period of the modulation used by
+    /// [`Device::set_rumble_intensity`].
+    ///
+    /// Shorter periods feel smoother, but the motor may not spin up
+    /// at low intensities. Defaults to 40 milliseconds.
+    pub fn set_rumble_pwm_period(&self, period: Duration) {
+        *lock(&self.pwm_period) = period;
+    }
+
+    /// Returns the period of the rumble intensity modulation.
+    pub fn rumble_pwm_period(&self) -> Duration {
+        *lock(&self.pwm_period)
+    }
+
+    /// Turns the rumble motor on for `duration` in the background,
+    /// on the output thread.
+    pub(crate) fn rumble_pulse(&self, duration: Duration) -> Result<()> {
+        let output = self.output_queue()?;
+        let (queue, handle) = output.as_ref().unwrap();
+        let handle = Arc::clone(handle);
+        let pwm = Pwm::once(duration, move |enabled| {
+            handle.with(|handle| write_rumble(handle, enabled))
+        });
+        queue.set_pwm(Some(pwm))
+    }
+
+    /// Turns the rumble motor off once it runs continuously for longer
+    /// than `limit` allows, and keeps it off for the cooldown, or lets
+    /// it run indefinitely if `limit` is [`None`].
+    ///
+    /// The motor counts as running while its
+    /// [intensity is modulated](`Device::set_rumble_intensity`). During
+    /// the cooldown, the requests to turn it on fail with an
+    /// [`Error::Io`] error of kind [`io::ErrorKind::ResourceBusy`], and
+    /// the modulation keeps it off. The limit is enforced by the output
+    /// thread, which is started if no
+    /// [output timeout](`Device::set_output_timeout`) is set.
+    ///
+    /// Disabled by default; [`RumbleLimit::default`] suits most games.
+    pub fn set_rumble_limit(&self, limit: Option<RumbleLimit>) -> Result<()> {
+        *lock(&self.rumble_limit) = limit;
+        let output = match limit {
+            Some(_) => self.output_queue()?,
+            None => lock(&self.output),
+        };
+        match &*output {
+            Some((queue, handle)) => queue.set_limit(rumble_limit(limit, handle)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the cap on the continuous operation of the rumble
+    /// motor, if any.
+    pub fn rumble_limit(&self) -> Option<RumbleLimit> {
+        *lock(&self.rumble_limit)
+    }
+
+    /// Returns a [`RumbleSink`] that plays the rumble commands sent to it.
+    ///
+    /// If the [core channel][core] is closed, it is opened in writable mode.
+    ///
+    /// [core]: `Channels::CORE`
+    pub fn rumble_sink(&self) -> Result<RumbleSink<'_>> {
+        self.ensure_core_open()?;
+        Ok(RumbleSink::new(self))
+    }
+
+    /// Turns the rumble motor on for `duration`; see
+    /// [`Device::rumble_pattern`].
+    pub async fn rumble_for(&self, duration: Duration) -> Result<()> {
+        self.rumble_pattern(&[(true, duration)]).await
+    }
+
+    /// Turns the rumble motor on and off, keeping each state for the
+    /// given duration, and then turns it off.
+    ///
+    /// Starting a pattern stops the one that is playing, if any, from
+    /// changing the motor; the future of the older pattern completes
+    /// successfully once its current step ends. The motor is turned off
+    /// if the future is dropped before completing.
+    ///
+    /// The [core channel](`Channels::CORE`) must be open in writable mode.
+    pub async fn rumble_pattern(&self, pattern: &[(bool, Duration)]) -> Result<()> {
+        if !self.core_open.load(Ordering::Relaxed) {
+            return Err(Error::ChannelClosed(Channels::CORE));
+        }
+        let id = self.claim_rumble();
+        let _guard = RumbleGuard { device: self, id };
+
+        let mut enabled = None;
+        for &(state, duration) in pattern {
+            if self.rumble_owner.load(Ordering::Relaxed) != id {
+                break; // a newer pattern took over.
+            }
+            if enabled != Some(state) {
+                self.rumble(state)?;
+                enabled = Some(state);
+            }
+            timer::sleep(duration).await?;
+        }
+        Ok(())
+    }
+
+    /// Stops the rumble pattern that is playing, if any, from changing
+    /// the motor, and returns the number of the pattern that takes over.
+    fn claim_rumble(&self) -> u64 {
+        self.rumble_owner
+            .fetch_add(1, Ordering::Relaxed)
+            .wrapping_add(1)
+    }
+
+    /// Toggles the rumble motor, assuming that the [core channel][core]
+    /// is open in writable mode.
+    ///
+    /// [core]: `Channels::CORE`
+    pub(crate) fn rumble(&self, enabled: bool) -> Result<()> {
+        // The limit is enforced by the output thread.
+        let output = match self.rumble_limit() {
+            Some(_) => self.output_queue()?,
+            None => lock(&self.output),
+        };
+        match &*output {
+            Some((queue, handle)) => {
+                queue.set_pwm(None)?;
+                let handle = Arc::clone(handle);
+                queue.run_limited(enabled, move || {
+                    handle.with(|handle| write_rumble(handle, enabled))
+                })
+            }
+            None => self.with_handle(|handle| write_rumble(handle, enabled)),
+        }
+    }
+
+    /// Sets the time after which the operations that change the LED lights
+    /// and the rumble motor fail with an [`Error::Io`] error of kind
+    /// [`io::ErrorKind::TimedOut`], or lets
+    /// them block indefinitely if `timeout` is [`None`].
+    ///
+    /// These operations may hang for several seconds while the Bluetooth
+    /// link of the device is dying. With a timeout, they are executed in
+    /// order on a dedicated thread, which completes them in the background
+    /// while the caller moves on. Opening or closing channels waits for
+    /// the pending operations, and may time out as well. The other calls
+    /// on the device handle, e.g. those that read events, still wait for
+    /// the operation that is running, since the `xwiimote` library does
+    /// not synchronize them.
+    ///
+    /// The same thread is started without a timeout once the device
+    /// [modulates the rumble intensity](`Device::set_rumble_intensity`),
+    /// plays a [rumble pulse](`RumbleSink`) or
+    /// [limits the rumble motor](`Device::set_rumble_limit`); the output
+    /// operations then run on it and block until they complete, unless
+    /// a timeout is set.
+    ///
+    /// No timeout is set by default.
+    pub fn set_output_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        let mut output = lock(&self.output);
+        match &mut *output {
+            // The thread may be modulating the rumble motor.
+            Some((queue, _)) => queue.set_timeout(timeout),
+            None if timeout.is_some() => *output = Some(self.start_output(timeout)?),
+            None => {}
+        }
         Ok(())
     }
 
+    /// Returns the timeout of the output operations, if set.
+    pub fn output_timeout(&self) -> Option<Duration> {
+        lock(&self.output)
+            .as_ref()
+            .and_then(|(queue, _)| queue.timeout())
+    }
+
+    /// Returns the output queue, which is started without a timeout
+    /// if needed.
+    fn output_queue(&self) -> Result<MutexGuard<'_, Option<Output>>> {
+        let mut output = lock(&self.output);
+        if output.is_none() {
+            *output = Some(self.start_output(None)?);
+        }
+        Ok(output)
+    }
+
+    /// Starts the output thread, which enforces the rumble limit.
+    fn start_output(&self, timeout: Option<Duration>) -> Result<Output> {
+        let handle =
+            Arc::new(unsafe { SharedHandle::new(self.handle, Arc::clone(&self.handle_lock)) });
+        let queue = OutputQueue::new(timeout)?;
+        queue.set_limit(rumble_limit(self.rumble_limit(), &handle))?;
+        Ok((queue, handle))
+    }
+
+    /// Runs an output operation on the device handle, through the output
+    /// queue if a timeout is set.
+    fn run_output(
+        &self,
+        op: impl FnOnce(*mut xwii_iface) -> Result<()> + Send + 'static,
+    ) -> Result<()> {
+        match &*lock(&self.output) {
+            Some((queue, handle)) => {
+                let handle = Arc::clone(handle);
+                queue.run(move || handle.with(op))
+            }
+            None => self.with_handle(op),
+        }
+    }
+
+    /// Runs `f` on the device handle, once the calls of other threads
+    /// on the handle complete.
+    pub(crate) fn with_handle<T>(&self, f: impl FnOnce(*mut xwii_iface) -> T) -> T {
+        let _lock = lock(&self.handle_lock);
+        f(self.handle)
+    }
+
+    /// Waits until the pending output operations complete, if a timeout is set.
+    fn sync_output(&self) -> Result<()> {
+        match &*lock(&self.output) {
+            Some((queue, _)) => queue.sync(),
+            None => Ok(()),
+        }
+    }
+
+    /// Plays a combination of LED light and rumble patterns, e.g. to tell
+    /// the user that the battery of the device is low.
+    ///
+    /// Once the cue ends, the rumble motor is turned off and the lights
+    /// return to their previous state. This also happens if the cue fails
+    /// to play, or if the future is dropped before completing.
+    ///
+    /// A cue that rumbles takes over the motor like a
+    /// [rumble pattern](`Device::rumble_pattern`): it stops the pattern
+    /// that is playing, and stops changing the motor once another pattern
+    /// or cue starts. If the [core channel][core] is closed, it is opened
+    /// in writable mode.
+    ///
+    /// [core]: `Channels::CORE`
+    pub async fn play_cue(&self, cue: &FeedbackCue) -> Result<()> {
+        let mut previous = Leds::empty();
+        for (light, cached) in Led::ALL.into_iter().zip(self.cached_leds()) {
+            let enabled = match cached {
+                Some(cached) => cached,
+                None => self.led(light)?,
+            };
+            previous.set(light.into(), enabled);
+        }
+        let rumbles = cue.steps().iter().any(|step| step.rumble);
+        if rumbles {
+            self.ensure_core_open()?;
+        }
+
+        let mut guard = CueGuard {
+            device: self,
+            rumble: rumbles.then(|| self.claim_rumble()),
+            leds: Some(previous),
+        };
+        let res = self.play_steps(cue, guard.rumble).await;
+        res.and(guard.restore())
+    }
+
+    /// Plays the steps of a cue, changing the motor while the rumble
+    /// pattern with number `rumble` owns it, if any.
+    async fn play_steps(&self, cue: &FeedbackCue, rumble: Option<u64>) -> Result<()> {
+        let mut enabled = false;
+        for step in cue.steps() {
+            self.set_leds(step.leds)?;
+            let owned = rumble == Some(self.rumble_owner.load(Ordering::Relaxed));
+            if owned && step.rumble != enabled {
+                self.rumble(step.rumble)?;
+                enabled = step.rumble;
+            }
+            timer::sleep(step.duration).await?;
+        }
+        Ok(())
+    }
+
+    /// Runs a hardware self-test: flashes the LED lights in turn, pulses
+    /// the rumble motor and samples each available sensor for a second,
+    /// checking that the data it reports is plausible.
+    ///
+    /// The channels that are not open are opened for the duration of
+    /// the test. Afterwards, the lights return to their previous state
+    /// and the rumble motor is turned off. Consumers of the events of
+    /// the device should not run meanwhile, since the test reads them.
+    /// See the [`self_test`] module for an example.
+    pub async fn self_test(&self) -> Result<SelfTestReport> {
+        self_test::run(self).await
+    }
+
     // Motion Plus sensor normalization
 
     /// Reads the Motion Plus sensor normalization values.
     pub fn mp_normalization(&self) -> Result<MotionPlusNormalization> {
         let mut values = MotionPlusNormalization::default();
-        unsafe {
+        self.with_handle(|handle| unsafe {
             xwii_iface_get_mp_normalization(
-                self.handle,
+                handle,
                 &mut values.x,
                 &mut values.y,
                 &mut values.z,
                 &mut values.factor,
             )
-        };
+        });
         Ok(values)
     }
 
     /// Updates the Motion Plus sensor normalization values.
-    pub fn set_mp_normalization(&mut self, values: &MotionPlusNormalization) -> Result<()> {
-        unsafe {
-            xwii_iface_set_mp_normalization(
-                self.handle,
-                values.x,
-                values.y,
-                values.z,
-                values.factor,
-            )
-        };
+    pub fn set_mp_normalization(&self, values: &MotionPlusNormalization) -> Result<()> {
+        self.with_handle(|handle| unsafe {
+            xwii_iface_set_mp_normalization(handle, values.x, values.y, values.z, values.factor)
+        });
         Ok(())
     }
 }
 
+/// Pairs `limit` with the function that turns the rumble motor off.
+fn rumble_limit(
+    limit: Option<RumbleLimit>,
+    handle: &Arc<SharedHandle>,
+) -> Option<(RumbleLimit, impl FnMut(bool) -> Result<()> + Send + 'static)> {
+    let handle = Arc::clone(handle);
+    limit.map(move |limit| {
+        (limit, move |enabled| {
+            handle.with(|handle| write_rumble(handle, enabled))
+        })
+    })
+}
+
+/// Toggles the rumble motor of `handle`.
+fn write_rumble(handle: *mut xwii_iface, enabled: bool) -> Result<()> {
+    let res_code = unsafe { xwii_iface_rumble(handle, enabled) };
+    if res_code != 0 {
+        // The channel might have been closed by the kernel.
+        let err = io::Error::last_os_error();
+        let open = Channels::from_bits_truncate(unsafe { xwii_iface_opened(handle) });
+        return Err(Error::from_channel_op(err, Channels::CORE, open));
+    }
+    Ok(())
+}
+
+/// Turns the rumble motor off once the pattern that owns it ends,
+/// even if its future is dropped.
+struct RumbleGuard<'d> {
+    device: &'d Device,
+    id: u64,
+}
+
+impl Drop for RumbleGuard<'_> {
+    fn drop(&mut self) {
+        if self.device.rumble_owner.load(Ordering::Relaxed) == self.id {
+            let _ = self.device.rumble(false);
+        }
+    }
+}
+
+/// Turns the rumble motor off and restores the lights once a cue ends,
+/// even if its future is dropped.
+struct CueGuard<'d> {
+    device: &'d Device,
+    /// The number of the rumble pattern of the cue, if it rumbles.
+    rumble: Option<u64>,
+    /// The state of the lights before the cue, until restored.
+    leds: Option<Leds>,
+}
+
+impl CueGuard<'_> {
+    fn restore(&mut self) -> Result<()> {
+        let Some(leds) = self.leds.take() else {
+            return Ok(());
+        };
+        if self.rumble == Some(self.device.rumble_owner.load(Ordering::Relaxed)) {
+            self.device.rumble(false)?;
+        }
+        self.device.set_leds(leds)
+    }
+}
+
+impl Drop for CueGuard<'_> {
+    fn drop(&mut self) {
+        // Do not panic, since we may be unwinding already.
+        let _ = self.restore();
+    }
+}
+
+/// The operations shared by the direct and the [brokered](broker)
+/// handles to a Wii Remote.
+///
+/// Applications that are generic over this trait work the same with
+/// a [`Device`] they own and with a [`BrokerClient`](broker::BrokerClient)
+/// connected to another process that owns the device.
+pub trait WiimoteDevice {
+    /// Reads the current state of an LED light.
+    fn led(&self, light: Led) -> Result<bool>;
+
+    /// Changes the state of an LED light.
+    fn set_led(&self, light: Led, enabled: bool) -> Result<()>;
+
+    /// Toggles the rumble motor.
+    fn set_rumble(&self, enabled: bool) -> Result<()>;
+
+    /// Reads the current battery level, as a percentage from 0 to 100%.
+    fn battery(&self) -> Result<u8>;
+
+    /// Returns a stream that produces the events received from the device,
+    /// including the time at which the kernel generated them.
+    fn events(&self) -> Result<impl Stream<Item = Result<(Event, SystemTime)>> + '_>;
+}
+
+impl WiimoteDevice for Device {
+    fn led(&self, light: Led) -> Result<bool> {
+        Device::led(self, light)
+    }
+
+    fn set_led(&self, light: Led, enabled: bool) -> Result<()> {
+        Device::set_led(self, light, enabled)
+    }
+
+    fn set_rumble(&self, enabled: bool) -> Result<()> {
+        Device::set_rumble(self, enabled)
+    }
+
+    fn battery(&self) -> Result<u8> {
+        Device::battery(self)
+    }
+
+    fn events(&self) -> Result<impl Stream<Item = Result<(Event, SystemTime)>> + '_> {
+        Device::events(self)
+    }
+}
+
 impl Drop for Device {
     fn drop(&mut self) {
+        // Let the observers know that no more events are coming.
+        self.broadcast.close(None);
         // Decrements ref-count to zero. This destroys the device.
         unsafe { xwii_iface_unref(self.handle) };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        epoll_targets, Address, Channels, Device, DeviceInfo, DeviceKind, Error, ExtensionKind,
+        Led, LedTriggers, Leds, Result, StableId,
+    };
+    use std::os::fd::AsRawFd;
+    use std::path::Path;
+    use std::{fs, io};
+
+    #[test]
+    fn parses_led_triggers() {
+        let triggers = LedTriggers::parse("none kbd-numlock [battery-charging] timer\n");
+        assert_eq!(
+            triggers.available,
+            ["none", "kbd-numlock", "battery-charging", "timer"]
+        );
+        assert_eq!(triggers.current.as_deref(), Some("battery-charging"));
+        assert_eq!(LedTriggers::parse("none timer").current, None);
+    }
+
+    #[test]
+    fn parses_stable_ids() {
+        let id: StableId = "00:1F:32:aa:BB:cc".parse().unwrap();
+        assert_eq!(id.as_str(), "00:1f:32:aa:bb:cc");
+        assert_eq!(id.to_string().parse::<StableId>().unwrap(), id);
+        for invalid in [
+            "",
+            "00:1f:32:aa:bb",
+            "00:1f:32:aa:bb:cg",
+            "001f:32:aa:bb:cc:dd",
+        ] {
+            assert!(invalid.parse::<StableId>().is_err());
+        }
+    }
+
+    #[test]
+    fn converts_leds() {
+        assert_eq!(Leds::from(Led::One), Leds::ONE);
+        assert_eq!(Leds::from(Led::Four), Leds::FOUR);
+        let leds = Led::ALL.into_iter().map(Leds::from).collect::<Leds>();
+        assert_eq!(leds, Leds::all());
+    }
+
+    #[test]
+    fn maps_channels_to_interfaces() {
+        let ifaces = [
+            (Channels::CORE, xwiimote_sys::XWII_IFACE_CORE),
+            (Channels::ACCELEROMETER, xwiimote_sys::XWII_IFACE_ACCEL),
+            (Channels::IR, xwiimote_sys::XWII_IFACE_IR),
+            (Channels::MOTION_PLUS, xwiimote_sys::XWII_IFACE_MOTION_PLUS),
+            (Channels::NUNCHUK, xwiimote_sys::XWII_IFACE_NUNCHUK),
+            (
+                Channels::CLASSIC_CONTROLLER,
+                xwiimote_sys::XWII_IFACE_CLASSIC_CONTROLLER,
+            ),
+            (
+                Channels::BALANCE_BOARD,
+                xwiimote_sys::XWII_IFACE_BALANCE_BOARD,
+            ),
+            (
+                Channels::PRO_CONTROLLER,
+                xwiimote_sys::XWII_IFACE_PRO_CONTROLLER,
+            ),
+            (Channels::DRUMS, xwiimote_sys::XWII_IFACE_DRUMS),
+            (Channels::GUITAR, xwiimote_sys::XWII_IFACE_GUITAR),
+        ];
+        for (channel, iface) in ifaces {
+            assert_eq!(channel.bits(), iface, "{channel:?}");
+        }
+        // Every channel has its own interface.
+        let all = ifaces.iter().fold(0, |all, &(_, iface)| all | iface);
+        assert_eq!(all.count_ones() as usize, ifaces.len());
+        assert_eq!(Channels::all().bits(), all);
+    }
+
+    #[test]
+    fn devices_can_be_shared() {
+        fn assert_shareable<T: Send + Sync>() {}
+        assert_shareable::<Device>();
+    }
+
+    #[test]
+    fn parses_device_kinds() {
+        for raw in ["gen10", "gen20", "balanceboard", "procontroller", "gen30"] {
+            let kind: DeviceKind = raw.parse().unwrap();
+            assert_eq!(kind.to_string(), raw);
+        }
+        assert_eq!("gen20".parse(), Ok(DeviceKind::WiiRemotePlus));
+        assert_eq!(
+            "unknown".parse(),
+            Ok(DeviceKind::Unknown("unknown".to_owned()))
+        );
+    }
+
+    #[test]
+    fn ignores_built_in_motion_plus() {
+        let available = Channels::CORE | Channels::MOTION_PLUS;
+        let detect = |raw, device| ExtensionKind::detect(raw, &device, available);
+        assert_eq!(
+            detect("none", DeviceKind::WiiRemote),
+            ExtensionKind::MotionPlus
+        );
+        assert_eq!(
+            detect("none", DeviceKind::WiiRemotePlus),
+            ExtensionKind::None
+        );
+        assert_eq!(
+            detect("nunchuk", DeviceKind::WiiRemotePlus),
+            ExtensionKind::Nunchuk
+        );
+        assert_eq!(
+            ExtensionKind::detect("none", &DeviceKind::WiiRemote, Channels::CORE),
+            ExtensionKind::None
+        );
+    }
+
+    #[test]
+    fn parses_extension_kinds() {
+        for raw in [
+            "none",
+            "nunchuk",
+            "classic",
+            "motionplus",
+            "guitar",
+            "drums",
+            "unknown",
+        ] {
+            let kind: ExtensionKind = raw.parse().unwrap();
+            assert_eq!(kind.to_string(), raw);
+        }
+        assert_eq!("classic".parse(), Ok(ExtensionKind::ClassicController));
+        assert_eq!(
+            "unknown".parse(),
+            Ok(ExtensionKind::Unknown("unknown".to_owned()))
+        );
+    }
+
+    #[test]
+    fn parses_device_info() {
+        let info = DeviceInfo::parse(
+            "DRIVER=wiimote\nHID_ID=0005:0000057E:00000306\nHID_NAME=Nintendo RVL-CNT-01\n\
+             HID_PHYS=00:1a:7d:da:71:13\nHID_UNIQ=00:1f:32:aa:bb:cc\n",
+        );
+        assert_eq!(info.driver(), Some("wiimote"));
+        assert_eq!(info.name(), Some("Nintendo RVL-CNT-01"));
+        assert_eq!(info.phys(), Some("00:1a:7d:da:71:13"));
+        assert_eq!(info.uniq(), Some("00:1f:32:aa:bb:cc"));
+        assert_eq!(info.property("HID_ID"), Some("0005:0000057E:00000306"));
+        assert_eq!(info.property("MODALIAS"), None);
+    }
+
+    #[test]
+    fn lists_epoll_targets() -> Result<()> {
+        let epoll_fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        assert!(epoll_fd >= 0);
+        let (watched, _other) = std::os::unix::net::UnixStream::pair()?;
+        let fd = watched.as_raw_fd();
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: 0,
+        };
+        assert_eq!(
+            unsafe { libc::epoll_ctl(epoll_fd, libc::EPOLL_CTL_ADD, fd, &mut event) },
+            0
+        );
+        let fdinfo = fs::read_to_string(format!("/proc/self/fdinfo/{epoll_fd}"))?;
+        unsafe { libc::close(epoll_fd) };
+        assert_eq!(epoll_targets(&fdinfo).collect::<Vec<_>>(), [fd]);
+        Ok(())
+    }
+
+    #[test]
+    fn reads_uniq() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("xwiimote-uniq-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let address = Address::from(dir.clone());
+
+        fs::write(dir.join("uevent"), "HID_UNIQ=00:1f:32:aa:bb:cc\n")?;
+        assert_eq!(address.uniq()?, "00:1f:32:aa:bb:cc");
+        assert_eq!(address.stable_id()?.as_str(), "00:1f:32:aa:bb:cc");
+        fs::write(dir.join("uevent"), "HID_UNIQ=\n")?;
+        assert!(address.uniq().is_err());
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn parses_address() -> Result<()> {
+        let address: Address = "0005:057E:0306.0001".parse()?;
+        assert_eq!(
+            address.to_string(),
+            "/sys/bus/hid/devices/0005:057E:0306.0001"
+        );
+        assert_eq!(address.to_string().parse::<Address>()?, address);
+        assert_eq!(
+            "/tmp/wiimote".parse::<Address>()?,
+            Address::from(Path::new("/tmp/wiimote").to_path_buf())
+        );
+        assert!("".parse::<Address>().is_err());
+        assert!("wii\0mote".parse::<Address>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn finds_address_by_mac() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("xwiimote-mac-{}", std::process::id()));
+        for (name, uniq) in [
+            ("0005:057E:0306.0001", "00:1f:32:aa:bb:cc"),
+            ("0005:057E:0330.0002", "00:1f:32:dd:ee:ff"),
+            ("0003:046D:C52B.0003", ""),
+        ] {
+            fs::create_dir_all(dir.join(name))?;
+            fs::write(dir.join(name).join("uevent"), format!("HID_UNIQ={uniq}\n"))?;
+        }
+
+        let id: StableId = "00:1F:32:DD:EE:FF".parse()?;
+        let address = Address::find_by_id(&dir, &id)?;
+        assert_eq!(
+            address.0,
+            fs::canonicalize(dir.join("0005:057E:0330.0002"))?
+        );
+        assert_eq!(address.stable_id()?.as_str(), "00:1f:32:dd:ee:ff");
+        let missing: StableId = "00:1f:32:00:00:00".parse()?;
+        assert!(Address::find_by_id(&dir, &missing).is_err());
+        assert!(Address::from_mac("not a mac").is_err());
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn finds_device_nodes() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("xwiimote-nodes-{}", std::process::id()));
+        let address = Address::from(dir.clone());
+        fs::create_dir_all(dir.join("hidraw/hidraw3"))?;
+        for (input, event) in [("input21", "event9"), ("input20", "event8")] {
+            let input = dir.join("input").join(input);
+            fs::create_dir_all(input.join(event))?;
+            fs::write(input.join("name"), "Nintendo Wii Remote\n")?;
+        }
+
+        assert_eq!(address.hidraw_node()?, Path::new("/dev/hidraw3"));
+        let nodes: Vec<_> = address
+            .evdev_nodes()?
+            .into_iter()
+            .map(|(node, _)| node)
+            .collect();
+        assert_eq!(
+            nodes,
+            [
+                Path::new("/dev/input/event8"),
+                Path::new("/dev/input/event9")
+            ]
+        );
+
+        fs::remove_dir_all(&dir)?;
+        assert!(address.hidraw_node().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn retries_transient_connection_errors() {
+        let err = |code| Error::from(io::Error::from_raw_os_error(code));
+        assert!(Device::is_transient(&err(libc::ENOTCONN)));
+        assert!(Device::is_transient(&err(libc::ENODEV)));
+        assert!(Device::is_transient(&err(libc::ENOENT)));
+        assert!(!Device::is_transient(&err(libc::EACCES)));
+        assert!(!Device::is_transient(&err(libc::EINVAL)));
+    }
+}
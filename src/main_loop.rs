@@ -0,0 +1,95 @@
+//! Integration with the GLib main loop, e.g. for GTK settings panels.
+//!
+//! The streams of this crate can be driven by any executor, including
+//! a GLib [`MainContext`]. The functions of this module spawn them on
+//! a context and call a closure with each item on the thread that owns
+//! the context, so that the closure can update the widgets directly.
+//!
+//! # Examples
+//! List the devices as they connect, and show the battery level of
+//! each device whenever it reports an event.
+//! ```no_run
+//! use glib::{ControlFlow, MainContext, MainLoop};
+//! use xwiimote::main_loop::{watch_devices, watch_events};
+//! use xwiimote::{Device, Monitor};
+//!
+//! let context = MainContext::default();
+//! let inner = context.clone();
+//! watch_devices(&context, Monitor::discover()?, move |address| {
+//!     let Ok(device) = address.and_then(|address| Device::connect(&address)) else {
+//!         return ControlFlow::Continue;
+//!     };
+//!     if let Ok(events) = device.into_events() {
+//!         watch_events(&inner, events, |device, _| {
+//!             if let Ok(level) = device.battery() {
+//!                 println!("battery: {level}%");
+//!             }
+//!             ControlFlow::Continue
+//!         });
+//!     }
+//!     ControlFlow::Continue
+//! });
+//! MainLoop::new(Some(&context), false).run();
+//! # Ok::<(), xwiimote::Error>(())
+//! ```
+
+use crate::events::{Event, OwnedEvents};
+use crate::{Address, Device, Monitor, Result};
+use futures_core::Stream;
+use glib::{ControlFlow, JoinHandle, MainContext};
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::time::SystemTime;
+
+/// Spawns a task on `context` that calls `callback` with each item
+/// of `stream`, until the stream ends or the callback returns
+/// [`ControlFlow::Break`]. The task can be aborted through the
+/// returned handle.
+///
+/// # Panics
+/// If the current thread does not own `context`; see
+/// [`MainContext::spawn_local`].
+pub fn attach<S, F>(context: &MainContext, mut stream: S, mut callback: F) -> JoinHandle<()>
+where
+    S: Stream + Unpin + 'static,
+    F: FnMut(S::Item) -> ControlFlow + 'static,
+{
+    context.spawn_local(async move {
+        while let Some(item) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+            if matches!(callback(item), ControlFlow::Break) {
+                break;
+            }
+        }
+    })
+}
+
+/// Calls `callback` with the address of each device produced by
+/// `monitor`, on the thread that owns `context`; see [`attach`].
+pub fn watch_devices<F>(context: &MainContext, monitor: Monitor, callback: F) -> JoinHandle<()>
+where
+    F: FnMut(Result<Address>) -> ControlFlow + 'static,
+{
+    attach(context, monitor, callback)
+}
+
+/// Calls `callback` with each event of a device, on the thread that owns
+/// `context`; see [`attach`]. The callback also receives the device, e.g.
+/// to show its battery level or to turn on its LED lights.
+///
+/// The device is closed once the task ends.
+pub fn watch_events<F>(
+    context: &MainContext,
+    mut events: OwnedEvents,
+    mut callback: F,
+) -> JoinHandle<()>
+where
+    F: FnMut(&Device, Result<(Event, SystemTime)>) -> ControlFlow + 'static,
+{
+    context.spawn_local(async move {
+        while let Some(item) = poll_fn(|cx| Pin::new(&mut events).poll_next(cx)).await {
+            if matches!(callback(events.device(), item), ControlFlow::Break) {
+                break;
+            }
+        }
+    })
+}
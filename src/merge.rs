@@ -0,0 +1,183 @@
+//! Merging of device events with external tick sources.
+//!
+//! Applications often react both to the events of a device and to
+//! periodic ticks, e.g. to redraw a frame or to refresh a status display.
+//! Instead of waiting on both sources in a `select!` loop, [`merge`]
+//! combines them into a single stream that can be iterated directly.
+//!
+//! # Examples
+//! Print the accelerometer data of a device, along with the frame ticks
+//! reported by a renderer.
+//! ```no_run
+//! use futures_util::TryStreamExt;
+//! use std::time::SystemTime;
+//! use xwiimote::merge::{merge, Merged};
+//! use xwiimote::{Channels, Device, Monitor};
+//! # fn vsync_ticks() -> impl futures_util::Stream<Item = (u64, SystemTime)> + Unpin {
+//! #     futures_util::stream::empty()
+//! # }
+//!
+//! # tokio_test::block_on(async {
+//! # let address = Monitor::enumerate()?.try_next().await?.unwrap();
+//! let device = Device::connect(&address)?;
+//! device.open(Channels::CORE | Channels::ACCELEROMETER, false)?;
+//!
+//! // Produces the number of each frame and the time it was presented.
+//! let frames = vsync_ticks();
+//! let mut merged = merge(device.events()?, frames);
+//! while let Some((item, time)) = merged.try_next().await? {
+//!     match item {
+//!         Merged::Event(event) => println!("{time:?}: {event:?}"),
+//!         Merged::Tick(frame) => println!("{time:?}: frame {frame}"),
+//!     }
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! # }).unwrap();
+//! ```
+
+use crate::events::Event;
+use crate::Result;
+use futures_core::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+
+/// An item produced by a [`Merge`] stream.
+#[derive(Copy, Clone, Debug)]
+pub enum Merged<T> {
+    /// An event received from the device.
+    Event(Event),
+    /// A tick produced by the tick source.
+    Tick(T),
+}
+
+/// Merges a stream of device events with a stream of timestamped ticks.
+///
+/// The ticks can carry arbitrary data, such as a frame number.
+/// See [`Merge`] for the order in which the items are produced.
+pub fn merge<E, T, U>(events: E, ticks: T) -> Merge<E, T, U>
+where
+    E: Stream<Item = Result<(Event, SystemTime)>> + Unpin,
+    T: Stream<Item = (U, SystemTime)> + Unpin,
+{
+    Merge {
+        events,
+        ticks,
+        next_event: None,
+        next_tick: None,
+        ticks_ended: false,
+    }
+}
+
+/// The stream returned by [`merge`].
+///
+/// Whenever both sources have an item ready, the stream produces the
+/// item with the earliest timestamp first. Otherwise the items are
+/// produced as soon as they arrive, since waiting for the other source
+/// would delay them indefinitely.
+///
+/// The stream ends when the event stream ends, e.g. once the device
+/// disconnects; an ended tick source is simply ignored. Errors from
+/// the event stream are passed through.
+pub struct Merge<E, T, U> {
+    events: E,
+    ticks: T,
+    /// The items that were received but not produced yet.
+    next_event: Option<(Event, SystemTime)>,
+    next_tick: Option<(U, SystemTime)>,
+    ticks_ended: bool,
+}
+
+impl<E, T, U> Stream for Merge<E, T, U>
+where
+    E: Stream<Item = Result<(Event, SystemTime)>> + Unpin,
+    T: Stream<Item = (U, SystemTime)> + Unpin,
+    U: Unpin,
+{
+    type Item = Result<(Merged<U>, SystemTime)>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if this.next_event.is_none() {
+            match Pin::new(&mut this.events).poll_next(cx) {
+                Poll::Ready(Some(Ok(event))) => this.next_event = Some(event),
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => {}
+            }
+        }
+        if this.next_tick.is_none() && !this.ticks_ended {
+            match Pin::new(&mut this.ticks).poll_next(cx) {
+                Poll::Ready(Some(tick)) => this.next_tick = Some(tick),
+                Poll::Ready(None) => this.ticks_ended = true,
+                Poll::Pending => {}
+            }
+        }
+
+        let tick_first = match (&this.next_event, &this.next_tick) {
+            (Some((_, event_time)), Some((_, tick_time))) => tick_time < event_time,
+            (None, Some(_)) => true,
+            (_, None) => false,
+        };
+        if tick_first {
+            let (tick, time) = this.next_tick.take().unwrap();
+            Poll::Ready(Some(Ok((Merged::Tick(tick), time))))
+        } else if let Some((event, time)) = this.next_event.take() {
+            Poll::Ready(Some(Ok((Merged::Event(event), time))))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::Event;
+    use crate::merge::{merge, Merged};
+    use crate::Result;
+    use futures_core::Stream;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+    use std::time::{Duration, SystemTime};
+
+    /// A stream that produces the items of a vector.
+    struct Items<T>(Vec<T>);
+
+    impl<T: Unpin> Stream for Items<T> {
+        type Item = T;
+
+        fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<T>> {
+            if self.0.is_empty() {
+                Poll::Ready(None)
+            } else {
+                Poll::Ready(Some(self.0.remove(0)))
+            }
+        }
+    }
+
+    fn at(millis: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn orders_ready_items_by_time() {
+        let events: Vec<Result<_>> = vec![Ok((Event::Other, at(10))), Ok((Event::Other, at(30)))];
+        let ticks = vec![(1, at(5)), (2, at(20)), (3, at(40))];
+        let mut merged = merge(Items(events), Items(ticks));
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut order = Vec::new();
+        while let Poll::Ready(Some(item)) = Pin::new(&mut merged).poll_next(&mut cx) {
+            let (item, time) = item.unwrap();
+            order.push(match item {
+                Merged::Event(_) => (0, time),
+                Merged::Tick(tick) => (tick, time),
+            });
+        }
+        // The stream ends with the events, dropping the last tick.
+        assert_eq!(
+            order,
+            vec![(1, at(5)), (0, at(10)), (2, at(20)), (0, at(30))]
+        );
+    }
+}
@@ -0,0 +1,1511 @@
+use crate::netlink::{Uevent, UeventSocket};
+use crate::reactor::{Interest, Reactor};
+use crate::timer::Sleep;
+use crate::{bail_if, free_str};
+use crate::{Address, ConnectOptions, Device, DeviceKind, Result, StableId};
+use futures_core::Stream;
+use libc::c_int;
+use std::collections::{HashSet, VecDeque};
+use std::ffi::CStr;
+#[cfg(feature = "udev")]
+use std::ffi::OsStr;
+use std::future::Future;
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll, Waker};
+use std::time::Duration;
+use std::{fs, future, io};
+use xwiimote_sys::{
+    xwii_monitor, xwii_monitor_get_fd, xwii_monitor_new, xwii_monitor_poll, xwii_monitor_unref,
+};
+
+/// The mechanisms a [`Monitor`] can use to find devices.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Backend {
+    /// Enumerate and discover devices through `udev`, as done by
+    /// the `xwiimote` library.
+    #[default]
+    Udev,
+    /// Enumerate and discover devices through `libudev` with the `udev`
+    /// crate, bypassing the monitor of the `xwiimote` library. Requires
+    /// the `udev` feature.
+    ///
+    /// The stream returned by [`Monitor::with_removals`] receives the
+    /// removals from the same `udev` monitor, rather than from the kernel.
+    #[cfg(feature = "udev")]
+    Libudev,
+    /// Enumerate devices by scanning the `sysfs` filesystem, and discover
+    /// new devices by listening to the events broadcast by the kernel
+    /// through netlink.
+    ///
+    /// The hot-plug events are received as soon as the kernel emits them,
+    /// instead of after `udevd` processes them; this reduces the discovery
+    /// latency when the system is under load. No `udevd` is needed.
+    Netlink,
+}
+
+/// Configures the behavior of a [`Monitor`].
+#[derive(Clone, Debug, Default)]
+pub struct MonitorBuilder {
+    discover: bool,
+    backend: Backend,
+    seat: Option<String>,
+    unique: bool,
+}
+
+impl MonitorBuilder {
+    /// Sets whether the monitor listens for hot-plugged devices
+    /// after enumerating the connected devices.
+    ///
+    /// Disabled by default.
+    pub fn discover(mut self, discover: bool) -> Self {
+        self.discover = discover;
+        self
+    }
+
+    /// Sets the mechanism used to find devices.
+    ///
+    /// Defaults to [`Backend::Udev`].
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Only produces the devices assigned to the given `logind` seat,
+    /// such as `seat0`.
+    ///
+    /// By default, the devices of every seat are produced.
+    pub fn seat(mut self, seat: impl Into<String>) -> Self {
+        self.seat = Some(seat.into());
+        self
+    }
+
+    /// Only produces the devices assigned to the seat of the current
+    /// session, as given by the `XDG_SEAT` environment variable.
+    ///
+    /// If the variable is not set, every device is produced.
+    pub fn current_seat(mut self) -> Self {
+        self.seat = std::env::var("XDG_SEAT")
+            .ok()
+            .filter(|seat| !seat.is_empty());
+        self
+    }
+
+    /// Sets whether the monitor produces each device only once, even if
+    /// it is reported several times; e.g. both when enumerating the
+    /// connected devices and by a hot-plug event, or by both the `add`
+    /// and `bind` kernel events of the [`Backend::Netlink`] backend.
+    ///
+    /// Devices are told apart by their `sysfs` path, so a device that
+    /// reconnects is produced again, with its new address. Disabled by
+    /// default.
+    pub fn unique(mut self, unique: bool) -> Self {
+        self.unique = unique;
+        self
+    }
+
+    /// Creates the configured monitor.
+    pub fn build(self) -> Result<Monitor> {
+        let source = match self.backend {
+            Backend::Udev => Source::udev(self.discover)?,
+            #[cfg(feature = "udev")]
+            Backend::Libudev => Source::libudev(self.discover)?,
+            Backend::Netlink => Source::netlink(self.discover)?,
+        };
+        Ok(Monitor {
+            source,
+            enumerated: false,
+            registered: false,
+            seat: self.seat,
+            produced: self.unique.then(HashSet::new),
+            paused: false,
+            resume_waker: None,
+        })
+    }
+}
+
+/// The source of device addresses of a [`Monitor`].
+enum Source {
+    Udev {
+        handle: *mut xwii_monitor,
+        /// The file descriptor used by the monitor referenced by `handle`.
+        /// Only present in discovery mode in order to monitor for hot-plug events.
+        mon_fd: Option<RawFd>,
+    },
+    #[cfg(feature = "udev")]
+    Libudev {
+        /// The addresses of the connected devices that have not been
+        /// produced yet.
+        connected: VecDeque<Address>,
+        /// The socket on which hot-plug events are received.
+        /// Only present in discovery mode.
+        socket: Option<udev::MonitorSocket>,
+        /// The hot-plugged devices received but not produced yet.
+        added: VecDeque<Address>,
+        /// The removed devices received but not produced yet, if the
+        /// removals are tracked; see [`Monitor::with_removals`].
+        removed: Option<VecDeque<Address>>,
+    },
+    Netlink {
+        /// The addresses of the connected devices that have not been
+        /// produced yet.
+        connected: VecDeque<Address>,
+        /// The socket on which hot-plug events are received.
+        /// Only present in discovery mode.
+        socket: Option<UeventSocket>,
+    },
+}
+
+impl Source {
+    /// The name of the kernel driver of the devices.
+    #[cfg(feature = "udev")]
+    const DRIVER: &'static str = "wiimote";
+
+    fn udev(discover: bool) -> Result<Self> {
+        // Create a monitor based on udevd events.
+        let handle = unsafe { xwii_monitor_new(discover, false) };
+        bail_if!(handle.is_null());
+
+        Ok(Self::Udev {
+            handle,
+            mon_fd: discover.then(|| unsafe { xwii_monitor_get_fd(handle, false) }),
+        })
+    }
+
+    #[cfg(feature = "udev")]
+    fn libudev(discover: bool) -> Result<Self> {
+        // Subscribe to hot-plug events before enumerating the connected
+        // devices, so that we do not miss any device in between.
+        let socket = if discover {
+            Some(
+                udev::MonitorBuilder::new()?
+                    .match_subsystem("hid")?
+                    .listen()?,
+            )
+        } else {
+            None
+        };
+        let mut enumerator = udev::Enumerator::new()?;
+        enumerator.match_subsystem("hid")?;
+        let connected = enumerator
+            .scan_devices()?
+            .filter(|device| device.driver() == Some(OsStr::new(Self::DRIVER)))
+            .map(|device| Address::from(device.syspath().to_owned()))
+            .collect();
+        Ok(Self::Libudev {
+            connected,
+            socket,
+            added: VecDeque::new(),
+            removed: None,
+        })
+    }
+
+    fn netlink(discover: bool) -> Result<Self> {
+        // Subscribe to hot-plug events before scanning the connected
+        // devices, so that we do not miss any device in between.
+        let socket = if discover {
+            Some(UeventSocket::new()?)
+        } else {
+            None
+        };
+        Ok(Self::Netlink {
+            connected: Self::scan_sysfs()?,
+            socket,
+        })
+    }
+
+    /// Lists the addresses of the devices bound to the kernel driver.
+    fn scan_sysfs() -> Result<VecDeque<Address>> {
+        let driver_dir = Path::new("/sys/bus/hid/drivers/wiimote");
+        let entries = match fs::read_dir(driver_dir) {
+            Ok(entries) => entries,
+            // The driver module is not loaded, hence no device is connected.
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(VecDeque::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut addresses = VecDeque::new();
+        for entry in entries {
+            let entry = entry?;
+            // Devices are symbolic links named after their HID identifiers,
+            // such as `0005:057E:0306.0001`. Other entries are attributes
+            // of the driver.
+            let is_device = entry.file_name().to_string_lossy().contains(':')
+                && entry.file_type()?.is_symlink();
+            if is_device {
+                let path = fs::canonicalize(entry.path())?;
+                addresses.push_back(Address::from(path));
+            }
+        }
+        Ok(addresses)
+    }
+
+    /// Checks whether a kernel event reports a newly bound Wii Remote.
+    fn is_new_device(event: &Uevent) -> bool {
+        matches!(event.action.as_str(), "add" | "bind")
+            && event.property("SUBSYSTEM") == Some("hid")
+            && event.property("DRIVER") == Some("wiimote")
+    }
+
+    /// Checks whether a kernel event reports a removed HID device.
+    ///
+    /// The driver is unbound before the device is removed, so the event
+    /// does not tell whether the device was a Wii Remote.
+    fn is_removed_device(event: &Uevent) -> bool {
+        event.action == "remove" && event.property("SUBSYSTEM") == Some("hid")
+    }
+
+    /// Classifies a `udev` event of a HID device as a newly bound
+    /// Wii Remote or a removed device, like [`Source::is_new_device`]
+    /// and [`Source::is_removed_device`] do for kernel events.
+    #[cfg(feature = "udev")]
+    fn classify_udev(
+        event_type: udev::EventType,
+        driver: Option<&OsStr>,
+        syspath: &Path,
+    ) -> Option<DeviceEvent> {
+        let address = || Address::from(syspath.to_owned());
+        match event_type {
+            udev::EventType::Add | udev::EventType::Bind
+                if driver == Some(OsStr::new(Self::DRIVER)) =>
+            {
+                Some(DeviceEvent::Added(address()))
+            }
+            udev::EventType::Remove => Some(DeviceEvent::Removed(address())),
+            _ => None,
+        }
+    }
+
+    /// Queues the devices reported by the pending events of a `libudev`
+    /// monitor, if in discovery mode.
+    #[cfg(feature = "udev")]
+    fn receive_udev(&mut self) {
+        let Self::Libudev {
+            socket: Some(socket),
+            added,
+            removed,
+            ..
+        } = self
+        else {
+            return;
+        };
+        for event in socket.iter() {
+            match Self::classify_udev(event.event_type(), event.driver(), event.syspath()) {
+                Some(DeviceEvent::Added(address)) => added.push_back(address),
+                Some(DeviceEvent::Removed(address)) => {
+                    if let Some(removed) = removed {
+                        removed.push_back(address);
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Starts queueing the removed devices, which are then produced by
+    /// [`Source::next_removed`]. Returns `false` if the source cannot
+    /// report removals, which are then received from the kernel instead.
+    fn track_removals(&mut self) -> bool {
+        match self {
+            #[cfg(feature = "udev")]
+            Self::Libudev { removed, .. } => {
+                removed.get_or_insert_with(VecDeque::new);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the address of the next removed device, if any is
+    /// available without blocking; see [`Source::track_removals`].
+    fn next_removed(&mut self) -> Option<Address> {
+        #[cfg(feature = "udev")]
+        {
+            self.receive_udev();
+            if let Self::Libudev {
+                removed: Some(removed),
+                ..
+            } = self
+            {
+                return removed.pop_front();
+            }
+        }
+        None
+    }
+
+    /// Returns the file descriptor to poll for hot-plug events, if any.
+    fn hotplug_fd(&self) -> Option<RawFd> {
+        match self {
+            Self::Udev { mon_fd, .. } => *mon_fd,
+            #[cfg(feature = "udev")]
+            Self::Libudev { socket, .. } => socket.as_ref().map(AsRawFd::as_raw_fd),
+            Self::Netlink { socket, .. } => socket.as_ref().map(AsRawFd::as_raw_fd),
+        }
+    }
+
+    /// Returns the address of the next connected device, if any.
+    fn next_connected(&mut self) -> Option<Address> {
+        match self {
+            Self::Udev { handle, .. } => Self::poll_udev(*handle),
+            #[cfg(feature = "udev")]
+            Self::Libudev { connected, .. } => connected.pop_front(),
+            Self::Netlink { connected, .. } => connected.pop_front(),
+        }
+    }
+
+    /// Returns the address of the next hot-plugged device, if any
+    /// is available without blocking.
+    fn next_discovered(&mut self) -> Result<Option<Address>> {
+        #[cfg(feature = "udev")]
+        self.receive_udev();
+        match self {
+            Self::Udev { handle, .. } => Ok(Self::poll_udev(*handle)),
+            #[cfg(feature = "udev")]
+            Self::Libudev { added, .. } => Ok(added.pop_front()),
+            Self::Netlink { socket, .. } => {
+                let socket = socket.as_ref().expect("not in discovery mode");
+                while let Some(event) = socket.receive()? {
+                    if Self::is_new_device(&event) {
+                        return Ok(Some(Address::from(PathBuf::from(event.syspath()))));
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Reads the next device address from an `xwiimote` monitor.
+    fn poll_udev(handle: *mut xwii_monitor) -> Option<Address> {
+        let raw_path = unsafe { xwii_monitor_poll(handle) };
+        if raw_path.is_null() {
+            return None;
+        }
+
+        // Convert the raw path into an address and free the original string.
+        let slice = unsafe { CStr::from_ptr(raw_path) };
+        let address = Address::from_raw(slice);
+        unsafe { free_str(raw_path) };
+        Some(address)
+    }
+}
+
+/// Enumerates the addresses of connected Wii Remotes and optionally streams
+/// device addresses as new devices are discovered. The same address may
+/// be produced multiple times, unless the monitor is created by
+/// [`Monitor::discover_unique`] or configured with
+/// [`MonitorBuilder::unique`].
+///
+/// When discovery mode is disabled, the stream returns [`None`]
+/// once the addresses of all connected devices have been produced.
+///
+/// A monitor should be dropped when no longer needed in order to avoid
+/// needlessly polling the system for new devices.
+pub struct Monitor {
+    source: Source,
+    /// Have we produced all the connected devices already?
+    enumerated: bool,
+    /// Is the hot-plug descriptor registered with the reactor?
+    registered: bool,
+    /// The seat of the devices to produce, if any.
+    seat: Option<String>,
+    /// The paths of the devices produced so far that still exist,
+    /// if duplicates are suppressed.
+    produced: Option<HashSet<PathBuf>>,
+    /// Have we stopped producing devices until resumed?
+    paused: bool,
+    /// The waker of the task that polled the monitor while paused,
+    /// to be called once the monitor resumes.
+    resume_waker: Option<Waker>,
+}
+
+impl Monitor {
+    const HOTPLUG_EVENTS: c_int = libc::EPOLLIN | libc::EPOLLHUP | libc::EPOLLPRI;
+
+    /// Creates a monitor that streams the addresses of all connected devices.
+    pub fn enumerate() -> Result<Self> {
+        Self::builder().build()
+    }
+
+    /// Creates a monitor that first streams the addresses of all connected
+    /// devices and then listens for hot-plugged devices, producing
+    /// their addresses as they are found.
+    pub fn discover() -> Result<Self> {
+        Self::builder().discover(true).build()
+    }
+
+    /// Like [`Monitor::discover`], but produces each device only once;
+    /// see [`MonitorBuilder::unique`].
+    ///
+    /// # Examples
+    /// ```
+    /// use futures_util::TryStreamExt;
+    /// use xwiimote::{Device, Monitor};
+    ///
+    /// # let _ = async {
+    /// let mut monitor = Monitor::discover_unique()?;
+    /// while let Some(address) = monitor.try_next().await? {
+    ///     // Each device is connected to once.
+    ///     let _device = Device::connect(&address)?;
+    /// }
+    /// # Ok::<(), xwiimote::Error>(())
+    /// # };
+    /// ```
+    pub fn discover_unique() -> Result<Self> {
+        Self::builder().discover(true).unique(true).build()
+    }
+
+    /// Returns a builder for a monitor with custom options.
+    pub fn builder() -> MonitorBuilder {
+        MonitorBuilder::default()
+    }
+
+    /// Lists the addresses of all connected devices.
+    ///
+    /// This is a shorthand for collecting the stream returned by
+    /// [`Monitor::enumerate`].
+    ///
+    /// # Examples
+    /// ```
+    /// use xwiimote::Monitor;
+    ///
+    /// # let _ = async {
+    /// for address in Monitor::list().await? {
+    ///     println!("found {address}");
+    /// }
+    /// # Ok::<(), xwiimote::Error>(())
+    /// # };
+    /// ```
+    pub async fn list() -> Result<Vec<Address>> {
+        let mut monitor = Self::enumerate()?;
+        let mut addresses = Vec::new();
+        while let Some(address) = future::poll_fn(|cx| Pin::new(&mut monitor).poll_next(cx)).await {
+            addresses.push(address?);
+        }
+        Ok(addresses)
+    }
+
+    /// Lists the addresses of all connected devices, without an
+    /// asynchronous runtime; see [`Monitor::list`].
+    ///
+    /// The enumeration never waits for the devices, so this does not
+    /// block for long.
+    pub fn list_blocking() -> Result<Vec<Address>> {
+        let mut monitor = Self::enumerate()?;
+        let mut addresses = Vec::new();
+        while let Some(address) = monitor.poll_once()? {
+            addresses.push(address);
+        }
+        Ok(addresses)
+    }
+
+    /// Stops producing devices until [`Monitor::resume`] is called,
+    /// e.g. so that a daemon ignores the devices that connect during
+    /// a game session.
+    ///
+    /// Unlike dropping the monitor, this keeps the enumeration state
+    /// and the underlying `udev` monitor or netlink socket, so the
+    /// hot-plug events received in the meantime are not lost: the
+    /// devices they report are produced after resuming, as long as the
+    /// system can buffer the events. Does nothing if already paused.
+    pub fn pause(&mut self) -> Result<()> {
+        if self.paused {
+            return Ok(());
+        }
+        if let (true, Some(mon_fd)) = (self.registered, self.source.hotplug_fd()) {
+            // The pending task, if any, is woken and then parked below.
+            let interest = Interest::new(mon_fd, Self::HOTPLUG_EVENTS);
+            self.registered = false;
+            Reactor::get().remove_interest(&interest)?;
+        }
+        self.paused = true;
+        Ok(())
+    }
+
+    /// Resumes producing devices after a call to [`Monitor::pause`].
+    /// Does nothing if the monitor is not paused.
+    pub fn resume(&mut self) -> Result<()> {
+        if !self.paused {
+            return Ok(());
+        }
+        // The hot-plug descriptor is registered again once polled.
+        self.paused = false;
+        if let Some(waker) = self.resume_waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /// Checks whether the monitor is paused; see [`Monitor::pause`].
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Returns the descriptor that becomes readable when a device is
+    /// hot-plugged, or [`None`] if the monitor is not in discovery mode.
+    ///
+    /// This lets applications with their own event loop (e.g. `calloop`
+    /// or GLib) discover devices without the reactor of this crate:
+    /// register the descriptor for readability, and call
+    /// [`Monitor::poll_once`] until it returns [`None`] once at first
+    /// (to enumerate the connected devices) and then whenever the
+    /// descriptor is readable. Such a monitor should not be polled as
+    /// a [`Stream`] as well.
+    ///
+    /// The descriptor belongs to the monitor, and is closed once
+    /// the monitor is dropped.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use xwiimote::Monitor;
+    ///
+    /// let mut monitor = Monitor::discover()?;
+    /// let fd = monitor.hotplug_fd().expect("monitor is in discovery mode");
+    /// loop {
+    ///     while let Some(address) = monitor.poll_once()? {
+    ///         println!("found {}", address.uniq()?);
+    ///     }
+    ///     // An event loop would register `fd` instead of blocking here.
+    ///     let mut pollfd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+    ///     unsafe { libc::poll(&mut pollfd, 1, -1) };
+    /// }
+    /// # Ok::<(), xwiimote::Error>(())
+    /// ```
+    pub fn hotplug_fd(&self) -> Option<RawFd> {
+        self.source.hotplug_fd()
+    }
+
+    /// Returns the address of the next device if one is available
+    /// without blocking, first enumerating the connected devices and
+    /// then those reported by the hot-plug events received so far.
+    ///
+    /// Returns [`None`] if no device is available at the moment, or if
+    /// the monitor is paused. See [`Monitor::hotplug_fd`] for how to
+    /// wait for new devices.
+    pub fn poll_once(&mut self) -> Result<Option<Address>> {
+        if self.paused {
+            return Ok(None);
+        }
+        while let Some(address) = self.next_address()? {
+            if self.accepts(&address)? {
+                return Ok(Some(address));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Converts the monitor into a stream that tells apart the devices
+    /// that are pairing from those that are reconnecting.
+    ///
+    /// A device is considered known if BlueZ had stored a link key for it
+    /// before this function was called, or if the stream produced it
+    /// already. This requires read access to the `/var/lib/bluetooth`
+    /// directory, which usually belongs to the root user.
+    pub fn classify(self) -> Result<Discoveries> {
+        Ok(Discoveries {
+            monitor: self,
+            known: bonded_devices(Path::new(BLUEZ_STORAGE_DIR))?,
+        })
+    }
+
+    /// Converts the monitor into a stream that produces the details of
+    /// each device found, such as its type and name.
+    ///
+    /// The details are read from the `sysfs` filesystem, which is much
+    /// faster than connecting to the device in order to decide whether
+    /// it is of interest.
+    ///
+    /// The kernel driver detects the type of a hot-plugged device shortly
+    /// after reporting it, so the stream waits for up to a second before
+    /// producing a device whose type is unknown.
+    pub fn with_details(self) -> DiscoveredDevices {
+        DiscoveredDevices {
+            monitor: self,
+            pending: None,
+        }
+    }
+
+    /// Converts the monitor into a stream that also reports the removal
+    /// of the devices it produced, so that device managers can clean up
+    /// the state of a device without keeping its event stream open just
+    /// to notice that it ends.
+    ///
+    /// The removals are received from the kernel through netlink, except
+    /// with the `Backend::Libudev` backend, whose `udev` monitor receives
+    /// them too. A monitor that is not in discovery mode reports no
+    /// removals.
+    pub fn with_removals(mut self) -> Result<HotplugEvents> {
+        // Subscribe to removals right away, so that we do not miss those
+        // of the devices produced from now on.
+        let socket = match self.hotplug_fd() {
+            Some(_) if !self.source.track_removals() => Some(UeventSocket::new()?),
+            _ => None,
+        };
+        Ok(HotplugEvents {
+            monitor: self,
+            socket,
+            registered: false,
+            added: HashSet::new(),
+        })
+    }
+
+    /// Converts the monitor into a stream that connects to the devices
+    /// it finds, one at a time, according to the given options.
+    ///
+    /// The stream produces an error item whenever a connection fails
+    /// after exhausting the retry policy of `options`, and then goes on
+    /// with the next device.
+    ///
+    /// # Examples
+    /// ```
+    /// use futures_util::TryStreamExt;
+    /// use xwiimote::{Channels, ConnectOptions, Monitor};
+    ///
+    /// # let _ = async {
+    /// let options = ConnectOptions::new().channels(Channels::CORE, false);
+    /// let mut devices = Monitor::discover()?.devices(options);
+    /// while let Some(device) = devices.try_next().await? {
+    ///     println!("connected to {}", device.device_kind()?);
+    /// }
+    /// # Ok::<(), xwiimote::Error>(())
+    /// # };
+    /// ```
+    pub fn devices(self, options: ConnectOptions) -> Devices {
+        Devices {
+            monitor: Some(self),
+            options,
+            pending: None,
+        }
+    }
+}
+
+impl Monitor {
+    /// Consumes the addresses produced by the monitor until finding the
+    /// device with the given stable identifier, and returns its current
+    /// address.
+    ///
+    /// Returns [`None`] if the stream ends without producing the device,
+    /// which happens if the device is not connected and the monitor does
+    /// not discover new devices. A discovering monitor instead waits
+    /// until the device connects.
+    pub async fn resolve(&mut self, id: &StableId) -> Result<Option<Address>> {
+        while let Some(address) = future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await {
+            let address = address?;
+            // Devices that disconnected in the meantime cannot match.
+            if address.stable_id().ok().as_ref() == Some(id) {
+                return Ok(Some(address));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Monitor {
+    /// Reads the address of the next device if one is available
+    /// without blocking, regardless of its seat.
+    fn next_address(&mut self) -> Result<Option<Address>> {
+        if !self.enumerated {
+            // Enumerate the next connected device, if any.
+            // This process requires no blocking; read directly.
+            if let Some(address) = self.source.next_connected() {
+                return Ok(Some(address));
+            }
+            // We just read the first `null` device address;
+            // the enumeration phase is complete.
+            self.enumerated = true;
+        }
+        if self.source.hotplug_fd().is_none() {
+            return Ok(None);
+        }
+        self.source.next_discovered()
+    }
+
+    /// Polls for the address of the next device, regardless of its seat.
+    fn poll_address(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Address>>> {
+        loop {
+            if let Some(address) = self.next_address()? {
+                return Poll::Ready(Some(Ok(address)));
+            }
+            // At this point every connected device has already been produced.
+            // If we have a hot-plug descriptor, we should now discover new
+            // devices. Otherwise the enumeration process is complete.
+            let Some(mon_fd) = self.source.hotplug_fd() else {
+                return Poll::Ready(None);
+            };
+            let interest = Interest::new(mon_fd, Self::HOTPLUG_EVENTS);
+            if self.registered {
+                // No new device is available; arrange for `wake` to be called
+                // once a new device is found.
+                Reactor::get().set_callback(interest, cx.waker().clone())?;
+                return Poll::Pending;
+            }
+            // Listen for hot-plug events on the monitor descriptor, and
+            // poll again to return the first discovered device.
+            Reactor::get().add_interest(&interest)?;
+            self.registered = true;
+        }
+    }
+
+    /// Checks whether a device belongs to the seat of the monitor, if any.
+    fn is_on_seat(&self, address: &Address) -> Result<bool> {
+        match &self.seat {
+            Some(seat) => Ok(device_seat(&address.0)? == *seat),
+            None => Ok(true),
+        }
+    }
+
+    /// Checks whether a device should be produced, i.e. it belongs to
+    /// the seat of the monitor and, if duplicates are suppressed, it was
+    /// not produced before.
+    fn accepts(&mut self, address: &Address) -> Result<bool> {
+        if !self.is_on_seat(address)? {
+            return Ok(false);
+        }
+        let Some(produced) = &mut self.produced else {
+            return Ok(true);
+        };
+        // Forget the devices that were removed, which keeps the set small.
+        produced.retain(|path| path.exists());
+        Ok(produced.insert(address.0.clone()))
+    }
+}
+
+impl Stream for Monitor {
+    type Item = Result<Address>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.paused {
+            self.resume_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        loop {
+            let address = match self.poll_address(cx)? {
+                Poll::Ready(Some(address)) => address,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+            if self.accepts(&address)? {
+                return Poll::Ready(Some(Ok(address)));
+            }
+        }
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        if let (true, Some(mon_fd)) = (self.registered, self.source.hotplug_fd()) {
+            // Do not panic, since we may be unwinding already.
+            let interest = Interest::new(mon_fd, Self::HOTPLUG_EVENTS);
+            let _ = Reactor::get().remove_interest(&interest);
+        }
+        if let Source::Udev { handle, .. } = self.source {
+            // Decrements ref-count to zero. This closes `mon_fd`, if set.
+            unsafe { xwii_monitor_unref(handle) };
+        }
+    }
+}
+
+/// The seat to which devices belong unless stated otherwise.
+const DEFAULT_SEAT: &str = "seat0";
+
+/// The directory of the `udev` database.
+const UDEV_DATA_DIR: &str = "/run/udev/data";
+
+/// Determines the `logind` seat of the device at the given path
+/// in the `sysfs` filesystem.
+///
+/// The seat is given by the `ID_SEAT` property of the device or
+/// of its closest ancestor that has one, as recorded in the `udev`
+/// database. Devices without this property belong to the default seat.
+fn device_seat(syspath: &Path) -> Result<String> {
+    for dir in syspath.ancestors() {
+        let subsystem = match fs::read_link(dir.join("subsystem")) {
+            Ok(link) => link,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        let (Some(subsystem), Some(sysname)) = (subsystem.file_name(), dir.file_name()) else {
+            continue;
+        };
+        let db_path = Path::new(UDEV_DATA_DIR).join(format!(
+            "+{}:{}",
+            subsystem.to_string_lossy(),
+            sysname.to_string_lossy()
+        ));
+        let data = match fs::read_to_string(db_path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        if let Some(seat) = parse_seat(&data) {
+            return Ok(seat.to_owned());
+        }
+    }
+    Ok(DEFAULT_SEAT.to_owned())
+}
+
+/// Extracts the `ID_SEAT` property from a `udev` database entry.
+fn parse_seat(data: &str) -> Option<&str> {
+    data.lines()
+        .find_map(|line| line.strip_prefix("E:ID_SEAT="))
+        .filter(|seat| !seat.is_empty())
+}
+
+/// The directory in which BlueZ stores the adapters and their paired devices.
+const BLUEZ_STORAGE_DIR: &str = "/var/lib/bluetooth";
+
+/// A device found by the stream returned by [`Monitor::classify`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Discovered {
+    /// A device that is connecting for the first time, typically
+    /// after the user pressed its sync button.
+    Pairing(Address),
+    /// A device that was paired before, typically reconnecting
+    /// after the user pressed any of its buttons.
+    Known(Address),
+}
+
+impl Discovered {
+    /// Returns the address of the discovered device.
+    pub fn address(&self) -> &Address {
+        match self {
+            Self::Pairing(address) | Self::Known(address) => address,
+        }
+    }
+
+    /// Converts into the address of the discovered device.
+    pub fn into_address(self) -> Address {
+        match self {
+            Self::Pairing(address) | Self::Known(address) => address,
+        }
+    }
+}
+
+/// Streams the devices found by a [`Monitor`], telling apart
+/// those that are pairing from those that are known.
+pub struct Discoveries {
+    monitor: Monitor,
+    /// The uppercase Bluetooth addresses of the known devices.
+    known: HashSet<String>,
+}
+
+impl Discoveries {
+    /// Classifies the device with the given Bluetooth address,
+    /// which is known afterwards.
+    fn classify(&mut self, address: Address, uniq: &str) -> Discovered {
+        if self.known.insert(uniq.to_ascii_uppercase()) {
+            Discovered::Pairing(address)
+        } else {
+            Discovered::Known(address)
+        }
+    }
+}
+
+impl Stream for Discoveries {
+    type Item = Result<Discovered>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let address = match Pin::new(&mut self.monitor).poll_next(cx)? {
+            Poll::Ready(Some(address)) => address,
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => return Poll::Pending,
+        };
+        let uniq = address.uniq()?;
+        Poll::Ready(Some(Ok(self.classify(address, &uniq))))
+    }
+}
+
+/// The details of a device found by a [`Monitor`], read without
+/// connecting to it; see [`Monitor::with_details`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredDevice {
+    /// The address of the device.
+    pub address: Address,
+    /// The type of the device, or [`None`] if the kernel driver
+    /// did not detect it in time.
+    pub kind: Option<DeviceKind>,
+    /// The Bluetooth address of the device, if it reports one;
+    /// see [`Address::uniq`].
+    pub uniq: Option<String>,
+    /// The name reported by the device, such as `Nintendo RVL-CNT-01`.
+    pub name: Option<String>,
+}
+
+impl DiscoveredDevice {
+    /// Reads the details of the device with the given address.
+    fn read(address: Address) -> Result<Self> {
+        let info = address.info()?;
+        // The driver reports `pending` until it detects the device type.
+        let kind = fs::read_to_string(address.0.join("devtype"))
+            .ok()
+            .map(|raw| raw.trim().to_owned())
+            .filter(|raw| !raw.is_empty() && raw != "pending")
+            .map(|raw| raw.parse().unwrap());
+        Ok(Self {
+            kind,
+            uniq: info
+                .uniq()
+                .filter(|uniq| !uniq.is_empty())
+                .map(str::to_owned),
+            name: info.name().map(str::to_owned),
+            address,
+        })
+    }
+}
+
+/// Streams the details of the devices found by a [`Monitor`].
+pub struct DiscoveredDevices {
+    monitor: Monitor,
+    /// The device whose type is not detected yet, if any, the timer
+    /// that expires once it should be read again, and the number of
+    /// attempts left after the next one.
+    pending: Option<(DiscoveredDevice, Sleep, u32)>,
+}
+
+impl DiscoveredDevices {
+    /// The time between two readings of the type of a device.
+    const KIND_POLL_INTERVAL: Duration = Duration::from_millis(50);
+    /// The number of readings of the type of a device after the first.
+    const KIND_RETRIES: u32 = 20;
+}
+
+impl Stream for DiscoveredDevices {
+    type Item = Result<DiscoveredDevice>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some((found, timer, retries)) = &mut self.pending {
+                ready!(Pin::new(&mut *timer).poll(cx))?;
+                match DiscoveredDevice::read(found.address.clone()) {
+                    Ok(again) if again.kind.is_none() && *retries > 0 => {
+                        *retries -= 1;
+                        timer.reset(Self::KIND_POLL_INTERVAL)?;
+                        continue;
+                    }
+                    res => {
+                        self.pending = None;
+                        return Poll::Ready(Some(res));
+                    }
+                }
+            }
+            let address = match Pin::new(&mut self.monitor).poll_next(cx)? {
+                Poll::Ready(Some(address)) => address,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+            match DiscoveredDevice::read(address) {
+                // The driver reports the type once it detects it.
+                Ok(found) if found.kind.is_none() => {
+                    let timer = Sleep::new(Self::KIND_POLL_INTERVAL)?;
+                    self.pending = Some((found, timer, Self::KIND_RETRIES));
+                }
+                res => return Poll::Ready(Some(res)),
+            }
+        }
+    }
+}
+
+/// A change in the set of connected devices, reported by the stream
+/// returned by [`Monitor::with_removals`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceEvent {
+    /// A device was found, either connected or hot-plugged.
+    Added(Address),
+    /// A device produced before was removed, typically because it
+    /// disconnected. Its address is no longer valid.
+    Removed(Address),
+}
+
+impl DeviceEvent {
+    /// Returns the address of the added or removed device.
+    pub fn address(&self) -> &Address {
+        match self {
+            Self::Added(address) | Self::Removed(address) => address,
+        }
+    }
+}
+
+/// Streams the devices found by a [`Monitor`], along with the removal
+/// of those devices.
+pub struct HotplugEvents {
+    monitor: Monitor,
+    /// The socket on which removals are received.
+    /// Only present in discovery mode.
+    socket: Option<UeventSocket>,
+    /// Is the removal socket registered with the reactor?
+    registered: bool,
+    /// The devices produced and not removed since.
+    added: HashSet<Address>,
+}
+
+impl HotplugEvents {
+    /// Returns the address of the next removed device that was produced
+    /// before, if any is available without blocking.
+    fn next_removal(&mut self) -> Result<Option<Address>> {
+        let Some(socket) = &self.socket else {
+            while let Some(address) = self.monitor.source.next_removed() {
+                if self.added.remove(&address) {
+                    return Ok(Some(address));
+                }
+            }
+            return Ok(None);
+        };
+        while let Some(event) = socket.receive()? {
+            if !Source::is_removed_device(&event) {
+                continue;
+            }
+            let address = Address::from(PathBuf::from(event.syspath()));
+            if self.added.remove(&address) {
+                return Ok(Some(address));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Stream for HotplugEvents {
+    type Item = Result<DeviceEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(address) = self.next_removal()? {
+                return Poll::Ready(Some(Ok(DeviceEvent::Removed(address))));
+            }
+            match Pin::new(&mut self.monitor).poll_next(cx)? {
+                Poll::Ready(Some(address)) => {
+                    self.added.insert(address.clone());
+                    return Poll::Ready(Some(Ok(DeviceEvent::Added(address))));
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => {}
+            }
+            // The monitor waits for hot-plug events; wait for removals too.
+            let Some(fd) = self.socket.as_ref().map(AsRawFd::as_raw_fd) else {
+                // The monitor may have received removals along with them.
+                return match self.next_removal()? {
+                    Some(address) => Poll::Ready(Some(Ok(DeviceEvent::Removed(address)))),
+                    None => Poll::Pending,
+                };
+            };
+            let interest = Interest::new(fd, Monitor::HOTPLUG_EVENTS);
+            if self.registered {
+                Reactor::get().set_callback(interest, cx.waker().clone())?;
+                return Poll::Pending;
+            }
+            // Removals may have been received before the registration;
+            // poll again before waiting.
+            Reactor::get().add_interest(&interest)?;
+            self.registered = true;
+        }
+    }
+}
+
+impl Drop for HotplugEvents {
+    fn drop(&mut self) {
+        if let (true, Some(socket)) = (self.registered, &self.socket) {
+            // Do not panic, since we may be unwinding already.
+            let interest = Interest::new(socket.as_raw_fd(), Monitor::HOTPLUG_EVENTS);
+            let _ = Reactor::get().remove_interest(&interest);
+        }
+    }
+}
+
+/// A connection attempt in progress.
+type Connecting = Pin<Box<dyn Future<Output = Result<Device>>>>;
+
+/// Streams the devices found by a [`Monitor`], once connected.
+pub struct Devices {
+    /// The monitor, until it produces no more addresses.
+    monitor: Option<Monitor>,
+    options: ConnectOptions,
+    /// The connection to the last device found, if in progress.
+    pending: Option<Connecting>,
+}
+
+impl Stream for Devices {
+    type Item = Result<Device>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(pending) = &mut self.pending {
+                let res = ready!(pending.as_mut().poll(cx));
+                self.pending = None;
+                return Poll::Ready(Some(res));
+            }
+            let Some(monitor) = &mut self.monitor else {
+                return Poll::Ready(None);
+            };
+            let address = match ready!(Pin::new(monitor).poll_next(cx)) {
+                Some(Ok(address)) => address,
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => {
+                    self.monitor = None;
+                    return Poll::Ready(None);
+                }
+            };
+            let options = self.options.clone();
+            self.pending = Some(Box::pin(async move { options.connect(&address).await }));
+        }
+    }
+}
+
+/// Lists the uppercase Bluetooth addresses of the devices for which
+/// BlueZ stores a link key, on any adapter.
+///
+/// BlueZ keeps the information of each device in the file
+/// `<dir>/<adapter address>/<device address>/info`.
+fn bonded_devices(dir: &Path) -> Result<HashSet<String>> {
+    let is_address = |entry: &fs::DirEntry| entry.file_name().to_string_lossy().contains(':');
+    let mut bonded = HashSet::new();
+    for adapter in fs::read_dir(dir)? {
+        let adapter = adapter?;
+        if !is_address(&adapter) {
+            continue;
+        }
+        for device in fs::read_dir(adapter.path())? {
+            let device = device?;
+            if !is_address(&device) {
+                continue;
+            }
+            let info = match fs::read_to_string(device.path().join("info")) {
+                Ok(info) => info,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+            if info.lines().any(|line| line.trim() == "[LinkKey]") {
+                let name = device.file_name().to_string_lossy().to_ascii_uppercase();
+                bonded.insert(name);
+            }
+        }
+    }
+    Ok(bonded)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::monitor::{
+        bonded_devices, parse_seat, DeviceEvent, Devices, DiscoveredDevice, Monitor, Source,
+    };
+    use crate::netlink::Uevent;
+    use crate::reactor::Reactor;
+    use crate::{Address, ConnectOptions, DeviceKind, Error, Result};
+    use futures_util::{Stream, StreamExt};
+    use std::collections::{HashSet, VecDeque};
+    use std::fs;
+    use std::os::fd::AsRawFd;
+    use std::path::PathBuf;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+    use std::time::Duration;
+
+    #[test]
+    fn parses_seat() {
+        let data = "I:1834022318\nE:ID_SEAT=seat1\nG:seat\nQ:seat\nV:1\n";
+        assert_eq!(parse_seat(data), Some("seat1"));
+        assert_eq!(parse_seat("E:ID_SEAT=\n"), None);
+        assert_eq!(parse_seat("I:1834022318\n"), None);
+    }
+
+    #[test]
+    fn finds_bonded_devices() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("xwiimote-bluez-{}", std::process::id()));
+        let adapter = dir.join("00:1A:7D:DA:71:13");
+        for (device, info) in [
+            (
+                "00:1F:32:AA:BB:CC",
+                "[General]\nName=Nintendo RVL-CNT-01\n\n[LinkKey]\nKey=0011\n",
+            ),
+            ("00:1F:32:DD:EE:FF", "[General]\nName=Nintendo RVL-CNT-01\n"),
+        ] {
+            fs::create_dir_all(adapter.join(device))?;
+            fs::write(adapter.join(device).join("info"), info)?;
+        }
+        fs::create_dir_all(adapter.join("cache"))?;
+        fs::write(dir.join("settings"), "")?;
+
+        let bonded = bonded_devices(&dir)?;
+        fs::remove_dir_all(dir)?;
+        assert_eq!(bonded.len(), 1);
+        assert!(bonded.contains("00:1F:32:AA:BB:CC"));
+        Ok(())
+    }
+
+    #[test]
+    fn pauses_and_resumes() -> Result<()> {
+        let address = Address::from(PathBuf::from("/sys/bus/hid/devices/0005:057E:0306.0001"));
+        let mut monitor = Monitor {
+            source: Source::Netlink {
+                connected: VecDeque::from([address.clone()]),
+                socket: None,
+            },
+            enumerated: false,
+            registered: false,
+            seat: None,
+            produced: None,
+            paused: false,
+            resume_waker: None,
+        };
+        let mut cx = Context::from_waker(Waker::noop());
+
+        monitor.pause()?;
+        assert!(monitor.is_paused());
+        assert!(Pin::new(&mut monitor).poll_next(&mut cx).is_pending());
+        monitor.resume()?;
+        assert!(!monitor.is_paused());
+        match Pin::new(&mut monitor).poll_next(&mut cx) {
+            Poll::Ready(Some(Ok(produced))) => assert_eq!(produced, address),
+            _ => panic!("expected the connected device"),
+        }
+        assert!(matches!(
+            Pin::new(&mut monitor).poll_next(&mut cx),
+            Poll::Ready(None)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn suppresses_duplicates() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("xwiimote-unique-{}", std::process::id()));
+        let paths = [
+            dir.join("0005:057E:0306.0001"),
+            dir.join("0005:057E:0306.0002"),
+        ];
+        for path in &paths {
+            fs::create_dir_all(path)?;
+        }
+        let [first, second] = paths.map(Address::from);
+        let mut monitor = Monitor {
+            source: Source::Netlink {
+                connected: VecDeque::from([first.clone(), second.clone(), first.clone()]),
+                socket: None,
+            },
+            enumerated: false,
+            registered: false,
+            seat: None,
+            produced: Some(HashSet::new()),
+            paused: false,
+            resume_waker: None,
+        };
+        assert_eq!(monitor.poll_once()?.as_ref(), Some(&first));
+        fs::remove_dir(&second.0)?;
+        assert_eq!(monitor.poll_once()?.as_ref(), Some(&second));
+        assert_eq!(monitor.poll_once()?, None);
+        // The removed device is forgotten.
+        assert_eq!(monitor.produced, Some(HashSet::from([first.0.clone()])));
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn polls_without_reactor() -> Result<()> {
+        let addresses = [
+            Address::from(PathBuf::from("/sys/bus/hid/devices/0005:057E:0306.0001")),
+            Address::from(PathBuf::from("/sys/bus/hid/devices/0005:057E:0330.0002")),
+        ];
+        let mut monitor = Monitor {
+            source: Source::Netlink {
+                connected: VecDeque::from(addresses.clone()),
+                socket: None,
+            },
+            enumerated: false,
+            registered: false,
+            seat: None,
+            produced: None,
+            paused: false,
+            resume_waker: None,
+        };
+        assert_eq!(monitor.hotplug_fd(), None);
+
+        assert_eq!(monitor.poll_once()?.as_ref(), Some(&addresses[0]));
+        monitor.pause()?;
+        assert_eq!(monitor.poll_once()?, None);
+        monitor.resume()?;
+        assert_eq!(monitor.poll_once()?.as_ref(), Some(&addresses[1]));
+        assert_eq!(monitor.poll_once()?, None);
+        assert!(!monitor.registered);
+        Ok(())
+    }
+
+    #[test]
+    fn reads_discovered_device() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("xwiimote-found-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        fs::write(
+            dir.join("uevent"),
+            "HID_NAME=Nintendo RVL-CNT-01-TR\nHID_UNIQ=00:1f:32:aa:bb:cc\n",
+        )?;
+        fs::write(dir.join("devtype"), "pending\n")?;
+        let address = Address::from(dir.clone());
+
+        let found = DiscoveredDevice::read(address.clone())?;
+        assert_eq!(found.address, address);
+        assert_eq!(found.kind, None);
+        assert_eq!(found.uniq.as_deref(), Some("00:1f:32:aa:bb:cc"));
+        assert_eq!(found.name.as_deref(), Some("Nintendo RVL-CNT-01-TR"));
+        fs::write(dir.join("devtype"), "gen20\n")?;
+        let found = DiscoveredDevice::read(address)?;
+        assert_eq!(found.kind, Some(DeviceKind::WiiRemotePlus));
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn waits_for_the_device_kind() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("xwiimote-kind-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("uevent"), "HID_NAME=Nintendo RVL-CNT-01\n")?;
+        fs::write(dir.join("devtype"), "pending\n")?;
+        let address = Address::from(dir.clone());
+        let monitor = Monitor {
+            source: Source::Netlink {
+                connected: VecDeque::from([address.clone()]),
+                socket: None,
+            },
+            enumerated: false,
+            registered: false,
+            seat: None,
+            produced: None,
+            paused: false,
+            resume_waker: None,
+        };
+        let mut found = monitor.with_details();
+        let detect = std::thread::spawn({
+            let dir = dir.clone();
+            move || {
+                std::thread::sleep(Duration::from_millis(120));
+                fs::write(dir.join("devtype"), "gen10\n")
+            }
+        });
+        futures_executor::block_on(async {
+            let device = found.next().await.unwrap()?;
+            assert_eq!(device.kind, Some(DeviceKind::WiiRemote));
+            assert!(found.next().await.is_none());
+            Ok::<_, Error>(())
+        })?;
+        detect.join().unwrap()?;
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn dropping_pending_discovery_removes_waker() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("xwiimote-drop-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("uevent"), "HID_NAME=Nintendo RVL-CNT-01\n")?;
+        fs::write(dir.join("devtype"), "pending\n")?;
+        let mut cx = Context::from_waker(Waker::noop());
+        for n_polls in 1..4 {
+            let monitor = Monitor {
+                source: Source::Netlink {
+                    connected: VecDeque::from([Address::from(dir.clone())]),
+                    socket: None,
+                },
+                enumerated: false,
+                registered: false,
+                seat: None,
+                produced: None,
+                paused: false,
+                resume_waker: None,
+            };
+            let mut found = monitor.with_details();
+            for _ in 0..n_polls {
+                assert!(Pin::new(&mut found).poll_next(&mut cx).is_pending());
+            }
+            let (_, timer, _) = found.pending.as_ref().unwrap();
+            let fd = timer.as_raw_fd();
+            assert!(Reactor::get().has_waker(fd));
+            drop(found);
+            assert!(!Reactor::get().has_waker(fd));
+        }
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn reports_added_devices() -> Result<()> {
+        let address = Address::from(PathBuf::from("/sys/bus/hid/devices/0005:057E:0306.0001"));
+        let monitor = Monitor {
+            source: Source::Netlink {
+                connected: VecDeque::from([address.clone()]),
+                socket: None,
+            },
+            enumerated: false,
+            registered: false,
+            seat: None,
+            produced: None,
+            paused: false,
+            resume_waker: None,
+        };
+        let mut events = monitor.with_removals()?;
+        assert!(events.socket.is_none());
+        futures_executor::block_on(async {
+            let event = events.next().await.unwrap()?;
+            assert_eq!(event, DeviceEvent::Added(address.clone()));
+            assert!(events.next().await.is_none());
+            Ok::<_, Error>(())
+        })?;
+        assert_eq!(events.added, HashSet::from([address]));
+        Ok(())
+    }
+
+    #[test]
+    fn detects_removed_devices() {
+        let remove = b"remove@/devices/virtual/misc/uhid/0005:057E:0306.0001\0\
+            ACTION=remove\0SUBSYSTEM=hid\0HID_UNIQ=00:11:22:33:44:55\0";
+        let unbind = b"unbind@/devices/virtual/misc/uhid/0005:057E:0306.0001\0\
+            ACTION=unbind\0SUBSYSTEM=hid\0";
+        let input = b"remove@/devices/virtual/misc/uhid/0005:057E:0306.0001/input/input7\0\
+            ACTION=remove\0SUBSYSTEM=input\0";
+        assert!(Source::is_removed_device(&Uevent::parse(remove).unwrap()));
+        assert!(!Source::is_removed_device(&Uevent::parse(unbind).unwrap()));
+        assert!(!Source::is_removed_device(&Uevent::parse(input).unwrap()));
+    }
+
+    #[cfg(feature = "udev")]
+    #[test]
+    fn classifies_udev_events() {
+        use std::ffi::OsStr;
+        use std::path::Path;
+        use udev::EventType;
+
+        let syspath = Path::new("/sys/devices/virtual/misc/uhid/0005:057E:0306.0001");
+        let address = Address::from(syspath.to_owned());
+        let wiimote = Some(OsStr::new("wiimote"));
+        let classify = |event_type, driver| Source::classify_udev(event_type, driver, syspath);
+        for event_type in [EventType::Add, EventType::Bind] {
+            assert_eq!(
+                classify(event_type, wiimote),
+                Some(DeviceEvent::Added(address.clone()))
+            );
+            // Other HID devices, or a Wii Remote not bound to the driver yet.
+            assert_eq!(classify(event_type, Some(OsStr::new("hid-generic"))), None);
+            assert_eq!(classify(event_type, None), None);
+        }
+        // The driver is unbound before the device is removed.
+        assert_eq!(
+            classify(EventType::Remove, None),
+            Some(DeviceEvent::Removed(address))
+        );
+        assert_eq!(classify(EventType::Unbind, wiimote), None);
+        assert_eq!(classify(EventType::Change, wiimote), None);
+    }
+
+    #[test]
+    fn yields_connection_failures() {
+        let mut devices = Devices {
+            monitor: None,
+            options: ConnectOptions::default(),
+            pending: Some(Box::pin(async { Err(Error::Disconnected) })),
+        };
+        futures_executor::block_on(async {
+            let res = devices.next().await.unwrap();
+            assert!(matches!(res, Err(Error::Disconnected)));
+            assert!(devices.next().await.is_none());
+        });
+    }
+}
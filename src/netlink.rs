@@ -0,0 +1,163 @@
+use crate::{bail_if, Result};
+use std::collections::HashMap;
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+/// A kernel object event, as broadcast by the kernel through netlink.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Uevent {
+    /// The action that occurred, such as `add`, `bind` or `remove`.
+    pub action: String,
+    /// The path of the device, relative to the `/sys` directory.
+    pub devpath: String,
+    /// The environment of the event, such as `SUBSYSTEM` or `DRIVER`.
+    pub properties: HashMap<String, String>,
+}
+
+impl Uevent {
+    /// Parses a message of the form `ACTION@DEVPATH\0KEY=VALUE\0...`.
+    ///
+    /// Returns [`None`] if the message is malformed.
+    pub fn parse(msg: &[u8]) -> Option<Self> {
+        let mut fields = msg
+            .split(|&b| b == 0)
+            .filter(|field| !field.is_empty())
+            .map(String::from_utf8_lossy);
+
+        let header = fields.next()?;
+        let (action, devpath) = header.split_once('@')?;
+        let properties = fields
+            .filter_map(|field| {
+                let (key, value) = field.split_once('=')?;
+                Some((key.to_owned(), value.to_owned()))
+            })
+            .collect();
+        Some(Self {
+            action: action.to_owned(),
+            devpath: devpath.to_owned(),
+            properties,
+        })
+    }
+
+    /// Returns the value of a property, if present.
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(String::as_str)
+    }
+
+    /// Returns the absolute path of the device in the `sysfs` filesystem.
+    pub fn syspath(&self) -> String {
+        format!("/sys{}", self.devpath)
+    }
+}
+
+/// A non-blocking socket that receives the kernel object events
+/// broadcast by the kernel.
+pub(crate) struct UeventSocket(OwnedFd);
+
+impl UeventSocket {
+    /// The multicast group of the events sent by the kernel, as opposed
+    /// to those relayed by `udevd`.
+    const KERNEL_GROUP: u32 = 1;
+
+    /// The maximum size of a message.
+    const BUF_SIZE: usize = 8192;
+
+    /// Opens a socket subscribed to the kernel object events.
+    pub fn new() -> Result<Self> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_DGRAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                libc::NETLINK_KOBJECT_UEVENT,
+            )
+        };
+        bail_if!(fd == -1);
+        let socket = Self(unsafe { OwnedFd::from_raw_fd(fd) });
+
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = Self::KERNEL_GROUP;
+        let res_code = unsafe {
+            libc::bind(
+                fd,
+                &addr as *const _ as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        bail_if!(res_code == -1);
+        Ok(socket)
+    }
+
+    /// Receives the next event sent by the kernel.
+    ///
+    /// Returns [`None`] if no event is available. Messages that do not
+    /// originate from the kernel, or that are malformed, are skipped.
+    pub fn receive(&self) -> Result<Option<Uevent>> {
+        let mut buf = [0u8; Self::BUF_SIZE];
+        loop {
+            let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+            let mut addr_len = mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t;
+            let len = unsafe {
+                libc::recvfrom(
+                    self.0.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    0,
+                    &mut addr as *mut _ as *mut libc::sockaddr,
+                    &mut addr_len,
+                )
+            };
+            if len == -1 {
+                let err = io::Error::last_os_error();
+                return match err.kind() {
+                    io::ErrorKind::WouldBlock => Ok(None),
+                    io::ErrorKind::Interrupted => continue,
+                    _ => Err(err.into()),
+                };
+            }
+
+            // Only trust messages sent by the kernel.
+            if addr.nl_pid != 0 {
+                continue;
+            }
+            if let Some(event) = Uevent::parse(&buf[..len as usize]) {
+                return Ok(Some(event));
+            }
+        }
+    }
+}
+
+impl AsRawFd for UeventSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::netlink::Uevent;
+
+    #[test]
+    fn parses_uevent() {
+        let msg = b"bind@/devices/virtual/misc/uhid/0005:057E:0306.0001\0\
+            ACTION=bind\0DEVPATH=/devices/virtual/misc/uhid/0005:057E:0306.0001\0\
+            SUBSYSTEM=hid\0DRIVER=wiimote\0HID_UNIQ=00:11:22:33:44:55\0";
+        let event = Uevent::parse(msg).unwrap();
+        assert_eq!(event.action, "bind");
+        assert_eq!(
+            event.syspath(),
+            "/sys/devices/virtual/misc/uhid/0005:057E:0306.0001"
+        );
+        assert_eq!(event.property("SUBSYSTEM"), Some("hid"));
+        assert_eq!(event.property("DRIVER"), Some("wiimote"));
+        assert_eq!(event.property("HID_UNIQ"), Some("00:11:22:33:44:55"));
+        assert_eq!(event.property("MODALIAS"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_uevent() {
+        assert_eq!(Uevent::parse(b""), None);
+        assert_eq!(Uevent::parse(b"libudev\0"), None);
+    }
+}
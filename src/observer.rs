@@ -0,0 +1,290 @@
+//! Read-only handles that watch a device beside its main consumer.
+
+use crate::events::Event;
+use crate::{Address, Error, Led, Result};
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::task::{Context, Poll, Waker};
+use std::time::SystemTime;
+
+/// The events received by a subscriber that have not been consumed yet.
+pub(crate) struct Queue {
+    events: VecDeque<(Event, SystemTime)>,
+    /// The maximum number of buffered events.
+    capacity: usize,
+    /// The number of events discarded to make room for newer ones.
+    missed: u64,
+    /// The waker of the task that waits for the next event, if any.
+    waker: Option<Waker>,
+    /// Are no more events coming?
+    closed: bool,
+    /// The error that closed the queue, if it was not produced yet.
+    error: Option<Error>,
+}
+
+impl Queue {
+    /// Returns the number of events discarded so far because the
+    /// queue was full.
+    pub(crate) fn missed(&self) -> u64 {
+        self.missed
+    }
+
+    /// Produces the oldest event, or the error that closed the queue
+    /// once it is empty; otherwise arranges for `wake` to be called
+    /// once an event is received.
+    pub(crate) fn poll_next(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<(Event, SystemTime)>>> {
+        if let Some(event) = self.events.pop_front() {
+            Poll::Ready(Some(Ok(event)))
+        } else if self.closed {
+            Poll::Ready(self.error.take().map(Err))
+        } else {
+            self.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    /// Wakes the task that waits for the next event, if any.
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Decides which events a subscriber receives.
+pub(crate) type Filter = Box<dyn Fn(&Event) -> bool + Send>;
+
+/// The subscribers of a broadcast.
+#[derive(Default)]
+struct Subscribers {
+    /// The queues of the live subscribers, and their filters.
+    queues: Vec<(Weak<Mutex<Queue>>, Filter)>,
+    /// Are no more events coming?
+    closed: bool,
+}
+
+/// Relays copies of the events received from a device to its observers,
+/// or to the subscriptions of an [`EventBus`](crate::bus::EventBus).
+#[derive(Default)]
+pub(crate) struct Broadcast {
+    subscribers: Mutex<Subscribers>,
+}
+
+impl Broadcast {
+    /// The maximum number of events buffered for an observer.
+    /// Older events are discarded to make room for new ones.
+    pub(crate) const CAPACITY: usize = 256;
+
+    /// Creates the queue of a new subscriber that receives the events
+    /// accepted by `filter`, and buffers up to `capacity` of them.
+    /// The queue is closed already if the broadcast is.
+    pub(crate) fn subscribe(&self, capacity: usize, filter: Filter) -> Arc<Mutex<Queue>> {
+        let mut subscribers = self.lock();
+        let queue = Arc::new(Mutex::new(Queue {
+            events: VecDeque::new(),
+            capacity: capacity.max(1),
+            missed: 0,
+            waker: None,
+            closed: subscribers.closed,
+            error: None,
+        }));
+        if !subscribers.closed {
+            subscribers.queues.push((Arc::downgrade(&queue), filter));
+        }
+        queue
+    }
+
+    /// Sends a copy of an event to every subscriber that accepts it.
+    pub fn send(&self, event: (Event, SystemTime)) {
+        self.lock()
+            .queues
+            .retain(|(queue, filter)| match queue.upgrade() {
+                Some(queue) => {
+                    if filter(&event.0) {
+                        let mut queue = queue.lock().unwrap_or_else(PoisonError::into_inner);
+                        if queue.events.len() == queue.capacity {
+                            queue.events.pop_front();
+                            queue.missed += 1;
+                        }
+                        queue.events.push_back(event);
+                        queue.wake();
+                    }
+                    true
+                }
+                None => false, // the stream was dropped
+            });
+    }
+
+    /// Tells the subscribers that no more events are coming, because
+    /// of the given error if any.
+    pub fn close(&self, error: Option<Error>) {
+        let mut subscribers = self.lock();
+        subscribers.closed = true;
+        for (queue, _) in subscribers.queues.drain(..) {
+            if let Some(queue) = queue.upgrade() {
+                let mut queue = queue.lock().unwrap_or_else(PoisonError::into_inner);
+                queue.closed = true;
+                queue.error = error.as_ref().map(Error::duplicate);
+                queue.wake();
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Subscribers> {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A read-only handle to a [`Device`](crate::Device), obtained through
+/// [`Device::observer`](crate::Device::observer).
+///
+/// Observers query the state of the device through the `sysfs`
+/// filesystem, and cannot open channels or change the outputs
+/// of the device. They are meant for status displays that run
+/// beside the main consumer of the events.
+#[derive(Clone)]
+pub struct Observer {
+    address: Address,
+    broadcast: Arc<Broadcast>,
+}
+
+impl Observer {
+    pub(crate) fn new(address: Address, broadcast: Arc<Broadcast>) -> Self {
+        Self { address, broadcast }
+    }
+
+    /// Returns the address of the observed device.
+    pub fn address(&self) -> &Address {
+        &self.address
+    }
+
+    /// Reads the current battery level, as a percentage from 0 to 100%.
+    pub fn battery(&self) -> Result<u8> {
+        if let Some(entry) = fs::read_dir(self.address.0.join("power_supply"))?.next() {
+            let capacity = fs::read_to_string(entry?.path().join("capacity"))?;
+            return capacity
+                .trim()
+                .parse()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err).into());
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound, "the device has no battery").into())
+    }
+
+    /// Reads the current state of an LED light.
+    pub fn led(&self, light: Led) -> Result<bool> {
+        let brightness = fs::read_to_string(self.address.led_dir(light)?.join("brightness"))?;
+        Ok(brightness.trim() != "0")
+    }
+
+    /// Returns the current extension type identifier.
+    pub fn extension(&self) -> Result<String> {
+        let extension = fs::read_to_string(self.address.0.join("extension"))?;
+        Ok(extension.trim().to_owned())
+    }
+
+    /// Returns a stream that produces copies of the events received
+    /// by the streams of the device, from now on.
+    ///
+    /// The stream ends once the device disconnects or is dropped.
+    /// If the stream falls behind by more than a few hundred events,
+    /// the oldest ones are discarded.
+    pub fn events(&self) -> ObservedEvents {
+        ObservedEvents {
+            queue: self
+                .broadcast
+                .subscribe(Broadcast::CAPACITY, Box::new(|_| true)),
+        }
+    }
+}
+
+/// The stream returned by [`Observer::events`].
+pub struct ObservedEvents {
+    queue: Arc<Mutex<Queue>>,
+}
+
+impl Stream for ObservedEvents {
+    type Item = (Event, SystemTime);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // The device closes the broadcast without an error.
+        let mut queue = self.queue.lock().unwrap_or_else(PoisonError::into_inner);
+        queue.poll_next(cx).map(|item| item.and_then(Result::ok))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::Event;
+    use crate::observer::{Broadcast, Observer};
+    use crate::Address;
+    use futures_core::Stream;
+    use std::path::PathBuf;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Waker};
+    use std::time::SystemTime;
+
+    fn poll(events: &mut (impl Stream<Item = (Event, SystemTime)> + Unpin)) -> Poll<Option<Event>> {
+        let mut cx = Context::from_waker(Waker::noop());
+        Pin::new(events)
+            .poll_next(&mut cx)
+            .map(|item| item.map(|(event, _)| event))
+    }
+
+    #[test]
+    fn relays_events_until_closed() {
+        let broadcast = Arc::new(Broadcast::default());
+        let observer = Observer::new(Address::from(PathBuf::new()), Arc::clone(&broadcast));
+        broadcast.send((Event::Other, SystemTime::now()));
+
+        // Only the events sent after subscribing are received.
+        let mut events = observer.events();
+        assert!(poll(&mut events).is_pending());
+        broadcast.send((Event::Other, SystemTime::now()));
+        assert!(matches!(poll(&mut events), Poll::Ready(Some(Event::Other))));
+
+        broadcast.close(None);
+        assert!(matches!(poll(&mut events), Poll::Ready(None)));
+        // Late streams end right away.
+        assert!(matches!(poll(&mut observer.events()), Poll::Ready(None)));
+    }
+
+    #[test]
+    fn discards_old_events() {
+        let broadcast = Arc::new(Broadcast::default());
+        let observer = Observer::new(Address::from(PathBuf::new()), Arc::clone(&broadcast));
+        let mut events = observer.events();
+        for _ in 0..Broadcast::CAPACITY + 10 {
+            broadcast.send((Event::Other, SystemTime::now()));
+        }
+        let mut n_received = 0;
+        while poll(&mut events).is_ready() {
+            n_received += 1;
+        }
+        assert_eq!(n_received, Broadcast::CAPACITY);
+    }
+
+    #[test]
+    fn observer_is_thread_safe() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Observer>();
+    }
+
+    #[test]
+    fn forgets_dropped_streams() {
+        let broadcast = Arc::new(Broadcast::default());
+        let observer = Observer::new(Address::from(PathBuf::new()), Arc::clone(&broadcast));
+        drop(observer.events());
+        broadcast.send((Event::Other, SystemTime::now()));
+        assert!(broadcast.lock().queues.is_empty());
+    }
+}
@@ -0,0 +1,497 @@
+use crate::{lock, Result, RumbleLimit};
+use std::io;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use xwiimote_sys::{xwii_iface, xwii_iface_ref, xwii_iface_unref};
+
+/// A reference to a device handle that can be moved to the output thread.
+pub(crate) struct SharedHandle {
+    handle: *mut xwii_iface,
+    /// Serializes the calls on `handle`; see [`Device::with_handle`].
+    ///
+    /// [`Device::with_handle`]: crate::Device::with_handle
+    lock: Arc<Mutex<()>>,
+}
+
+// The calls on the handle are serialized by `lock`, which is shared with
+// the device that the handle belongs to.
+unsafe impl Send for SharedHandle {}
+unsafe impl Sync for SharedHandle {}
+
+impl SharedHandle {
+    /// Takes a new reference to `handle`, which is released on drop.
+    /// The calls on `handle` must be serialized by `lock`.
+    ///
+    /// # Safety
+    /// `handle` must point to a valid device.
+    pub unsafe fn new(handle: *mut xwii_iface, lock: Arc<Mutex<()>>) -> Self {
+        xwii_iface_ref(handle);
+        Self { handle, lock }
+    }
+
+    /// Runs `f` on the handle, once the calls of other threads on
+    /// the handle complete.
+    pub fn with<T>(&self, f: impl FnOnce(*mut xwii_iface) -> T) -> T {
+        let _lock = lock(&self.lock);
+        f(self.handle)
+    }
+}
+
+impl Drop for SharedHandle {
+    fn drop(&mut self) {
+        unsafe { xwii_iface_unref(self.handle) };
+    }
+}
+
+type Job = Box<dyn FnOnce(&mut Limiter) + Send>;
+
+/// Toggles a binary output, such as the rumble motor.
+type Toggle = Box<dyn FnMut(bool) -> Result<()> + Send>;
+
+/// Modulates a binary output by turning it on and off periodically.
+pub(crate) struct Pwm {
+    on: Duration,
+    /// How long the output stays off, or [`None`] for a single pulse.
+    off: Option<Duration>,
+    toggle: Toggle,
+    enabled: bool,
+    /// The time of the next transition.
+    next: Instant,
+}
+
+impl Pwm {
+    /// Keeps the output on for `on` and then off for `off`, repeatedly.
+    pub fn new(
+        on: Duration,
+        off: Duration,
+        toggle: impl FnMut(bool) -> Result<()> + Send + 'static,
+    ) -> Self {
+        Self {
+            on,
+            off: Some(off),
+            toggle: Box::new(toggle),
+            enabled: false,
+            next: Instant::now(),
+        }
+    }
+
+    /// Turns the output on for `on`, and then off for good.
+    pub fn once(on: Duration, toggle: impl FnMut(bool) -> Result<()> + Send + 'static) -> Self {
+        Self {
+            off: None,
+            ..Self::new(on, Duration::ZERO, toggle)
+        }
+    }
+
+    /// Makes the next transition, and schedules the following one.
+    ///
+    /// Returns whether the modulation continues.
+    fn step(&mut self) -> Result<bool> {
+        self.enabled = !self.enabled;
+        (self.toggle)(self.enabled)?;
+        let wait = match (self.enabled, self.off) {
+            (true, _) => self.on,
+            (false, Some(off)) => off,
+            (false, None) => return Ok(false),
+        };
+        // Skip the missed cycles if a write blocked.
+        self.next = (self.next + wait).max(Instant::now());
+        Ok(true)
+    }
+}
+
+/// Tracks for how long the limited output has been on, and turns it
+/// off once the [`RumbleLimit`] is exceeded, if set.
+#[derive(Default)]
+struct Limiter {
+    /// The limit, and how to turn the output off.
+    limit: Option<(RumbleLimit, Toggle)>,
+    /// The time at which the output was turned on, if it is on.
+    on_since: Option<Instant>,
+    /// The time until which the output must stay off, if cooling down.
+    cool_until: Option<Instant>,
+}
+
+impl Limiter {
+    /// Records a request to turn the output on or off at `now`, and
+    /// returns whether to carry it out.
+    fn request(&mut self, enabled: bool, now: Instant) -> bool {
+        if !enabled {
+            self.on_since = None;
+        } else if self.limit.is_some() && self.cool_until.is_some_and(|until| now < until) {
+            return false;
+        } else {
+            self.on_since.get_or_insert(now);
+        }
+        true
+    }
+
+    /// Returns the time at which the output must be turned off, if any.
+    fn deadline(&self) -> Option<Instant> {
+        let (limit, _) = self.limit.as_ref()?;
+        Some(self.on_since? + limit.max_on)
+    }
+
+    /// Turns the output off, and keeps it off for the cooldown.
+    fn trip(&mut self, now: Instant) {
+        if let Some((limit, toggle)) = &mut self.limit {
+            // There is nothing else to do if the write fails.
+            let _ = toggle(false);
+            self.cool_until = Some(now + limit.cooldown);
+        }
+        self.on_since = None;
+    }
+}
+
+enum Message {
+    Job(Job),
+    Pwm(Option<Pwm>),
+    Limit(Option<(RumbleLimit, Toggle)>),
+}
+
+/// Executes output operations, in order, on a dedicated thread, and
+/// gives up waiting for those that take longer than a timeout.
+///
+/// A write to a device whose Bluetooth link is dying may block for
+/// many seconds. The thread stays blocked, but the caller does not.
+///
+/// Between operations, the thread can also modulate an output; see
+/// [`OutputQueue::set_pwm`]. The time for which this output stays on
+/// can be capped; see [`OutputQueue::set_limit`].
+pub(crate) struct OutputQueue {
+    messages: Sender<Message>,
+    timeout: Option<Duration>,
+}
+
+impl OutputQueue {
+    /// Starts the output thread.
+    pub fn new(timeout: Option<Duration>) -> Result<Self> {
+        let (messages, queue) = mpsc::channel::<Message>();
+        thread::Builder::new()
+            .name("xwiimote-output".to_owned())
+            .spawn(move || {
+                let mut pwm: Option<Pwm> = None;
+                let mut limiter = Limiter::default();
+                // Exits once the queue is dropped and the pending jobs ran.
+                loop {
+                    let next = pwm.as_ref().map(|state| state.next);
+                    let message = match next.into_iter().chain(limiter.deadline()).min() {
+                        Some(next) => {
+                            let wait = next.saturating_duration_since(Instant::now());
+                            match queue.recv_timeout(wait) {
+                                Ok(message) => message,
+                                Err(RecvTimeoutError::Timeout) => {
+                                    let now = Instant::now();
+                                    if limiter.deadline().is_some_and(|limit| limit <= now) {
+                                        pwm = None;
+                                        limiter.trip(now);
+                                    } else if let Some(state) = &mut pwm {
+                                        // Also stop if the output cannot be written.
+                                        if !matches!(state.step(), Ok(true)) {
+                                            pwm = None;
+                                            limiter.request(false, now);
+                                        }
+                                    }
+                                    continue;
+                                }
+                                Err(RecvTimeoutError::Disconnected) => break,
+                            }
+                        }
+                        None => match queue.recv() {
+                            Ok(message) => message,
+                            Err(_) => break,
+                        },
+                    };
+                    match (message, &mut pwm) {
+                        (Message::Job(job), _) => job(&mut limiter),
+                        (Message::Limit(limit), _) => limiter.limit = limit,
+                        // A modulated output counts as on until it stops.
+                        (Message::Pwm(Some(_)), _) if !limiter.request(true, Instant::now()) => {
+                            pwm = None;
+                        }
+                        // Keep the phase, so that frequent updates of
+                        // the duty cycle do not restart it.
+                        (Message::Pwm(Some(new)), Some(state))
+                            if new.off.is_some() && state.off.is_some() =>
+                        {
+                            state.on = new.on;
+                            state.off = new.off;
+                            state.toggle = new.toggle;
+                        }
+                        (Message::Pwm(new), _) => pwm = new,
+                    }
+                }
+            })?;
+        Ok(Self { messages, timeout })
+    }
+
+    /// Returns the time after which an operation fails, if any.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Sets the time after which an operation fails, or lets the
+    /// operations block indefinitely if `timeout` is [`None`].
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Starts modulating an output between the queued operations,
+    /// replacing the previous modulation if any, or stops it if `pwm`
+    /// is [`None`]. The output is left in its current state once stopped.
+    ///
+    /// The modulation stops if the output cannot be written, and does
+    /// not start while the [limit](`OutputQueue::set_limit`) cools down.
+    pub fn set_pwm(&self, pwm: Option<Pwm>) -> Result<()> {
+        self.send(Message::Pwm(pwm))
+    }
+
+    /// Turns the modulated output off with `toggle` once it stays on
+    /// for longer than `limit` allows, either modulated or through
+    /// [`OutputQueue::run_limited`], or removes the limit if [`None`].
+    pub fn set_limit(
+        &self,
+        limit: Option<(RumbleLimit, impl FnMut(bool) -> Result<()> + Send + 'static)>,
+    ) -> Result<()> {
+        let limit = limit.map(|(limit, toggle)| (limit, Box::new(toggle) as Toggle));
+        self.send(Message::Limit(limit))
+    }
+
+    fn send(&self, message: Message) -> Result<()> {
+        self.messages
+            .send(message)
+            .map_err(|_| io::Error::other("the output thread exited").into())
+    }
+
+    /// Runs `op` after the operations queued before it, and waits
+    /// for its result. Fails with [`io::ErrorKind::TimedOut`] if the
+    /// operation does not complete within the timeout; it still runs
+    /// to completion in the background.
+    pub fn run(&self, op: impl FnOnce() -> Result<()> + Send + 'static) -> Result<()> {
+        self.submit(move |_| op())
+    }
+
+    /// Runs `op`, which turns the modulated output on or off as given
+    /// by `enabled`, like [`OutputQueue::run`]. Operations that turn
+    /// the output on fail with [`io::ErrorKind::ResourceBusy`] while
+    /// the limit cools down.
+    pub fn run_limited(
+        &self,
+        enabled: bool,
+        op: impl FnOnce() -> Result<()> + Send + 'static,
+    ) -> Result<()> {
+        self.submit(move |limiter| {
+            if limiter.request(enabled, Instant::now()) {
+                op()
+            } else {
+                Err(io::Error::new(
+                    io::ErrorKind::ResourceBusy,
+                    "the rumble motor is cooling down",
+                )
+                .into())
+            }
+        })
+    }
+
+    fn submit(&self, op: impl FnOnce(&mut Limiter) -> Result<()> + Send + 'static) -> Result<()> {
+        let (reply, result) = mpsc::sync_channel(1);
+        self.send(Message::Job(Box::new(move |limiter| {
+            // The caller may have given up already.
+            let _ = reply.send(op(limiter));
+        })))?;
+        let res = match self.timeout {
+            Some(timeout) => result.recv_timeout(timeout),
+            None => result.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match res {
+            Ok(res) => res,
+            Err(RecvTimeoutError::Timeout) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the output operation timed out",
+            )
+            .into()),
+            Err(RecvTimeoutError::Disconnected) => {
+                Err(io::Error::other("the output operation panicked").into())
+            }
+        }
+    }
+
+    /// Waits until the operations queued so far complete.
+    pub fn sync(&self) -> Result<()> {
+        self.run(|| Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output::{OutputQueue, Pwm};
+    use crate::RumbleLimit;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn runs_operations_in_order() {
+        let queue = OutputQueue::new(Some(Duration::from_secs(5))).unwrap();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        for ix in 0..3 {
+            let log = Arc::clone(&log);
+            queue
+                .run(move || {
+                    log.lock().unwrap().push(ix);
+                    Ok(())
+                })
+                .unwrap();
+        }
+        assert_eq!(*log.lock().unwrap(), [0, 1, 2]);
+        let err = queue
+            .run(|| Err(io::Error::from_raw_os_error(libc::EIO).into()))
+            .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EIO));
+    }
+
+    #[test]
+    fn times_out_hung_operations() {
+        let queue = OutputQueue::new(Some(Duration::from_millis(20))).unwrap();
+        let err = queue
+            .run(|| {
+                thread::sleep(Duration::from_millis(200));
+                Ok(())
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        // The next operations wait behind the hung one.
+        assert_eq!(queue.sync().unwrap_err().kind(), io::ErrorKind::TimedOut);
+        thread::sleep(Duration::from_millis(250));
+        queue.sync().unwrap();
+    }
+
+    #[test]
+    fn modulates_output() {
+        let queue = OutputQueue::new(Some(Duration::from_secs(5))).unwrap();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let states = Arc::clone(&log);
+        let pwm = Pwm::new(
+            Duration::from_millis(5),
+            Duration::from_millis(5),
+            move |on| {
+                states.lock().unwrap().push(on);
+                Ok(())
+            },
+        );
+        queue.set_pwm(Some(pwm)).unwrap();
+        thread::sleep(Duration::from_millis(60));
+        // The operations still run while the output is modulated.
+        queue.sync().unwrap();
+        queue.set_pwm(None).unwrap();
+        queue.sync().unwrap();
+        let states = log.lock().unwrap().clone();
+        assert!(states.len() >= 4, "{states:?}");
+        assert!(states.iter().step_by(2).all(|&on| on));
+        assert!(states.iter().skip(1).step_by(2).all(|&on| !on));
+
+        // A failed write stops the modulation.
+        let writes = Arc::new(Mutex::new(0));
+        let count = Arc::clone(&writes);
+        let pwm = Pwm::new(
+            Duration::from_millis(1),
+            Duration::from_millis(1),
+            move |_| {
+                *count.lock().unwrap() += 1;
+                Err(io::Error::from_raw_os_error(libc::ENODEV).into())
+            },
+        );
+        queue.set_pwm(Some(pwm)).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        queue.sync().unwrap();
+        assert_eq!(*writes.lock().unwrap(), 1);
+
+        // A single pulse ends with the output off.
+        log.lock().unwrap().clear();
+        let states = Arc::clone(&log);
+        let pwm = Pwm::once(Duration::from_millis(5), move |on| {
+            states.lock().unwrap().push(on);
+            Ok(())
+        });
+        queue.set_pwm(Some(pwm)).unwrap();
+        thread::sleep(Duration::from_millis(30));
+        queue.sync().unwrap();
+        assert_eq!(*log.lock().unwrap(), [true, false]);
+    }
+
+    #[test]
+    fn limits_continuous_output() {
+        let queue = OutputQueue::new(Some(Duration::from_secs(5))).unwrap();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let states = Arc::clone(&log);
+        let limit = RumbleLimit {
+            max_on: Duration::from_millis(20),
+            cooldown: Duration::from_millis(100),
+        };
+        let toggle = move |on| {
+            states.lock().unwrap().push(on);
+            Ok(())
+        };
+        queue.set_limit(Some((limit, toggle.clone()))).unwrap();
+        let try_turn = |on: bool| {
+            let toggle = toggle.clone();
+            queue.run_limited(on, move || toggle(on))
+        };
+        let turn = |on: bool| try_turn(on).unwrap();
+
+        // Turning the output off in time resets the limit.
+        turn(true);
+        turn(false);
+        turn(true);
+        thread::sleep(Duration::from_millis(50));
+        queue.sync().unwrap();
+        assert_eq!(*log.lock().unwrap(), [true, false, true, false]);
+
+        // The output stays off during the cooldown, even if modulated.
+        let err = try_turn(true).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ResourceBusy);
+        let states = Arc::clone(&log);
+        let pwm = Pwm::once(Duration::from_millis(5), move |on| {
+            states.lock().unwrap().push(on);
+            Ok(())
+        });
+        queue.set_pwm(Some(pwm)).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        queue.sync().unwrap();
+        assert_eq!(log.lock().unwrap().len(), 4);
+
+        // A modulated output is turned off once the limit expires.
+        thread::sleep(Duration::from_millis(100));
+        log.lock().unwrap().clear();
+        let states = Arc::clone(&log);
+        let pwm = Pwm::new(
+            Duration::from_millis(1),
+            Duration::from_millis(1),
+            move |on| {
+                states.lock().unwrap().push(on);
+                Ok(())
+            },
+        );
+        queue.set_pwm(Some(pwm)).unwrap();
+        thread::sleep(Duration::from_millis(60));
+        queue.sync().unwrap();
+        let states = log.lock().unwrap().clone();
+        assert_eq!(states.last(), Some(&false), "{states:?}");
+        let len = states.len();
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(log.lock().unwrap().len(), len);
+
+        // Without a limit, the output stays on.
+        queue
+            .set_limit(None::<(RumbleLimit, fn(bool) -> _)>)
+            .unwrap();
+        log.lock().unwrap().clear();
+        turn(true);
+        thread::sleep(Duration::from_millis(50));
+        queue.sync().unwrap();
+        assert_eq!(*log.lock().unwrap(), [true]);
+    }
+}
@@ -0,0 +1,593 @@
+//! Arbitration of on-screen cursors between several pointing devices.
+//!
+//! When more than one Wii Remote points at the same screen, e.g. in a
+//! shared whiteboard, the application must decide which device moves
+//! which cursor. An [`Arbiter`] applies an [`Arbitration`] policy to the
+//! IR events of every device, and reports the cursors they take and
+//! release along with their motion. The [`arbitrate`] function does the
+//! same for the event streams of the devices.
+//!
+//! # Examples
+//! Give each of two devices its own cursor.
+//! ```no_run
+//! use futures_util::TryStreamExt;
+//! use xwiimote::pointer::{arbitrate, Arbitration, CursorEvent};
+//! use xwiimote::{Channels, Device, Monitor};
+//!
+//! # tokio_test::block_on(async {
+//! let mut addresses = Monitor::enumerate()?;
+//! let mut devices = Vec::new();
+//! for id in 0..2 {
+//!     let address = addresses.try_next().await?.unwrap();
+//!     let device = Device::connect(&address)?;
+//!     device.open(Channels::CORE | Channels::IR, false)?;
+//!     devices.push((id, device.into_events()?));
+//! }
+//!
+//! let mut cursors = arbitrate(devices, Arbitration::PerCursor);
+//! while let Some((event, _)) = cursors.try_next().await? {
+//!     match event {
+//!         CursorEvent::Acquired { device, cursor } => println!("{device} shows cursor {cursor}"),
+//!         CursorEvent::Moved { cursor, x, y, .. } => println!("cursor {cursor} at ({x}, {y})"),
+//!         CursorEvent::Released { device, cursor } => println!("{device} hides cursor {cursor}"),
+//!     }
+//! }
+//! # Ok::<(), xwiimote::Error>(())
+//! # }).unwrap();
+//! ```
+
+use crate::events::Event;
+use crate::frame::CoordinateFrame;
+use crate::ranges::{IR_HEIGHT, IR_WIDTH};
+use crate::Result;
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+
+/// The distance that a device must move its aim to take the cursor under
+/// the [`Arbitration::FocusFollowsActivity`] policy, as a fraction of the
+/// screen size.
+pub const ACTIVITY_THRESHOLD: f32 = 0.02;
+
+/// The policy that decides which device controls which cursor.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Arbitration {
+    /// There is a single cursor, controlled by the first device that
+    /// points at the screen until it looks away.
+    #[default]
+    FirstCome,
+    /// Every device that points at the screen controls its own cursor.
+    /// Cursors are numbered from zero, and each device takes the lowest
+    /// number that is free.
+    PerCursor,
+    /// There is a single cursor, controlled by the device that moved last.
+    /// Another device takes the cursor once its aim moves by more than
+    /// [`ACTIVITY_THRESHOLD`], so that the small tremors of a device
+    /// held still do not steal it.
+    FocusFollowsActivity,
+}
+
+/// A change in the state of a cursor.
+///
+/// The position of a cursor is the point of the screen that the device
+/// aims at, from (0, 0) at the top-left corner to (1, 1) at the bottom-right
+/// corner, assuming that the sensor bar is centered on the screen.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CursorEvent<K> {
+    /// The device took control of the cursor.
+    Acquired {
+        /// The device.
+        device: K,
+        /// The number of the cursor.
+        cursor: usize,
+    },
+    /// The device moved the cursor it controls.
+    Moved {
+        /// The device.
+        device: K,
+        /// The number of the cursor.
+        cursor: usize,
+        /// The horizontal position.
+        x: f32,
+        /// The vertical position.
+        y: f32,
+    },
+    /// The device no longer controls the cursor, because it looked away,
+    /// another device took the cursor, or the device was removed.
+    Released {
+        /// The device.
+        device: K,
+        /// The number of the cursor.
+        cursor: usize,
+    },
+}
+
+/// Returns the point of the screen aimed at by the device that reported
+/// an IR event in the given frame, and the number of sources it sees;
+/// or [`None`] if it does not see any.
+///
+/// The aim is the average of the sources, so it jumps when a source
+/// enters or leaves the view of the camera.
+fn aim(event: &Event, frame: CoordinateFrame) -> Option<((f32, f32), usize)> {
+    let (mut n, mut x, mut y) = (0, 0, 0);
+    for (_, source) in event.ir_sources() {
+        // Measure the aim in the device frame.
+        let source = frame.ir_source(source);
+        n += 1;
+        x += source.x;
+        y += source.y;
+    }
+    if n == 0 {
+        return None;
+    }
+    // The sources move opposite to the aim of the device along the x-axis.
+    let point = (
+        (1.0 - x as f32 / (n * IR_WIDTH) as f32).clamp(0.0, 1.0),
+        (y as f32 / (n * IR_HEIGHT) as f32).clamp(0.0, 1.0),
+    );
+    Some((point, n as usize))
+}
+
+/// Decides which device controls which cursor according to an
+/// [`Arbitration`] policy.
+///
+/// Devices are identified by keys of type `K` chosen by the application,
+/// e.g. an index or the [`Address`](crate::Address) of the device.
+#[derive(Clone, Debug)]
+pub struct Arbiter<K> {
+    policy: Arbitration,
+    /// The frame in which the IR events report the sources.
+    frame: CoordinateFrame,
+    /// The device that controls each cursor, if any.
+    owners: Vec<Option<K>>,
+    /// The last aim of each device that points at the screen, and
+    /// the number of sources it was computed from.
+    aims: Vec<(K, (f32, f32), usize)>,
+}
+
+impl<K: Clone + PartialEq> Arbiter<K> {
+    /// Creates an arbiter that applies the given policy.
+    pub fn new(policy: Arbitration) -> Self {
+        let owners = match policy {
+            Arbitration::PerCursor => Vec::new(),
+            _ => vec![None],
+        };
+        Self {
+            policy,
+            frame: CoordinateFrame::DEVICE,
+            owners,
+            aims: Vec::new(),
+        }
+    }
+
+    /// Returns the policy applied by the arbiter.
+    pub fn policy(&self) -> Arbitration {
+        self.policy
+    }
+
+    /// Sets the frame in which the IR events given to the arbiter report
+    /// the sources, which must match the frame set on the devices with
+    /// [`Device::set_coordinate_frame`](crate::Device::set_coordinate_frame).
+    /// The cursor positions do not depend on the frame.
+    ///
+    /// Defaults to [`CoordinateFrame::DEVICE`].
+    pub fn set_frame(&mut self, frame: CoordinateFrame) {
+        self.frame = frame;
+    }
+
+    /// Returns the frame in which the IR events report the sources.
+    pub fn frame(&self) -> CoordinateFrame {
+        self.frame
+    }
+
+    /// Returns the device that controls the given cursor, if any.
+    pub fn owner(&self, cursor: usize) -> Option<&K> {
+        self.owners.get(cursor)?.as_ref()
+    }
+
+    /// Returns the cursor controlled by the given device, if any.
+    pub fn cursor(&self, device: &K) -> Option<usize> {
+        self.owners
+            .iter()
+            .position(|owner| owner.as_ref() == Some(device))
+    }
+
+    /// Processes an event of a device, and returns the resulting changes
+    /// in the state of the cursors, in order.
+    ///
+    /// Only IR events are considered; other events are ignored.
+    pub fn update(&mut self, device: &K, event: &Event) -> Vec<CursorEvent<K>> {
+        let mut changes = Vec::new();
+        if !matches!(event, Event::Ir(_)) {
+            return changes;
+        }
+        let Some(((x, y), count)) = aim(event, self.frame) else {
+            // The device looked away from the screen.
+            self.remove_into(device, &mut changes);
+            return changes;
+        };
+        let previous = match self.aims.iter_mut().find(|(other, ..)| other == device) {
+            Some((_, aim, n)) => {
+                let previous = std::mem::replace(aim, (x, y));
+                // The aim jumps if the device sees another number of
+                // sources, which does not mean that it moved.
+                (std::mem::replace(n, count) == count).then_some(previous)
+            }
+            None => {
+                self.aims.push((device.clone(), (x, y), count));
+                None
+            }
+        };
+
+        let cursor = match self.cursor(device) {
+            Some(cursor) => cursor,
+            None => {
+                let Some(cursor) = self.claim(previous, (x, y), &mut changes) else {
+                    return changes;
+                };
+                self.owners[cursor] = Some(device.clone());
+                changes.push(CursorEvent::Acquired {
+                    device: device.clone(),
+                    cursor,
+                });
+                cursor
+            }
+        };
+        changes.push(CursorEvent::Moved {
+            device: device.clone(),
+            cursor,
+            x,
+            y,
+        });
+        changes
+    }
+
+    /// Forgets a device, e.g. once it disconnects, and returns the
+    /// resulting changes in the state of the cursors.
+    pub fn remove(&mut self, device: &K) -> Vec<CursorEvent<K>> {
+        let mut changes = Vec::new();
+        self.remove_into(device, &mut changes);
+        changes
+    }
+
+    fn remove_into(&mut self, device: &K, changes: &mut Vec<CursorEvent<K>>) {
+        self.aims.retain(|(other, ..)| other != device);
+        if let Some(cursor) = self.cursor(device) {
+            self.owners[cursor] = None;
+            changes.push(CursorEvent::Released {
+                device: device.clone(),
+                cursor,
+            });
+        }
+    }
+
+    /// Returns the cursor that a device without one may take given
+    /// its previous and current aims, after releasing the cursor from
+    /// its current owner if needed.
+    fn claim(
+        &mut self,
+        previous: Option<(f32, f32)>,
+        (x, y): (f32, f32),
+        changes: &mut Vec<CursorEvent<K>>,
+    ) -> Option<usize> {
+        match self.policy {
+            Arbitration::PerCursor => {
+                let free = self.owners.iter().position(Option::is_none);
+                Some(free.unwrap_or_else(|| {
+                    self.owners.push(None);
+                    self.owners.len() - 1
+                }))
+            }
+            Arbitration::FirstCome => self.owners[0].is_none().then_some(0),
+            Arbitration::FocusFollowsActivity => {
+                if let Some(owner) = &self.owners[0] {
+                    let (px, py) = previous?;
+                    if (x - px).hypot(y - py) <= ACTIVITY_THRESHOLD {
+                        return None;
+                    }
+                    changes.push(CursorEvent::Released {
+                        device: owner.clone(),
+                        cursor: 0,
+                    });
+                    self.owners[0] = None;
+                }
+                Some(0)
+            }
+        }
+    }
+}
+
+/// Arbitrates the cursors of several devices, given their keys and
+/// event streams.
+///
+/// See [`Arbiter`] for details.
+pub fn arbitrate<K, S>(
+    devices: impl IntoIterator<Item = (K, S)>,
+    policy: Arbitration,
+) -> Cursors<K, S>
+where
+    K: Clone + PartialEq + Unpin,
+    S: Stream<Item = Result<(Event, SystemTime)>> + Unpin,
+{
+    Cursors {
+        devices: devices.into_iter().collect(),
+        arbiter: Arbiter::new(policy),
+        pending: VecDeque::new(),
+    }
+}
+
+/// The stream returned by [`arbitrate`].
+///
+/// The stream produces the changes in the state of every cursor, along
+/// with the time of the event that caused them. Each change names the
+/// device and the cursor it concerns, so that the application can route
+/// it to the right cursor.
+///
+/// Once the event stream of a device ends, e.g. because it disconnected,
+/// the device is removed and its cursor released. The stream ends when
+/// every event stream has ended. Errors from the event streams are
+/// passed through.
+pub struct Cursors<K, S> {
+    /// The devices whose event streams have not ended yet.
+    devices: Vec<(K, S)>,
+    arbiter: Arbiter<K>,
+    /// The changes that were computed but not produced yet.
+    pending: VecDeque<(CursorEvent<K>, SystemTime)>,
+}
+
+impl<K, S> Cursors<K, S> {
+    /// Returns the arbiter, e.g. to find the device that controls a cursor.
+    pub fn arbiter(&self) -> &Arbiter<K> {
+        &self.arbiter
+    }
+
+    /// Returns the arbiter mutably, e.g. to set the frame of the events.
+    pub fn arbiter_mut(&mut self) -> &mut Arbiter<K> {
+        &mut self.arbiter
+    }
+}
+
+impl<K, S> Stream for Cursors<K, S>
+where
+    K: Clone + PartialEq + Unpin,
+    S: Stream<Item = Result<(Event, SystemTime)>> + Unpin,
+{
+    type Item = Result<(CursorEvent<K>, SystemTime)>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some(change) = this.pending.pop_front() {
+                return Poll::Ready(Some(Ok(change)));
+            }
+            if this.devices.is_empty() {
+                return Poll::Ready(None);
+            }
+            // Poll every device once, so that a busy device cannot
+            // starve the others.
+            let mut progressed = false;
+            let mut ix = 0;
+            while ix < this.devices.len() {
+                let (device, events) = &mut this.devices[ix];
+                match Pin::new(events).poll_next(cx) {
+                    Poll::Ready(Some(Ok((event, time)))) => {
+                        let changes = this.arbiter.update(device, &event);
+                        this.pending.extend(changes.into_iter().map(|c| (c, time)));
+                        progressed = true;
+                    }
+                    Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+                    Poll::Ready(None) => {
+                        let (device, _) = this.devices.remove(ix);
+                        let time = SystemTime::now();
+                        let changes = this.arbiter.remove(&device);
+                        this.pending.extend(changes.into_iter().map(|c| (c, time)));
+                        progressed = true;
+                        continue;
+                    }
+                    Poll::Pending => {}
+                }
+                ix += 1;
+            }
+            if !progressed {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::{Event, IrSource, MAX_IR_SOURCES};
+    use crate::frame::CoordinateFrame;
+    use crate::pointer::{arbitrate, Arbiter, Arbitration, CursorEvent};
+    use crate::Result;
+    use futures_core::Stream;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+    use std::time::SystemTime;
+
+    /// Returns an IR event of a device that sees a single source.
+    fn ir(x: i32, y: i32) -> Event {
+        let mut sources = [None; MAX_IR_SOURCES];
+        sources[0] = Some(IrSource { x, y });
+        Event::Ir(sources)
+    }
+
+    fn looks_away() -> Event {
+        Event::Ir([None; MAX_IR_SOURCES])
+    }
+
+    /// Returns the acquired and released cursors, ignoring motion.
+    fn ownership(changes: Vec<CursorEvent<u8>>) -> Vec<CursorEvent<u8>> {
+        changes
+            .into_iter()
+            .filter(|change| !matches!(change, CursorEvent::Moved { .. }))
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_cursor_of_the_first_device() {
+        let mut arbiter = Arbiter::new(Arbitration::FirstCome);
+        assert_eq!(
+            arbiter.update(&1, &ir(512, 384)),
+            vec![
+                CursorEvent::Acquired {
+                    device: 1,
+                    cursor: 0
+                },
+                CursorEvent::Moved {
+                    device: 1,
+                    cursor: 0,
+                    x: 0.5,
+                    y: 0.5
+                },
+            ]
+        );
+        assert!(arbiter.update(&2, &ir(0, 0)).is_empty());
+        assert_eq!(
+            arbiter.update(&1, &looks_away()),
+            vec![CursorEvent::Released {
+                device: 1,
+                cursor: 0
+            }]
+        );
+        assert_eq!(
+            ownership(arbiter.update(&2, &ir(0, 0))),
+            vec![CursorEvent::Acquired {
+                device: 2,
+                cursor: 0
+            }]
+        );
+        assert_eq!(arbiter.owner(0), Some(&2));
+    }
+
+    #[test]
+    fn gives_each_device_a_cursor() {
+        let mut arbiter = Arbiter::new(Arbitration::PerCursor);
+        arbiter.update(&1, &ir(100, 100));
+        arbiter.update(&2, &ir(200, 200));
+        arbiter.update(&3, &ir(300, 300));
+        assert_eq!(arbiter.cursor(&3), Some(2));
+        assert_eq!(
+            arbiter.remove(&2),
+            vec![CursorEvent::Released {
+                device: 2,
+                cursor: 1
+            }]
+        );
+        // The free cursor is reused.
+        assert_eq!(
+            ownership(arbiter.update(&4, &ir(400, 400))),
+            vec![CursorEvent::Acquired {
+                device: 4,
+                cursor: 1
+            }]
+        );
+        assert!(arbiter.update(&1, &Event::Other).is_empty());
+    }
+
+    #[test]
+    fn moves_the_focus_to_the_active_device() {
+        let mut arbiter = Arbiter::new(Arbitration::FocusFollowsActivity);
+        arbiter.update(&1, &ir(500, 400));
+        arbiter.update(&2, &ir(100, 100));
+        // A small tremor does not steal the cursor.
+        assert!(arbiter.update(&2, &ir(102, 101)).is_empty());
+        assert_eq!(
+            ownership(arbiter.update(&2, &ir(300, 100))),
+            vec![
+                CursorEvent::Released {
+                    device: 1,
+                    cursor: 0
+                },
+                CursorEvent::Acquired {
+                    device: 2,
+                    cursor: 0
+                },
+            ]
+        );
+        assert!(arbiter.update(&1, &ir(500, 400)).is_empty());
+    }
+
+    #[test]
+    fn ignores_jumps_when_sources_appear() {
+        let mut arbiter = Arbiter::new(Arbitration::FocusFollowsActivity);
+        arbiter.update(&1, &ir(500, 400));
+        arbiter.update(&2, &ir(100, 100));
+        // The average of the sources moves although the device holds still.
+        let mut sources = [None; MAX_IR_SOURCES];
+        sources[0] = Some(IrSource { x: 100, y: 100 });
+        sources[1] = Some(IrSource { x: 500, y: 100 });
+        assert!(arbiter.update(&2, &Event::Ir(sources)).is_empty());
+        assert!(arbiter.update(&2, &ir(102, 101)).is_empty());
+        assert_eq!(arbiter.owner(0), Some(&1));
+    }
+
+    #[test]
+    fn aims_in_any_frame() {
+        let frame = CoordinateFrame::SCREEN;
+        let mut device = Arbiter::new(Arbitration::FirstCome);
+        let mut screen = Arbiter::new(Arbitration::FirstCome);
+        screen.set_frame(frame);
+        let event = ir(100, 200);
+        assert_eq!(
+            screen.update(&1, &frame.event(event)),
+            device.update(&1, &event)
+        );
+    }
+
+    /// A stream that produces the items of a vector.
+    struct Items<T>(Vec<T>);
+
+    impl<T: Unpin> Stream for Items<T> {
+        type Item = T;
+
+        fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<T>> {
+            if self.0.is_empty() {
+                Poll::Ready(None)
+            } else {
+                Poll::Ready(Some(self.0.remove(0)))
+            }
+        }
+    }
+
+    #[test]
+    fn releases_the_cursor_when_a_stream_ends() {
+        let now = SystemTime::now();
+        let first: Vec<Result<_>> = vec![Ok((ir(512, 384), now))];
+        let second: Vec<Result<_>> = vec![Ok((ir(512, 384), now)), Ok((ir(0, 0), now))];
+        let mut cursors = arbitrate(
+            vec![(1, Items(first)), (2, Items(second))],
+            Arbitration::FirstCome,
+        );
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut changes = Vec::new();
+        while let Poll::Ready(Some(change)) = Pin::new(&mut cursors).poll_next(&mut cx) {
+            changes.push(change.unwrap().0);
+        }
+        assert_eq!(
+            ownership(changes),
+            vec![
+                CursorEvent::Acquired {
+                    device: 1,
+                    cursor: 0
+                },
+                CursorEvent::Released {
+                    device: 1,
+                    cursor: 0
+                },
+                CursorEvent::Acquired {
+                    device: 2,
+                    cursor: 0
+                },
+                CursorEvent::Released {
+                    device: 2,
+                    cursor: 0
+                },
+            ]
+        );
+    }
+}
@@ -1,14 +1,28 @@
-use crate::{bail_if, Result};
+//! The event loop that drives the streams produced by this crate.
+//!
+//! All device and monitor streams are polled by a single background
+//! thread that blocks on an `epoll` descriptor. The [`Reactor::stats`]
+//! counters help to tell whether delayed events are caused by the event
+//! loop, or by the device and the Bluetooth stack.
+//!
+//! Applications that use a device as a low-latency controller, such as
+//! a musical instrument, can run the event loop thread under a real-time
+//! scheduling policy and pin it to a set of CPUs; see
+//! [`Reactor::set_realtime_priority`] and [`Reactor::set_cpu_affinity`].
+
+use crate::{bail_if, lock, Result};
 use libc::epoll_event;
 use libc::{c_int, c_uint};
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard, PoisonError};
 use std::task::Waker;
 use std::thread;
+use std::time::{Duration, Instant};
 
 /// Describes the events a task wants to be notified of.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -21,6 +35,13 @@ pub(crate) struct Interest {
 }
 
 impl Interest {
+    /// The events that indicate that a file can be read from.
+    const READ_EVENTS: c_int =
+        libc::EPOLLIN | libc::EPOLLPRI | libc::EPOLLHUP | libc::EPOLLRDHUP | libc::EPOLLERR;
+
+    /// The events that indicate that a file can be written to.
+    const WRITE_EVENTS: c_int = libc::EPOLLOUT | libc::EPOLLHUP | libc::EPOLLERR;
+
     /// Creates a new interest description.
     pub fn new<F: IntoRawFd>(fd: F, events: c_int) -> Self {
         Self {
@@ -28,6 +49,49 @@ impl Interest {
             events,
         }
     }
+
+    /// Lists the directions of readiness described by the interest.
+    fn directions(&self) -> impl Iterator<Item = Direction> + '_ {
+        Direction::ALL
+            .into_iter()
+            .filter(|dir| dir.matches(self.events & !libc::EPOLLET))
+    }
+}
+
+/// The kind of readiness a task waits for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Direction {
+    /// The file can be read from without blocking.
+    Read,
+    /// The file can be written to without blocking.
+    Write,
+}
+
+impl Direction {
+    const ALL: [Self; 2] = [Self::Read, Self::Write];
+
+    /// Checks whether a bit field of `epoll` events describes
+    /// readiness in this direction.
+    fn matches(self, events: c_int) -> bool {
+        let mask = match self {
+            Self::Read => Interest::READ_EVENTS & !libc::EPOLLERR & !libc::EPOLLHUP,
+            Self::Write => libc::EPOLLOUT,
+        };
+        events & mask != 0
+    }
+
+    /// Checks whether a bit field of events reported by `epoll_wait`
+    /// should wake the tasks waiting for readiness in this direction.
+    ///
+    /// Errors and hang-ups wake every task, since they should
+    /// observe the condition with their next read or write.
+    fn is_ready(self, events: c_int) -> bool {
+        let mask = match self {
+            Self::Read => Interest::READ_EVENTS,
+            Self::Write => Interest::WRITE_EVENTS,
+        };
+        events & mask != 0
+    }
 }
 
 impl From<&Interest> for epoll_event {
@@ -36,41 +100,102 @@ impl From<&Interest> for epoll_event {
             // Enable edge-triggered mechanism, since the interested task
             // is expected to read all available data from `fd`.
             events: (interest.events | libc::EPOLLET) as c_uint,
-            u64: interest.fd.try_into().unwrap(), // `fd` is valid
+            // Negative descriptors are rejected by `epoll_ctl` itself.
+            u64: interest.fd as u64,
         }
     }
 }
 
-impl From<&epoll_event> for Interest {
-    fn from(event: &epoll_event) -> Self {
-        Self {
-            fd: event.u64.try_into().unwrap(),
-            events: event.events.try_into().unwrap(),
+/// A buffer of readiness events polled from an epoll descriptor.
+type Events = Vec<epoll_event>;
+
+/// A snapshot of the activity counters of the [`Reactor`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReactorStats {
+    /// The number of times the event loop woke up from `epoll_wait`.
+    pub wakeups: u64,
+    /// The number of readiness events reported by `epoll_wait`.
+    pub events: u64,
+    /// The number of tasks woken up as a result of readiness events.
+    pub woken_tasks: u64,
+    /// The number of wake-ups that reported no readiness events.
+    pub empty_wakeups: u64,
+    /// The number of times `epoll_wait` was interrupted by a signal.
+    pub interruptions: u64,
+    /// The number of times `epoll_wait` failed for other reasons.
+    pub errors: u64,
+    /// The number of task wakers that panicked when called.
+    /// The event loop keeps running after such a panic.
+    pub waker_panics: u64,
+    /// The total time spent waiting to acquire the lock that guards
+    /// the task wakers, both by the event loop and by the tasks.
+    pub lock_wait: Duration,
+}
+
+impl ReactorStats {
+    /// Returns the average number of readiness events per wake-up.
+    pub fn events_per_wakeup(&self) -> f64 {
+        if self.wakeups == 0 {
+            0.0
+        } else {
+            self.events as f64 / self.wakeups as f64
         }
     }
 }
 
-/// A buffer of readiness events polled from an epoll descriptor.
-type Events = Vec<epoll_event>;
+/// The activity counters of the [`Reactor`], updated atomically.
+#[derive(Debug, Default)]
+struct Counters {
+    wakeups: AtomicU64,
+    events: AtomicU64,
+    woken_tasks: AtomicU64,
+    empty_wakeups: AtomicU64,
+    interruptions: AtomicU64,
+    errors: AtomicU64,
+    waker_panics: AtomicU64,
+    /// The lock wait time, in nanoseconds.
+    lock_wait: AtomicU64,
+}
 
 /// An event loop that blocks on asynchronous IO events and
 /// notifies interested tasks of their occurrence.
 pub struct Reactor {
     /// The epoll file descriptor.
     ep_fd: OwnedFd,
-    /// The handles for waking up the interested tasks.
-    wakers: Mutex<HashMap<Interest, Waker>>,
+    /// The handles for waking up the interested tasks, indexed
+    /// by file descriptor and readiness direction.
+    wakers: Mutex<HashMap<(RawFd, Direction), Waker>>,
+    /// Statistics about the activity of the event loop.
+    counters: Counters,
+    /// The number of consecutive `epoll_wait` failures after which
+    /// the event loop gives up.
+    max_consecutive_errors: AtomicU32,
+    /// The error that stopped the event loop, if it gave up.
+    failure: Mutex<Option<String>>,
+    /// The thread identifier of the event loop thread, if it runs.
+    thread: Option<libc::pid_t>,
 }
 
 impl Reactor {
+    /// The default value of [`Reactor::set_max_consecutive_errors`].
+    pub const DEFAULT_MAX_CONSECUTIVE_ERRORS: u32 = 16;
+
     /// Returns a reference to the global event loop.
     pub fn get() -> &'static Self {
         static REACTOR: Lazy<Reactor> = Lazy::new(|| {
-            // Start the event loop in a separate thread.
-            thread::spawn(|| {
-                Reactor::get().run().expect("event loop failed");
+            // Start the event loop in a separate thread, which tells its
+            // identifier before waiting for the initialization to finish.
+            let (tid_tx, tid_rx) = mpsc::sync_channel(1);
+            thread::spawn(move || {
+                let _ = tid_tx.send(unsafe { libc::gettid() });
+                let reactor = Reactor::get();
+                if let Err(err) = reactor.run() {
+                    reactor.fail(&err);
+                }
             });
-            Reactor::new().expect("failed to create global event loop")
+            let mut reactor = Reactor::new().expect("failed to create global event loop");
+            reactor.thread = tid_rx.recv().ok();
+            reactor
         });
         &REACTOR
     }
@@ -83,9 +208,46 @@ impl Reactor {
             ep_fd: unsafe { OwnedFd::from_raw_fd(ep_fd) },
             // todo: pre-allocate the hashmap.
             wakers: Mutex::default(),
+            counters: Counters::default(),
+            max_consecutive_errors: AtomicU32::new(Self::DEFAULT_MAX_CONSECUTIVE_ERRORS),
+            failure: Mutex::default(),
+            thread: None,
         })
     }
 
+    /// Returns the activity counters of the event loop, accumulated since
+    /// its creation.
+    ///
+    /// A low number of wake-ups while a device is supposed to report events
+    /// suggests that the events are delayed before reaching the event loop.
+    pub fn stats(&self) -> ReactorStats {
+        let counters = &self.counters;
+        ReactorStats {
+            wakeups: counters.wakeups.load(Ordering::Relaxed),
+            events: counters.events.load(Ordering::Relaxed),
+            woken_tasks: counters.woken_tasks.load(Ordering::Relaxed),
+            empty_wakeups: counters.empty_wakeups.load(Ordering::Relaxed),
+            interruptions: counters.interruptions.load(Ordering::Relaxed),
+            errors: counters.errors.load(Ordering::Relaxed),
+            waker_panics: counters.waker_panics.load(Ordering::Relaxed),
+            lock_wait: Duration::from_nanos(counters.lock_wait.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Acquires the lock that guards the task wakers, recording
+    /// the time spent waiting for it.
+    fn lock_wakers(&self) -> MutexGuard<'_, HashMap<(RawFd, Direction), Waker>> {
+        let start = Instant::now();
+        // A panicking task cannot leave the map in an inconsistent state,
+        // so recover from poisoning instead of propagating the panic
+        // (which would abort the process if we are dropping a stream
+        // during unwinding).
+        let wakers = self.wakers.lock().unwrap_or_else(PoisonError::into_inner);
+        let waited = start.elapsed().as_nanos().try_into().unwrap_or(u64::MAX);
+        self.counters.lock_wait.fetch_add(waited, Ordering::Relaxed);
+        wakers
+    }
+
     /// Executes the event loop.
     fn run(&self) -> Result<()> {
         let term = Arc::new(AtomicBool::new(false));
@@ -94,14 +256,138 @@ impl Reactor {
         // Poll for events until the process is terminated.
         // Reuse the readiness event buffer across `wake_ready` calls.
         let mut events = Events::with_capacity(16);
+        let mut n_errors = 0;
         while !term.load(Ordering::Relaxed) {
-            self.wake_ready(&mut events)?;
+            match self.wake_ready(&mut events) {
+                Ok(()) => n_errors = 0,
+                Err(err) => {
+                    // Keep serving the other tasks, unless `epoll_wait`
+                    // fails persistently.
+                    self.counters.errors.fetch_add(1, Ordering::Relaxed);
+                    n_errors += 1;
+                    if n_errors >= self.max_consecutive_errors.load(Ordering::Relaxed) {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks the event loop as failed with the given error, and wakes
+    /// every task so that its pending poll fails instead of waiting
+    /// forever.
+    fn fail(&self, err: &crate::Error) {
+        let mut wakers = self.lock_wakers();
+        *lock(&self.failure) = Some(err.to_string());
+        let removed: Vec<_> = wakers.drain().map(|(_, waker)| waker).collect();
+        drop(wakers);
+        for waker in removed {
+            self.wake(waker);
+        }
+    }
+
+    /// Fails if the event loop stopped, in which case no task would
+    /// ever be woken.
+    fn check_running(&self) -> Result<()> {
+        match &*lock(&self.failure) {
+            Some(err) => Err(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                format!("the event loop failed: {err}"),
+            )
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Sets the number of consecutive `epoll_wait` failures after which
+    /// the event loop stops, which defaults to
+    /// [`Reactor::DEFAULT_MAX_CONSECUTIVE_ERRORS`].
+    ///
+    /// Interruptions by signals and wake-ups without readiness events
+    /// are not considered failures. Once the event loop stops, the
+    /// pending and later polls of the streams and futures of this crate
+    /// fail.
+    pub fn set_max_consecutive_errors(&self, max: u32) {
+        self.max_consecutive_errors
+            .store(max.max(1), Ordering::Relaxed);
+    }
+
+    /// Runs the event loop thread, which reads the events of every device,
+    /// under the real-time `SCHED_FIFO` scheduling policy with the given
+    /// priority, from 1 (lowest) to 99; or restores the default policy
+    /// if `priority` is [`None`].
+    ///
+    /// A real-time thread preempts all the threads under the default
+    /// policy as soon as an event arrives. Only the event loop is affected;
+    /// the tasks that consume the events run on the threads of the async
+    /// executor, which may need the same treatment.
+    ///
+    /// Raising the priority requires the `CAP_SYS_NICE` capability or
+    /// a suitable `RLIMIT_RTPRIO` resource limit. Otherwise this method
+    /// fails with [`std::io::ErrorKind::PermissionDenied`] and the thread
+    /// keeps its current policy, so callers may treat the error as
+    /// a warning.
+    pub fn set_realtime_priority(&self, priority: Option<u8>) -> Result<()> {
+        let tid = self.thread()?;
+        let (policy, priority) = match priority {
+            Some(priority) => (libc::SCHED_FIFO, priority as c_int),
+            None => (libc::SCHED_OTHER, 0),
+        };
+        let param = libc::sched_param {
+            sched_priority: priority,
+        };
+        let res_code = unsafe { libc::sched_setscheduler(tid, policy, &param) };
+        bail_if!(res_code == -1);
+        Ok(())
+    }
+
+    /// Restricts the event loop thread to the CPUs with the given indices,
+    /// or lets it run on any CPU if `cpus` is [`None`].
+    ///
+    /// Pinning the thread to a CPU that runs little else avoids the cost
+    /// of migrating it between CPUs, and keeps its caches warm.
+    pub fn set_cpu_affinity(&self, cpus: Option<&[usize]>) -> Result<()> {
+        let tid = self.thread()?;
+        let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        let max_cpus = std::mem::size_of::<libc::cpu_set_t>() * 8;
+        match cpus {
+            Some(cpus) => {
+                for &cpu in cpus {
+                    if cpu >= max_cpus {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidInput,
+                            "CPU index out of range",
+                        )
+                        .into());
+                    }
+                    unsafe { libc::CPU_SET(cpu, &mut set) };
+                }
+            }
+            // The kernel ignores the CPUs that are not present.
+            None => (0..max_cpus).for_each(|cpu| unsafe { libc::CPU_SET(cpu, &mut set) }),
         }
+        let res_code = unsafe { libc::sched_setaffinity(tid, std::mem::size_of_val(&set), &set) };
+        bail_if!(res_code == -1);
         Ok(())
     }
 
+    /// Returns the thread identifier of the event loop thread.
+    fn thread(&self) -> Result<libc::pid_t> {
+        self.thread.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "the event loop is not running",
+            )
+            .into()
+        })
+    }
+
     /// Blocks until one or more events occur, and wakes the tasks
     /// that expressed interest in them.
+    ///
+    /// Returns early without an error if the wait is interrupted
+    /// by a signal.
     fn wake_ready(&self, events: &mut Events) -> Result<()> {
         events.clear();
         let n_ready = unsafe {
@@ -112,22 +398,56 @@ impl Reactor {
                 -1, // todo: set reasonable timeout
             )
         };
-        bail_if!(n_ready == -1);
+        let counters = &self.counters;
+        if n_ready == -1 {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::EINTR) {
+                // A signal was delivered to this thread; the caller
+                // checks for termination requests and waits again.
+                counters.interruptions.fetch_add(1, Ordering::Relaxed);
+                return Ok(());
+            }
+            return Err(err.into());
+        }
 
         // SAFETY: `epoll_wait` ensures `n_ready` events are assigned.
         unsafe { events.set_len(n_ready as usize) };
+        counters.wakeups.fetch_add(1, Ordering::Relaxed);
+        if n_ready == 0 {
+            counters.empty_wakeups.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+        counters.events.fetch_add(n_ready as u64, Ordering::Relaxed);
 
-        // Notify all interested tasks.
-        let mut wakers = self.wakers.lock().unwrap();
+        // Notify all interested tasks. The wakers are called after releasing
+        // the lock, since they may register new interests.
+        let mut ready_wakers = Vec::new();
+        let mut wakers = self.lock_wakers();
         for event in events.iter() {
-            let interest = event.into();
-            if let Some(waker) = wakers.remove(&interest) {
-                waker.wake();
+            let fd = event.u64 as RawFd;
+            let ready = event.events as c_int;
+            for dir in Direction::ALL.into_iter().filter(|dir| dir.is_ready(ready)) {
+                if let Some(waker) = wakers.remove(&(fd, dir)) {
+                    ready_wakers.push(waker);
+                }
             }
         }
+        drop(wakers);
+        for waker in ready_wakers {
+            counters.woken_tasks.fetch_add(1, Ordering::Relaxed);
+            self.wake(waker);
+        }
         Ok(())
     }
 
+    /// Wakes a task. A panic in a custom waker implementation is caught,
+    /// so that the event loop keeps serving the other tasks.
+    fn wake(&self, waker: Waker) {
+        if panic::catch_unwind(AssertUnwindSafe(|| waker.wake())).is_err() {
+            self.counters.waker_panics.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
     // Interests.
 
     fn ctl_interest(&self, op: c_int, interest: &Interest) -> Result<()> {
@@ -139,29 +459,70 @@ impl Reactor {
     }
 
     /// Expresses an interest in a particular kind of event on a file.
+    ///
+    /// A file can only be registered once; an interest that includes
+    /// [`libc::EPOLLOUT`] lets tasks wait for the file to become writable,
+    /// in addition to readable.
     pub(crate) fn add_interest(&self, interest: &Interest) -> Result<()> {
+        self.check_running()?;
         self.ctl_interest(libc::EPOLL_CTL_ADD, interest)
     }
 
     /// Removes the interest in a particular kind of event on a file.
     ///
-    /// This also wakes the pending future, if set.
+    /// This also wakes the pending futures, if set. The wakers are
+    /// discarded even if the file could not be removed from the `epoll`
+    /// set, e.g. because it was closed already.
     pub(crate) fn remove_interest(&self, interest: &Interest) -> Result<()> {
-        self.ctl_interest(libc::EPOLL_CTL_DEL, interest)?;
-        if let Some(waker) = self.wakers.lock().unwrap().remove(interest) {
-            waker.wake();
+        let result = self.ctl_interest(libc::EPOLL_CTL_DEL, interest);
+        self.wake_waiters(interest.fd);
+        result
+    }
+
+    /// Wakes the tasks that wait for an event on the given file, e.g.
+    /// because some of its data was read on their behalf.
+    pub(crate) fn wake_waiters(&self, fd: RawFd) {
+        let mut wakers = self.lock_wakers();
+        let removed: Vec<_> = Direction::ALL
+            .into_iter()
+            .filter_map(|dir| wakers.remove(&(fd, dir)))
+            .collect();
+        drop(wakers);
+        for waker in removed {
+            self.wake(waker);
         }
-        Ok(())
+    }
+
+    /// Checks whether a task waits for an event on the given file.
+    #[cfg(test)]
+    pub(crate) fn has_waker(&self, fd: RawFd) -> bool {
+        let wakers = self.lock_wakers();
+        Direction::ALL
+            .into_iter()
+            .any(|dir| wakers.contains_key(&(fd, dir)))
     }
 
     /// Stores the task waker to be called once an IO event that matches
     /// the given interest description occurs.
     ///
-    /// The associated future is expected to read all available data
-    /// from `interest.fd` once waken up. Otherwise the event loop
+    /// The waker is called when the file becomes readable if the interest
+    /// includes [`libc::EPOLLIN`] or [`libc::EPOLLPRI`], and when it becomes
+    /// writable if it includes [`libc::EPOLLOUT`]. Errors and hang-ups wake
+    /// the task in either case.
+    ///
+    /// The associated future is expected to read (or write) all available
+    /// data from `interest.fd` once waken up. Otherwise the event loop
     /// may block indefinitely.
-    pub(crate) fn set_callback(&self, interest: Interest, waker: Waker) {
-        self.wakers.lock().unwrap().insert(interest, waker);
+    ///
+    /// Fails if the event loop stopped, since the task would never
+    /// be woken.
+    pub(crate) fn set_callback(&self, interest: Interest, waker: Waker) -> Result<()> {
+        let mut wakers = self.lock_wakers();
+        self.check_running()?;
+        for dir in interest.directions() {
+            wakers.insert((interest.fd, dir), waker.clone());
+        }
+        Ok(())
     }
 }
 
@@ -172,10 +533,47 @@ mod tests {
     use libc::c_int;
     use std::fs::File;
     use std::future::Future;
-    use std::io::Write;
-    use std::os::fd::{AsRawFd, OwnedFd};
+    use std::io::{Read, Write};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
     use std::pin::Pin;
-    use std::task::{Context, Poll};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    #[test]
+    fn pins_event_loop_thread() -> Result<()> {
+        // Act on the test thread, which stands for the event loop thread.
+        let mut reactor = Reactor::new()?;
+        assert!(reactor.set_cpu_affinity(None).is_err());
+        reactor.thread = Some(unsafe { libc::gettid() });
+
+        let mut allowed: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        let size = std::mem::size_of_val(&allowed);
+        bail_if!(unsafe { libc::sched_getaffinity(0, size, &mut allowed) } == -1);
+        let cpu = (0..size * 8)
+            .find(|&cpu| unsafe { libc::CPU_ISSET(cpu, &allowed) })
+            .unwrap();
+        reactor.set_cpu_affinity(Some(&[cpu]))?;
+        let mut pinned: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        bail_if!(unsafe { libc::sched_getaffinity(0, size, &mut pinned) } == -1);
+        assert_eq!(unsafe { libc::CPU_COUNT(&pinned) }, 1);
+        assert!(unsafe { libc::CPU_ISSET(cpu, &pinned) });
+
+        let err = reactor.set_cpu_affinity(Some(&[size * 8])).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        reactor.set_cpu_affinity(None)?;
+        // Restore the original mask for the other tests in this process.
+        bail_if!(unsafe { libc::sched_setaffinity(0, size, &allowed) } == -1);
+
+        // Unprivileged processes may not use real-time policies.
+        match reactor.set_realtime_priority(Some(10)) {
+            Ok(()) => assert_eq!(unsafe { libc::sched_getscheduler(0) }, libc::SCHED_FIFO),
+            Err(err) => assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied),
+        }
+        reactor.set_realtime_priority(None)?;
+        assert_eq!(unsafe { libc::sched_getscheduler(0) }, libc::SCHED_OTHER);
+        Ok(())
+    }
 
     #[test]
     fn double_interest_fails() -> Result<()> {
@@ -187,6 +585,153 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn failed_removal_discards_wakers() -> Result<()> {
+        let reactor = Reactor::new()?;
+        // The file was never added to the `epoll` set.
+        let interest = Interest::new(0, libc::EPOLLIN);
+        reactor.set_callback(interest.clone(), Waker::noop().clone())?;
+
+        assert!(reactor.remove_interest(&interest).is_err());
+        assert!(!reactor.has_waker(0));
+        Ok(())
+    }
+
+    #[test]
+    fn survives_panicking_wakers() -> Result<()> {
+        struct Panicking;
+
+        impl Wake for Panicking {
+            fn wake(self: Arc<Self>) {
+                panic!("the waker panicked");
+            }
+        }
+
+        let reactor = Reactor::new()?;
+        let interest = Interest::new(0, libc::EPOLLIN);
+        reactor.set_callback(interest.clone(), Waker::from(Arc::new(Panicking)))?;
+        let _ = reactor.remove_interest(&interest);
+        assert_eq!(reactor.stats().waker_panics, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn failure_wakes_every_task() -> Result<()> {
+        struct Flag(AtomicBool);
+
+        impl Wake for Flag {
+            fn wake(self: Arc<Self>) {
+                self.0.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let reactor = Reactor::new()?;
+        let interest = Interest::new(0, libc::EPOLLIN);
+        let woken = Arc::new(Flag(AtomicBool::new(false)));
+        reactor.set_callback(interest.clone(), Waker::from(Arc::clone(&woken)))?;
+
+        let err = std::io::Error::from_raw_os_error(libc::EBADF);
+        reactor.fail(&err.into());
+        assert!(woken.0.load(Ordering::Relaxed));
+        assert!(!reactor.has_waker(0));
+        // The task fails once it polls again, instead of waiting forever.
+        let err = reactor
+            .set_callback(interest.clone(), Waker::noop().clone())
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+        assert!(reactor.add_interest(&interest).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn interrupted_wait_is_not_an_error() -> Result<()> {
+        extern "C" fn ignore(_: c_int) {}
+
+        let reactor = Reactor::new()?;
+        // Install a handler without `SA_RESTART`, so that the signal
+        // interrupts the `epoll_wait` call of the event loop.
+        let handler: extern "C" fn(c_int) = ignore;
+        let previous = unsafe { libc::signal(libc::SIGUSR1, handler as libc::sighandler_t) };
+        bail_if!(previous == libc::SIG_ERR);
+
+        // Signal the waiting thread until it wakes up, since the first
+        // signal may arrive before the call to `epoll_wait`.
+        let done = Arc::new(AtomicBool::new(false));
+        let thread = unsafe { libc::pthread_self() };
+        let signaler = {
+            let done = Arc::clone(&done);
+            std::thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    std::thread::sleep(std::time::Duration::from_millis(20));
+                    unsafe { libc::pthread_kill(thread, libc::SIGUSR1) };
+                }
+            })
+        };
+        let res = reactor.wake_ready(&mut Vec::with_capacity(1));
+        done.store(true, Ordering::Relaxed);
+        signaler.join().unwrap();
+        // Restore the handler for the other tests in this process.
+        let res_code = unsafe { libc::signal(libc::SIGUSR1, previous) };
+        bail_if!(res_code == libc::SIG_ERR);
+        res?;
+
+        assert!(reactor.stats().interruptions >= 1);
+        Ok(())
+    }
+
+    #[test]
+    fn writable_event_wakes_task() -> Result<()> {
+        // Create a non-blocking pipe and fill it, so that the write end
+        // becomes writable only after the read end is drained.
+        let mut fds = [0; 2];
+        let res_code = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) };
+        bail_if!(res_code != 0);
+        let (mut reader, mut writer) = unsafe {
+            (
+                File::from(OwnedFd::from_raw_fd(fds[0])),
+                File::from(OwnedFd::from_raw_fd(fds[1])),
+            )
+        };
+        while writer.write(&[0; 4096]).is_ok() {}
+
+        let interest = Interest::new(writer.as_raw_fd(), libc::EPOLLOUT);
+        Reactor::get().add_interest(&interest)?;
+
+        struct WriterFuture {
+            first_try: bool,
+            interest: Interest,
+        }
+        impl Future for WriterFuture {
+            type Output = ();
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+                if self.first_try {
+                    self.first_try = false;
+                    Reactor::get()
+                        .set_callback(self.interest.clone(), cx.waker().clone())
+                        .unwrap();
+                    Poll::Pending
+                } else {
+                    Poll::Ready(())
+                }
+            }
+        }
+
+        // Drain the pipe once the task waits for writability.
+        let drainer = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            let mut buf = [0; 4096];
+            while reader.read(&mut buf).is_ok_and(|n| n > 0) {}
+            reader
+        });
+        futures_executor::block_on(WriterFuture {
+            first_try: true,
+            interest: interest.clone(),
+        });
+        drainer.join().unwrap();
+        Reactor::get().remove_interest(&interest)
+    }
+
     #[test]
     fn event_wakes_task() -> Result<()> {
         // Create a pipe whose read end we will poll on.
@@ -211,7 +756,9 @@ mod tests {
                 if self.first_try {
                     // Ask the reactor to wake us up for the second try.
                     self.first_try = false;
-                    Reactor::get().set_callback(self.interest.clone(), cx.waker().clone());
+                    Reactor::get()
+                        .set_callback(self.interest.clone(), cx.waker().clone())
+                        .unwrap();
 
                     // Write to pipe in order to generate an epoll event.
                     self.file
@@ -227,11 +774,18 @@ mod tests {
         }
 
         // Wait for the future to complete after two tries.
+        let before = Reactor::get().stats();
         futures_executor::block_on(ReaderFuture {
             first_try: true,
             interest,
             file: File::from(fds.remove(1)),
         });
+
+        // Other tests may share the global event loop; the counters
+        // are only guaranteed to increase.
+        let after = Reactor::get().stats();
+        assert!(after.wakeups > before.wakeups);
+        assert!(after.woken_tasks > before.woken_tasks);
         Ok(())
     }
 }
@@ -0,0 +1,818 @@
+//! Recording of device events to files, and replay of the recordings.
+//!
+//! The events are stored in a compact binary format, which is split
+//! into chunks of a few thousand events. An index at the end of the
+//! file lists the time span of each chunk, so that a [`ReplayDevice`]
+//! can seek to any point of an hours-long recording without reading
+//! the events that come before it. The file is memory-mapped while
+//! replaying, hence only the chunks that are actually read are loaded
+//! from the disk.
+//!
+//! # Examples
+//! Record the accelerometer data of a device for ten seconds.
+//! ```no_run
+//! use futures_util::TryStreamExt;
+//! use std::time::{Duration, Instant};
+//! use xwiimote::recording::Recorder;
+//! use xwiimote::{Channels, Device, Monitor};
+//!
+//! # tokio_test::block_on(async {
+//! # let address = Monitor::enumerate()?.try_next().await?.unwrap();
+//! let device = Device::connect(&address)?;
+//! device.open(Channels::CORE | Channels::ACCELEROMETER, false)?;
+//!
+//! let mut recorder = Recorder::create("session.xwiirec")?;
+//! let start = Instant::now();
+//! let mut events = device.events()?;
+//! while let Some((event, time)) = events.try_next().await? {
+//!     recorder.record(&event, time)?;
+//!     if start.elapsed() > Duration::from_secs(10) {
+//!         break;
+//!     }
+//! }
+//! recorder.finish()?;
+//! # Ok::<(), std::io::Error>(())
+//! # }).unwrap();
+//! ```
+
+use crate::events::{Event, RawEvent, XWII_EVENT_DROPPED, XWII_EVENT_REOPENED};
+use crate::timer::Sleep;
+use crate::{bail_if, Error, Result};
+use futures_core::Stream;
+use std::fs::File;
+use std::future::Future;
+use std::io::{BufWriter, Write};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant, SystemTime};
+use std::{io, ptr, slice};
+use xwiimote_sys::{xwii_event, xwii_event_abs, XWII_EVENT_GONE};
+
+// File layout, with every integer in little-endian byte order:
+//
+// - The `MAGIC` bytes.
+// - A sequence of chunks, each of which starts with a `ChunkHeader`
+//   followed by the records of its events. A record consists of the
+//   time of the event in microseconds since the Unix epoch (`u64`),
+//   the raw event type (`u16`), the number of payload positions (`u16`),
+//   and the `x`, `y` and `z` values of each position (`i32`). Trailing
+//   zero positions are omitted.
+// - The index, which holds the offset of each chunk (`u64`) followed
+//   by a copy of its header.
+// - The trailer: the offset of the index (`u64`), the number of chunks
+//   (`u32`), and the `INDEX_MAGIC` bytes.
+
+/// The bytes at the start of every recording file.
+const MAGIC: &[u8; 8] = b"XWIIREC1";
+
+/// The bytes at the end of a complete recording file.
+const INDEX_MAGIC: &[u8; 8] = b"XWIIIDX1";
+
+/// The length of the trailer that follows the index.
+const TRAILER_LEN: usize = 8 + 4 + INDEX_MAGIC.len();
+
+/// The number of positions in the payload of a raw event.
+const MAX_POSITIONS: usize = 8;
+
+/// Describes the events of a chunk.
+#[derive(Copy, Clone, Debug, Default)]
+struct ChunkHeader {
+    /// The length of the records, in bytes.
+    len: u32,
+    /// The number of events.
+    count: u32,
+    /// The time of the first event, in microseconds since the Unix epoch.
+    first: u64,
+    /// The time of the last event, in microseconds since the Unix epoch.
+    last: u64,
+}
+
+impl ChunkHeader {
+    const LEN: usize = 4 + 4 + 8 + 8;
+
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.len.to_le_bytes());
+        buf.extend_from_slice(&self.count.to_le_bytes());
+        buf.extend_from_slice(&self.first.to_le_bytes());
+        buf.extend_from_slice(&self.last.to_le_bytes());
+    }
+
+    fn read(data: &[u8], pos: &mut usize) -> Result<Self> {
+        Ok(Self {
+            len: u32::from_le_bytes(take(data, pos)?),
+            count: u32::from_le_bytes(take(data, pos)?),
+            first: u64::from_le_bytes(take(data, pos)?),
+            last: u64::from_le_bytes(take(data, pos)?),
+        })
+    }
+}
+
+/// Reads the next `N` bytes of `data`, starting at `pos`.
+pub(crate) fn take<const N: usize>(data: &[u8], pos: &mut usize) -> Result<[u8; N]> {
+    let bytes = pos
+        .checked_add(N)
+        .and_then(|end| data.get(*pos..end))
+        .ok_or_else(|| invalid_data("the recording is truncated"))?;
+    *pos += N;
+    Ok(bytes.try_into().unwrap())
+}
+
+fn invalid_data(msg: &str) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+/// Returns the number of microseconds elapsed since the Unix epoch.
+pub(crate) fn micros_since_epoch(time: SystemTime) -> u64 {
+    let since_epoch = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    since_epoch.as_micros() as u64
+}
+
+/// Appends the record of an event that happened `micros` microseconds
+/// after the Unix epoch to `buf`.
+pub(crate) fn encode_record(event: &Event, micros: u64, buf: &mut Vec<u8>) {
+    let raw = event.to_raw(SystemTime::UNIX_EPOCH + Duration::from_micros(micros));
+    // Omit the trailing positions that carry no data.
+    let positions = unsafe { RawEvent::new(&raw) }.payload();
+    let n_positions = positions
+        .iter()
+        .rposition(|pos| (pos.x, pos.y, pos.z) != (0, 0, 0))
+        .map_or(0, |ix| ix + 1);
+    buf.extend_from_slice(&micros.to_le_bytes());
+    buf.extend_from_slice(&(raw.type_ as u16).to_le_bytes());
+    buf.extend_from_slice(&(n_positions as u16).to_le_bytes());
+    for pos in &positions[..n_positions] {
+        for value in [pos.x, pos.y, pos.z] {
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+}
+
+/// Reads the record of an event starting at `pos`, and advances `pos`
+/// past it.
+///
+/// Returns the time of the event in microseconds since the Unix epoch,
+/// and its raw representation.
+pub(crate) fn decode_record(records: &[u8], pos: &mut usize) -> Result<(u64, xwii_event)> {
+    let micros = u64::from_le_bytes(take(records, pos)?);
+    let type_ = u16::from_le_bytes(take(records, pos)?) as u32;
+    let n_positions = u16::from_le_bytes(take(records, pos)?) as usize;
+    let known_type =
+        type_ < XWII_EVENT_GONE || type_ == XWII_EVENT_DROPPED || type_ == XWII_EVENT_REOPENED;
+    if !known_type || n_positions > MAX_POSITIONS {
+        return Err(invalid_data("invalid event record"));
+    }
+    let mut positions = [xwii_event_abs::default(); MAX_POSITIONS];
+    for position in &mut positions[..n_positions] {
+        position.x = i32::from_le_bytes(take(records, pos)?);
+        position.y = i32::from_le_bytes(take(records, pos)?);
+        position.z = i32::from_le_bytes(take(records, pos)?);
+    }
+
+    let since_epoch = Duration::from_micros(micros);
+    let mut raw = xwii_event::default();
+    raw.time.tv_sec = since_epoch.as_secs() as _;
+    raw.time.tv_usec = since_epoch.subsec_micros() as _;
+    raw.type_ = type_;
+    // The payload of key events overlaps the first position.
+    raw.v.abs = positions;
+    Ok((micros, raw))
+}
+
+/// Writes device events to a recording file.
+///
+/// The index of the recording is written by [`Recorder::finish`].
+/// If the recorder is dropped before, e.g. because the application
+/// crashed, the recording can still be replayed but [`Recording::open`]
+/// must scan the whole file to rebuild the index.
+pub struct Recorder<W: Write> {
+    writer: W,
+    /// The number of bytes written so far.
+    offset: u64,
+    /// The header and records of the chunk being filled.
+    chunk: ChunkHeader,
+    records: Vec<u8>,
+    /// The offsets and headers of the chunks written so far.
+    index: Vec<(u64, ChunkHeader)>,
+}
+
+impl Recorder<BufWriter<File>> {
+    /// Creates a recording file at the given path, replacing
+    /// the file if it already exists.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    /// The maximum number of events in a chunk.
+    const CHUNK_EVENTS: u32 = 4096;
+
+    /// Creates a recorder that writes a recording to `writer`.
+    pub fn new(mut writer: W) -> Result<Self> {
+        writer.write_all(MAGIC)?;
+        Ok(Self {
+            writer,
+            offset: MAGIC.len() as u64,
+            chunk: ChunkHeader::default(),
+            records: Vec::new(),
+            index: Vec::new(),
+        })
+    }
+
+    /// Appends an event received at the given time to the recording.
+    ///
+    /// The events of a recording are sorted by time. If the given time
+    /// is earlier than that of the previous event, e.g. because the
+    /// system clock was adjusted, the event is recorded with the time
+    /// of the previous event instead.
+    pub fn record(&mut self, event: &Event, time: SystemTime) -> Result<()> {
+        let mut micros = micros_since_epoch(time);
+        if let Some((_, last_chunk)) = self.index.last() {
+            micros = micros.max(last_chunk.last);
+        }
+        if self.chunk.count == 0 {
+            self.chunk.first = micros;
+        } else {
+            micros = micros.max(self.chunk.last);
+        }
+
+        encode_record(event, micros, &mut self.records);
+        self.chunk.count += 1;
+        self.chunk.last = micros;
+
+        if self.chunk.count == Self::CHUNK_EVENTS {
+            self.flush_chunk()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the chunk being filled, if it holds any events.
+    fn flush_chunk(&mut self) -> Result<()> {
+        if self.chunk.count == 0 {
+            return Ok(());
+        }
+        self.chunk.len = self.records.len() as u32;
+        let mut header = Vec::with_capacity(ChunkHeader::LEN);
+        self.chunk.write(&mut header);
+        self.writer.write_all(&header)?;
+        self.writer.write_all(&self.records)?;
+
+        self.index.push((self.offset, self.chunk));
+        self.offset += (header.len() + self.records.len()) as u64;
+        self.chunk = ChunkHeader::default();
+        self.records.clear();
+        Ok(())
+    }
+
+    /// Writes the pending events and the index of the recording,
+    /// and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.flush_chunk()?;
+        let mut index = Vec::new();
+        for (offset, header) in &self.index {
+            index.extend_from_slice(&offset.to_le_bytes());
+            header.write(&mut index);
+        }
+        index.extend_from_slice(&self.offset.to_le_bytes());
+        index.extend_from_slice(&(self.index.len() as u32).to_le_bytes());
+        index.extend_from_slice(INDEX_MAGIC);
+        self.writer.write_all(&index)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// A read-only memory mapping of a file.
+struct Mapping {
+    addr: *mut libc::c_void,
+    len: usize,
+}
+
+// The mapping is never written to, so it can be shared between threads.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    fn new(file: &File, len: usize) -> Result<Self> {
+        let addr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        bail_if!(addr == libc::MAP_FAILED);
+        Ok(Self { addr, len })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.addr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.addr, self.len) };
+    }
+}
+
+/// The position of the next event to read from a recording.
+#[derive(Copy, Clone, Debug, Default)]
+struct Cursor {
+    /// The index of the current chunk.
+    chunk: usize,
+    /// The offset of the next record, relative to the records
+    /// of the current chunk.
+    pos: usize,
+}
+
+/// A recording file, opened for reading.
+///
+/// The file is memory-mapped, and must not be modified while open.
+pub struct Recording {
+    map: Mapping,
+    /// The offset of the records of each chunk, and its header.
+    chunks: Vec<(usize, ChunkHeader)>,
+}
+
+impl Recording {
+    /// Opens the recording file at the given path.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len < MAGIC.len() {
+            return Err(invalid_data("not a recording file"));
+        }
+        let map = Mapping::new(&file, len)?;
+        let data = map.bytes();
+        if &data[..MAGIC.len()] != MAGIC {
+            return Err(invalid_data("not a recording file"));
+        }
+        let chunks = match Self::read_index(data)? {
+            Some(chunks) => chunks,
+            None => Self::scan_chunks(data)?,
+        };
+        Ok(Self { map, chunks })
+    }
+
+    /// Reads the index at the end of a complete recording.
+    ///
+    /// Returns [`None`] if the recording has no index.
+    fn read_index(data: &[u8]) -> Result<Option<Vec<(usize, ChunkHeader)>>> {
+        if data.len() < MAGIC.len() + TRAILER_LEN || !data.ends_with(INDEX_MAGIC) {
+            return Ok(None);
+        }
+        let corrupted = || invalid_data("the recording index is corrupted");
+        let mut pos = data.len() - TRAILER_LEN;
+        let index_offset = usize::try_from(u64::from_le_bytes(take(data, &mut pos)?))
+            .ok()
+            .filter(|&offset| offset <= data.len() - TRAILER_LEN)
+            .ok_or_else(corrupted)?;
+        let n_chunks = u32::from_le_bytes(take(data, &mut pos)?) as usize;
+
+        let mut pos = index_offset;
+        let mut chunks = Vec::with_capacity(n_chunks.min(data.len() / ChunkHeader::LEN));
+        for _ in 0..n_chunks {
+            let offset = u64::from_le_bytes(take(data, &mut pos)?);
+            let header = ChunkHeader::read(data, &mut pos)?;
+            let records = usize::try_from(offset)
+                .ok()
+                .and_then(|offset| offset.checked_add(ChunkHeader::LEN))
+                .filter(|records| {
+                    records
+                        .checked_add(header.len as usize)
+                        .is_some_and(|end| end <= index_offset)
+                })
+                .ok_or_else(corrupted)?;
+            chunks.push((records, header));
+        }
+        Ok(Some(chunks))
+    }
+
+    /// Rebuilds the index of an incomplete recording by reading
+    /// the headers of its chunks. A truncated last chunk is ignored.
+    fn scan_chunks(data: &[u8]) -> Result<Vec<(usize, ChunkHeader)>> {
+        let mut chunks = Vec::new();
+        let mut pos = MAGIC.len();
+        while let Ok(header) = ChunkHeader::read(data, &mut pos) {
+            let Some(end) = pos
+                .checked_add(header.len as usize)
+                .filter(|&end| end <= data.len())
+            else {
+                break;
+            };
+            chunks.push((pos, header));
+            pos = end;
+        }
+        Ok(chunks)
+    }
+
+    /// Returns the number of recorded events.
+    pub fn len(&self) -> u64 {
+        self.chunks
+            .iter()
+            .map(|(_, header)| header.count as u64)
+            .sum()
+    }
+
+    /// Checks whether the recording holds no events.
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Returns the time of the first recorded event, if any.
+    pub fn start_time(&self) -> Option<SystemTime> {
+        let (_, header) = self.chunks.first()?;
+        Some(SystemTime::UNIX_EPOCH + Duration::from_micros(header.first))
+    }
+
+    /// Returns the time of the last recorded event, if any.
+    pub fn end_time(&self) -> Option<SystemTime> {
+        let (_, header) = self.chunks.last()?;
+        Some(SystemTime::UNIX_EPOCH + Duration::from_micros(header.last))
+    }
+
+    /// Returns the records of a chunk.
+    fn records(&self, chunk: usize) -> &[u8] {
+        let (offset, header) = self.chunks[chunk];
+        &self.map.bytes()[offset..offset + header.len as usize]
+    }
+
+    /// Reads the event at `cursor`, and advances the cursor past it.
+    ///
+    /// Returns the time of the event in microseconds since the Unix epoch,
+    /// and its raw representation; or [`None`] at the end of the recording.
+    fn read(&self, cursor: &mut Cursor) -> Result<Option<(u64, xwii_event)>> {
+        while cursor.chunk < self.chunks.len() {
+            let records = self.records(cursor.chunk);
+            if cursor.pos < records.len() {
+                return decode_record(records, &mut cursor.pos).map(Some);
+            }
+            cursor.chunk += 1;
+            cursor.pos = 0;
+        }
+        Ok(None)
+    }
+
+    /// Returns the position of the first event that happened
+    /// at or after `time`.
+    fn locate(&self, time: SystemTime) -> Result<Cursor> {
+        let micros = micros_since_epoch(time);
+        let chunk = self
+            .chunks
+            .partition_point(|(_, header)| header.last < micros);
+        let mut cursor = Cursor { chunk, pos: 0 };
+        if chunk < self.chunks.len() {
+            let records = self.records(chunk);
+            while cursor.pos < records.len() {
+                let mut next = cursor.pos;
+                let (event_micros, _) = decode_record(records, &mut next)?;
+                if event_micros >= micros {
+                    break;
+                }
+                cursor.pos = next;
+            }
+        }
+        Ok(cursor)
+    }
+}
+
+/// Replays the events of a [`Recording`] as if they came from a device.
+///
+/// The stream produces the recorded events together with the times
+/// at which they were originally received, and waits between events
+/// to reproduce their original pacing. Events of extensions whose
+/// support is disabled at compile time are skipped.
+///
+/// The replay can be sped up or slowed down with [`ReplayDevice::set_speed`],
+/// paused, and moved to any point of the recording. While paused, the
+/// events can still be read one at a time through [`ReplayDevice::step`].
+pub struct ReplayDevice {
+    recording: Recording,
+    cursor: Cursor,
+    /// The instant at which the replay started or last seeked,
+    /// and the recording time at that instant in microseconds
+    /// since the Unix epoch.
+    anchor: Option<(Instant, u64)>,
+    /// The playback speed multiplier.
+    speed: f64,
+    /// The instant at which the replay was paused, if paused.
+    paused_at: Option<Instant>,
+    /// The waker of the task that waits for the replay to resume.
+    waker: Option<Waker>,
+    /// The timer that expires once the next event is due.
+    sleep: Option<Sleep>,
+}
+
+impl ReplayDevice {
+    /// Creates a device that replays a recording from the beginning,
+    /// at the original speed.
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            recording,
+            cursor: Cursor::default(),
+            anchor: None,
+            speed: 1.0,
+            paused_at: None,
+            waker: None,
+            sleep: None,
+        }
+    }
+
+    /// Opens the recording file at the given path for replay.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Recording::open(path).map(Self::new)
+    }
+
+    /// Returns the replayed recording.
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    /// Moves the replay to the first event that happened at or after
+    /// `time`. The replay continues from there without waiting.
+    ///
+    /// Seeking past the end of the recording ends the stream.
+    pub fn seek(&mut self, time: SystemTime) -> Result<()> {
+        self.cursor = self.recording.locate(time)?;
+        self.anchor = None;
+        Ok(())
+    }
+
+    /// Returns the playback speed multiplier.
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Sets the playback speed multiplier. For example, the events
+    /// are replayed twice as fast as they were recorded if `speed`
+    /// is 2, and at half the original pace if `speed` is 0.5.
+    ///
+    /// # Panics
+    /// Panics if `speed` is not a positive, finite number.
+    pub fn set_speed(&mut self, speed: f64) {
+        assert!(
+            speed.is_finite() && speed > 0.0,
+            "invalid playback speed {speed}"
+        );
+        // Keep the current position of the replay.
+        let now = self.paused_at.unwrap_or_else(Instant::now);
+        self.anchor = self.playhead(now).map(|micros| (now, micros));
+        self.speed = speed;
+    }
+
+    /// Returns the recording time reached by the replay at `now`,
+    /// in microseconds since the Unix epoch.
+    fn playhead(&self, now: Instant) -> Option<u64> {
+        let (start, start_micros) = self.anchor?;
+        let elapsed = now.saturating_duration_since(start).mul_f64(self.speed);
+        Some(start_micros + elapsed.as_micros() as u64)
+    }
+
+    /// Checks whether the replay is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    /// Pauses the replay. The stream produces no events until
+    /// [`ReplayDevice::resume`] is called.
+    pub fn pause(&mut self) {
+        self.paused_at.get_or_insert_with(Instant::now);
+    }
+
+    /// Resumes a paused replay from the point where it stopped.
+    pub fn resume(&mut self) {
+        if let Some(paused_at) = self.paused_at.take() {
+            if let Some((start, _)) = &mut self.anchor {
+                *start += paused_at.elapsed();
+            }
+            if let Some(waker) = self.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Returns the next event right away, regardless of its original
+    /// pacing and of whether the replay is paused.
+    ///
+    /// The replay continues from the returned event once resumed.
+    pub fn step(&mut self) -> Result<Option<(Event, SystemTime)>> {
+        while let Some((micros, raw)) = self.recording.read(&mut self.cursor)? {
+            let now = self.paused_at.unwrap_or_else(Instant::now);
+            self.anchor = Some((now, micros));
+            if let Some(event) = Event::parse(unsafe { RawEvent::new(&raw) }) {
+                return Ok(Some(event));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Waits until the event recorded at `micros` is due.
+    fn poll_due(&mut self, micros: u64, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if self.paused_at.is_some() {
+            self.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let now = Instant::now();
+        let (start, start_micros) = *self.anchor.get_or_insert((now, micros));
+        let wait = Duration::from_micros(micros.saturating_sub(start_micros));
+        let due = start + wait.div_f64(self.speed);
+        if due <= now {
+            return Poll::Ready(Ok(()));
+        }
+        let sleep = match &mut self.sleep {
+            Some(sleep) => {
+                sleep.reset(due - now)?;
+                sleep
+            }
+            None => self.sleep.insert(Sleep::new(due - now)?),
+        };
+        Pin::new(sleep).poll(cx)
+    }
+}
+
+impl Stream for ReplayDevice {
+    type Item = Result<(Event, SystemTime)>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let mut next = self.cursor;
+            let (micros, raw) = match self.recording.read(&mut next) {
+                Ok(Some(record)) => record,
+                Ok(None) => return Poll::Ready(None),
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            };
+            match self.poll_due(micros, cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(err)) => return Poll::Ready(Some(Err(err))),
+                Poll::Pending => return Poll::Pending,
+            }
+            self.cursor = next;
+            if let Some(event) = Event::parse(unsafe { RawEvent::new(&raw) }) {
+                return Poll::Ready(Some(Ok(event)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::{Event, Key, KeyState};
+    use crate::recording::{ChunkHeader, Recorder, Recording, ReplayDevice, INDEX_MAGIC, MAGIC};
+    use crate::Result;
+    use futures_core::Stream;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::pin::Pin;
+    use std::task::{Context, Waker};
+    use std::time::{Duration, Instant, SystemTime};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("xwiimote-{name}-{}.xwiirec", std::process::id()))
+    }
+
+    fn next(replay: &mut ReplayDevice) -> Option<Result<(Event, SystemTime)>> {
+        futures_executor::block_on(std::future::poll_fn(|cx| {
+            Pin::new(&mut *replay).poll_next(cx)
+        }))
+    }
+
+    #[test]
+    fn round_trips_events() -> Result<()> {
+        let path = temp_path("round-trip");
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut recorder = Recorder::create(&path)?;
+        recorder.record(&Event::Key(Key::A, KeyState::Down), start)?;
+        recorder.record(&Event::Accelerometer { x: 1, y: -2, z: 3 }, start)?;
+        recorder.record(&Event::Other, start)?;
+        recorder.finish()?;
+
+        let mut replay = ReplayDevice::open(&path)?;
+        assert_eq!(replay.recording().len(), 3);
+        assert_eq!(replay.recording().start_time(), Some(start));
+        let (event, time) = next(&mut replay).unwrap()?;
+        assert!(matches!(event, Event::Key(Key::A, KeyState::Down)));
+        assert_eq!(time, start);
+        let (event, _) = next(&mut replay).unwrap()?;
+        assert!(matches!(event, Event::Accelerometer { x: 1, y: -2, z: 3 }));
+        assert!(matches!(next(&mut replay), Some(Ok((Event::Other, _)))));
+        assert!(next(&mut replay).is_none());
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn seeks_across_chunks() -> Result<()> {
+        let path = temp_path("seek");
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut recorder = Recorder::create(&path)?;
+        for ix in 0..10_000 {
+            let event = Event::MotionPlus { x: ix, y: 0, z: 0 };
+            recorder.record(&event, start + Duration::from_millis(ix as u64))?;
+        }
+        recorder.finish()?;
+
+        let mut replay = ReplayDevice::open(&path)?;
+        assert!(replay.recording().chunks.len() > 1);
+        replay.seek(start + Duration::from_millis(9_999))?;
+        assert!(matches!(
+            next(&mut replay),
+            Some(Ok((Event::MotionPlus { x: 9_999, .. }, _)))
+        ));
+        assert!(next(&mut replay).is_none());
+
+        replay.seek(start + Duration::from_micros(5_000_500))?;
+        assert!(matches!(
+            next(&mut replay),
+            Some(Ok((Event::MotionPlus { x: 5_001, .. }, _)))
+        ));
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_corrupted_indexes() {
+        let index = |index_offset: u64, entries: &[(u64, u32)]| {
+            let mut data = MAGIC.to_vec();
+            for &(offset, len) in entries {
+                data.extend_from_slice(&offset.to_le_bytes());
+                data.extend_from_slice(&len.to_le_bytes());
+                data.extend_from_slice(&[0; ChunkHeader::LEN - 4]);
+            }
+            data.extend_from_slice(&index_offset.to_le_bytes());
+            data.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+            data.extend_from_slice(INDEX_MAGIC);
+            data
+        };
+        let read = |data: Vec<u8>| Recording::read_index(&data).map(|chunks| chunks.unwrap());
+
+        assert!(read(index(8, &[])).unwrap().is_empty());
+        assert!(read(index(u64::MAX, &[])).is_err());
+        assert!(read(index(64, &[])).is_err());
+        assert!(read(index(8, &[(u64::MAX, 0)])).is_err());
+        assert!(read(index(8, &[(u64::MAX - 8, 0)])).is_err());
+        assert!(read(index(8, &[(0, u32::MAX)])).is_err());
+        // The chunk would overlap the index.
+        assert!(read(index(8, &[(0, 1)])).is_err());
+    }
+
+    #[test]
+    fn replays_unfinished_recordings() -> Result<()> {
+        let path = temp_path("unfinished");
+        let mut recorder = Recorder::create(&path)?;
+        for _ in 0..5_000 {
+            recorder.record(&Event::Other, SystemTime::UNIX_EPOCH)?;
+        }
+        // Only the first, complete chunk is written.
+        drop(recorder);
+
+        let recording = Recording::open(&path)?;
+        assert_eq!(recording.len(), 4096);
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn controls_playback() -> Result<()> {
+        let path = temp_path("playback");
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut recorder = Recorder::create(&path)?;
+        for ix in 0..4 {
+            let event = Event::MotionPlus { x: ix, y: 0, z: 0 };
+            recorder.record(&event, start + Duration::from_secs(ix as u64))?;
+        }
+        recorder.finish()?;
+
+        let mut replay = ReplayDevice::open(&path)?;
+        replay.pause();
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(Pin::new(&mut replay).poll_next(&mut cx).is_pending());
+        assert!(matches!(
+            replay.step()?,
+            Some((Event::MotionPlus { x: 0, .. }, _))
+        ));
+
+        // The remaining events are one second apart.
+        replay.set_speed(1000.0);
+        replay.resume();
+        let started = Instant::now();
+        assert!(matches!(
+            next(&mut replay),
+            Some(Ok((Event::MotionPlus { x: 1, .. }, _)))
+        ));
+        assert!(matches!(
+            next(&mut replay),
+            Some(Ok((Event::MotionPlus { x: 2, .. }, _)))
+        ));
+        assert!(started.elapsed() < Duration::from_millis(500));
+        fs::remove_file(path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,377 @@
+//! Hardware self-test of a device, e.g. to triage returned units.
+//!
+//! [`Device::self_test`] flashes the LED lights one at a time, pulses
+//! the rumble motor and samples each available sensor for a moment,
+//! checking that the values it reports are plausible. The buttons are
+//! not tested, since that requires the help of the user.
+//!
+//! # Examples
+//! ```no_run
+//! use futures_util::TryStreamExt;
+//! use xwiimote::{Device, Monitor};
+//!
+//! # tokio_test::block_on(async {
+//! # let address = Monitor::enumerate()?.try_next().await?.unwrap();
+//! let device = Device::connect(&address)?;
+//! let report = device.self_test().await?;
+//! println!("LEDs: {:?}, rumble: {:?}", report.leds, report.rumble);
+//! for check in &report.sensors {
+//!     println!("{:?}: {:?} ({} samples)", check.channel, check.outcome, check.samples);
+//! }
+//! assert!(report.passed());
+//! # Ok::<(), xwiimote::Error>(())
+//! # }).unwrap();
+//! ```
+
+use crate::events::Event;
+use crate::ranges::{ACCELERATION_MAX, ACCELERATION_PER_G, IR_HEIGHT, IR_WIDTH, ROTATION_MAX};
+use crate::timer::{self, Sleep};
+use crate::{Channels, Device, Error, Led, Leds, Result};
+use futures_core::Stream;
+use std::future::{poll_fn, Future};
+use std::pin::{pin, Pin};
+use std::task::Poll;
+use std::time::Duration;
+
+/// The time for which each LED light is turned on.
+const LED_STEP: Duration = Duration::from_millis(150);
+
+/// The time for which the rumble motor is turned on.
+const RUMBLE_PULSE: Duration = Duration::from_millis(300);
+
+/// The time for which the sensors are sampled.
+const SAMPLE_TIME: Duration = Duration::from_secs(1);
+
+/// The range of the mean magnitude of the acceleration, in raw units.
+/// The readings must at least reflect gravity, and a device held by hand
+/// should not average more than a few g.
+const MEAN_ACCELERATION: std::ops::RangeInclusive<f64> =
+    0.3 * ACCELERATION_PER_G as f64..=3.0 * ACCELERATION_PER_G as f64;
+
+/// The result of a check of the self-test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The component works as expected.
+    Passed,
+    /// The component produced no data while sampled. This is expected
+    /// from the IR camera if it sees no IR light, e.g. from a sensor
+    /// bar, and from the extensions that are left untouched.
+    NoData,
+    /// The component produced data that a working component cannot
+    /// produce, as explained by the message.
+    Implausible(String),
+    /// The component could not be operated, as explained by the message.
+    Failed(String),
+}
+
+impl Outcome {
+    /// Converts the result of an operation into an outcome.
+    fn of(res: Result<Outcome>) -> Self {
+        res.unwrap_or_else(|err| Self::Failed(err.to_string()))
+    }
+}
+
+/// The check of a sensor, reported in a [`SelfTestReport`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SensorCheck {
+    /// The channel of the sensor, e.g. [`Channels::ACCELEROMETER`].
+    /// The extensions are checked together.
+    pub channel: Channels,
+    /// The number of events received from the sensor.
+    pub samples: u64,
+    /// The result of the check.
+    pub outcome: Outcome,
+}
+
+/// The results of [`Device::self_test`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestReport {
+    /// Do the LED lights turn on and off?
+    pub leds: Outcome,
+    /// Does the rumble motor accept commands?
+    pub rumble: Outcome,
+    /// The checks of the available sensors.
+    pub sensors: Vec<SensorCheck>,
+    /// The battery level, if it could be read.
+    pub battery: Option<u8>,
+}
+
+impl SelfTestReport {
+    /// Checks whether every component passed the self-test. The IR camera
+    /// and the extensions may have produced no data; see [`Outcome::NoData`].
+    pub fn passed(&self) -> bool {
+        let sensors_passed = self.sensors.iter().all(|check| match check.outcome {
+            Outcome::Passed => true,
+            Outcome::NoData => !check
+                .channel
+                .intersects(Channels::ACCELEROMETER | Channels::MOTION_PLUS),
+            Outcome::Implausible(_) | Outcome::Failed(_) => false,
+        });
+        self.leds == Outcome::Passed && self.rumble == Outcome::Passed && sensors_passed
+    }
+}
+
+/// The channels of the sensors built into the device.
+const BUILT_IN: Channels = Channels::ACCELEROMETER
+    .union(Channels::IR)
+    .union(Channels::MOTION_PLUS);
+
+/// The statistics of the data reported by a sensor.
+#[derive(Debug, Default)]
+struct SensorStats {
+    samples: u64,
+    /// The sum of the magnitudes of the accelerometer readings.
+    magnitude_sum: f64,
+    /// A description of the first implausible value, if any.
+    implausible: Option<String>,
+}
+
+impl SensorStats {
+    fn record(&mut self, event: &Event) {
+        self.samples += 1;
+        let implausible = match *event {
+            Event::Accelerometer { x, y, z } => {
+                let (fx, fy, fz) = (x as f64, y as f64, z as f64);
+                self.magnitude_sum += (fx * fx + fy * fy + fz * fz).sqrt();
+                [x, y, z]
+                    .iter()
+                    .any(|axis| axis.abs() > ACCELERATION_MAX)
+                    .then(|| format!("acceleration ({x}, {y}, {z}) is out of range"))
+            }
+            Event::Ir(sources) => sources
+                .iter()
+                .flatten()
+                .find(|source| {
+                    !(0..IR_WIDTH).contains(&source.x) || !(0..IR_HEIGHT).contains(&source.y)
+                })
+                .map(|source| format!("IR source at ({}, {}) is out of range", source.x, source.y)),
+            Event::MotionPlus { x, y, z } => [x, y, z]
+                .iter()
+                .any(|axis| axis.abs() > ROTATION_MAX)
+                .then(|| format!("rotation ({x}, {y}, {z}) is out of range")),
+            _ => None,
+        };
+        if self.implausible.is_none() {
+            self.implausible = implausible;
+        }
+    }
+
+    /// Returns the outcome of the check of the sensor of `channel`.
+    fn outcome(&self, channel: Channels) -> Outcome {
+        if self.samples == 0 {
+            return Outcome::NoData;
+        }
+        if let Some(message) = &self.implausible {
+            return Outcome::Implausible(message.clone());
+        }
+        if channel == Channels::ACCELEROMETER {
+            let mean = self.magnitude_sum / self.samples as f64;
+            if !MEAN_ACCELERATION.contains(&mean) {
+                return Outcome::Implausible(format!(
+                    "mean acceleration magnitude {mean:.0} does not reflect gravity"
+                ));
+            }
+        }
+        Outcome::Passed
+    }
+}
+
+/// Returns the channel through which an event is received, if it
+/// reports sensor data.
+fn sensor_channel(event: &Event) -> Option<Channels> {
+    Some(match event {
+        Event::Accelerometer { .. } => Channels::ACCELEROMETER,
+        Event::Ir(_) => Channels::IR,
+        Event::MotionPlus { .. } => Channels::MOTION_PLUS,
+        #[cfg(feature = "balance-board")]
+        Event::BalanceBoard(_) => Channels::BALANCE_BOARD,
+        #[cfg(feature = "pro-controller")]
+        Event::ProControllerKey(..) | Event::ProControllerMove { .. } => Channels::PRO_CONTROLLER,
+        #[cfg(feature = "classic")]
+        Event::ClassicControllerKey(..) | Event::ClassicControllerMove { .. } => {
+            Channels::CLASSIC_CONTROLLER
+        }
+        #[cfg(feature = "nunchuk")]
+        Event::NunchukKey(..) | Event::NunchukMove { .. } => Channels::NUNCHUK,
+        #[cfg(feature = "drums")]
+        Event::DrumsKey(..) | Event::DrumsMove { .. } => Channels::DRUMS,
+        #[cfg(feature = "guitar")]
+        Event::GuitarKey(..) | Event::GuitarMove { .. } => Channels::GUITAR,
+        _ => return None,
+    })
+}
+
+/// Runs the self-test of a device; see [`Device::self_test`].
+pub(crate) async fn run(device: &Device) -> Result<SelfTestReport> {
+    let available = device.available();
+    let extensions = available - BUILT_IN - Channels::CORE;
+    let mut checked: Vec<Channels> = BUILT_IN.iter().filter(|c| available.contains(*c)).collect();
+    if !extensions.is_empty() {
+        checked.push(extensions);
+    }
+
+    let was_open = device.get_open();
+    device.open(Channels::CORE | available, true)?;
+    let previous_leds = device.leds()?;
+
+    let res = run_checks(device, &checked).await;
+
+    // Leave the device as we found it, even if a check failed.
+    let restored = device
+        .set_leds(previous_leds)
+        .and_then(|()| device.set_rumble(false))
+        .and_then(|()| device.close(device.get_open() - was_open));
+    let (leds, rumble, sensors) = res?;
+    restored?;
+    Ok(SelfTestReport {
+        leds,
+        rumble,
+        sensors,
+        battery: device.battery().ok(),
+    })
+}
+
+async fn run_checks(
+    device: &Device,
+    checked: &[Channels],
+) -> Result<(Outcome, Outcome, Vec<SensorCheck>)> {
+    let leds = Outcome::of(check_leds(device).await);
+    let rumble = Outcome::of(
+        device
+            .rumble_for(RUMBLE_PULSE)
+            .await
+            .map(|()| Outcome::Passed),
+    );
+
+    let stats = sample(device, checked).await?;
+    let sensors = checked
+        .iter()
+        .zip(stats)
+        .map(|(&channel, stats)| SensorCheck {
+            channel,
+            samples: stats.samples,
+            outcome: stats.outcome(channel),
+        })
+        .collect();
+    Ok((leds, rumble, sensors))
+}
+
+/// Turns on each LED light in turn, checking that the device reports
+/// the expected state.
+async fn check_leds(device: &Device) -> Result<Outcome> {
+    for light in Led::ALL {
+        device.set_leds(light.into())?;
+        timer::sleep(LED_STEP).await?;
+        let lit = device.leds()?;
+        if lit != Leds::from(light) {
+            return Ok(Outcome::Implausible(format!(
+                "lights {lit:?} are on instead of {light:?}"
+            )));
+        }
+    }
+    device.set_leds(Leds::empty())?;
+    Ok(Outcome::Passed)
+}
+
+/// Collects the statistics of the data reported through each of the
+/// `checked` channels for [`SAMPLE_TIME`].
+async fn sample(device: &Device, checked: &[Channels]) -> Result<Vec<SensorStats>> {
+    let mut stats: Vec<SensorStats> = checked.iter().map(|_| SensorStats::default()).collect();
+    let mut events = pin!(device.events()?);
+    let mut deadline = Sleep::new(SAMPLE_TIME)?;
+    poll_fn(|cx| loop {
+        if Pin::new(&mut deadline).poll(cx)?.is_ready() {
+            return Poll::Ready(Ok(()));
+        }
+        let (event, _) = match events.as_mut().poll_next(cx) {
+            Poll::Ready(Some(item)) => item?,
+            Poll::Ready(None) => return Poll::Ready(Err(Error::Disconnected)),
+            Poll::Pending => return Poll::Pending,
+        };
+        let Some(channel) = sensor_channel(&event) else {
+            continue;
+        };
+        let index = checked.iter().position(|c| c.intersects(channel));
+        if let Some(index) = index {
+            stats[index].record(&event);
+        }
+    })
+    .await?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::{Event, IrSource};
+    use crate::self_test::{Outcome, SelfTestReport, SensorCheck, SensorStats};
+    use crate::Channels;
+
+    #[test]
+    fn checks_accelerometer() {
+        let mut stats = SensorStats::default();
+        assert_eq!(stats.outcome(Channels::ACCELEROMETER), Outcome::NoData);
+        stats.record(&Event::Accelerometer { x: 2, y: -3, z: 98 });
+        stats.record(&Event::Accelerometer {
+            x: 4,
+            y: -1,
+            z: 101,
+        });
+        assert_eq!(stats.outcome(Channels::ACCELEROMETER), Outcome::Passed);
+
+        let mut stuck = SensorStats::default();
+        stuck.record(&Event::Accelerometer { x: 0, y: 0, z: 0 });
+        assert!(matches!(
+            stuck.outcome(Channels::ACCELEROMETER),
+            Outcome::Implausible(_)
+        ));
+        let mut broken = SensorStats::default();
+        broken.record(&Event::Accelerometer { x: 0, y: 900, z: 0 });
+        assert!(matches!(
+            broken.outcome(Channels::ACCELEROMETER),
+            Outcome::Implausible(_)
+        ));
+    }
+
+    #[test]
+    fn checks_ir_positions() {
+        let mut stats = SensorStats::default();
+        stats.record(&Event::Ir([
+            Some(IrSource { x: 512, y: 384 }),
+            None,
+            None,
+            None,
+        ]));
+        assert_eq!(stats.outcome(Channels::IR), Outcome::Passed);
+        stats.record(&Event::Ir([
+            None,
+            Some(IrSource { x: 512, y: 900 }),
+            None,
+            None,
+        ]));
+        assert!(matches!(
+            stats.outcome(Channels::IR),
+            Outcome::Implausible(_)
+        ));
+    }
+
+    #[test]
+    fn tolerates_missing_ir_data() {
+        let check = |channel, outcome| SensorCheck {
+            channel,
+            samples: 0,
+            outcome,
+        };
+        let mut report = SelfTestReport {
+            leds: Outcome::Passed,
+            rumble: Outcome::Passed,
+            sensors: vec![
+                check(Channels::ACCELEROMETER, Outcome::Passed),
+                check(Channels::IR, Outcome::NoData),
+            ],
+            battery: Some(80),
+        };
+        assert!(report.passed());
+        report.sensors[0].outcome = Outcome::NoData;
+        assert!(!report.passed());
+    }
+}
@@ -0,0 +1,339 @@
+//! A high-level interface that hides device discovery and channel management.
+
+use crate::events::{Event, Key, KeyState, OwnedEvents};
+use crate::gesture::{GestureCapture, GestureSet};
+use crate::pointer::{Arbiter, Arbitration, CursorEvent};
+use crate::{Address, Channels, Device, Monitor, Result};
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::time::SystemTime;
+
+/// An input produced by a [`Session`].
+#[derive(Debug, Clone)]
+pub enum Input {
+    /// The session connected to the device with the given address.
+    Connected(Address),
+    /// The device of the session disconnected.
+    Disconnected,
+    /// An event was received from the device, at the time given
+    /// by the kernel.
+    Event(Event, SystemTime),
+    /// The device points at the screen, from (0, 0) at the top-left
+    /// corner to (1, 1) at the bottom-right corner.
+    ///
+    /// Only produced if the session [tracks the pointer](Session::pointer).
+    Pointer {
+        /// The horizontal position.
+        x: f32,
+        /// The vertical position.
+        y: f32,
+    },
+    /// The device no longer points at the screen.
+    PointerLost,
+    /// The user performed the gesture with the given name while holding
+    /// the trigger key down.
+    ///
+    /// Only produced if the session [recognizes gestures](Session::gestures).
+    Gesture(String),
+}
+
+/// Connects to the first available Wii Remote and produces its events,
+/// reconnecting to a new device whenever the current one disconnects.
+///
+/// # Examples
+/// ```
+/// use xwiimote::events::Event;
+/// use xwiimote::session::{Input, Session};
+///
+/// # let _ = async { // the `while` loop runs indefinitely.
+/// let mut session = Session::discover();
+/// while let Some(input) = session.next_input().await? {
+///     match input {
+///         Input::Connected(address) => println!("connected to {address}"),
+///         Input::Event(Event::Key(key, state), _) => println!("{key:?} is {state:?}"),
+///         _ => {}
+///     }
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// # };
+/// ```
+pub struct Session {
+    /// Do we wait for new devices to be discovered?
+    discover: bool,
+    /// The channels to open on every connected device.
+    channels: Channels,
+    /// Do we report the point of the screen aimed at by the device?
+    pointer: bool,
+    /// The gestures to recognize, and the key held down while the user
+    /// performs them.
+    gestures: Option<(GestureSet, Key)>,
+    /// The events of the device we are currently connected to, if any.
+    events: Option<OwnedEvents>,
+    /// Tracks whether the device points at the screen.
+    arbiter: Arbiter<()>,
+    /// The motion performed while the trigger key is held down.
+    capture: GestureCapture,
+    /// The inputs derived from the last event that were not produced yet.
+    pending: VecDeque<Input>,
+}
+
+impl Session {
+    fn new(discover: bool) -> Self {
+        Self {
+            discover,
+            channels: Channels::CORE,
+            pointer: false,
+            gestures: None,
+            events: None,
+            arbiter: Arbiter::new(Arbitration::FirstCome),
+            capture: GestureCapture::default(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Creates a session that connects to an already connected device.
+    ///
+    /// The session ends once no connected device is found.
+    pub fn enumerate() -> Self {
+        Self::new(false)
+    }
+
+    /// Creates a session that connects to an already connected device,
+    /// or waits for a new device to be discovered.
+    ///
+    /// The session never ends.
+    pub fn discover() -> Self {
+        Self::new(true)
+    }
+
+    /// Sets the channels to open on every device the session connects to.
+    ///
+    /// The [core channel](`Channels::CORE`) is always opened in writable
+    /// mode, so that key events are reported and the rumble motor works.
+    /// The channels needed by [`Session::pointer`] and [`Session::gestures`]
+    /// are opened as well.
+    pub fn channels(mut self, channels: Channels) -> Self {
+        self.channels = channels | Channels::CORE;
+        self
+    }
+
+    /// Reports the point of the screen aimed at by the device as
+    /// [`Input::Pointer`] and [`Input::PointerLost`] inputs, which
+    /// requires the [IR channel](`Channels::IR`).
+    pub fn pointer(mut self) -> Self {
+        self.pointer = true;
+        self
+    }
+
+    /// Recognizes the motions performed while `trigger` is held down
+    /// among `gestures`, and reports them as [`Input::Gesture`] inputs.
+    /// This requires the [accelerometer channel](`Channels::ACCELEROMETER`).
+    pub fn gestures(mut self, gestures: GestureSet, trigger: Key) -> Self {
+        self.gestures = Some((gestures, trigger));
+        self
+    }
+
+    /// Returns the device the session is connected to, if any.
+    pub fn device(&self) -> Option<&Device> {
+        self.events.as_ref().map(OwnedEvents::device)
+    }
+
+    /// Waits for the next input.
+    ///
+    /// Every event is produced as an [`Input::Event`], followed by the
+    /// pointer and gesture inputs derived from it, if any.
+    ///
+    /// # Returns
+    /// The next input, or [`None`] if the session ended. If a device
+    /// fails, the error is returned and the session connects to the
+    /// next available device when this method is called again.
+    ///
+    /// # Cancel safety
+    /// This method is cancel safe. If the future is dropped before it
+    /// completes, no input is lost and the session stays connected
+    /// to its current device, if any.
+    pub async fn next_input(&mut self) -> Result<Option<Input>> {
+        if let Some(input) = self.pending.pop_front() {
+            return Ok(Some(input));
+        }
+        let Some(events) = &mut self.events else {
+            return Ok(match self.find_device().await? {
+                Some(address) => {
+                    self.connect(&address)?;
+                    Some(Input::Connected(address))
+                }
+                None => None,
+            });
+        };
+
+        match poll_fn(|cx| Pin::new(&mut *events).poll_next(cx)).await {
+            Some(Ok((event, time))) => {
+                self.process(&event);
+                Ok(Some(Input::Event(event, time)))
+            }
+            Some(Err(err)) => {
+                self.disconnect();
+                Err(err)
+            }
+            None => {
+                self.disconnect();
+                Ok(Some(Input::Disconnected))
+            }
+        }
+    }
+
+    /// Queues the pointer and gesture inputs derived from an event.
+    fn process(&mut self, event: &Event) {
+        if self.pointer {
+            let changes = self.arbiter.update(&(), event);
+            self.queue_pointer(changes);
+        }
+        let Some((gestures, trigger)) = &self.gestures else {
+            return;
+        };
+        match *event {
+            Event::Key(key, KeyState::Down) if key as u32 == *trigger as u32 => {
+                self.capture.begin()
+            }
+            Event::Key(key, KeyState::Up) if key as u32 == *trigger as u32 => {
+                let samples = self.capture.end().unwrap_or_default();
+                if let Some(found) = gestures.recognize(&samples) {
+                    self.pending
+                        .push_back(Input::Gesture(found.name.to_owned()));
+                }
+            }
+            Event::Accelerometer { x, y, z } => self.capture.push([x, y, z]),
+            _ => {}
+        }
+    }
+
+    fn queue_pointer(&mut self, changes: Vec<CursorEvent<()>>) {
+        for change in changes {
+            self.pending.push_back(match change {
+                CursorEvent::Acquired { .. } => continue,
+                CursorEvent::Moved { x, y, .. } => Input::Pointer { x, y },
+                CursorEvent::Released { .. } => Input::PointerLost,
+            });
+        }
+    }
+
+    /// Forgets the current device, once it disconnected or failed.
+    ///
+    /// The pointer is reported lost after the disconnection.
+    fn disconnect(&mut self) {
+        self.events = None;
+        self.capture.end();
+        let changes = self.arbiter.remove(&());
+        self.queue_pointer(changes);
+    }
+
+    /// Finds the address of a device to connect to.
+    async fn find_device(&self) -> Result<Option<Address>> {
+        let mut monitor = if self.discover {
+            Monitor::discover()
+        } else {
+            Monitor::enumerate()
+        }?;
+        poll_fn(|cx| Pin::new(&mut monitor).poll_next(cx))
+            .await
+            .transpose()
+    }
+
+    /// Connects to a device and opens the requested channels.
+    fn connect(&mut self, address: &Address) -> Result<()> {
+        let mut channels = self.channels;
+        if self.pointer {
+            channels |= Channels::IR;
+        }
+        if self.gestures.is_some() {
+            channels |= Channels::ACCELEROMETER;
+        }
+        let device = Device::connect(address)?;
+        device.open(channels, true)?;
+        self.events = Some(device.into_events()?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::{Event, IrSource, Key, KeyState, MAX_IR_SOURCES};
+    use crate::gesture::{Gesture, GestureSet};
+    use crate::session::{Input, Session};
+
+    fn ir(x: i32, y: i32) -> Event {
+        let mut sources = [None; MAX_IR_SOURCES];
+        sources[0] = Some(IrSource { x, y });
+        Event::Ir(sources)
+    }
+
+    #[test]
+    fn reports_the_pointer() {
+        let mut session = Session::enumerate().pointer();
+        session.process(&ir(512, 384));
+        session.process(&Event::Key(Key::A, KeyState::Down));
+        session.process(&Event::Ir([None; MAX_IR_SOURCES]));
+        assert!(matches!(
+            session.pending.pop_front(),
+            Some(Input::Pointer { x, y }) if x == 0.5 && y == 0.5
+        ));
+        assert!(matches!(
+            session.pending.pop_front(),
+            Some(Input::PointerLost)
+        ));
+        assert!(session.pending.is_empty());
+
+        session.process(&ir(0, 0));
+        session.disconnect();
+        assert!(matches!(
+            session.pending.pop_front(),
+            Some(Input::Pointer { .. })
+        ));
+        assert!(matches!(
+            session.pending.pop_front(),
+            Some(Input::PointerLost)
+        ));
+    }
+
+    #[test]
+    fn recognizes_gestures_while_the_trigger_is_held() {
+        let motion: Vec<_> = (0..20).map(|i| [i * 10, 0, 100]).collect();
+        let mut gestures = GestureSet::new();
+        let mut gesture = Gesture::new("swipe");
+        gesture.add_example(motion.clone());
+        gestures.insert(gesture);
+        let mut session = Session::enumerate().gestures(gestures, Key::B);
+
+        let perform = |session: &mut Session| {
+            session.process(&Event::Key(Key::B, KeyState::Down));
+            for [x, y, z] in &motion {
+                session.process(&Event::Accelerometer {
+                    x: *x,
+                    y: *y,
+                    z: *z,
+                });
+            }
+            session.process(&Event::Key(Key::B, KeyState::Up));
+        };
+        perform(&mut session);
+        assert!(matches!(
+            session.pending.pop_front(),
+            Some(Input::Gesture(name)) if name == "swipe"
+        ));
+        // The pointer is not tracked.
+        session.process(&ir(512, 384));
+        assert!(session.pending.is_empty());
+        // Motions without the trigger are ignored.
+        for [x, y, z] in &motion {
+            session.process(&Event::Accelerometer {
+                x: *x,
+                y: *y,
+                z: *z,
+            });
+        }
+        session.process(&Event::Key(Key::A, KeyState::Up));
+        assert!(session.pending.is_empty());
+    }
+}
@@ -0,0 +1,93 @@
+//! Halves of a [`Device`] that can be handed to different tasks.
+//!
+//! See [`Device::split`] for details.
+
+use crate::events::{Event, EventStream};
+use crate::{Device, Result};
+use futures_core::Stream;
+use std::fmt;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+
+/// The event half of a [`Device`], created by [`Device::split`].
+///
+/// Produces the same events as the stream returned by [`Device::events`],
+/// including the time at which the kernel generated them. Unlike that
+/// stream, this type can be named, e.g. to store it in a struct.
+pub struct DeviceEvents<'d> {
+    stream: EventStream<'d>,
+}
+
+impl<'d> DeviceEvents<'d> {
+    pub(crate) fn new(device: &'d Device) -> Result<Self> {
+        Ok(Self {
+            stream: EventStream::new(device)?,
+        })
+    }
+}
+
+impl Stream for DeviceEvents<'_> {
+    type Item = Result<(Event, SystemTime)>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.stream).poll_next(cx)
+    }
+}
+
+impl fmt::Debug for DeviceEvents<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeviceEvents").finish_non_exhaustive()
+    }
+}
+
+/// The control half of a [`Device`], created by [`Device::split`].
+///
+/// Dereferences to the device, so that LED lights, the rumble motor and
+/// the open channels can be managed while another task reads the events
+/// through the [`DeviceEvents`] half. The handle is cheap to copy.
+#[derive(Clone, Copy)]
+pub struct DeviceControl<'d> {
+    device: &'d Device,
+}
+
+impl<'d> DeviceControl<'d> {
+    pub(crate) fn new(device: &'d Device) -> Self {
+        Self { device }
+    }
+
+    /// Returns the device this handle controls.
+    ///
+    /// Unlike dereferencing the handle, the returned reference
+    /// lives as long as the device is borrowed.
+    pub fn device(&self) -> &'d Device {
+        self.device
+    }
+}
+
+impl Deref for DeviceControl<'_> {
+    type Target = Device;
+
+    fn deref(&self) -> &Device {
+        self.device
+    }
+}
+
+impl fmt::Debug for DeviceControl<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeviceControl").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::split::{DeviceControl, DeviceEvents};
+
+    #[test]
+    fn halves_can_be_sent() {
+        fn assert_send<T: Send>() {}
+        assert_send::<DeviceEvents<'_>>();
+        assert_send::<DeviceControl<'_>>();
+    }
+}
@@ -0,0 +1,321 @@
+//! Keeps a connection to a device alive, reconnecting after failures.
+
+use crate::timer::sleep;
+use crate::{Address, Device, Error, MonitorBuilder, Result};
+use futures_core::Stream;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A policy that determines how long to wait between consecutive
+/// failed connection attempts.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Backoff {
+    /// The delay before the first retry.
+    pub initial: Duration,
+    /// The maximum delay between two retries.
+    pub max: Duration,
+    /// The factor by which the delay grows after every failure.
+    pub multiplier: f64,
+    /// The fraction of the delay, from 0 to 1, that is randomized.
+    ///
+    /// A random jitter keeps several processes from retrying
+    /// at the same time, e.g. after the Bluetooth adapter restarts.
+    pub jitter: f64,
+    /// The number of consecutive failures after which the supervisor
+    /// gives up, or [`None`] to retry indefinitely.
+    pub max_retries: Option<u32>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_retries: None,
+        }
+    }
+}
+
+impl Backoff {
+    /// Computes the delay after the given number of consecutive failures.
+    ///
+    /// `random` is a value in the range `[0, 1)` that determines
+    /// the fraction of the jitter to subtract from the delay.
+    pub fn delay(&self, failures: u32, random: f64) -> Duration {
+        let exp = failures.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let base = self.initial.as_secs_f64() * self.multiplier.powi(exp);
+        let base = base.min(self.max.as_secs_f64());
+        let jitter = self.jitter.clamp(0.0, 1.0) * random.clamp(0.0, 1.0);
+        // A negative multiplier or a NaN jitter must not make it panic.
+        Duration::from_secs_f64((base * (1.0 - jitter)).max(0.0))
+    }
+
+    /// Checks whether the supervisor should give up after the given
+    /// number of consecutive failures.
+    pub(crate) fn exhausted(&self, failures: u32) -> bool {
+        self.max_retries.is_some_and(|max| failures > max)
+    }
+}
+
+/// A change in the state of the connection managed by a [`Supervisor`].
+#[derive(Debug)]
+pub enum Lifecycle<'a> {
+    /// The supervisor is looking for a device.
+    Searching,
+    /// The supervisor connected to the device at the given address.
+    Connected(&'a Address),
+    /// The device at the given address disconnected gracefully.
+    Disconnected(&'a Address),
+    /// A connection attempt failed, or the connection was lost.
+    Failed {
+        /// The number of consecutive failures, starting from 1.
+        attempt: u32,
+        /// The cause of the failure.
+        error: &'a Error,
+        /// The time until the next attempt.
+        retry_in: Duration,
+    },
+    /// The supervisor exceeded the maximum number of retries.
+    GaveUp {
+        /// The cause of the last failure.
+        error: &'a Error,
+    },
+}
+
+/// A function that is notified of changes in the connection state.
+type LifecycleHandler = Box<dyn FnMut(&Lifecycle<'_>)>;
+
+/// Finds and connects to devices, and restarts the connection
+/// process after failures with an exponential [`Backoff`].
+///
+/// # Examples
+/// ```
+/// use xwiimote::supervisor::Supervisor;
+/// use xwiimote::{Channels, Monitor};
+///
+/// # let _ = async { // the supervisor runs indefinitely.
+/// let mut supervisor = Supervisor::new(Monitor::builder().discover(true))
+///     .on_event(|event| println!("{event:?}"));
+/// supervisor
+///     .run(|mut device| async move {
+///         device.open(Channels::CORE, true)?;
+///         // Process the events of the device...
+///         Ok(())
+///     })
+///     .await?;
+/// # Ok::<(), std::io::Error>(())
+/// # };
+/// ```
+pub struct Supervisor {
+    monitor: MonitorBuilder,
+    backoff: Backoff,
+    /// The time for which a discovered device may not be ready yet.
+    connect_timeout: Duration,
+    on_event: Option<LifecycleHandler>,
+    /// The state of the pseudo-random number generator for the jitter.
+    seed: u64,
+}
+
+impl Supervisor {
+    /// Creates a supervisor that finds devices with monitors
+    /// built by `monitor`.
+    ///
+    /// If discovery mode is disabled, the supervisor stops once
+    /// no connected device is found.
+    pub fn new(monitor: MonitorBuilder) -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.subsec_nanos());
+        Self {
+            monitor,
+            backoff: Backoff::default(),
+            connect_timeout: Duration::from_secs(2),
+            on_event: None,
+            // The state of the generator must be non-zero.
+            seed: u64::from(nanos) | 1,
+        }
+    }
+
+    /// Sets the policy for waiting between failed attempts.
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets the time to wait for a discovered device to become ready
+    /// before a connection attempt fails; see [`Device::connect_async`].
+    ///
+    /// Defaults to 2 seconds.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Sets a function that is called on every change in the state
+    /// of the connection.
+    pub fn on_event(mut self, f: impl FnMut(&Lifecycle<'_>) + 'static) -> Self {
+        self.on_event = Some(Box::new(f));
+        self
+    }
+
+    /// Repeatedly finds a device, connects to it and runs `handler`
+    /// until it returns.
+    ///
+    /// If the handler returns `Ok(())`, the device is considered to have
+    /// disconnected gracefully and the supervisor looks for a new device
+    /// immediately. Otherwise, and if connecting fails, the supervisor
+    /// waits according to its [`Backoff`] policy before trying again.
+    ///
+    /// # Returns
+    /// `Ok(())` if discovery mode is disabled and no connected device
+    /// is found, or the last error if the supervisor gives up.
+    ///
+    /// # Cancel safety
+    /// The future can be dropped at any time, which drops the future
+    /// returned by `handler` and disconnects from the current device.
+    /// Calling this method again starts a new search with the failure
+    /// count reset.
+    pub async fn run<F, Fut>(&mut self, mut handler: F) -> Result<()>
+    where
+        F: FnMut(Device) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let mut failures = 0;
+        loop {
+            self.emit(Lifecycle::Searching);
+            let address = match self.find_device().await {
+                Ok(Some(address)) => address,
+                Ok(None) => return Ok(()),
+                Err(err) => {
+                    failures += 1;
+                    self.fail(failures, err).await?;
+                    continue;
+                }
+            };
+
+            let result = match Device::connect_async(&address, self.connect_timeout).await {
+                Ok(device) => {
+                    failures = 0;
+                    self.emit(Lifecycle::Connected(&address));
+                    handler(device).await
+                }
+                Err(err) => Err(err),
+            };
+            match result {
+                Ok(()) => self.emit(Lifecycle::Disconnected(&address)),
+                Err(err) => {
+                    failures += 1;
+                    self.fail(failures, err).await?;
+                }
+            }
+        }
+    }
+
+    /// Finds the address of a device to connect to.
+    async fn find_device(&self) -> Result<Option<Address>> {
+        let mut monitor = self.monitor.clone().build()?;
+        poll_fn(|cx| Pin::new(&mut monitor).poll_next(cx))
+            .await
+            .transpose()
+    }
+
+    /// Handles a failure, waiting before the next attempt.
+    ///
+    /// Returns the error if the maximum number of retries is exceeded.
+    async fn fail(&mut self, failures: u32, error: Error) -> Result<()> {
+        if self.backoff.exhausted(failures) {
+            self.emit(Lifecycle::GaveUp { error: &error });
+            return Err(error);
+        }
+
+        let random = self.next_random();
+        let retry_in = self.backoff.delay(failures, random);
+        self.emit(Lifecycle::Failed {
+            attempt: failures,
+            error: &error,
+            retry_in,
+        });
+        sleep(retry_in).await
+    }
+
+    fn emit(&mut self, event: Lifecycle<'_>) {
+        if let Some(on_event) = &mut self.on_event {
+            on_event(&event);
+        }
+    }
+
+    /// Returns a pseudo-random number in the range `[0, 1)`.
+    fn next_random(&mut self) -> f64 {
+        // This is the xorshift64 generator, which is good enough for jitter.
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        (self.seed >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::supervisor::{Backoff, Supervisor};
+    use crate::Monitor;
+    use std::time::Duration;
+
+    #[test]
+    fn delay_grows_exponentially() {
+        let backoff = Backoff {
+            jitter: 0.0,
+            ..Backoff::default()
+        };
+        assert_eq!(backoff.delay(1, 0.5), Duration::from_millis(500));
+        assert_eq!(backoff.delay(2, 0.5), Duration::from_secs(1));
+        assert_eq!(backoff.delay(3, 0.5), Duration::from_secs(2));
+        assert_eq!(backoff.delay(100, 0.5), backoff.max);
+    }
+
+    #[test]
+    fn jitter_shortens_delay() {
+        let backoff = Backoff {
+            jitter: 0.5,
+            ..Backoff::default()
+        };
+        assert_eq!(backoff.delay(2, 0.0), Duration::from_secs(1));
+        assert_eq!(backoff.delay(2, 1.0), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn delay_is_never_negative() {
+        let backoff = Backoff {
+            multiplier: -2.0,
+            ..Backoff::default()
+        };
+        assert_eq!(backoff.delay(2, 0.5), Duration::ZERO);
+        let backoff = Backoff {
+            jitter: f64::NAN,
+            ..Backoff::default()
+        };
+        assert_eq!(backoff.delay(2, 0.5), Duration::ZERO);
+    }
+
+    #[test]
+    fn retries_are_bounded() {
+        let backoff = Backoff {
+            max_retries: Some(2),
+            ..Backoff::default()
+        };
+        assert!(!backoff.exhausted(2));
+        assert!(backoff.exhausted(3));
+        assert!(!Backoff::default().exhausted(u32::MAX));
+    }
+
+    #[test]
+    fn random_numbers_are_in_range() {
+        let mut supervisor = Supervisor::new(Monitor::builder());
+        for _ in 0..1000 {
+            let random = supervisor.next_random();
+            assert!((0.0..1.0).contains(&random));
+        }
+    }
+}
@@ -0,0 +1,358 @@
+//! Use of a single button as an accessibility switch.
+//!
+//! Switch-access software, such as scanning keyboards, is operated with
+//! one or two large buttons. A [`SwitchInput`] turns the presses of a
+//! chosen button into [`SwitchEvent`]s, telling short presses from long
+//! presses, and filters out the presses that users with tremors or
+//! limited motor control make unintentionally:
+//!
+//! - presses shorter than the [minimum hold time](SwitchInput::min_hold)
+//!   are ignored;
+//! - releases shorter than the [release debounce](SwitchInput::release_debounce)
+//!   are ignored, so that a shaky hold counts as a single press;
+//! - presses that start within the [lockout](SwitchInput::lockout)
+//!   after the previous press are ignored.
+//!
+//! A short press is reported once the button has stayed released for the
+//! release debounce, and a long press as soon as the button has been held
+//! for the [long press time](SwitchInput::long_press), without waiting
+//! for the release.
+//!
+//! # Examples
+//! Print the switch activations of the A button.
+//! ```no_run
+//! use futures_util::TryStreamExt;
+//! use xwiimote::events::Key;
+//! use xwiimote::switch::{switch_events, SwitchInput};
+//! use xwiimote::{Channels, Device, Monitor};
+//!
+//! # tokio_test::block_on(async {
+//! # let address = Monitor::enumerate()?.try_next().await?.unwrap();
+//! let device = Device::connect(&address)?;
+//! device.open(Channels::CORE, false)?;
+//!
+//! let mut switch = switch_events(device.events()?, SwitchInput::new(Key::A));
+//! while let Some((event, time)) = switch.try_next().await? {
+//!     println!("{time:?}: {event:?}");
+//! }
+//! # Ok::<(), xwiimote::Error>(())
+//! # }).unwrap();
+//! ```
+
+use crate::events::{Event, Key, KeyState};
+use crate::timer::Sleep;
+use crate::Result;
+use futures_core::Stream;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+
+/// An activation of a switch, reported by a [`SwitchInput`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SwitchEvent {
+    /// The button was pressed and released, typically to move to
+    /// the next item of a scanning interface.
+    Press,
+    /// The button was held down for the long press time, typically
+    /// to select the current item.
+    LongPress,
+}
+
+/// Turns the key events of a single button into [`SwitchEvent`]s.
+///
+/// The input is a state machine driven by [`SwitchInput::update`] and
+/// [`SwitchInput::poll`]; the [`switch_events`] stream drives it from
+/// the events of a device, along with the timers it needs.
+#[derive(Clone, Debug)]
+pub struct SwitchInput {
+    button: Key,
+    min_hold: Duration,
+    release_debounce: Duration,
+    long_press: Duration,
+    lockout: Duration,
+    /// The time at which the current press started, if any.
+    held_since: Option<SystemTime>,
+    /// The time at which the button was released during the current
+    /// press, if it was not pressed again since.
+    released_at: Option<SystemTime>,
+    /// Have we reported the current press as a long press already?
+    reported_long: bool,
+    /// The time before which new presses are ignored, if any.
+    locked_until: Option<SystemTime>,
+}
+
+impl SwitchInput {
+    /// The default minimum hold time.
+    pub const DEFAULT_MIN_HOLD: Duration = Duration::from_millis(50);
+    /// The default release debounce.
+    pub const DEFAULT_RELEASE_DEBOUNCE: Duration = Duration::from_millis(150);
+    /// The default long press time.
+    pub const DEFAULT_LONG_PRESS: Duration = Duration::from_millis(1000);
+    /// The default lockout after a press.
+    pub const DEFAULT_LOCKOUT: Duration = Duration::from_millis(300);
+
+    /// Creates an input that reports the presses of `button`,
+    /// with the default timings.
+    pub fn new(button: Key) -> Self {
+        Self {
+            button,
+            min_hold: Self::DEFAULT_MIN_HOLD,
+            release_debounce: Self::DEFAULT_RELEASE_DEBOUNCE,
+            long_press: Self::DEFAULT_LONG_PRESS,
+            lockout: Self::DEFAULT_LOCKOUT,
+            held_since: None,
+            released_at: None,
+            reported_long: false,
+            locked_until: None,
+        }
+    }
+
+    /// Sets the time that the button must be held for a press to count.
+    ///
+    /// Defaults to [`SwitchInput::DEFAULT_MIN_HOLD`].
+    pub fn min_hold(mut self, duration: Duration) -> Self {
+        self.min_hold = duration;
+        self
+    }
+
+    /// Sets the time that the button must stay released for a press
+    /// to end. Shorter releases are considered part of the press.
+    ///
+    /// Defaults to [`SwitchInput::DEFAULT_RELEASE_DEBOUNCE`].
+    pub fn release_debounce(mut self, duration: Duration) -> Self {
+        self.release_debounce = duration;
+        self
+    }
+
+    /// Sets the time that the button must be held for a long press.
+    ///
+    /// Defaults to [`SwitchInput::DEFAULT_LONG_PRESS`].
+    pub fn long_press(mut self, duration: Duration) -> Self {
+        self.long_press = duration;
+        self
+    }
+
+    /// Sets the time after the end of a press during which new presses
+    /// are ignored.
+    ///
+    /// Defaults to [`SwitchInput::DEFAULT_LOCKOUT`].
+    pub fn lockout(mut self, duration: Duration) -> Self {
+        self.lockout = duration;
+        self
+    }
+
+    /// Returns the button used as a switch.
+    pub fn button(&self) -> Key {
+        self.button
+    }
+
+    /// Processes an event of the device received at `time`, and returns
+    /// the switch activation that it completes, if any. Events of other
+    /// buttons are ignored.
+    pub fn update(&mut self, event: &Event, time: SystemTime) -> Option<(SwitchEvent, SystemTime)> {
+        if let Event::Key(key, state) = event {
+            if *key as u32 == self.button as u32 {
+                match state {
+                    KeyState::Down => self.press(time),
+                    KeyState::Up if self.held_since.is_some() && self.released_at.is_none() => {
+                        self.released_at = Some(time);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        self.poll(time)
+    }
+
+    /// Starts a press at `time`, or resumes the current one if the
+    /// button bounced.
+    fn press(&mut self, time: SystemTime) {
+        if self.held_since.is_some() {
+            self.released_at = None;
+            return;
+        }
+        if self.locked_until.is_some_and(|until| time < until) {
+            return;
+        }
+        self.held_since = Some(time);
+    }
+
+    /// Returns the switch activation completed at `now`, if any.
+    ///
+    /// This must be called once the time returned by
+    /// [`SwitchInput::deadline`] is reached.
+    pub fn poll(&mut self, now: SystemTime) -> Option<(SwitchEvent, SystemTime)> {
+        let since = self.held_since?;
+        match self.released_at {
+            None => {
+                let long_at = since + self.long_press;
+                if self.reported_long || now < long_at {
+                    return None;
+                }
+                self.reported_long = true;
+                Some((SwitchEvent::LongPress, long_at))
+            }
+            Some(released_at) => {
+                if now < released_at + self.release_debounce {
+                    return None;
+                }
+                // The press ended when the button was released.
+                self.held_since = None;
+                self.released_at = None;
+                self.locked_until = Some(released_at + self.lockout);
+                let held = released_at.duration_since(since).unwrap_or_default();
+                let short = !std::mem::take(&mut self.reported_long) && held >= self.min_hold;
+                short.then_some((SwitchEvent::Press, released_at))
+            }
+        }
+    }
+
+    /// Returns the time at which [`SwitchInput::poll`] may report an
+    /// activation without further events, if any.
+    pub fn deadline(&self) -> Option<SystemTime> {
+        let since = self.held_since?;
+        match self.released_at {
+            None if !self.reported_long => Some(since + self.long_press),
+            None => None,
+            Some(released_at) => Some(released_at + self.release_debounce),
+        }
+    }
+}
+
+/// Produces the switch activations of a button given the events of
+/// a device, as detected by `input`.
+pub fn switch_events<S>(events: S, input: SwitchInput) -> SwitchEvents<S>
+where
+    S: Stream<Item = Result<(Event, SystemTime)>> + Unpin,
+{
+    SwitchEvents {
+        events,
+        input,
+        timer: None,
+    }
+}
+
+/// A stream of switch activations, created by [`switch_events`].
+///
+/// The stream ends once the stream of device events ends.
+pub struct SwitchEvents<S> {
+    events: S,
+    input: SwitchInput,
+    /// Expires at the deadline of the input, if armed.
+    timer: Option<Sleep>,
+}
+
+impl<S> SwitchEvents<S> {
+    /// Returns the input that detects the activations.
+    pub fn input(&self) -> &SwitchInput {
+        &self.input
+    }
+}
+
+impl<S> Stream for SwitchEvents<S>
+where
+    S: Stream<Item = Result<(Event, SystemTime)>> + Unpin,
+{
+    type Item = Result<(SwitchEvent, SystemTime)>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match Pin::new(&mut self.events).poll_next(cx)? {
+                Poll::Ready(Some((event, time))) => {
+                    if let Some(activation) = self.input.update(&event, time) {
+                        return Poll::Ready(Some(Ok(activation)));
+                    }
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => break,
+            }
+        }
+
+        // No event is available; wake up at the deadline of the input.
+        let Some(deadline) = self.input.deadline() else {
+            self.timer = None;
+            return Poll::Pending;
+        };
+        let now = SystemTime::now();
+        if let Some(activation) = self.input.poll(now) {
+            return Poll::Ready(Some(Ok(activation)));
+        }
+        let wait = deadline.duration_since(now).unwrap_or_default();
+        let timer = match &mut self.timer {
+            Some(timer) => {
+                timer.reset(wait)?;
+                timer
+            }
+            None => self.timer.insert(Sleep::new(wait)?),
+        };
+        if Pin::new(timer).poll(cx)?.is_ready() {
+            // The deadline passed in the meantime; poll the input again.
+            cx.waker().wake_by_ref();
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::{Event, Key, KeyState};
+    use crate::switch::{SwitchEvent, SwitchInput};
+    use std::time::{Duration, SystemTime};
+
+    fn at(ms: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_millis(ms)
+    }
+
+    fn key(state: KeyState) -> Event {
+        Event::Key(Key::A, state)
+    }
+
+    #[test]
+    fn reports_press_after_debounce() {
+        let mut input = SwitchInput::new(Key::A);
+        assert_eq!(input.update(&key(KeyState::Down), at(0)), None);
+        assert_eq!(input.update(&key(KeyState::Up), at(200)), None);
+        assert_eq!(input.deadline(), Some(at(350)));
+        assert_eq!(input.poll(at(349)), None);
+        assert_eq!(input.poll(at(350)), Some((SwitchEvent::Press, at(200))));
+        assert_eq!(input.deadline(), None);
+    }
+
+    #[test]
+    fn ignores_bounces_and_brief_presses() {
+        let mut input = SwitchInput::new(Key::A);
+        // A shaky hold is a single press.
+        input.update(&key(KeyState::Down), at(0));
+        input.update(&key(KeyState::Up), at(100));
+        input.update(&key(KeyState::Down), at(180));
+        input.update(&key(KeyState::Up), at(300));
+        assert_eq!(input.poll(at(450)), Some((SwitchEvent::Press, at(300))));
+
+        // Presses during the lockout are ignored.
+        input.update(&key(KeyState::Down), at(500));
+        input.update(&key(KeyState::Up), at(580));
+        assert_eq!(input.poll(at(1000)), None);
+
+        // So are the presses of other buttons, and brief touches.
+        input.update(&Event::Key(Key::B, KeyState::Down), at(1000));
+        input.update(&key(KeyState::Down), at(1100));
+        input.update(&key(KeyState::Up), at(1120));
+        assert_eq!(input.poll(at(2000)), None);
+    }
+
+    #[test]
+    fn reports_long_press_while_held() {
+        let mut input = SwitchInput::new(Key::A).long_press(Duration::from_millis(800));
+        input.update(&key(KeyState::Down), at(0));
+        assert_eq!(input.deadline(), Some(at(800)));
+        let repeat = input.update(&key(KeyState::AutoRepeat), at(820));
+        assert_eq!(repeat, Some((SwitchEvent::LongPress, at(800))));
+        assert_eq!(input.update(&key(KeyState::AutoRepeat), at(900)), None);
+
+        // The release does not report another activation.
+        input.update(&key(KeyState::Up), at(1200));
+        assert_eq!(input.poll(at(2000)), None);
+        assert_eq!(input.deadline(), None);
+    }
+}
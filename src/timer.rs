@@ -0,0 +1,173 @@
+use crate::reactor::{Interest, Reactor};
+use crate::{bail_if, Result};
+use libc::c_int;
+use std::future::Future;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::ptr;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// A future that completes after a given duration.
+///
+/// The timer is backed by a `timerfd` descriptor, which is polled
+/// by the [`Reactor`]. Dropping the future cancels the timer.
+pub(crate) struct Sleep {
+    fd: OwnedFd,
+    /// Whether the `epoll` interest is currently registered.
+    have_interest: bool,
+}
+
+impl Sleep {
+    const EPOLL_EVENTS: c_int = libc::EPOLLIN;
+
+    /// Creates a timer that expires after `duration`.
+    pub fn new(duration: Duration) -> Result<Self> {
+        let fd = unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_NONBLOCK | libc::TFD_CLOEXEC,
+            )
+        };
+        bail_if!(fd == -1);
+        let mut sleep = Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            have_interest: false,
+        };
+        sleep.reset(duration)?;
+        Ok(sleep)
+    }
+
+    /// Rearms the timer to expire after `duration` from now.
+    pub fn reset(&mut self, duration: Duration) -> Result<()> {
+        // A zero value disarms the timer; expire as soon as possible instead.
+        let duration = duration.max(Duration::from_nanos(1));
+        let spec = libc::itimerspec {
+            it_interval: libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            it_value: libc::timespec {
+                tv_sec: duration.as_secs() as libc::time_t,
+                tv_nsec: duration.subsec_nanos() as libc::c_long,
+            },
+        };
+        let res_code =
+            unsafe { libc::timerfd_settime(self.fd.as_raw_fd(), 0, &spec, ptr::null_mut()) };
+        bail_if!(res_code == -1);
+        Ok(())
+    }
+
+    /// Checks whether the timer expired, without blocking.
+    fn expired(&self) -> Result<bool> {
+        let mut n_expirations = 0u64;
+        let res = unsafe {
+            libc::read(
+                self.fd.as_raw_fd(),
+                &mut n_expirations as *mut u64 as *mut libc::c_void,
+                std::mem::size_of::<u64>(),
+            )
+        };
+        if res == -1 {
+            let err = io::Error::last_os_error();
+            return match err.kind() {
+                io::ErrorKind::WouldBlock => Ok(false),
+                _ => Err(err.into()),
+            };
+        }
+        Ok(true)
+    }
+
+    fn interest(&self) -> Interest {
+        Interest::new(self.fd.as_raw_fd(), Self::EPOLL_EVENTS)
+    }
+}
+
+impl Future for Sleep {
+    type Output = Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.expired()? {
+            return Poll::Ready(Ok(()));
+        }
+
+        // Arrange for `wake` to be called once the timer expires.
+        Reactor::get().set_callback(self.interest(), cx.waker().clone())?;
+        if !self.have_interest {
+            Reactor::get().add_interest(&self.interest())?;
+            self.have_interest = true;
+        }
+
+        // The timer may have expired before the callback was set,
+        // in which case the reactor would not wake us up.
+        if self.expired()? {
+            return Poll::Ready(Ok(()));
+        }
+        Poll::Pending
+    }
+}
+
+impl AsRawFd for Sleep {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if self.have_interest {
+            // The descriptor is closed right after, which also removes
+            // it from the `epoll` set; ignore any error.
+            let _ = Reactor::get().remove_interest(&self.interest());
+        }
+    }
+}
+
+/// Waits until `duration` elapses.
+///
+/// The future is cancel safe; dropping it disarms the timer.
+pub(crate) async fn sleep(duration: Duration) -> Result<()> {
+    Sleep::new(duration)?.await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reactor::Reactor;
+    use crate::timer::{sleep, Sleep};
+    use crate::Result;
+    use std::future::Future;
+    use std::os::fd::AsRawFd;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn sleep_waits() -> Result<()> {
+        let start = Instant::now();
+        futures_executor::block_on(sleep(Duration::from_millis(30)))?;
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        Ok(())
+    }
+
+    #[test]
+    fn zero_sleep_completes() -> Result<()> {
+        futures_executor::block_on(sleep(Duration::ZERO))
+    }
+
+    #[test]
+    fn dropping_pending_sleep_removes_waker() -> Result<()> {
+        let mut cx = Context::from_waker(Waker::noop());
+        for n_polls in 0..3 {
+            let mut sleep = Sleep::new(Duration::from_secs(60))?;
+            let fd = sleep.fd.as_raw_fd();
+            for _ in 0..n_polls {
+                assert!(matches!(pin!(&mut sleep).poll(&mut cx), Poll::Pending));
+            }
+            assert_eq!(Reactor::get().has_waker(fd), n_polls > 0);
+            drop(sleep);
+            assert!(!Reactor::get().has_waker(fd));
+        }
+        Ok(())
+    }
+}
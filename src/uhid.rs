@@ -0,0 +1,541 @@
+//! Software Wii Remotes backed by the `uhid` kernel module.
+//!
+//! A [`VirtualRemote`] registers a Bluetooth HID device that looks like
+//! a Wii Remote to the kernel, so the `hid-wiimote` driver binds to it
+//! and the `xwiimote` library can connect to it like to a real device.
+//! The remote answers the requests of the driver and reports the keys
+//! pressed through [`VirtualRemote::press`] and [`VirtualRemote::release`].
+//!
+//! This lets contributors without hardware run the integration tests,
+//! which are ignored by default:
+//! ```sh
+//! cargo test --features uhid -- --ignored
+//! ```
+//! The tests require read and write access to `/dev/uhid`, and
+//! the `uhid` and `hid-wiimote` kernel modules to be loaded.
+//!
+//! Only the core buttons are emulated: the remote reports no extension,
+//! no accelerometer or IR data, and no Motion Plus.
+
+use crate::events::Key;
+use crate::{bail_if, Result};
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+
+// Definitions from `linux/uhid.h`, which the `libc` crate lacks.
+// All the event structures are packed and use the native byte order.
+
+const UHID_DESTROY: u32 = 1;
+const UHID_OUTPUT: u32 = 6;
+const UHID_GET_REPORT: u32 = 9;
+const UHID_GET_REPORT_REPLY: u32 = 10;
+const UHID_CREATE2: u32 = 11;
+const UHID_INPUT2: u32 = 12;
+const UHID_SET_REPORT: u32 = 13;
+const UHID_SET_REPORT_REPLY: u32 = 14;
+
+/// The maximum size of a report.
+const UHID_DATA_MAX: usize = 4096;
+
+/// The size of `struct uhid_event`, whose largest member is
+/// `struct uhid_create2_req`.
+const UHID_EVENT_LEN: usize = 4 + 128 + 64 + 64 + 2 + 2 + 4 * 4 + UHID_DATA_MAX;
+
+const BUS_BLUETOOTH: u16 = 5;
+const NINTENDO_VENDOR_ID: u32 = 0x057e;
+const WIIMOTE_PRODUCT_ID: u32 = 0x0306;
+
+// Wii Remote report identifiers.
+
+const REPORT_RUMBLE: u8 = 0x10;
+const REPORT_LEDS: u8 = 0x11;
+const REPORT_STATUS_REQUEST: u8 = 0x15;
+const REPORT_WRITE_MEMORY: u8 = 0x16;
+const REPORT_READ_MEMORY: u8 = 0x17;
+const REPORT_STATUS: u8 = 0x20;
+const REPORT_READ_DATA: u8 = 0x21;
+const REPORT_ACK: u8 = 0x22;
+const REPORT_KEYS: u8 = 0x30;
+
+/// The identifiers and payload lengths of the output reports.
+const OUTPUT_REPORTS: [(u8, u8); 11] = [
+    (0x10, 1),
+    (0x11, 1),
+    (0x12, 2),
+    (0x13, 1),
+    (0x14, 1),
+    (0x15, 1),
+    (0x16, 21),
+    (0x17, 6),
+    (0x18, 21),
+    (0x19, 1),
+    (0x1a, 1),
+];
+
+/// The identifiers and payload lengths of the input reports.
+const INPUT_REPORTS: [(u8, u8); 13] = [
+    (0x20, 6),
+    (0x21, 21),
+    (0x22, 4),
+    (0x30, 2),
+    (0x31, 5),
+    (0x32, 10),
+    (0x33, 17),
+    (0x34, 21),
+    (0x35, 21),
+    (0x36, 21),
+    (0x37, 21),
+    (0x3d, 21),
+    (0x3e, 21),
+];
+
+/// Builds the HID report descriptor of a Wii Remote, which declares
+/// every report as an array of vendor-defined bytes.
+fn report_descriptor() -> Vec<u8> {
+    let mut desc = vec![
+        0x05, 0x01, // Usage Page (Generic Desktop)
+        0x09, 0x05, // Usage (Game Pad)
+        0xa1, 0x01, // Collection (Application)
+        0x15, 0x00, // Logical Minimum (0)
+        0x26, 0xff, 0x00, // Logical Maximum (255)
+        0x75, 0x08, // Report Size (8)
+        0x06, 0x00, 0xff, // Usage Page (Vendor Defined)
+    ];
+    let reports = OUTPUT_REPORTS.iter().map(|&(id, len)| (id, len, 0x91));
+    let reports = reports.chain(INPUT_REPORTS.iter().map(|&(id, len)| (id, len, 0x81)));
+    for (id, len, kind) in reports {
+        desc.extend_from_slice(&[
+            0x85, id, // Report ID
+            0x95, len, // Report Count
+            0x09, 0x01, // Usage (Vendor Usage 1)
+            kind, 0x00, // Output or Input (Data, Array, Absolute)
+        ]);
+    }
+    desc.push(0xc0); // End Collection
+    desc
+}
+
+/// The bit of a key in the button bytes of the input reports.
+fn key_mask(key: Key) -> u16 {
+    match key {
+        Key::Left => 0x0100,
+        Key::Right => 0x0200,
+        Key::Down => 0x0400,
+        Key::Up => 0x0800,
+        Key::Plus => 0x1000,
+        Key::Two => 0x0001,
+        Key::One => 0x0002,
+        Key::B => 0x0004,
+        Key::A => 0x0008,
+        Key::Minus => 0x0010,
+        Key::Home => 0x0080,
+    }
+}
+
+/// Options for creating a [`VirtualRemote`].
+#[derive(Clone, Debug)]
+pub struct VirtualRemoteOptions {
+    /// The Bluetooth address reported as the unique identifier
+    /// of the device, which becomes its [`StableId`](crate::StableId).
+    pub uniq: String,
+    /// The initial battery level, as a percentage from 0 to 100%.
+    pub battery: u8,
+}
+
+impl Default for VirtualRemoteOptions {
+    fn default() -> Self {
+        Self {
+            uniq: "00:1f:32:00:00:01".into(),
+            battery: 100,
+        }
+    }
+}
+
+/// The state of a virtual remote, shared with its request handler.
+#[derive(Debug, Default)]
+struct State {
+    /// The pressed keys, in the format of the input reports.
+    keys: u16,
+    leds: [bool; 4],
+    rumble: bool,
+    battery: u8,
+}
+
+/// A software Wii Remote, registered through the `uhid` kernel module.
+///
+/// A background thread answers the requests that the kernel driver sends
+/// to the remote. The device is removed when the remote is dropped.
+///
+/// # Examples
+/// ```no_run
+/// use futures_util::TryStreamExt;
+/// use xwiimote::events::{Event, Key};
+/// use xwiimote::uhid::VirtualRemote;
+/// use xwiimote::{Channels, Device, Monitor};
+///
+/// # tokio_test::block_on(async {
+/// let mut remote = VirtualRemote::create(Default::default())?;
+/// let address = Monitor::discover()?
+///     .resolve(&remote.stable_id())
+///     .await?
+///     .unwrap();
+/// let device = Device::connect(&address)?;
+/// device.open(Channels::CORE, false)?;
+///
+/// remote.press(Key::A)?;
+/// let (event, _) = device.events()?.try_next().await?.unwrap();
+/// assert!(matches!(event, Event::Key(Key::A, _)));
+/// # Ok::<(), std::io::Error>(())
+/// # }).unwrap();
+/// ```
+pub struct VirtualRemote {
+    uhid: Arc<File>,
+    uniq: String,
+    state: Arc<Mutex<State>>,
+    /// Tells the request handler to stop.
+    stop: Arc<AtomicBool>,
+    /// Wakes the request handler up when set to stop.
+    stop_fd: Arc<OwnedFd>,
+    handler: Option<JoinHandle<()>>,
+}
+
+impl VirtualRemote {
+    /// The name of the device, which the driver expects.
+    const NAME: &'static str = "Nintendo RVL-CNT-01";
+
+    /// Registers a virtual remote with the kernel.
+    pub fn create(options: VirtualRemoteOptions) -> Result<Self> {
+        let uhid = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_CLOEXEC)
+            .open("/dev/uhid")?;
+
+        let mut event = [0u8; UHID_EVENT_LEN];
+        event[..4].copy_from_slice(&UHID_CREATE2.to_ne_bytes());
+        let mut pos = 4;
+        for (field, len) in [(Self::NAME, 128), ("", 64), (options.uniq.as_str(), 64)] {
+            let bytes = CString::new(field)?.into_bytes_with_nul();
+            if bytes.len() > len {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the unique identifier is too long",
+                )
+                .into());
+            }
+            event[pos..pos + bytes.len()].copy_from_slice(&bytes);
+            pos += len;
+        }
+        let desc = report_descriptor();
+        event[pos..pos + 2].copy_from_slice(&(desc.len() as u16).to_ne_bytes());
+        event[pos + 2..pos + 4].copy_from_slice(&BUS_BLUETOOTH.to_ne_bytes());
+        event[pos + 4..pos + 8].copy_from_slice(&NINTENDO_VENDOR_ID.to_ne_bytes());
+        event[pos + 8..pos + 12].copy_from_slice(&WIIMOTE_PRODUCT_ID.to_ne_bytes());
+        // The version and country fields stay zero.
+        pos += 20;
+        event[pos..pos + desc.len()].copy_from_slice(&desc);
+        (&uhid).write_all(&event)?;
+
+        let stop_fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        bail_if!(stop_fd == -1);
+        let mut remote = Self {
+            uhid: Arc::new(uhid),
+            uniq: options.uniq,
+            state: Arc::new(Mutex::new(State {
+                battery: options.battery.min(100),
+                ..Default::default()
+            })),
+            stop: Arc::default(),
+            stop_fd: Arc::new(unsafe { OwnedFd::from_raw_fd(stop_fd) }),
+            handler: None,
+        };
+        let handler = Handler {
+            uhid: Arc::clone(&remote.uhid),
+            state: Arc::clone(&remote.state),
+            stop: Arc::clone(&remote.stop),
+            stop_fd: Arc::clone(&remote.stop_fd),
+        };
+        remote.handler = Some(thread::spawn(move || handler.run()));
+        Ok(remote)
+    }
+
+    /// Returns the identifier under which the remote is found.
+    pub fn stable_id(&self) -> crate::StableId {
+        self.uniq.parse().expect("invalid unique identifier")
+    }
+
+    /// Presses a key, and reports it to the kernel.
+    pub fn press(&mut self, key: Key) -> Result<()> {
+        let keys = {
+            let mut state = lock(&self.state);
+            state.keys |= key_mask(key);
+            state.keys
+        };
+        send_keys(&self.uhid, keys)
+    }
+
+    /// Releases a key, and reports it to the kernel.
+    pub fn release(&mut self, key: Key) -> Result<()> {
+        let keys = {
+            let mut state = lock(&self.state);
+            state.keys &= !key_mask(key);
+            state.keys
+        };
+        send_keys(&self.uhid, keys)
+    }
+
+    /// Changes the battery level, as a percentage from 0 to 100%.
+    /// The new level is reported the next time the driver asks for it.
+    pub fn set_battery(&mut self, level: u8) {
+        lock(&self.state).battery = level.min(100);
+    }
+
+    /// Returns the state of each LED light, from left to right,
+    /// as last set by the driver.
+    pub fn leds(&self) -> [bool; 4] {
+        lock(&self.state).leds
+    }
+
+    /// Checks whether the driver turned the rumble motor on.
+    pub fn rumble(&self) -> bool {
+        lock(&self.state).rumble
+    }
+}
+
+impl Drop for VirtualRemote {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        let one = 1u64.to_ne_bytes();
+        unsafe { libc::write(self.stop_fd.as_raw_fd(), one.as_ptr().cast(), one.len()) };
+        if let Some(handler) = self.handler.take() {
+            let _ = handler.join();
+        }
+        let mut event = [0u8; UHID_EVENT_LEN];
+        event[..4].copy_from_slice(&UHID_DESTROY.to_ne_bytes());
+        // Closing the file also destroys the device.
+        let _ = (&*self.uhid).write_all(&event);
+    }
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Sends an input report to the kernel.
+fn send_report(uhid: &File, report: &[u8]) -> Result<()> {
+    let mut event = [0u8; UHID_EVENT_LEN];
+    event[..4].copy_from_slice(&UHID_INPUT2.to_ne_bytes());
+    event[4..6].copy_from_slice(&(report.len() as u16).to_ne_bytes());
+    event[6..6 + report.len()].copy_from_slice(report);
+    (&*uhid).write_all(&event)?;
+    Ok(())
+}
+
+fn send_keys(uhid: &File, keys: u16) -> Result<()> {
+    let [high, low] = keys.to_be_bytes();
+    send_report(uhid, &[REPORT_KEYS, high, low])
+}
+
+/// Answers the requests of the kernel driver.
+struct Handler {
+    uhid: Arc<File>,
+    state: Arc<Mutex<State>>,
+    stop: Arc<AtomicBool>,
+    stop_fd: Arc<OwnedFd>,
+}
+
+impl Handler {
+    fn run(self) {
+        let mut event = [0u8; UHID_EVENT_LEN];
+        while !self.stop.load(Ordering::Acquire) {
+            let mut fds = [
+                libc::pollfd {
+                    fd: self.uhid.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+                libc::pollfd {
+                    fd: self.stop_fd.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+            ];
+            if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } == -1 {
+                if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return;
+            }
+            if fds[0].revents & libc::POLLIN == 0 {
+                continue;
+            }
+            match (&*self.uhid).read(&mut event) {
+                // Errors only occur if the device is gone.
+                Ok(len) if len >= 4 => {
+                    if self.handle(&event).is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => return,
+            }
+        }
+    }
+
+    fn handle(&self, event: &[u8; UHID_EVENT_LEN]) -> Result<()> {
+        let field = |pos: usize, len: usize| &event[pos..pos + len];
+        match u32::from_ne_bytes(field(0, 4).try_into().unwrap()) {
+            UHID_OUTPUT => {
+                let size = u16::from_ne_bytes(field(4 + UHID_DATA_MAX, 2).try_into().unwrap());
+                self.handle_output(field(4, (size as usize).min(UHID_DATA_MAX)))
+            }
+            UHID_SET_REPORT => {
+                // Some transports send the output reports this way.
+                let size = u16::from_ne_bytes(field(10, 2).try_into().unwrap()) as usize;
+                self.handle_output(field(12, size.min(UHID_DATA_MAX)))?;
+                let mut reply = [0u8; UHID_EVENT_LEN];
+                reply[..4].copy_from_slice(&UHID_SET_REPORT_REPLY.to_ne_bytes());
+                reply[4..8].copy_from_slice(field(4, 4)); // request ID
+                (&*self.uhid).write_all(&reply)?;
+                Ok(())
+            }
+            UHID_GET_REPORT => {
+                let mut reply = [0u8; UHID_EVENT_LEN];
+                reply[..4].copy_from_slice(&UHID_GET_REPORT_REPLY.to_ne_bytes());
+                reply[4..8].copy_from_slice(field(4, 4)); // request ID
+                reply[8..10].copy_from_slice(&(libc::EIO as u16).to_ne_bytes());
+                (&*self.uhid).write_all(&reply)?;
+                Ok(())
+            }
+            // The start, stop, open and close notifications need no answer.
+            _ => Ok(()),
+        }
+    }
+
+    fn handle_output(&self, report: &[u8]) -> Result<()> {
+        let Some((&id, payload)) = report.split_first() else {
+            return Ok(());
+        };
+        let mut state = lock(&self.state);
+        // Every output report carries the rumble flag in its first bit.
+        if let Some(flags) = payload.first() {
+            state.rumble = flags & 0x01 != 0;
+        }
+        let [high, low] = state.keys.to_be_bytes();
+        match id {
+            REPORT_RUMBLE => Ok(()),
+            REPORT_LEDS => {
+                let flags = payload.first().copied().unwrap_or_default();
+                for (ix, led) in state.leds.iter_mut().enumerate() {
+                    *led = flags & (0x10 << ix) != 0;
+                }
+                Ok(())
+            }
+            REPORT_STATUS_REQUEST => {
+                let leds = state.leds.iter().enumerate();
+                let flags = leds.fold(0, |flags, (ix, &on)| flags | ((on as u8) << (4 + ix)));
+                // The battery level is reported on a scale from 0 to 255.
+                let battery = (state.battery as u16 * 255 / 100) as u8;
+                send_report(
+                    &self.uhid,
+                    &[REPORT_STATUS, high, low, flags, 0, 0, battery],
+                )
+            }
+            REPORT_WRITE_MEMORY => {
+                send_report(&self.uhid, &[REPORT_ACK, high, low, REPORT_WRITE_MEMORY, 0])
+            }
+            REPORT_READ_MEMORY if payload.len() >= 4 => {
+                // No memory is mapped: neither extensions nor calibration
+                // data exist. Reply with the "nonexistent address" error.
+                let mut reply = [0u8; 22];
+                reply[..6].copy_from_slice(&[
+                    REPORT_READ_DATA,
+                    high,
+                    low,
+                    0x08,
+                    payload[2],
+                    payload[3],
+                ]);
+                send_report(&self.uhid, &reply)
+            }
+            // The report mode, IR camera and speaker settings are ignored.
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::{Event, Key, KeyState};
+    use crate::uhid::{report_descriptor, VirtualRemote};
+    use crate::{Channels, Device, Leds, Monitor, Result};
+    use futures_core::Stream;
+    use std::future;
+    use std::pin::Pin;
+    use std::time::Duration;
+
+    #[test]
+    fn describes_every_report() {
+        let desc = report_descriptor();
+        assert_eq!(desc.last(), Some(&0xc0));
+        let n_reports = desc.windows(2).filter(|pair| pair[0] == 0x85).count();
+        assert_eq!(n_reports, 24);
+    }
+
+    #[test]
+    #[ignore = "requires access to /dev/uhid and the hid-wiimote driver"]
+    fn emulates_a_remote() -> Result<()> {
+        futures_executor::block_on(async {
+            let mut remote = VirtualRemote::create(Default::default())?;
+            let address = Monitor::discover()?
+                .resolve(&remote.stable_id())
+                .await?
+                .expect("the virtual remote was not found");
+            let device = Device::connect(&address)?;
+            device.open(Channels::CORE, true)?;
+            device.set_led(crate::Led::Two, true)?;
+            assert_eq!(device.battery()?, 100);
+            let info = device.info()?;
+            assert_eq!(info.battery, Some(100));
+            assert!(info.leds.contains(Leds::TWO));
+            assert!(info.open_channels.contains(Channels::CORE));
+
+            remote.press(Key::A)?;
+            let mut events = device.events()?;
+            let next = future::poll_fn(|cx| Pin::new(&mut events).poll_next(cx));
+            let (event, _) = next.await.unwrap()?;
+            assert!(matches!(event, Event::Key(Key::A, KeyState::Down)));
+            assert!(remote.leds()[1]);
+            Ok(())
+        })
+    }
+
+    #[test]
+    #[ignore = "requires access to /dev/uhid and the hid-wiimote driver"]
+    fn opens_channels_once_available() -> Result<()> {
+        futures_executor::block_on(async {
+            let remote = VirtualRemote::create(Default::default())?;
+            let address = Monitor::discover()?
+                .resolve(&remote.stable_id())
+                .await?
+                .expect("the virtual remote was not found");
+            // The handle is created as soon as the core interface exists,
+            // likely before the kernel sets up the accelerometer.
+            let device = Device::connect_ready(&address, Duration::from_secs(2), true).await?;
+            let channels = Channels::CORE | Channels::ACCELEROMETER;
+            device
+                .open_when_available(channels, false, Duration::from_secs(2))
+                .await?;
+            assert!(device.available().contains(channels));
+            assert!(device.get_open().contains(channels));
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,293 @@
+use crate::keyboard::KeyMap;
+use crate::pointer::Pointer;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use xwiimote::bridge::uinput;
+use xwiimote::events::Key;
+
+/// The formats to which a key map can be exported.
+#[derive(Copy, Clone, Debug, clap::ValueEnum)]
+pub enum Format {
+    /// An `xbindkeys` configuration that runs `ydotool` to press the keys.
+    Xbindkeys,
+    /// A `keyd` configuration that remaps the keys of the Wii Remote.
+    Keyd,
+    /// A table of SDL scancodes, e.g. for the input settings of a game.
+    Sdl,
+}
+
+/// The buttons of a Wii Remote, by their names in `wminput` configuration
+/// files, together with the Linux input event codes that the `hid-wiimote`
+/// kernel driver reports for them.
+const BUTTONS: [(Key, &str, u16, &str); 11] = [
+    (Key::Up, "Up", 103, "KEY_UP"),
+    (Key::Down, "Down", 108, "KEY_DOWN"),
+    (Key::Left, "Left", 105, "KEY_LEFT"),
+    (Key::Right, "Right", 106, "KEY_RIGHT"),
+    (Key::A, "A", 0x130, "BTN_A"),
+    (Key::B, "B", 0x131, "BTN_B"),
+    (Key::Plus, "Plus", 0x197, "KEY_NEXT"),
+    (Key::Minus, "Minus", 0x19c, "KEY_PREVIOUS"),
+    (Key::Home, "Home", 0x13c, "BTN_MODE"),
+    (Key::One, "1", 0x101, "BTN_1"),
+    (Key::Two, "2", 0x102, "BTN_2"),
+];
+
+/// Finds the entry of `button` in [`BUTTONS`].
+fn lookup(button: Key) -> (&'static str, u16, &'static str) {
+    let (_, name, code, code_name) = BUTTONS
+        .iter()
+        .find(|(other, ..)| *other as u32 == button as u32)
+        .unwrap();
+    (name, *code, code_name)
+}
+
+/// Converts `map` into a configuration snippet in the given format.
+pub fn export(map: &KeyMap, format: Format) -> String {
+    let mut out = String::new();
+    match format {
+        Format::Xbindkeys => {
+            for (button, key) in map.iter() {
+                let (name, code, code_name) = lookup(button);
+                // X11 numbers the keys from 8, up to a keycode of 255.
+                let res = if code > 247 {
+                    writeln!(
+                        out,
+                        "# The {name} button ({code_name}) is not visible to X11.\n"
+                    )
+                } else {
+                    writeln!(
+                        out,
+                        "# Wii Remote {name}\n\"ydotool key {key}:1 {key}:0\"\n    c:{}\n",
+                        code + 8
+                    )
+                };
+                res.unwrap();
+            }
+        }
+        Format::Keyd => {
+            out.push_str("[ids]\n057e:0306\n057e:0330\n\n[main]\n");
+            for (button, key) in map.iter() {
+                let (_, _, code_name) = lookup(button);
+                let source = code_name.strip_prefix("KEY_").unwrap_or(code_name);
+                let target = match key {
+                    uinput::BTN_LEFT => "leftmouse",
+                    uinput::BTN_RIGHT => "rightmouse",
+                    uinput::BTN_MIDDLE => "middlemouse",
+                    key => uinput::key_name(key).unwrap_or("noop"),
+                };
+                writeln!(
+                    out,
+                    "{} = {}",
+                    source.to_ascii_lowercase(),
+                    target.to_ascii_lowercase()
+                )
+                .unwrap();
+            }
+        }
+        Format::Sdl => {
+            out.push_str("/* Wii Remote button, SDL scancode */\n");
+            for (button, key) in map.iter() {
+                let (name, _, _) = lookup(button);
+                let res = match uinput::key_name(key) {
+                    Some(key) => {
+                        let scancode = sdl_scancode(key);
+                        writeln!(out, "{{ \"{name}\", SDL_SCANCODE_{scancode} }},")
+                    }
+                    None => writeln!(out, "/* The {name} button is mapped to a mouse button. */"),
+                };
+                res.unwrap();
+            }
+        }
+    }
+    out
+}
+
+/// Converts the name of a Linux key code into the name of the SDL
+/// scancode of the same key, which is the same for most keys.
+fn sdl_scancode(name: &str) -> &str {
+    match name {
+        "ENTER" => "RETURN",
+        "ESC" => "ESCAPE",
+        "PLAYPAUSE" => "AUDIOPLAY",
+        "NEXTSONG" => "AUDIONEXT",
+        "PREVIOUSSONG" => "AUDIOPREV",
+        "STOPCD" => "AUDIOSTOP",
+        "CALC" => "CALCULATOR",
+        "HOMEPAGE" => "AC_HOME",
+        "SEARCH" => "AC_SEARCH",
+        other => other,
+    }
+}
+
+/// The directories in which `wminput` looks for configuration files,
+/// after the directory of the including file. A leading `~` stands for
+/// the home directory of the user.
+const WMINPUT_DIRS: [&str; 2] = ["~/.cwiid/wminput", "/etc/cwiid/wminput"];
+
+/// The maximum nesting depth of `include` directives.
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// The settings of a `wminput` configuration file that wiinote supports.
+#[derive(Debug)]
+pub struct WminputConfig {
+    /// The mappings of the Wii Remote buttons to keyboard keys
+    /// and mouse buttons.
+    pub keys: KeyMap,
+    /// The sensor that moves the mouse pointer, if any.
+    pub pointer: Option<Pointer>,
+}
+
+impl WminputConfig {
+    /// Reads the configuration file at `path`, including the files
+    /// named by its `include` directives.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut config = Self {
+            keys: KeyMap::new(),
+            pointer: None,
+        };
+        config.read(path, 0)?;
+        Ok(config)
+    }
+
+    /// Reads the settings of a single configuration file.
+    fn read(&mut self, path: &Path, depth: usize) -> Result<(), String> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(format!("{}: too many nested includes", path.display()));
+        }
+        let input = fs::read_to_string(path)
+            .map_err(|err| format!("cannot read {}: {err}", path.display()))?;
+        for (ix, line) in input.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if let Some(name) = line.strip_prefix("include") {
+                let include = find_include(path, name.trim()).ok_or_else(|| {
+                    format!(
+                        "{}:{}: cannot find `{}`",
+                        path.display(),
+                        ix + 1,
+                        name.trim()
+                    )
+                })?;
+                self.read(&include, depth + 1)?;
+            } else if let Some((name, value)) = line.split_once('=') {
+                let skipped = self
+                    .set(name.trim(), value.trim())
+                    .map_err(|err| format!("{}:{}: {err}", path.display(), ix + 1))?;
+                if let Some(warning) = skipped {
+                    eprintln!("{}:{}: {warning}", path.display(), ix + 1);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies a single setting, of the form `name = value`.
+    ///
+    /// The settings of the extensions and of the plugins other than
+    /// the IR and accelerometer pointers are ignored. A button mapped to
+    /// a key that wiinote cannot emit is left unmapped, and the returned
+    /// warning explains why.
+    fn set(&mut self, name: &str, value: &str) -> Result<Option<String>, String> {
+        // A leading `~` inverts an axis, which does not matter here.
+        let value = value.trim_start_matches('~');
+        match name.split('.').collect::<Vec<_>>()[..] {
+            ["Wiimote", "Dpad", _] => {} // the D-pad keys are mapped one by one.
+            ["Wiimote", button] => {
+                let (button, ..) = BUTTONS
+                    .iter()
+                    .find(|(_, other, ..)| other.eq_ignore_ascii_case(button))
+                    .ok_or_else(|| format!("unknown Wii Remote button `{button}`"))?;
+                match output_code(value) {
+                    Some(code) => self.keys.set(*button, code),
+                    None => return Ok(Some(format!("ignoring unsupported key `{value}`"))),
+                }
+            }
+            ["Plugin", "ir_ptr", "X" | "Y"] if value.starts_with("ABS_") => {
+                self.pointer = Some(Pointer::Ir);
+            }
+            ["Plugin", "acc", "X" | "Y"] if value.starts_with("REL_") => {
+                self.pointer = Some(Pointer::Tilt);
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+}
+
+/// Finds the file named by an `include` directive in `path`.
+fn find_include(path: &Path, name: &str) -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let dirs =
+        path.parent()
+            .map(Path::to_path_buf)
+            .into_iter()
+            .chain(
+                WMINPUT_DIRS
+                    .iter()
+                    .filter_map(|dir| match dir.strip_prefix("~/") {
+                        Some(rest) => home.as_ref().map(|home| home.join(rest)),
+                        None => Some(PathBuf::from(dir)),
+                    }),
+            );
+    dirs.map(|dir| dir.join(name)).find(|path| path.is_file())
+}
+
+/// Finds the code of a keyboard key or mouse button by its Linux input
+/// event code name.
+fn output_code(name: &str) -> Option<u16> {
+    match name {
+        "BTN_LEFT" => Some(uinput::BTN_LEFT),
+        "BTN_RIGHT" => Some(uinput::BTN_RIGHT),
+        "BTN_MIDDLE" => Some(uinput::BTN_MIDDLE),
+        _ => uinput::key_code(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::formats::WminputConfig;
+    use crate::keyboard::KeyMap;
+    use crate::pointer::Pointer;
+    use xwiimote::bridge::uinput;
+    use xwiimote::events::Key;
+
+    fn config() -> WminputConfig {
+        WminputConfig {
+            keys: KeyMap::new(),
+            pointer: None,
+        }
+    }
+
+    #[test]
+    fn maps_buttons_to_keys() {
+        let mut config = config();
+        assert_eq!(config.set("Wiimote.A", "BTN_LEFT"), Ok(None));
+        assert_eq!(config.set("Wiimote.1", "KEY_BACK"), Ok(None));
+        assert_eq!(config.set("Wiimote.Dpad.X", "ABS_X"), Ok(None));
+        let keys: Vec<_> = config.keys.iter().map(|(b, k)| (b as u32, k)).collect();
+        assert!(keys.contains(&(Key::A as u32, uinput::BTN_LEFT)));
+        assert!(keys.contains(&(Key::One as u32, 158)));
+
+        assert!(config.set("Wiimote.C", "KEY_A").is_err());
+    }
+
+    #[test]
+    fn skips_unsupported_keys() {
+        let mut config = config();
+        let warning = config.set("Wiimote.B", "KEY_PROG1").unwrap();
+        assert!(warning.unwrap().contains("KEY_PROG1"));
+        assert_eq!(config.keys.iter().count(), 0);
+    }
+
+    #[test]
+    fn detects_pointer_plugins() {
+        let mut config = config();
+        config.set("Plugin.ir_ptr.X", "ABS_X").unwrap();
+        assert!(matches!(config.pointer, Some(Pointer::Ir)));
+        config.set("Plugin.acc.Y", "~REL_Y").unwrap();
+        assert!(matches!(config.pointer, Some(Pointer::Tilt)));
+        config.set("Plugin.nunchuk_acc.X", "REL_X").unwrap();
+        assert!(matches!(config.pointer, Some(Pointer::Tilt)));
+    }
+}
@@ -0,0 +1,71 @@
+use std::time::{Duration, Instant};
+use zbus::Connection;
+
+/// The well-known name, object path and interface of the freedesktop.org
+/// screensaver service, implemented by most desktop environments.
+const SERVICE: &str = "org.freedesktop.ScreenSaver";
+const PATH: &str = "/org/freedesktop/ScreenSaver";
+
+/// The time without events after which the screensaver is allowed
+/// to start again.
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Keeps the desktop screensaver from starting while a Wii Remote
+/// is in use.
+pub struct Inhibitor {
+    /// The connection to the session bus.
+    conn: Connection,
+    /// The identifier of the active inhibition request, if any.
+    cookie: Option<u32>,
+    /// The time of the last user activity.
+    last_activity: Instant,
+}
+
+impl Inhibitor {
+    /// Connects to the session bus.
+    pub async fn new() -> zbus::Result<Self> {
+        Ok(Self {
+            conn: Connection::session().await?,
+            cookie: None,
+            last_activity: Instant::now(),
+        })
+    }
+
+    /// Records user activity, inhibiting the screensaver if needed.
+    pub async fn activity(&mut self) -> zbus::Result<()> {
+        self.last_activity = Instant::now();
+        if self.cookie.is_none() {
+            let reply = self
+                .conn
+                .call_method(
+                    Some(SERVICE),
+                    PATH,
+                    Some(SERVICE),
+                    "Inhibit",
+                    &("wiinote", "A Wii Remote is in use"),
+                )
+                .await?;
+            self.cookie = Some(reply.body::<u32>()?);
+        }
+        Ok(())
+    }
+
+    /// Lets the screensaver start if no activity was recorded
+    /// during the last [`IDLE_TIMEOUT`].
+    pub async fn release_if_idle(&mut self) -> zbus::Result<()> {
+        if self.last_activity.elapsed() >= IDLE_TIMEOUT {
+            self.release().await?;
+        }
+        Ok(())
+    }
+
+    /// Lets the screensaver start, e.g. once the remote disconnects.
+    pub async fn release(&mut self) -> zbus::Result<()> {
+        if let Some(cookie) = self.cookie.take() {
+            self.conn
+                .call_method(Some(SERVICE), PATH, Some(SERVICE), "UnInhibit", &(cookie,))
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -1,79 +1,370 @@
-use std::error::Error;
-use std::io;
-use std::io::ErrorKind;
-use uinput_tokio::event;
-use uinput_tokio::event::keyboard;
+use std::ops::RangeInclusive;
+use std::time::{Duration, SystemTime};
+use xwiimote::bridge::sink::{Backend, InputSink};
+use xwiimote::bridge::uinput::{self, KeyMacro, MacroStep, VirtualDevice, REL_WHEEL, REL_X, REL_Y};
 use xwiimote::events::{Key, KeyState};
-
-/// A result that may contain a `uinput` error value.
-type UInputResult<T> = std::result::Result<T, Box<dyn Error>>;
+use xwiimote::Result;
 
 /// The virtual device name to use for all events
 /// originating from this application.
 static DEV_NAME: &str = "Wiinote";
 
+/// The number of taps per second of a [`Repeat::Tap`] mode given
+/// without a rate.
+const DEFAULT_TAP_RATE: f32 = 10.0;
+
+/// The numbers of taps per second that a [`Repeat::Tap`] mode accepts.
+const TAP_RATES: RangeInclusive<f32> = 0.1..=100.0;
+
+/// How a keyboard key behaves while the Wii Remote button mapped to it
+/// is held down, which the device reports through repeated
+/// [`KeyState::AutoRepeat`] events.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum Repeat {
+    /// Keep the key pressed until the button is released, leaving any
+    /// repetition to the application that receives the key.
+    #[default]
+    Hold,
+    /// Keep the key pressed, and forward every repetition reported by
+    /// the device as a key repeat event.
+    Forward,
+    /// Press and release the key once when the button is pressed,
+    /// however long it is held.
+    Suppress,
+    /// Press and release the key when the button is pressed, and again
+    /// at the given rate, in taps per second, while it is held.
+    Tap(f32),
+}
+
+/// Associates Wii Remote keys with keyboard key codes or macros, and
+/// with the behavior of the keys while the buttons are held down.
+#[derive(Debug, Clone)]
+pub struct KeyMap {
+    keys: Vec<(Key, u16)>,
+    /// The repeat modes other than [`Repeat::Hold`].
+    repeats: Vec<(Key, Repeat)>,
+    /// The macros played when the buttons are pressed.
+    macros: Vec<(Key, KeyMacro)>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self::from_keys(vec![
+            (Key::Up, uinput::KEY_UP),
+            (Key::Down, uinput::KEY_DOWN),
+            (Key::Left, uinput::KEY_LEFT),
+            (Key::Right, uinput::KEY_RIGHT),
+            (Key::A, uinput::KEY_ENTER),
+            (Key::B, uinput::KEY_LEFT),
+            (Key::Plus, uinput::KEY_VOLUMEUP),
+            (Key::Home, uinput::KEY_ESC),
+            (Key::Minus, uinput::KEY_VOLUMEDOWN),
+        ])
+    }
+}
+
+impl KeyMap {
+    /// Creates a map without any mapping.
+    pub fn new() -> Self {
+        Self::from_keys(Vec::new())
+    }
+
+    fn from_keys(keys: Vec<(Key, u16)>) -> Self {
+        Self {
+            keys,
+            repeats: Vec::new(),
+            macros: Vec::new(),
+        }
+    }
+
+    /// Maps `button` to `key`, replacing the previous mapping of `button`.
+    pub fn set(&mut self, button: Key, key: u16) {
+        self.macros
+            .retain(|(other, _)| *other as u32 != button as u32);
+        self.keys
+            .retain(|(other, _)| *other as u32 != button as u32);
+        self.keys.push((button, key));
+    }
+
+    /// Iterates over the mappings, in the order they were set.
+    pub fn iter(&self) -> impl Iterator<Item = (Key, u16)> + '_ {
+        self.keys.iter().copied()
+    }
+
+    /// Returns the keyboard key mapped to `button`, if any.
+    pub fn get(&self, button: &Key) -> Option<u16> {
+        self.keys
+            .iter()
+            .find(|(other, _)| *other as u32 == *button as u32)
+            .map(|(_, key)| *key)
+    }
+
+    /// Sets the behavior of the key mapped to `button` while
+    /// the button is held down.
+    pub fn set_repeat(&mut self, button: Key, repeat: Repeat) {
+        self.repeats
+            .retain(|(other, _)| *other as u32 != button as u32);
+        if repeat != Repeat::Hold {
+            self.repeats.push((button, repeat));
+        }
+    }
+
+    /// Returns the behavior of the key mapped to `button` while
+    /// the button is held down.
+    pub fn repeat(&self, button: &Key) -> Repeat {
+        self.repeats
+            .iter()
+            .find(|(other, _)| *other as u32 == *button as u32)
+            .map_or(Repeat::Hold, |(_, repeat)| *repeat)
+    }
+
+    /// Maps `button` to a macro, played whenever the button is pressed,
+    /// replacing the previous mapping of `button`.
+    pub fn set_macro(&mut self, button: Key, key_macro: KeyMacro) {
+        self.keys
+            .retain(|(other, _)| *other as u32 != button as u32);
+        self.macros
+            .retain(|(other, _)| *other as u32 != button as u32);
+        self.macros.push((button, key_macro));
+    }
+
+    /// Returns the macro mapped to `button`, if any.
+    pub fn get_macro(&self, button: &Key) -> Option<&KeyMacro> {
+        self.macros
+            .iter()
+            .find(|(other, _)| *other as u32 == *button as u32)
+            .map(|(_, key_macro)| key_macro)
+    }
+}
+
 /// A virtual keyboard device.
-pub struct Keyboard(uinput_tokio::Device);
+///
+/// The events emitted through the device carry the time at which the
+/// Wii Remote reported the original events as `MSC_TIMESTAMP` events;
+/// see [`VirtualDevice::sync_at`].
+pub struct Keyboard {
+    output: InputSink,
+    map: KeyMap,
+    /// The time of the last tap of each key held down in
+    /// the [`Repeat::Tap`] mode.
+    last_taps: Vec<(u16, SystemTime)>,
+}
 
 impl Keyboard {
-    /// Creates a new virtual keyboard device.
-    pub async fn new() -> UInputResult<Self> {
-        // Register certain keys for sending press and release events.
-        let events = [
-            event::Keyboard::Key(keyboard::Key::Up),
-            event::Keyboard::Key(keyboard::Key::Down),
-            event::Keyboard::Key(keyboard::Key::Left),
-            event::Keyboard::Key(keyboard::Key::Right),
-            event::Keyboard::Key(keyboard::Key::Enter),
-            event::Keyboard::Misc(keyboard::Misc::VolumeUp),
-            event::Keyboard::Key(keyboard::Key::Esc),
-            event::Keyboard::Misc(keyboard::Misc::VolumeDown),
-        ];
-        let mut builder = uinput_tokio::default()?.name(DEV_NAME)?;
-        for event in events {
-            builder = builder.event(event)?;
+    /// Creates a new virtual keyboard device that emits the keys
+    /// given by `map` and its macros, in addition to the standard media,
+    /// navigation and letter keys.
+    ///
+    /// If `scroll` is set, the device can also emit mouse wheel events,
+    /// and if `pointer` is set, mouse pointer motion events. The portal
+    /// `backend` can always emit any of them.
+    pub async fn new(map: KeyMap, scroll: bool, pointer: bool, backend: Backend) -> Result<Self> {
+        let macro_keys = map
+            .macros
+            .iter()
+            .flat_map(|(_, key_macro)| key_macro.keys());
+        let mut builder = VirtualDevice::keyboard(DEV_NAME)
+            .keys(map.iter().map(|(_, key)| key))
+            .keys(macro_keys);
+        if scroll {
+            builder = builder.relative_axis(REL_WHEEL);
+        }
+        if pointer {
+            builder = builder.relative_axis(REL_X).relative_axis(REL_Y);
+        }
+        Ok(Self {
+            output: InputSink::new(backend, builder).await?,
+            map,
+            last_taps: Vec::new(),
+        })
+    }
+
+    /// Checks whether `button` is mapped to a keyboard key or a macro.
+    pub fn is_mapped(&self, button: &Key) -> bool {
+        self.map.get(button).is_some() || self.map.get_macro(button).is_some()
+    }
+
+    /// Presses, repeats or releases the key mapped to `button` given
+    /// the state of the button at `time`, according to the repeat mode
+    /// of the key, or plays the macro mapped to `button` when pressed.
+    /// Does nothing if the button is not mapped.
+    pub async fn update(&mut self, button: &Key, state: &KeyState, time: SystemTime) -> Result<()> {
+        if let Some(key_macro) = self.map.get_macro(button).cloned() {
+            if matches!(state, KeyState::Down) {
+                self.play(&key_macro, time).await?;
+            }
+            return Ok(());
         }
-        builder.create().await.map(Self)
-    }
-
-    /// Presses or releases the key mapped to `button`, if any.
-    /// Otherwise does nothing.
-    pub async fn update(&mut self, button: &Key, state: &KeyState) -> UInputResult<()> {
-        if let Some(key) = key_event(button) {
-            match *state {
-                KeyState::Down => self.0.press(&key).await?,
-                KeyState::Up => self.0.release(&key).await?,
-                KeyState::AutoRepeat => {} // leave the key pressed.
-            };
-            self.0.synchronize().await
-        } else {
+        let Some(key) = self.map.get(button) else {
             // The button is not matched to any key, ignore.
-            Ok(())
+            return Ok(());
+        };
+        match (self.map.repeat(button), *state) {
+            (Repeat::Hold | Repeat::Forward, KeyState::Down) => {
+                self.output.key(key, true, time).await
+            }
+            (Repeat::Hold | Repeat::Forward, KeyState::Up) => {
+                self.output.key(key, false, time).await
+            }
+            (Repeat::Hold, KeyState::AutoRepeat) => Ok(()), // leave the key pressed.
+            (Repeat::Forward, KeyState::AutoRepeat) => self.output.repeat(key, time).await,
+            (Repeat::Suppress, KeyState::Down) => self.tap(key, time).await,
+            (Repeat::Suppress, _) => Ok(()),
+            (Repeat::Tap(_), KeyState::Down) => {
+                self.last_taps.retain(|(other, _)| *other != key);
+                self.last_taps.push((key, time));
+                self.tap(key, time).await
+            }
+            (Repeat::Tap(rate), KeyState::AutoRepeat) => {
+                // Tap once per press if the rate is out of range.
+                let Ok(period) = Duration::try_from_secs_f32(1.0 / rate) else {
+                    return Ok(());
+                };
+                let Some((_, last_tap)) =
+                    self.last_taps.iter_mut().find(|(other, _)| *other == key)
+                else {
+                    return Ok(());
+                };
+                if time.duration_since(*last_tap).unwrap_or_default() < period {
+                    return Ok(());
+                }
+                *last_tap = time;
+                self.tap(key, time).await
+            }
+            (Repeat::Tap(_), KeyState::Up) => {
+                self.last_taps.retain(|(other, _)| *other != key);
+                Ok(())
+            }
+        }
+    }
+
+    /// Plays the steps of a macro triggered at `time`, waiting during
+    /// its delays without blocking the runtime. Each step is timestamped
+    /// after the delays that precede it.
+    async fn play(&mut self, key_macro: &KeyMacro, mut time: SystemTime) -> Result<()> {
+        for &step in key_macro.steps() {
+            match step {
+                MacroStep::Press(key) => self.output.key(key, true, time).await?,
+                MacroStep::Release(key) => self.output.key(key, false, time).await?,
+                MacroStep::Delay(duration) => {
+                    tokio::time::sleep(duration).await;
+                    time += duration;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Presses and releases a key at `time`.
+    pub async fn tap(&mut self, key: u16, time: SystemTime) -> Result<()> {
+        self.output.key(key, true, time).await?;
+        self.output.key(key, false, time).await
+    }
+
+    /// Moves the mouse pointer by the given distances at `time`, where
+    /// positive values move right and down.
+    /// Does nothing if both distances are zero.
+    pub async fn move_pointer(&mut self, dx: i32, dy: i32, time: SystemTime) -> Result<()> {
+        if dx == 0 && dy == 0 {
+            return Ok(());
         }
+        self.output.move_pointer(dx, dy, time).await
     }
+
+    /// Scrolls the mouse wheel by `steps` at `time`, where positive
+    /// values scroll up.
+    pub async fn scroll(&mut self, steps: i32, time: SystemTime) -> Result<()> {
+        self.output.scroll(steps, time).await
+    }
+}
+
+/// Parses a key mapping of the form `BUTTON=KEY`, where `BUTTON` is the
+/// name of a Wii Remote key such as `plus`, and `KEY` is the name of
+/// a Linux input event code such as `KEY_NEXTSONG`.
+pub fn parse_mapping(input: &str) -> std::result::Result<(Key, u16), String> {
+    let (button, key) = input
+        .split_once('=')
+        .ok_or_else(|| format!("expected BUTTON=KEY, found `{input}`"))?;
+    let button = parse_button(button)?;
+    let key = uinput::key_code(key).ok_or_else(|| format!("unsupported key `{}`", key.trim()))?;
+    Ok((button, key))
 }
 
-/// Converts a Wii Remote key into a keyboard event.
-pub fn key_event(key: &Key) -> Option<event::Keyboard> {
-    Some(match *key {
-        Key::Up => event::Keyboard::Key(keyboard::Key::Up),
-        Key::Down => event::Keyboard::Key(keyboard::Key::Down),
-        Key::Left => event::Keyboard::Key(keyboard::Key::Left),
-        Key::Right => event::Keyboard::Key(keyboard::Key::Right),
-        Key::A => event::Keyboard::Key(keyboard::Key::Enter),
-        Key::B => event::Keyboard::Key(keyboard::Key::Left),
-        Key::Plus => event::Keyboard::Misc(keyboard::Misc::VolumeUp),
-        Key::Home => event::Keyboard::Key(keyboard::Key::Esc),
-        Key::Minus => event::Keyboard::Misc(keyboard::Misc::VolumeDown),
-        _ => return None,
+/// Parses a repeat mode of the form `BUTTON=MODE`, where `BUTTON` is the
+/// name of a Wii Remote key such as `plus`, and `MODE` is one of `hold`,
+/// `forward`, `suppress` or `tap`, optionally followed by the number of
+/// taps per second as in `tap:5`.
+pub fn parse_repeat(input: &str) -> std::result::Result<(Key, Repeat), String> {
+    let (button, mode) = input
+        .split_once('=')
+        .ok_or_else(|| format!("expected BUTTON=MODE, found `{input}`"))?;
+    let button = parse_button(button)?;
+    let mode = mode.trim().to_ascii_lowercase();
+    let repeat = match mode.split_once(':') {
+        Some(("tap", rate)) => match rate.parse() {
+            Ok(rate) if TAP_RATES.contains(&rate) => Repeat::Tap(rate),
+            _ => {
+                return Err(format!(
+                    "invalid tap rate `{rate}`, expected {} to {} taps per second",
+                    TAP_RATES.start(),
+                    TAP_RATES.end()
+                ))
+            }
+        },
+        Some(_) => return Err(format!("unknown repeat mode `{mode}`")),
+        None => match mode.as_str() {
+            "hold" => Repeat::Hold,
+            "forward" => Repeat::Forward,
+            "suppress" => Repeat::Suppress,
+            "tap" => Repeat::Tap(DEFAULT_TAP_RATE),
+            _ => return Err(format!("unknown repeat mode `{mode}`")),
+        },
+    };
+    Ok((button, repeat))
+}
+
+/// Parses a macro mapping of the form `BUTTON=MACRO`, where `BUTTON` is
+/// the name of a Wii Remote key such as `plus`, and `MACRO` follows the
+/// syntax of [`KeyMacro`].
+pub fn parse_macro(input: &str) -> std::result::Result<(Key, KeyMacro), String> {
+    let (button, key_macro) = input
+        .split_once('=')
+        .ok_or_else(|| format!("expected BUTTON=MACRO, found `{input}`"))?;
+    let button = parse_button(button)?;
+    let key_macro = key_macro.parse().map_err(|err| format!("{err}"))?;
+    Ok((button, key_macro))
+}
+
+/// Parses the name of a Wii Remote key, such as `plus`.
+pub fn parse_button(button: &str) -> std::result::Result<Key, String> {
+    Ok(match button.trim().to_ascii_lowercase().as_str() {
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "a" => Key::A,
+        "b" => Key::B,
+        "plus" | "+" => Key::Plus,
+        "minus" | "-" => Key::Minus,
+        "home" => Key::Home,
+        "one" | "1" => Key::One,
+        "two" | "2" => Key::Two,
+        other => return Err(format!("unknown Wii Remote button `{other}`")),
     })
 }
 
-/// Converts a boxed `uinput` error into an I/O error.
-pub fn to_io_err(err: Box<dyn Error>) -> io::Error {
-    // todo: the `uinput_tokio` crate doesn't specify the `Sized` trait
-    //       for errors, so we cannot convert the error directly into
-    //       an I/O error. See if we can retain the source information
-    //       in some other way.
-    io::Error::new(ErrorKind::Other, err.to_string())
+#[cfg(test)]
+mod tests {
+    use crate::keyboard::{parse_repeat, Repeat};
+    use xwiimote::events::Key;
+
+    #[test]
+    fn parses_tap_rates() {
+        let (button, repeat) = parse_repeat("plus=tap:5").unwrap();
+        assert_eq!(button as u32, Key::Plus as u32);
+        assert_eq!(repeat, Repeat::Tap(5.0));
+        for rate in ["0", "-1", "1e-40", "inf", "NaN", "1000"] {
+            assert!(parse_repeat(&format!("a=tap:{rate}")).is_err(), "{rate}");
+        }
+    }
 }
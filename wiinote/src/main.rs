@@ -1,14 +1,34 @@
-use crate::keyboard::{to_io_err, Keyboard};
+use crate::formats::{export, Format, WminputConfig};
+use crate::inhibit::Inhibitor;
+use crate::keyboard::{
+    parse_button, parse_macro, parse_mapping, parse_repeat, KeyMap, Keyboard, Repeat,
+};
+use crate::pointer::{Pointer, PointerMotion};
+use crate::scroll::TiltScroll;
 use clap::Parser;
-use futures_util::TryStreamExt;
-use num_traits::cast::FromPrimitive;
+use futures_util::{stream, Stream, TryStreamExt};
+use std::cell::{Cell, RefCell};
+use std::net::SocketAddr;
 use std::path::PathBuf;
-use std::time::Duration;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
 use tokio::time::MissedTickBehavior;
-use xwiimote::events::{Event, Key};
-use xwiimote::{Address, Channels, Device, Led, Monitor, Result};
+use xwiimote::bridge::sink::Backend;
+use xwiimote::bridge::uinput::KeyMacro;
+use xwiimote::channels::Acceleration;
+use xwiimote::events::{Event, Key, KeyState};
+use xwiimote::frame::CoordinateFrame;
+use xwiimote::merge::{merge, Merged};
+use xwiimote::supervisor::{Backoff, Lifecycle, Supervisor};
+use xwiimote::{Address, Channels, Device, Leds, Monitor, Result};
 
+mod formats;
+mod inhibit;
 mod keyboard;
+mod pointer;
+mod scroll;
+mod switch;
+mod whiteboard;
 
 #[derive(Debug, Parser)]
 #[command(version, author, about, long_about = None)]
@@ -23,6 +43,107 @@ struct Args {
     /// is found.
     #[arg(short, long)]
     discover: bool,
+    /// Blink the lights of a Wii Remote once for every failed connection
+    /// attempt that preceded the connection to the device.
+    ///
+    /// Only meaningful together with the `--discover` option.
+    #[arg(long, requires = "discover")]
+    blink_retries: bool,
+    /// Map a Wii Remote button to a keyboard key, given by the name of its
+    /// Linux input event code (e.g. `--map plus=KEY_NEXTSONG`). May be
+    /// repeated to map several buttons.
+    ///
+    /// The supported keys include letters, function keys, navigation keys
+    /// and media keys such as `KEY_PLAYPAUSE`, `KEY_MUTE` and
+    /// `KEY_BRIGHTNESSUP`. The `1` and `2` buttons are mapped to keys only
+    /// if requested, instead of switching the metric shown by the lights.
+    #[arg(long = "map", value_name = "BUTTON=KEY", value_parser = parse_mapping)]
+    mappings: Vec<(Key, u16)>,
+    /// Choose how the key mapped to a Wii Remote button behaves while the
+    /// button is held down (e.g. `--repeat plus=tap:8`). May be repeated
+    /// to configure several buttons.
+    ///
+    /// The key is kept pressed (`hold`) by default. It can also send a
+    /// repeat event whenever the remote reports one (`forward`), be tapped
+    /// once however long the button is held (`suppress`), or be tapped
+    /// repeatedly at the given number of times per second (`tap:RATE`,
+    /// from 0.1 to 100, or `tap` for 10 taps per second), which suits the
+    /// volume keys.
+    #[arg(long = "repeat", value_name = "BUTTON=MODE", value_parser = parse_repeat)]
+    repeats: Vec<(Key, Repeat)>,
+    /// Map a Wii Remote button to a sequence of keys, played whenever the
+    /// button is pressed (e.g. `--macro 'a=ctrl+alt+t, 300ms, "htop", enter'`).
+    /// May be repeated to map several buttons.
+    ///
+    /// The sequence lists key combinations joined by `+`, quoted texts to
+    /// type in the US keyboard layout, and delays such as `300ms`,
+    /// separated by commas.
+    #[arg(long = "macro", value_name = "BUTTON=MACRO", value_parser = parse_macro)]
+    macros: Vec<(Key, KeyMacro)>,
+    /// Read the button mappings and the pointer settings of a `wminput`
+    /// configuration file, such as `Wiimote.A = KEY_ENTER`, instead of
+    /// using the default mappings.
+    ///
+    /// The `include` directives are resolved like `wminput` does. The
+    /// `--map` and `--pointer` options take precedence.
+    #[arg(long, value_name = "FILE")]
+    wminput_config: Option<PathBuf>,
+    /// Print the key map as a configuration snippet for another tool
+    /// and exit, without connecting to any Wii Remote.
+    #[arg(long, value_name = "FORMAT")]
+    export: Option<Format>,
+    /// Move the mouse pointer with the Wii Remote.
+    #[arg(long, value_name = "SENSOR")]
+    pointer: Option<Pointer>,
+    /// Scroll by tilting the Wii Remote up or down while holding
+    /// the B button, with a speed proportional to the tilt angle.
+    ///
+    /// The B button is then not mapped to any key.
+    #[arg(long)]
+    tilt_scroll: bool,
+    /// Inject the keys and the pointer motion through a `uinput` virtual
+    /// device, or through the remote desktop portal of the desktop
+    /// environment, which works in Wayland sessions where `/dev/uinput`
+    /// is not writable by unprivileged users.
+    #[arg(long, value_name = "BACKEND", default_value = "uinput")]
+    backend: Backend,
+    /// Keep the desktop screensaver from starting while the Wii Remote
+    /// is in use, through the `org.freedesktop.ScreenSaver` D-Bus service.
+    ///
+    /// The screensaver may start again after five minutes without
+    /// any button press.
+    #[arg(long)]
+    inhibit_screensaver: bool,
+    /// Turn the Wii Remote into an interactive whiteboard, sending the
+    /// positions of IR pens on the screen as TUIO touch events to the
+    /// given address (by default `127.0.0.1:3333`).
+    ///
+    /// Place the remote so that its camera sees the whole screen. After
+    /// connecting, touch each corner of the screen with an IR pen as
+    /// instructed to calibrate the positions. The buttons of the remote
+    /// are not mapped to any key.
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        num_args = 0..=1,
+        default_missing_value = whiteboard::DEFAULT_TARGET,
+        conflicts_with_all = ["pointer", "tilt_scroll"]
+    )]
+    whiteboard: Option<SocketAddr>,
+    /// Use a single Wii Remote button as an accessibility switch for
+    /// switch-access scanning software (e.g. `--switch a`).
+    ///
+    /// A short press of the button taps the space key, and holding it
+    /// for a second taps the enter key. Brief touches, shaky releases
+    /// and presses right after another press are ignored. The other
+    /// buttons are not mapped to any key.
+    #[arg(
+        long,
+        value_name = "BUTTON",
+        value_parser = parse_button,
+        conflicts_with_all = ["pointer", "tilt_scroll", "whiteboard"]
+    )]
+    switch: Option<Key>,
     /// Connect to the Wii Remote identified by a `sysfs` device directory,
     /// which is typically of the form `/sys/bus/hid/devices/[dev]`.
     ///
@@ -40,52 +161,211 @@ fn parse_address(input: &str) -> Result<Address> {
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<()> {
     let args = Args::parse();
-    let mut keyboard = Keyboard::new().await.map_err(to_io_err)?;
+    let (mut key_map, mut pointer) = match args.wminput_config {
+        Some(path) => {
+            let config = WminputConfig::load(&path)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+            (config.keys, config.pointer)
+        }
+        None => (KeyMap::default(), None),
+    };
+    pointer = args.pointer.or(pointer);
+    for (button, key) in args.mappings {
+        key_map.set(button, key);
+    }
+    for (button, repeat) in args.repeats {
+        key_map.set_repeat(button, repeat);
+    }
+    for (button, key_macro) in args.macros {
+        key_map.set_macro(button, key_macro);
+    }
+    if let Some(format) = args.export {
+        print!("{}", export(&key_map, format));
+        return Ok(());
+    }
+    let mode = match (args.whiteboard, args.switch) {
+        (Some(target), _) => Mode::Whiteboard(target),
+        (None, Some(button)) => Mode::Switch(button),
+        (None, None) => Mode::Keys {
+            tilt_scroll: args.tilt_scroll,
+            pointer,
+        },
+    };
+    let mut keyboard =
+        Keyboard::new(key_map, args.tilt_scroll, pointer.is_some(), args.backend).await?;
+    let mut inhibitor = if args.inhibit_screensaver {
+        match Inhibitor::new().await {
+            Ok(inhibitor) => Some(inhibitor),
+            Err(err) => {
+                eprintln!("Cannot inhibit the screensaver: {err}");
+                None
+            }
+        }
+    } else {
+        None
+    };
     if let Some(address) = args.address {
         // Connect to the device specified by the given address.
-        connect(&address, &mut keyboard).await?;
+        let device = Device::connect_async(&address, CONNECT_TIMEOUT).await?;
+        serve(device, &mut keyboard, &mut inhibitor, 0, mode).await?;
     } else {
-        // Enumerate devices and connect to the first one found.
-        while let Some(address) = find_device(args.discover).await? {
-            connect(&address, &mut keyboard).await?;
-            // The previous device has disconnected gracefully; restart
-            // the enumeration process to find a new device address.
-        }
-        // A device monitor produces `None` only if discovery mode
-        // is disabled, and consequently so does `find_device`.
+        // Enumerate devices and connect to the first one found. In
+        // discovery mode, retry after failures; otherwise give up.
+        let failures = Rc::new(Cell::new(0));
+        let backoff = Backoff {
+            max_retries: (!args.discover).then_some(0),
+            ..Backoff::default()
+        };
+        let mut supervisor = Supervisor::new(Monitor::builder().discover(args.discover))
+            .backoff(backoff)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .on_event(report_lifecycle(args.discover, Rc::clone(&failures)));
+        let keyboard = RefCell::new(keyboard);
+        let inhibitor = RefCell::new(inhibitor);
+        let blink_retries = args.blink_retries;
+        // The supervisor serves a single device at a time, so the
+        // borrows never overlap.
+        #[allow(clippy::await_holding_refcell_ref)]
+        supervisor
+            .run(|device| {
+                let failed = failures.replace(0);
+                // Keep the pattern short even after many failures.
+                let blinks = if blink_retries { failed.min(4) } else { 0 };
+                let (keyboard, inhibitor) = (&keyboard, &inhibitor);
+                async move {
+                    let mut keyboard = keyboard.borrow_mut();
+                    let mut inhibitor = inhibitor.borrow_mut();
+                    serve(device, &mut keyboard, &mut inhibitor, blinks, mode).await
+                }
+            })
+            .await?;
+        // The supervisor stops only if discovery mode is disabled
+        // and no connected device is found.
         eprintln!("No connected devices found");
     }
     Ok(())
 }
 
-/// Finds the address of a connected device.
-///
-/// If `discover` is true and no device is found, blocks until
-/// a new device is hot-plugged. Otherwise returns `Ok(None)`.
-async fn find_device(discover: bool) -> Result<Option<Address>> {
-    let mut monitor = if discover {
-        println!("Discovering devices");
-        Monitor::discover()
-    } else {
-        println!("Enumerating connected devices");
-        Monitor::enumerate()
-    }?;
-    monitor.try_next().await
+/// The time to wait for a discovered device to become ready.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Returns a function that reports the changes in the connection state
+/// of a [`Supervisor`], and counts the consecutive failed connection
+/// attempts in `failures`.
+fn report_lifecycle(discover: bool, failures: Rc<Cell<u32>>) -> impl FnMut(&Lifecycle<'_>) {
+    // The description of the error that caused the last failed attempt.
+    let mut last_error = None;
+    move |event| match event {
+        Lifecycle::Searching if discover => println!("Discovering devices"),
+        Lifecycle::Searching => println!("Enumerating connected devices"),
+        Lifecycle::Connected(_) => {
+            if let Some(err) = last_error.take() {
+                println!(
+                    "Connected after {} failed attempts (last error: {err})",
+                    failures.get()
+                );
+            }
+        }
+        Lifecycle::Failed {
+            attempt,
+            error,
+            retry_in,
+        } => {
+            failures.set(*attempt);
+            last_error = Some(error.to_string());
+            eprintln!(
+                "Connection attempt {attempt} failed: {error}; retrying in {:.1}s",
+                retry_in.as_secs_f32()
+            );
+        }
+        Lifecycle::Disconnected(_) | Lifecycle::GaveUp { .. } => {}
+    }
 }
 
-/// Initiates the connection to the device specified by `address`.
+/// Blinks all the lights of a device `times` times.
+async fn blink(device: &Device, times: u32) -> Result<()> {
+    const PERIOD: Duration = Duration::from_millis(250);
+    for _ in 0..times {
+        for leds in [Leds::all(), Leds::empty()] {
+            device.set_leds(leds)?;
+            tokio::time::sleep(PERIOD).await;
+        }
+    }
+    Ok(())
+}
+
+/// What to do with the input of a connected device.
+#[derive(Debug, Copy, Clone)]
+enum Mode {
+    /// Press keys, and optionally scroll and move the pointer.
+    Keys {
+        /// Scroll by tilting the device while holding the B button.
+        tilt_scroll: bool,
+        /// The sensor that moves the pointer, if any.
+        pointer: Option<Pointer>,
+    },
+    /// Send the positions of IR pens as TUIO touch events to an address.
+    Whiteboard(SocketAddr),
+    /// Tap keys when a single button is used as an accessibility switch.
+    Switch(Key),
+}
+
+/// Serves a connected device.
+///
+/// The device lights blink `blinks` times first, e.g. once for every
+/// failed connection attempt that preceded the connection. The channels needed
+/// by the `mode` are opened: if `tilt_scroll` is set, the accelerometer
+/// channel for scrolling, and the channel of the `pointer` sensor to
+/// move the pointer. The whiteboard mode needs the IR channel, and
+/// the switch mode only the core channel.
 ///
 /// # Returns
 /// On success, the function blocks until the device is disconnected gracefully,
 /// returning `Ok(())`. Otherwise an error is raised.
-async fn connect(address: &Address, keyboard: &mut Keyboard) -> Result<()> {
-    let mut device = Device::connect(address)?;
+async fn serve(
+    device: Device,
+    keyboard: &mut Keyboard,
+    inhibitor: &mut Option<Inhibitor>,
+    blinks: u32,
+    mode: Mode,
+) -> Result<()> {
     let name = device.kind()?;
 
-    device.open(Channels::CORE, true)?;
+    let mut channels = Channels::CORE;
+    match mode {
+        Mode::Keys {
+            tilt_scroll,
+            pointer,
+        } => {
+            if tilt_scroll || pointer == Some(Pointer::Tilt) {
+                channels |= Channels::ACCELEROMETER;
+            }
+            if pointer == Some(Pointer::Ir) {
+                channels |= Channels::IR;
+            }
+        }
+        Mode::Whiteboard(_) => channels |= Channels::IR,
+        Mode::Switch(_) => {}
+    }
+    device.set_coordinate_frame(CoordinateFrame::SCREEN);
+    device.open(channels, true)?;
     println!("Device connected: {name}");
+    blink(&device, blinks).await?;
 
-    handle(&mut device, keyboard).await?;
+    let result = match mode {
+        Mode::Keys {
+            tilt_scroll,
+            pointer,
+        } => handle(&device, keyboard, inhibitor, tilt_scroll, pointer).await,
+        Mode::Whiteboard(target) => whiteboard::serve(&device, target).await,
+        Mode::Switch(button) => switch::serve(&device, keyboard, inhibitor, button).await,
+    };
+    if let Some(inhibitor) = inhibitor {
+        if let Err(err) = inhibitor.release().await {
+            eprintln!("Cannot release the screensaver inhibition: {err}");
+        }
+    }
+    result?;
     println!("Device disconnected: {name}");
     Ok(())
 }
@@ -105,31 +385,35 @@ struct LightsDisplay<'d> {
     device: &'d Device,
     /// The metric to display.
     metric: LightsMetric,
-    /// An interval that ticks whenever the display needs to be updated.
-    interval: tokio::time::Interval,
+    /// Whether the last attempt to read the connection strength failed
+    /// and was reported, so that the failure is not reported on every tick.
+    rssi_warned: bool,
 }
 
 impl<'d> LightsDisplay<'d> {
     /// Creates a wrapper for the display of a Wii Remote.
     pub fn new(device: &'d Device) -> Self {
-        let mut interval = tokio::time::interval(Duration::from_secs(20));
-        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
         Self {
             device,
             // The connection strength is probably high immediately
             // after pairing; display the battery level by default.
             metric: LightsMetric::Battery,
-            interval,
+            rssi_warned: false,
         }
     }
 
-    /// Completes when the device display should be updated.
-    pub async fn tick(&mut self) -> tokio::time::Instant {
-        self.interval.tick().await
+    /// Returns a stream that ticks whenever the display needs to be updated.
+    pub fn ticks() -> impl Stream<Item = ((), SystemTime)> + Unpin {
+        let mut interval = tokio::time::interval(Duration::from_secs(20));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Box::pin(stream::unfold(interval, |mut interval| async move {
+            interval.tick().await;
+            Some((((), SystemTime::now()), interval))
+        }))
     }
 
     /// Updates the device lights according to the current metric.
-    pub async fn update(&self) -> Result<()> {
+    pub async fn update(&mut self) -> Result<()> {
         let level = match self.metric {
             LightsMetric::Battery => self.device.battery()?,
             LightsMetric::Connection => {
@@ -137,17 +421,28 @@ impl<'d> LightsDisplay<'d> {
                 // rather than connection quality. This is good enough for
                 // the Wii Remote. The scale goes from -80 to 0, where 0
                 // represents the greatest signal strength.
-                let rssi = 0i8; // todo
-                !((rssi as i16 * 100 / -80) as u8)
+                let rssi = match self.device.signal_strength_async().await {
+                    Ok(rssi) => {
+                        self.rssi_warned = false;
+                        rssi.clamp(-80, 0)
+                    }
+                    Err(err) => {
+                        // The adapter may not accept the request; keep
+                        // the lights as they are.
+                        if !self.rssi_warned {
+                            self.rssi_warned = true;
+                            eprintln!("Cannot read the connection strength: {err}");
+                        }
+                        return Ok(());
+                    }
+                };
+                (100 - rssi as i16 * 100 / -80) as u8
             }
         };
 
         // `level` is a value from 0 to 100 (inclusive).
-        let last_ix = 1 + level / 30; // 1..=4
-        for ix in 1..=4 {
-            let light = Led::from_u8(ix).unwrap();
-            self.device.set_led(light, ix <= last_ix)?;
-        }
+        let count = 1 + level / 30; // 1..=4
+        self.device.set_leds(Leds::from_bits_truncate((1 << count) - 1))?;
         Ok(())
     }
 
@@ -163,34 +458,76 @@ impl<'d> LightsDisplay<'d> {
 /// # Returns
 /// If the device is disconnected gracefully, returns `Ok(())`.
 /// Otherwise an error is raised.
-async fn handle(device: &mut Device, keyboard: &mut Keyboard) -> Result<()> {
-    let mut event_stream = device.events()?;
+async fn handle(
+    device: &Device,
+    keyboard: &mut Keyboard,
+    inhibitor: &mut Option<Inhibitor>,
+    tilt_scroll: bool,
+    pointer: Option<Pointer>,
+) -> Result<()> {
+    // Each item is either an event emitted by the device
+    // or a display update request.
+    let mut stream = merge(device.events()?, LightsDisplay::ticks());
     let mut display = LightsDisplay::new(device);
+    let mut scroll = TiltScroll::default();
+    let mut motion = pointer.map(PointerMotion::new);
 
-    loop {
-        // Wait for the next event, which is either an event
-        // emitted by the device or a display update request.
-        let maybe_event = tokio::select! {
-            res = event_stream.try_next() => res?,
-            _ = display.tick() => {
+    // The stream ends once the connection is closed.
+    while let Some((item, time)) = stream.try_next().await? {
+        let event = match item {
+            Merged::Event(event) => event,
+            Merged::Tick(()) => {
                 display.update().await?;
+                if let Some(inhibitor) = inhibitor {
+                    if let Err(err) = inhibitor.release_if_idle().await {
+                        eprintln!("Cannot release the screensaver inhibition: {err}");
+                    }
+                }
                 continue;
             }
         };
 
-        let (event, _time) = match maybe_event {
-            Some(event) => event,
-            None => return Ok(()), // connection closed
-        };
-
-        if let Event::Key(key, state) = event {
+        if let Event::Accelerometer { x, y, z } = event {
+            // The tilt is measured in the device frame.
+            let (x, y, z) = device.coordinate_frame().vector((x, y, z));
+            let acc = Acceleration { x, y, z };
+            let steps = scroll.update(acc, time);
+            if steps != 0 {
+                keyboard.scroll(steps, time).await?;
+            }
+            if let Some(motion) = &mut motion {
+                let (dx, dy) = motion.update_tilt(acc, time);
+                keyboard.move_pointer(dx, dy, time).await?;
+            }
+        } else if let Event::Ir(sources) = event {
+            if let Some(motion) = &mut motion {
+                let (dx, dy) = motion.update_ir(&sources);
+                keyboard.move_pointer(dx, dy, time).await?;
+            }
+        } else if let Event::Key(key, state) = event {
+            if let Some(inhibitor) = inhibitor {
+                // D-Bus errors should not interrupt the remote's operation.
+                if let Err(err) = inhibitor.activity().await {
+                    eprintln!("Cannot inhibit the screensaver: {err}");
+                }
+            }
             match key {
-                Key::One => display.set_metric(LightsMetric::Battery).await,
-                Key::Two => display.set_metric(LightsMetric::Connection).await,
+                // Scroll while the B button is held down.
+                Key::B if tilt_scroll => {
+                    scroll.set_active(!matches!(state, KeyState::Up));
+                    Ok(())
+                }
+                Key::One if !keyboard.is_mapped(&key) => {
+                    display.set_metric(LightsMetric::Battery).await
+                }
+                Key::Two if !keyboard.is_mapped(&key) => {
+                    display.set_metric(LightsMetric::Connection).await
+                }
                 // If the remote key is mapped to a regular keyboard key,
-                // send a press or release event via the `uinput` API.
-                _ => keyboard.update(&key, &state).await.map_err(to_io_err),
+                // send a press or release event via the selected backend.
+                _ => keyboard.update(&key, &state, time).await,
             }?;
         }
     }
+    Ok(())
 }
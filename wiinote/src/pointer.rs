@@ -0,0 +1,121 @@
+use std::time::SystemTime;
+use xwiimote::channels::Acceleration;
+use xwiimote::events::IrSource;
+use xwiimote::filter::dead_zone;
+use xwiimote::orientation::Tilt;
+use xwiimote::ranges::IR_WIDTH;
+
+/// The number of pointer units that the pointer moves when the IR
+/// sources cross the whole image of the camera.
+const IR_RANGE: f32 = 1920.0;
+
+/// The angle, in degrees, below which tilting the remote does not move
+/// the pointer.
+const DEAD_ZONE: f32 = 8.0;
+
+/// The number of pointer units per second for every degree of tilt
+/// beyond the dead zone.
+const UNITS_PER_DEGREE: f32 = 25.0;
+
+/// The sensor that moves the mouse pointer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Pointer {
+    /// Point at the screen, which requires a sensor bar or another
+    /// pair of IR sources near the screen.
+    Ir,
+    /// Tilt the remote left, right, up or down to move the pointer
+    /// in that direction, with a speed proportional to the tilt angle.
+    Tilt,
+}
+
+/// Converts the IR sources or the tilt of a Wii Remote into
+/// relative pointer motion.
+#[derive(Debug)]
+pub struct PointerMotion {
+    /// The sensor that moves the pointer.
+    source: Pointer,
+    /// The last average position of the IR sources and their number,
+    /// if visible.
+    last_ir: Option<((f32, f32), usize)>,
+    /// The fraction of a pointer unit accumulated so far, per axis.
+    remainder: (f32, f32),
+    /// The time of the last accelerometer reading, if any.
+    last_time: Option<SystemTime>,
+}
+
+impl PointerMotion {
+    /// Creates a converter for the readings of the given sensor.
+    pub fn new(source: Pointer) -> Self {
+        Self {
+            source,
+            last_ir: None,
+            remainder: (0.0, 0.0),
+            last_time: None,
+        }
+    }
+
+    /// Processes the IR sources detected by the camera, in the
+    /// [screen frame](xwiimote::frame::CoordinateFrame::SCREEN).
+    ///
+    /// # Returns
+    /// The distance to move the pointer along the x and y axes.
+    pub fn update_ir(&mut self, sources: &[Option<IrSource>]) -> (i32, i32) {
+        let visible: Vec<_> = sources.iter().flatten().collect();
+        if self.source != Pointer::Ir || visible.is_empty() {
+            // Start over once the sources are visible again.
+            self.last_ir = None;
+            return (0, 0);
+        }
+        let n = visible.len() as f32;
+        let x = visible.iter().map(|source| source.x as f32).sum::<f32>() / n;
+        let y = visible.iter().map(|source| source.y as f32).sum::<f32>() / n;
+        let Some(((last_x, last_y), last_count)) = self.last_ir.replace(((x, y), visible.len()))
+        else {
+            return (0, 0);
+        };
+        if last_count != visible.len() {
+            // The average jumps as a source enters or leaves the view
+            // of the camera, although the remote did not move.
+            return (0, 0);
+        }
+
+        // The camera sees the sources move opposite to the remote.
+        let scale = IR_RANGE / IR_WIDTH as f32;
+        self.advance((last_x - x) * scale, (last_y - y) * scale)
+    }
+
+    /// Processes an accelerometer reading received at the given time.
+    ///
+    /// # Returns
+    /// The distance to move the pointer along the x and y axes.
+    pub fn update_tilt(&mut self, acc: Acceleration, time: SystemTime) -> (i32, i32) {
+        if self.source != Pointer::Tilt {
+            return (0, 0);
+        }
+        let elapsed = match self.last_time.replace(time) {
+            Some(last_time) => time.duration_since(last_time).unwrap_or_default(),
+            None => return (0, 0), // start measuring from this reading.
+        };
+        let Some(tilt) = Tilt::from_acceleration(acc) else {
+            return (0, 0);
+        };
+
+        let speed = |angle: f32| {
+            let excess = dead_zone(angle, DEAD_ZONE);
+            excess * UNITS_PER_DEGREE * elapsed.as_secs_f32()
+        };
+        // Pointing the remote up moves the pointer up, towards lower y.
+        self.advance(speed(tilt.roll_degrees()), -speed(tilt.pitch_degrees()))
+    }
+
+    /// Adds the given distances to the remainders, and takes
+    /// the whole pointer units out of them.
+    fn advance(&mut self, dx: f32, dy: f32) -> (i32, i32) {
+        self.remainder.0 += dx;
+        self.remainder.1 += dy;
+        let steps = (self.remainder.0.trunc(), self.remainder.1.trunc());
+        self.remainder.0 -= steps.0;
+        self.remainder.1 -= steps.1;
+        (steps.0 as i32, steps.1 as i32)
+    }
+}
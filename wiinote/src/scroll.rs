@@ -0,0 +1,60 @@
+use std::time::SystemTime;
+use xwiimote::channels::Acceleration;
+use xwiimote::filter::dead_zone;
+use xwiimote::orientation::Tilt;
+
+/// The pitch, in degrees, below which tilting the remote does not scroll.
+const DEAD_ZONE: f32 = 10.0;
+
+/// The number of wheel steps per second for every degree of pitch
+/// beyond the dead zone.
+const STEPS_PER_DEGREE: f32 = 0.5;
+
+/// Converts the tilt of a Wii Remote into mouse wheel steps, while
+/// the tilt-scroll mode is active.
+///
+/// The scrolling speed is proportional to the pitch of the remote:
+/// pointing the remote up scrolls up, and pointing it down scrolls down.
+#[derive(Debug, Default)]
+pub struct TiltScroll {
+    /// Is the tilt-scroll mode active?
+    active: bool,
+    /// The fraction of a wheel step accumulated so far.
+    remainder: f32,
+    /// The time of the last accelerometer reading, if any.
+    last_time: Option<SystemTime>,
+}
+
+impl TiltScroll {
+    /// Activates or deactivates the tilt-scroll mode.
+    pub fn set_active(&mut self, active: bool) {
+        self.active = active;
+        self.remainder = 0.0;
+        self.last_time = None;
+    }
+
+    /// Processes an accelerometer reading received at the given time.
+    ///
+    /// # Returns
+    /// The number of wheel steps to scroll, where positive values
+    /// scroll up.
+    pub fn update(&mut self, acc: Acceleration, time: SystemTime) -> i32 {
+        if !self.active {
+            return 0;
+        }
+        let elapsed = match self.last_time.replace(time) {
+            Some(last_time) => time.duration_since(last_time).unwrap_or_default(),
+            None => return 0, // start measuring from this reading.
+        };
+        let Some(tilt) = Tilt::from_acceleration(acc) else {
+            return 0;
+        };
+
+        let pitch = tilt.pitch_degrees();
+        let excess = dead_zone(pitch, DEAD_ZONE);
+        self.remainder += excess * STEPS_PER_DEGREE * elapsed.as_secs_f32();
+        let steps = self.remainder.trunc();
+        self.remainder -= steps;
+        steps as i32
+    }
+}
@@ -0,0 +1,46 @@
+use crate::inhibit::Inhibitor;
+use crate::keyboard::Keyboard;
+use futures_util::TryStreamExt;
+use xwiimote::bridge::uinput;
+use xwiimote::events::Key;
+use xwiimote::switch::{switch_events, SwitchEvent, SwitchInput};
+use xwiimote::{Device, Result};
+
+/// The key tapped on a short press of the switch, which moves to the
+/// next item in most scanning interfaces.
+const PRESS_KEY: u16 = uinput::KEY_SPACE;
+
+/// The key tapped on a long press of the switch, which selects the
+/// current item in most scanning interfaces.
+const LONG_PRESS_KEY: u16 = uinput::KEY_ENTER;
+
+/// Uses `button` as an accessibility switch, tapping the space key on
+/// short presses and the enter key on long presses. The other buttons
+/// are ignored.
+///
+/// # Returns
+/// If the device is disconnected gracefully, returns `Ok(())`.
+/// Otherwise an error is raised.
+pub async fn serve(
+    device: &Device,
+    keyboard: &mut Keyboard,
+    inhibitor: &mut Option<Inhibitor>,
+    button: Key,
+) -> Result<()> {
+    let mut events = switch_events(device.events()?, SwitchInput::new(button));
+    // The stream ends once the connection is closed.
+    while let Some((event, time)) = events.try_next().await? {
+        if let Some(inhibitor) = inhibitor {
+            // D-Bus errors should not interrupt the remote's operation.
+            if let Err(err) = inhibitor.activity().await {
+                eprintln!("Cannot inhibit the screensaver: {err}");
+            }
+        }
+        let key = match event {
+            SwitchEvent::Press => PRESS_KEY,
+            SwitchEvent::LongPress => LONG_PRESS_KEY,
+        };
+        keyboard.tap(key, time).await?;
+    }
+    Ok(())
+}
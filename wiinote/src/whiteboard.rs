@@ -0,0 +1,474 @@
+use futures_util::TryStreamExt;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::SystemTime;
+use xwiimote::events::{Event, IrSource, MAX_IR_SOURCES};
+use xwiimote::{Device, Result};
+
+/// The default address of the TUIO clients, which listen on port 3333.
+pub const DEFAULT_TARGET: &str = "127.0.0.1:3333";
+
+/// The corners of the screen touched during the calibration, in order,
+/// along with their normalized screen coordinates.
+const CORNERS: [(&str, (f32, f32)); 4] = [
+    ("top-left", (0.0, 0.0)),
+    ("top-right", (1.0, 0.0)),
+    ("bottom-right", (1.0, 1.0)),
+    ("bottom-left", (0.0, 1.0)),
+];
+
+/// The number of IR reports that a touch must last to be accepted
+/// during the calibration, which filters out reflections and flickers.
+const MIN_TOUCH_REPORTS: usize = 10;
+
+/// Maps the positions seen by the IR camera to normalized screen
+/// coordinates, from (0, 0) at the top-left corner to (1, 1) at the
+/// bottom-right corner.
+///
+/// The remote is placed in front of the screen, so the camera sees the
+/// screen in perspective; a homography corrects the distortion given
+/// the camera positions of the four corners.
+#[derive(Debug, Copy, Clone)]
+pub struct Calibration {
+    /// The first eight coefficients of the homography matrix, in
+    /// row-major order. The last coefficient is 1.
+    matrix: [f64; 8],
+}
+
+impl Calibration {
+    /// Computes the calibration given the camera positions of the
+    /// [`CORNERS`], in order.
+    ///
+    /// # Returns
+    /// [`None`] if three of the positions lie on a line.
+    pub fn new(points: [(f32, f32); 4]) -> Option<Self> {
+        // Each correspondence (x, y) -> (u, v) yields two linear
+        // equations on the coefficients; solve them by Gaussian
+        // elimination with partial pivoting.
+        let mut rows = [[0.0f64; 9]; 8];
+        for (ix, (&(x, y), (_, (u, v)))) in points.iter().zip(CORNERS).enumerate() {
+            let (x, y, u, v) = (x as f64, y as f64, u as f64, v as f64);
+            rows[2 * ix] = [x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y, u];
+            rows[2 * ix + 1] = [0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y, v];
+        }
+        for col in 0..8 {
+            let pivot =
+                (col..8).max_by(|&a, &b| rows[a][col].abs().total_cmp(&rows[b][col].abs()))?;
+            if rows[pivot][col].abs() < 1e-9 {
+                return None;
+            }
+            rows.swap(col, pivot);
+            let pivot_row = rows[col];
+            for (ix, row) in rows.iter_mut().enumerate() {
+                if ix != col {
+                    let factor = row[col] / pivot_row[col];
+                    for (value, pivot_value) in row.iter_mut().zip(pivot_row).skip(col) {
+                        *value -= factor * pivot_value;
+                    }
+                }
+            }
+        }
+        let mut matrix = [0.0; 8];
+        for (ix, coefficient) in matrix.iter_mut().enumerate() {
+            *coefficient = rows[ix][8] / rows[ix][ix];
+        }
+        Some(Self { matrix })
+    }
+
+    /// Converts a camera position into screen coordinates, which are
+    /// clamped to the bounds of the screen.
+    pub fn map(&self, source: IrSource) -> (f32, f32) {
+        let [a, b, c, d, e, f, g, h] = self.matrix;
+        let (x, y) = (source.x as f64, source.y as f64);
+        let w = g * x + h * y + 1.0;
+        let u = (a * x + b * y + c) / w;
+        let v = (d * x + e * y + f) / w;
+        (u.clamp(0.0, 1.0) as f32, v.clamp(0.0, 1.0) as f32)
+    }
+}
+
+/// Waits for the IR pen to touch the screen and be lifted again.
+///
+/// # Returns
+/// The average camera position of the pen during the touch, or [`None`]
+/// if the device disconnected.
+async fn touch(
+    events: &mut (impl futures_util::Stream<Item = Result<(Event, SystemTime)>> + Unpin),
+) -> Result<Option<(f32, f32)>> {
+    let mut positions = Vec::new();
+    while let Some((event, _)) = events.try_next().await? {
+        if !matches!(event, Event::Ir(_)) {
+            continue;
+        }
+        match event.ir_sources().next() {
+            Some((_, source)) => positions.push((source.x as f32, source.y as f32)),
+            None if positions.len() >= MIN_TOUCH_REPORTS => {
+                let n = positions.len() as f32;
+                let (x, y) = positions
+                    .iter()
+                    .fold((0.0, 0.0), |(x, y), p| (x + p.0, y + p.1));
+                return Ok(Some((x / n, y / n)));
+            }
+            None => positions.clear(),
+        }
+    }
+    Ok(None)
+}
+
+/// Guides the user through the calibration, touching each corner
+/// of the screen with the IR pen.
+///
+/// # Returns
+/// The calibration, or [`None`] if the device disconnected.
+async fn calibrate(
+    events: &mut (impl futures_util::Stream<Item = Result<(Event, SystemTime)>> + Unpin),
+) -> Result<Option<Calibration>> {
+    loop {
+        let mut points = [(0.0, 0.0); 4];
+        for (point, (name, _)) in points.iter_mut().zip(CORNERS) {
+            println!("Touch the {name} corner of the screen with the IR pen");
+            match touch(events).await? {
+                Some(position) => *point = position,
+                None => return Ok(None),
+            }
+        }
+        match Calibration::new(points) {
+            Some(calibration) => return Ok(Some(calibration)),
+            None => println!("The corners are not distinct; calibrating again"),
+        }
+    }
+}
+
+/// An argument of an OSC message.
+enum OscArg<'a> {
+    Int(i32),
+    Float(f32),
+    Str(&'a str),
+}
+
+/// Appends an OSC string, which is null-terminated and padded
+/// to a multiple of four bytes.
+fn push_osc_str(buf: &mut Vec<u8>, str: &str) {
+    buf.extend_from_slice(str.as_bytes());
+    let padding = 4 - str.len() % 4;
+    buf.resize(buf.len() + padding, 0);
+}
+
+/// Encodes an OSC message.
+fn osc_message(address: &str, args: &[OscArg]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    push_osc_str(&mut buf, address);
+    let tags: String = std::iter::once(',')
+        .chain(args.iter().map(|arg| match arg {
+            OscArg::Int(_) => 'i',
+            OscArg::Float(_) => 'f',
+            OscArg::Str(_) => 's',
+        }))
+        .collect();
+    push_osc_str(&mut buf, &tags);
+    for arg in args {
+        match arg {
+            OscArg::Int(value) => buf.extend_from_slice(&value.to_be_bytes()),
+            OscArg::Float(value) => buf.extend_from_slice(&value.to_be_bytes()),
+            OscArg::Str(value) => push_osc_str(&mut buf, value),
+        }
+    }
+    buf
+}
+
+/// Encodes an OSC bundle to be processed immediately.
+fn osc_bundle(messages: &[Vec<u8>]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    push_osc_str(&mut buf, "#bundle");
+    buf.extend_from_slice(&1u64.to_be_bytes());
+    for message in messages {
+        buf.extend_from_slice(&(message.len() as i32).to_be_bytes());
+        buf.extend_from_slice(message);
+    }
+    buf
+}
+
+/// A cursor tracked by the IR camera.
+#[derive(Debug, Copy, Clone)]
+struct Cursor {
+    /// The TUIO session identifier.
+    session: i32,
+    /// The last screen position of the cursor.
+    position: (f32, f32),
+    /// The last velocity of the cursor, in screen units per second.
+    velocity: (f32, f32),
+    /// The time of the last position.
+    time: SystemTime,
+}
+
+/// Sends the positions of the IR pens to TUIO clients, following
+/// the `2Dcur` profile of the TUIO 1.1 protocol.
+struct TuioSender {
+    socket: UdpSocket,
+    /// The cursor tracked in each slot of the IR camera, if any.
+    cursors: [Option<Cursor>; MAX_IR_SOURCES],
+    /// The identifier of the next session.
+    next_session: i32,
+    /// The sequence number of the next frame.
+    next_frame: i32,
+}
+
+impl TuioSender {
+    fn new(target: SocketAddr) -> io::Result<Self> {
+        let bind_address: SocketAddr = if target.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(bind_address)?;
+        socket.connect(target)?;
+        Ok(Self {
+            socket,
+            cursors: [None; MAX_IR_SOURCES],
+            next_session: 0,
+            next_frame: 0,
+        })
+    }
+
+    /// Updates the cursors given the IR sources reported at `time`,
+    /// and sends a frame describing them.
+    fn send_frame(
+        &mut self,
+        sources: &[Option<IrSource>; MAX_IR_SOURCES],
+        calibration: &Calibration,
+        time: SystemTime,
+    ) -> io::Result<()> {
+        let mut messages = vec![osc_message(
+            "/tuio/2Dcur",
+            &[OscArg::Str("source"), OscArg::Str("wiinote")],
+        )];
+        let mut set_messages = Vec::new();
+        for (cursor, source) in self.cursors.iter_mut().zip(sources) {
+            let Some(source) = source else {
+                *cursor = None; // the pen was lifted
+                continue;
+            };
+            let position = calibration.map(*source);
+            let (velocity, acceleration) = match cursor {
+                Some(last) => {
+                    let elapsed = time.duration_since(last.time).unwrap_or_default();
+                    let secs = elapsed.as_secs_f32().max(f32::EPSILON);
+                    let velocity = (
+                        (position.0 - last.position.0) / secs,
+                        (position.1 - last.position.1) / secs,
+                    );
+                    let speed = |(x, y): (f32, f32)| x.hypot(y);
+                    (velocity, (speed(velocity) - speed(last.velocity)) / secs)
+                }
+                None => ((0.0, 0.0), 0.0),
+            };
+            let session = match cursor {
+                Some(last) => last.session,
+                None => {
+                    self.next_session = self.next_session.wrapping_add(1);
+                    self.next_session
+                }
+            };
+            *cursor = Some(Cursor {
+                session,
+                position,
+                velocity,
+                time,
+            });
+            set_messages.push(osc_message(
+                "/tuio/2Dcur",
+                &[
+                    OscArg::Str("set"),
+                    OscArg::Int(session),
+                    OscArg::Float(position.0),
+                    OscArg::Float(position.1),
+                    OscArg::Float(velocity.0),
+                    OscArg::Float(velocity.1),
+                    OscArg::Float(acceleration),
+                ],
+            ));
+        }
+
+        let mut alive = vec![OscArg::Str("alive")];
+        alive.extend(
+            self.cursors
+                .iter()
+                .flatten()
+                .map(|c| OscArg::Int(c.session)),
+        );
+        messages.push(osc_message("/tuio/2Dcur", &alive));
+        messages.extend(set_messages);
+        self.next_frame = self.next_frame.wrapping_add(1);
+        messages.push(osc_message(
+            "/tuio/2Dcur",
+            &[OscArg::Str("fseq"), OscArg::Int(self.next_frame)],
+        ));
+        self.socket.send(&osc_bundle(&messages))?;
+        Ok(())
+    }
+}
+
+/// Runs the whiteboard server on a connected device, whose IR channel
+/// must be open.
+///
+/// After the calibration, every report of the IR camera is sent as
+/// a TUIO frame to `target`.
+///
+/// # Returns
+/// If the device is disconnected gracefully, returns `Ok(())`.
+/// Otherwise an error is raised.
+pub async fn serve(device: &Device, target: SocketAddr) -> Result<()> {
+    let mut events = device.events()?;
+    let Some(calibration) = calibrate(&mut events).await? else {
+        return Ok(());
+    };
+    let mut sender = TuioSender::new(target)?;
+    println!("Sending TUIO touch events to {target}");
+
+    // The clients may not be listening yet; report the failures only
+    // once until a frame is sent again.
+    let mut failing = false;
+    // The stream ends once the connection is closed.
+    while let Some((event, time)) = events.try_next().await? {
+        if let Event::Ir(sources) = event {
+            match sender.send_frame(&sources, &calibration, time) {
+                Ok(()) => failing = false,
+                Err(err) if !failing => {
+                    eprintln!("Cannot send TUIO frames: {err}");
+                    failing = true;
+                }
+                Err(_) => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::whiteboard::{osc_bundle, osc_message, Calibration, OscArg, TuioSender};
+    use std::net::UdpSocket;
+    use std::time::{Duration, SystemTime};
+    use xwiimote::events::{IrSource, MAX_IR_SOURCES};
+
+    /// The camera positions of the corners of a screen seen in perspective.
+    const POINTS: [(f32, f32); 4] = [
+        (200.0, 100.0),
+        (800.0, 100.0),
+        (900.0, 800.0),
+        (100.0, 800.0),
+    ];
+
+    fn source((x, y): (f32, f32)) -> IrSource {
+        IrSource {
+            x: x as i32,
+            y: y as i32,
+        }
+    }
+
+    fn assert_near((x, y): (f32, f32), (u, v): (f32, f32)) {
+        assert!(
+            (x - u).abs() < 1e-4 && (y - v).abs() < 1e-4,
+            "({x}, {y}) vs ({u}, {v})"
+        );
+    }
+
+    #[test]
+    fn maps_the_corners() {
+        let calibration = Calibration::new(POINTS).unwrap();
+        for (point, expected) in
+            POINTS
+                .into_iter()
+                .zip([(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)])
+        {
+            assert_near(calibration.map(source(point)), expected);
+        }
+        // The intersection of the diagonals is the center of the screen.
+        let center = calibration.map(source((500.0, 400.0)));
+        assert_near(center, (0.5, 0.5));
+        // Positions beyond the corners are clamped.
+        assert_eq!(calibration.map(source((150.0, 50.0))), (0.0, 0.0));
+        assert_eq!(calibration.map(source((950.0, 850.0))), (1.0, 1.0));
+    }
+
+    #[test]
+    fn rejects_collinear_corners() {
+        let points = [(0.0, 0.0), (100.0, 100.0), (200.0, 200.0), (0.0, 500.0)];
+        assert!(Calibration::new(points).is_none());
+    }
+
+    #[test]
+    fn encodes_osc_messages() {
+        let message = osc_message("/tuio/2Dcur", &[OscArg::Str("fseq"), OscArg::Int(7)]);
+        let mut expected = b"/tuio/2Dcur\0,si\0fseq\0\0\0\0".to_vec();
+        expected.extend_from_slice(&7i32.to_be_bytes());
+        assert_eq!(message, expected);
+
+        let message = osc_message("/a", &[OscArg::Float(1.5)]);
+        let mut expected = b"/a\0\0,f\0\0".to_vec();
+        expected.extend_from_slice(&1.5f32.to_be_bytes());
+        assert_eq!(message, expected);
+
+        let bundle = osc_bundle(std::slice::from_ref(&message));
+        let mut expected = b"#bundle\0\0\0\0\0\0\0\0\x01".to_vec();
+        expected.extend_from_slice(&(message.len() as i32).to_be_bytes());
+        expected.extend_from_slice(&message);
+        assert_eq!(bundle, expected);
+    }
+
+    #[test]
+    fn sends_tuio_frames() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut sender = TuioSender::new(receiver.local_addr().unwrap()).unwrap();
+        let calibration = Calibration::new(POINTS).unwrap();
+        let mut sources = [None; MAX_IR_SOURCES];
+        let time = SystemTime::UNIX_EPOCH;
+
+        let mut buf = [0; 1024];
+        let mut expect_frame = |alive: &[i32], set: Option<i32>, fseq: i32| {
+            let (x, y) = calibration.map(source(POINTS[0]));
+            let mut messages = vec![osc_message(
+                "/tuio/2Dcur",
+                &[OscArg::Str("source"), OscArg::Str("wiinote")],
+            )];
+            let ids = alive.iter().map(|&session| OscArg::Int(session));
+            let alive: Vec<_> = std::iter::once(OscArg::Str("alive")).chain(ids).collect();
+            messages.push(osc_message("/tuio/2Dcur", &alive));
+            if let Some(session) = set {
+                let args = [
+                    OscArg::Str("set"),
+                    OscArg::Int(session),
+                    OscArg::Float(x),
+                    OscArg::Float(y),
+                ];
+                let still = [OscArg::Float(0.0), OscArg::Float(0.0), OscArg::Float(0.0)];
+                let args: Vec<_> = args.into_iter().chain(still).collect();
+                messages.push(osc_message("/tuio/2Dcur", &args));
+            }
+            messages.push(osc_message(
+                "/tuio/2Dcur",
+                &[OscArg::Str("fseq"), OscArg::Int(fseq)],
+            ));
+            let n = receiver.recv(&mut buf).unwrap();
+            assert_eq!(buf[..n], osc_bundle(&messages));
+        };
+
+        // The pen keeps its session while it touches the screen.
+        sources[0] = Some(source(POINTS[0]));
+        sender.send_frame(&sources, &calibration, time).unwrap();
+        expect_frame(&[1], Some(1), 1);
+        let later = time + Duration::from_millis(10);
+        sender.send_frame(&sources, &calibration, later).unwrap();
+        expect_frame(&[1], Some(1), 2);
+        // Another touch starts a new session.
+        sender
+            .send_frame(&[None; MAX_IR_SOURCES], &calibration, later)
+            .unwrap();
+        expect_frame(&[], None, 3);
+        sender.send_frame(&sources, &calibration, later).unwrap();
+        expect_frame(&[2], Some(2), 4);
+    }
+}
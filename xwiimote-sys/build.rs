@@ -2,13 +2,17 @@ use bindgen::CargoCallbacks;
 use std::env;
 use std::path::PathBuf;
 
-#[cfg(target_os = "linux")]
 fn main() {
-    println!("cargo:rustc-link-lib=udev");
+    // The build script runs on the host, which may not be the target.
+    if env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("linux") {
+        // The crate declares stubs instead; see `src/stub.rs`.
+        return;
+    }
     println!("cargo:rerun-if-changed=vendor/lib");
     println!("cargo:rerun-if-changed=wrapper.h");
 
-    build_xwiimote();
+    let udev_include_paths = link_udev();
+    build_xwiimote(&udev_include_paths);
 
     // Generate the Rust FFI bindings to the xwiimote library.
     let bindings = bindgen::Builder::default()
@@ -30,10 +34,60 @@ fn main() {
         .expect("failed to write bindings");
 }
 
-fn build_xwiimote() {
-    println!("cargo:rerun-if-env-changed=XWIIMOTE_SYS_STATIC");
-    let want_static =
-        cfg!(feature = "static") || env::var("XWIIMOTE_SYS_STATIC").unwrap_or(String::new()) == "1";
+/// Checks whether a boolean option is enabled, either through
+/// the given feature or by setting the environment variable to `1`.
+fn option_enabled(feature: bool, var: &str) -> bool {
+    println!("cargo:rerun-if-env-changed={var}");
+    feature || env::var(var).as_deref() == Ok("1")
+}
+
+/// Emits the directives to link against libudev, which the xwiimote
+/// library depends on, and returns the directories of its headers.
+///
+/// The library is located with pkg-config, unless its directory
+/// is given by `XWIIMOTE_SYS_UDEV_LIB_DIR`.
+fn link_udev() -> Vec<PathBuf> {
+    let want_static = option_enabled(cfg!(feature = "static-udev"), "XWIIMOTE_SYS_UDEV_STATIC");
+    let kind = if want_static { "static" } else { "dylib" };
+
+    println!("cargo:rerun-if-env-changed=XWIIMOTE_SYS_UDEV_LIB_DIR");
+    if let Some(lib_dir) = env::var_os("XWIIMOTE_SYS_UDEV_LIB_DIR") {
+        let lib_dir = PathBuf::from(lib_dir);
+        let file_name = if want_static {
+            "libudev.a"
+        } else {
+            "libudev.so"
+        };
+        if !lib_dir.join(file_name).exists() {
+            panic!(
+                "XWIIMOTE_SYS_UDEV_LIB_DIR is set to {}, which does not contain {file_name}",
+                lib_dir.display()
+            );
+        }
+        println!("cargo:rustc-link-search=native={}", lib_dir.display());
+        println!("cargo:rustc-link-lib={kind}=udev");
+        return Vec::new();
+    }
+
+    // The pkg-config crate honors `PKG_CONFIG_PATH` and `PKG_CONFIG_SYSROOT_DIR`,
+    // and their target-specific variants, which override the search path.
+    match pkg_config::Config::new()
+        .atleast_version("183")
+        .statik(want_static)
+        .probe("libudev")
+    {
+        Ok(udev) => udev.include_paths,
+        Err(e) => panic!(
+            "could not find libudev >= 183 ({kind} linking): {e}\n\
+             Install the libudev development files, point PKG_CONFIG_PATH to \
+             the directory of libudev.pc, or set XWIIMOTE_SYS_UDEV_LIB_DIR to \
+             the directory that contains the library."
+        ),
+    }
+}
+
+fn build_xwiimote(udev_include_paths: &[PathBuf]) {
+    let want_static = option_enabled(cfg!(feature = "static"), "XWIIMOTE_SYS_STATIC");
     if !want_static {
         // Run pkg-config since we're linking dynamically.
         let xwiimote = pkg_config::Config::new()
@@ -43,13 +97,14 @@ fn build_xwiimote() {
             Ok(_) => return,
             Err(e) => {
                 // Couldn't locate the library; fall back to static build.
-                println!("cargo-warning={}", e.to_string());
+                println!("cargo:warning=building the vendored xwiimote library: {e}");
             }
         }
     }
 
     // Compile the source files into a static library.
     cc::Build::new()
+        .includes(udev_include_paths)
         .define("XWII__EXPORT", r#"__attribute__((visibility("default")))"#)
         .file("vendor/lib/core.c")
         .file("vendor/lib/monitor.c")
@@ -60,8 +115,3 @@ fn build_xwiimote() {
         .flag("-Wno-override-init")
         .compile("xwiimote");
 }
-
-#[cfg(not(target_os = "linux"))]
-fn main() {
-    panic!("Cannot build xwiimote on non-Linux system");
-}
@@ -4,4 +4,10 @@
 #![allow(improper_ctypes)]
 #![allow(rustdoc::broken_intra_doc_links)]
 
+#[cfg(target_os = "linux")]
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+
+#[cfg(not(target_os = "linux"))]
+mod stub;
+#[cfg(not(target_os = "linux"))]
+pub use stub::*;
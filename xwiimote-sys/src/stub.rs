@@ -0,0 +1,224 @@
+//! Stand-ins for the bindings on targets where the xwiimote library is
+//! not available, so that crates depending on this one still compile.
+//!
+//! The types and constants match those generated from `xwiimote.h`. The
+//! functions do nothing; those that can fail set `errno` to `ENOSYS` and
+//! return `-ENOSYS` or a null pointer, and the others report no channels
+//! and no file descriptor.
+
+use std::os::raw::{c_char, c_int, c_uint};
+use std::ptr;
+
+pub use libc::timeval;
+
+pub type xwii_event_types = c_uint;
+pub const XWII_EVENT_KEY: xwii_event_types = 0;
+pub const XWII_EVENT_ACCEL: xwii_event_types = 1;
+pub const XWII_EVENT_IR: xwii_event_types = 2;
+pub const XWII_EVENT_BALANCE_BOARD: xwii_event_types = 3;
+pub const XWII_EVENT_MOTION_PLUS: xwii_event_types = 4;
+pub const XWII_EVENT_PRO_CONTROLLER_KEY: xwii_event_types = 5;
+pub const XWII_EVENT_PRO_CONTROLLER_MOVE: xwii_event_types = 6;
+pub const XWII_EVENT_WATCH: xwii_event_types = 7;
+pub const XWII_EVENT_CLASSIC_CONTROLLER_KEY: xwii_event_types = 8;
+pub const XWII_EVENT_CLASSIC_CONTROLLER_MOVE: xwii_event_types = 9;
+pub const XWII_EVENT_NUNCHUK_KEY: xwii_event_types = 10;
+pub const XWII_EVENT_NUNCHUK_MOVE: xwii_event_types = 11;
+pub const XWII_EVENT_DRUMS_KEY: xwii_event_types = 12;
+pub const XWII_EVENT_DRUMS_MOVE: xwii_event_types = 13;
+pub const XWII_EVENT_GUITAR_KEY: xwii_event_types = 14;
+pub const XWII_EVENT_GUITAR_MOVE: xwii_event_types = 15;
+pub const XWII_EVENT_GONE: xwii_event_types = 16;
+pub const XWII_EVENT_NUM: xwii_event_types = 17;
+
+pub type xwii_event_keys = c_uint;
+pub const XWII_KEY_LEFT: xwii_event_keys = 0;
+pub const XWII_KEY_RIGHT: xwii_event_keys = 1;
+pub const XWII_KEY_UP: xwii_event_keys = 2;
+pub const XWII_KEY_DOWN: xwii_event_keys = 3;
+pub const XWII_KEY_A: xwii_event_keys = 4;
+pub const XWII_KEY_B: xwii_event_keys = 5;
+pub const XWII_KEY_PLUS: xwii_event_keys = 6;
+pub const XWII_KEY_MINUS: xwii_event_keys = 7;
+pub const XWII_KEY_HOME: xwii_event_keys = 8;
+pub const XWII_KEY_ONE: xwii_event_keys = 9;
+pub const XWII_KEY_TWO: xwii_event_keys = 10;
+pub const XWII_KEY_X: xwii_event_keys = 11;
+pub const XWII_KEY_Y: xwii_event_keys = 12;
+pub const XWII_KEY_TL: xwii_event_keys = 13;
+pub const XWII_KEY_TR: xwii_event_keys = 14;
+pub const XWII_KEY_ZL: xwii_event_keys = 15;
+pub const XWII_KEY_ZR: xwii_event_keys = 16;
+pub const XWII_KEY_THUMBL: xwii_event_keys = 17;
+pub const XWII_KEY_THUMBR: xwii_event_keys = 18;
+pub const XWII_KEY_C: xwii_event_keys = 19;
+pub const XWII_KEY_Z: xwii_event_keys = 20;
+pub const XWII_KEY_STRUM_BAR_UP: xwii_event_keys = 21;
+pub const XWII_KEY_STRUM_BAR_DOWN: xwii_event_keys = 22;
+pub const XWII_KEY_FRET_FAR_UP: xwii_event_keys = 23;
+pub const XWII_KEY_FRET_UP: xwii_event_keys = 24;
+pub const XWII_KEY_FRET_MID: xwii_event_keys = 25;
+pub const XWII_KEY_FRET_LOW: xwii_event_keys = 26;
+pub const XWII_KEY_FRET_FAR_LOW: xwii_event_keys = 27;
+pub const XWII_KEY_NUM: xwii_event_keys = 28;
+
+pub type xwii_drums_abs = c_uint;
+pub const XWII_DRUMS_ABS_PAD: xwii_drums_abs = 0;
+pub const XWII_DRUMS_ABS_CYMBAL_LEFT: xwii_drums_abs = 1;
+pub const XWII_DRUMS_ABS_CYMBAL_RIGHT: xwii_drums_abs = 2;
+pub const XWII_DRUMS_ABS_TOM_LEFT: xwii_drums_abs = 3;
+pub const XWII_DRUMS_ABS_TOM_RIGHT: xwii_drums_abs = 4;
+pub const XWII_DRUMS_ABS_TOM_FAR_RIGHT: xwii_drums_abs = 5;
+pub const XWII_DRUMS_ABS_BASS: xwii_drums_abs = 6;
+pub const XWII_DRUMS_ABS_HI_HAT: xwii_drums_abs = 7;
+pub const XWII_DRUMS_ABS_NUM: xwii_drums_abs = 8;
+
+pub type xwii_led = c_uint;
+pub const XWII_LED1: xwii_led = 1;
+pub const XWII_LED2: xwii_led = 2;
+pub const XWII_LED3: xwii_led = 3;
+pub const XWII_LED4: xwii_led = 4;
+
+pub const XWII_IFACE_CORE: u32 = 0x1;
+pub const XWII_IFACE_ACCEL: u32 = 0x2;
+pub const XWII_IFACE_IR: u32 = 0x4;
+pub const XWII_IFACE_MOTION_PLUS: u32 = 0x100;
+pub const XWII_IFACE_NUNCHUK: u32 = 0x200;
+pub const XWII_IFACE_CLASSIC_CONTROLLER: u32 = 0x400;
+pub const XWII_IFACE_BALANCE_BOARD: u32 = 0x800;
+pub const XWII_IFACE_PRO_CONTROLLER: u32 = 0x1000;
+pub const XWII_IFACE_DRUMS: u32 = 0x2000;
+pub const XWII_IFACE_GUITAR: u32 = 0x4000;
+pub const XWII_IFACE_ALL: u32 = 0x7f07;
+pub const XWII_IFACE_WRITABLE: u32 = 0x10000;
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct xwii_event_key {
+    pub code: c_uint,
+    pub state: c_uint,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct xwii_event_abs {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union xwii_event_union {
+    pub key: xwii_event_key,
+    pub abs: [xwii_event_abs; 8usize],
+    pub reserved: [u8; 128usize],
+}
+
+impl Default for xwii_event_union {
+    fn default() -> Self {
+        unsafe { std::mem::zeroed() }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct xwii_event {
+    pub time: timeval,
+    pub type_: c_uint,
+    pub v: xwii_event_union,
+}
+
+impl Default for xwii_event {
+    fn default() -> Self {
+        unsafe { std::mem::zeroed() }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct xwii_iface {
+    _unused: [u8; 0],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct xwii_monitor {
+    _unused: [u8; 0],
+}
+
+/// Sets `errno` to `ENOSYS` and returns `ret`, since callers read the
+/// error from `errno` when a function fails.
+fn unsupported<T>(ret: T) -> T {
+    #[cfg(any(target_os = "linux", target_os = "redox", target_os = "fuchsia"))]
+    unsafe {
+        *libc::__errno_location() = libc::ENOSYS;
+    }
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    unsafe {
+        *libc::__error() = libc::ENOSYS;
+    }
+    #[cfg(any(target_os = "android", target_os = "openbsd", target_os = "netbsd"))]
+    unsafe {
+        *libc::__errno() = libc::ENOSYS;
+    }
+    ret
+}
+
+/// Defines functions with the signatures of the library functions,
+/// which ignore their arguments and return the given value.
+macro_rules! stub {
+    ($(pub fn $name:ident($($arg:ident: $ty:ty),*) $(-> $ret:ty)? = $val:expr;)*) => {
+        $(
+            #[allow(unused_variables, clippy::missing_safety_doc)]
+            pub unsafe extern "C" fn $name($($arg: $ty),*) $(-> $ret)? {
+                $val
+            }
+        )*
+    };
+}
+
+stub! {
+    pub fn xwii_iface_new(dev: *mut *mut xwii_iface, syspath: *const c_char) -> c_int =
+        unsupported(-libc::ENOSYS);
+    pub fn xwii_iface_ref(dev: *mut xwii_iface) = ();
+    pub fn xwii_iface_unref(dev: *mut xwii_iface) = ();
+    pub fn xwii_iface_get_syspath(dev: *mut xwii_iface) -> *const c_char = ptr::null();
+    pub fn xwii_iface_get_fd(dev: *mut xwii_iface) -> c_int = -1;
+    pub fn xwii_iface_watch(dev: *mut xwii_iface, watch: bool) -> c_int =
+        unsupported(-libc::ENOSYS);
+    pub fn xwii_iface_open(dev: *mut xwii_iface, ifaces: c_uint) -> c_int =
+        unsupported(-libc::ENOSYS);
+    pub fn xwii_iface_close(dev: *mut xwii_iface, ifaces: c_uint) = ();
+    pub fn xwii_iface_opened(dev: *mut xwii_iface) -> c_uint = 0;
+    pub fn xwii_iface_available(dev: *mut xwii_iface) -> c_uint = 0;
+    pub fn xwii_iface_poll(dev: *mut xwii_iface, ev: *mut xwii_event) -> c_int =
+        unsupported(-libc::ENOSYS);
+    pub fn xwii_iface_dispatch(dev: *mut xwii_iface, ev: *mut xwii_event, size: usize) -> c_int =
+        unsupported(-libc::ENOSYS);
+    pub fn xwii_iface_rumble(dev: *mut xwii_iface, on: bool) -> c_int = unsupported(-libc::ENOSYS);
+    pub fn xwii_iface_get_led(dev: *mut xwii_iface, led: c_uint, state: *mut bool) -> c_int =
+        unsupported(-libc::ENOSYS);
+    pub fn xwii_iface_set_led(dev: *mut xwii_iface, led: c_uint, state: bool) -> c_int =
+        unsupported(-libc::ENOSYS);
+    pub fn xwii_iface_get_battery(dev: *mut xwii_iface, capacity: *mut u8) -> c_int =
+        unsupported(-libc::ENOSYS);
+    pub fn xwii_iface_get_devtype(dev: *mut xwii_iface, devtype: *mut *mut c_char) -> c_int =
+        unsupported(-libc::ENOSYS);
+    pub fn xwii_iface_get_extension(dev: *mut xwii_iface, extension: *mut *mut c_char) -> c_int =
+        unsupported(-libc::ENOSYS);
+    pub fn xwii_iface_set_mp_normalization(dev: *mut xwii_iface, x: i32, y: i32, z: i32, factor: i32) = ();
+    pub fn xwii_iface_get_mp_normalization(
+        dev: *mut xwii_iface,
+        x: *mut i32,
+        y: *mut i32,
+        z: *mut i32,
+        factor: *mut i32
+    ) = ();
+    pub fn xwii_monitor_new(poll: bool, direct: bool) -> *mut xwii_monitor =
+        unsupported(ptr::null_mut());
+    pub fn xwii_monitor_ref(mon: *mut xwii_monitor) = ();
+    pub fn xwii_monitor_unref(mon: *mut xwii_monitor) = ();
+    pub fn xwii_monitor_get_fd(mon: *mut xwii_monitor, blocking: bool) -> c_int = -1;
+    pub fn xwii_monitor_poll(mon: *mut xwii_monitor) -> *mut c_char = ptr::null_mut();
+}
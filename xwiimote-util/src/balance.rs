@@ -0,0 +1,252 @@
+//! Motion derived from the weights measured by a Balance Board.
+//!
+//! The sensors of the board are noisy, so differentiating the raw
+//! readings of consecutive events yields rates that jump wildly.
+//! A [`BalanceTracker`] smooths the readings first, and reports how fast
+//! the total weight and the center of pressure change, which is what
+//! applications need to detect squats, hops and sways.
+
+use crate::filter::smoothing_factor;
+use crate::ranges::BALANCE_UNITS_PER_KG;
+use std::time::{Duration, SystemTime};
+
+/// The total weight below which the center of pressure is not
+/// computed, in kilograms. Lighter loads mean that nobody is
+/// standing on the board.
+pub const MIN_CENTER_WEIGHT: f32 = 5.0;
+
+/// A vector in the plane of the board. The x-axis points to the right
+/// and the y-axis points to the front of the board, where the power
+/// button is.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct BoardVector {
+    /// The x-axis component.
+    pub x: f32,
+    /// The y-axis component.
+    pub y: f32,
+}
+
+impl BoardVector {
+    /// Computes the center of pressure of the weights reported by
+    /// a `BalanceBoard` event of the `xwiimote` crate.
+    ///
+    /// Both components range from -1 at the left (or back) edge to 1
+    /// at the right (or front) edge. Returns [`None`] if the total
+    /// weight is less than [`MIN_CENTER_WEIGHT`].
+    pub fn center_of_pressure(weights: [i32; 4]) -> Option<Self> {
+        let [top_right, bottom_right, top_left, bottom_left] = weights.map(|w| w as f32);
+        let total = top_right + bottom_right + top_left + bottom_left;
+        if total / (BALANCE_UNITS_PER_KG as f32) < MIN_CENTER_WEIGHT {
+            return None;
+        }
+        Some(Self {
+            x: (top_right + bottom_right - top_left - bottom_left) / total,
+            y: (top_right + top_left - bottom_right - bottom_left) / total,
+        })
+    }
+
+    fn lerp(self, to: Self, alpha: f32) -> Self {
+        Self {
+            x: self.x + alpha * (to.x - self.x),
+            y: self.y + alpha * (to.y - self.y),
+        }
+    }
+}
+
+/// The smoothed load on a Balance Board, along with its rate of change.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BalanceMotion {
+    /// The total weight on the board, in kilograms.
+    pub weight: f32,
+    /// The rate at which the weight changes, in kilograms per second.
+    /// Positive while the load increases, e.g. at the bottom of a hop.
+    ///
+    /// Only available after the weights have been read at least twice.
+    pub weight_rate: Option<f32>,
+    /// The center of pressure, as computed by
+    /// [`BoardVector::center_of_pressure`].
+    pub center: Option<BoardVector>,
+    /// The velocity of the center of pressure, in board half-widths
+    /// per second.
+    ///
+    /// Only available after the center has been computed at least twice
+    /// in a row.
+    pub center_velocity: Option<BoardVector>,
+}
+
+/// Computes the exponential moving average of the weights measured by
+/// a Balance Board, and of the rates at which the total weight and the
+/// center of pressure change.
+#[derive(Clone, Debug)]
+pub struct BalanceTracker {
+    /// The time over which the weight of a reading decays by a factor of _e_.
+    time_constant: Duration,
+    /// The current motion and the time of the last reading, if any.
+    state: Option<(BalanceMotion, SystemTime)>,
+}
+
+impl Default for BalanceTracker {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_TIME_CONSTANT)
+    }
+}
+
+impl BalanceTracker {
+    /// The default smoothing time constant, which removes the sensor
+    /// noise while keeping up with the movements of a person.
+    pub const DEFAULT_TIME_CONSTANT: Duration = Duration::from_millis(50);
+
+    /// Creates a tracker that smooths the readings over
+    /// the given time constant.
+    pub fn new(time_constant: Duration) -> Self {
+        Self {
+            time_constant,
+            state: None,
+        }
+    }
+
+    /// Adds the weights reported by a `BalanceBoard` event of the
+    /// `xwiimote` crate, generated at the given time.
+    ///
+    /// Readings older than the previous one are ignored.
+    pub fn update(&mut self, weights: [i32; 4], at: SystemTime) -> BalanceMotion {
+        let weight = weights.iter().map(|&w| w as f32).sum::<f32>() / BALANCE_UNITS_PER_KG as f32;
+        let center = BoardVector::center_of_pressure(weights);
+        let motion = match self.state {
+            None => BalanceMotion {
+                weight,
+                weight_rate: None,
+                center,
+                center_velocity: None,
+            },
+            Some((prev, prev_at)) => {
+                let Ok(elapsed) = at.duration_since(prev_at) else {
+                    return prev;
+                };
+                let alpha = smoothing_factor(elapsed, self.time_constant);
+                let secs = elapsed.as_secs_f32();
+                let smooth_rate = |prev_rate: Option<f32>, rate: f32| match prev_rate {
+                    Some(prev_rate) => prev_rate + alpha * (rate - prev_rate),
+                    None => rate,
+                };
+
+                let new_weight = prev.weight + alpha * (weight - prev.weight);
+                let weight_rate = if secs > 0.0 {
+                    Some(smooth_rate(
+                        prev.weight_rate,
+                        (new_weight - prev.weight) / secs,
+                    ))
+                } else {
+                    prev.weight_rate
+                };
+
+                // The center is tracked only while somebody stands on the board.
+                let (new_center, center_velocity) = match (prev.center, center) {
+                    (Some(prev_center), Some(center)) => {
+                        let new_center = prev_center.lerp(center, alpha);
+                        let velocity = if secs > 0.0 {
+                            let prev_velocity = prev.center_velocity;
+                            Some(BoardVector {
+                                x: smooth_rate(
+                                    prev_velocity.map(|v| v.x),
+                                    (new_center.x - prev_center.x) / secs,
+                                ),
+                                y: smooth_rate(
+                                    prev_velocity.map(|v| v.y),
+                                    (new_center.y - prev_center.y) / secs,
+                                ),
+                            })
+                        } else {
+                            prev.center_velocity
+                        };
+                        (Some(new_center), velocity)
+                    }
+                    (_, center) => (center, None),
+                };
+                BalanceMotion {
+                    weight: new_weight,
+                    weight_rate,
+                    center: new_center,
+                    center_velocity,
+                }
+            }
+        };
+        self.state = Some((motion, at));
+        motion
+    }
+
+    /// Returns the current motion, if any reading was added.
+    pub fn motion(&self) -> Option<BalanceMotion> {
+        self.state.map(|(motion, _)| motion)
+    }
+
+    /// Discards all readings, e.g. after the board is recalibrated.
+    pub fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::balance::{BalanceTracker, BoardVector};
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn computes_center_of_pressure() {
+        let center = BoardVector::center_of_pressure([2000, 2000, 2000, 2000]).unwrap();
+        assert_eq!(center, BoardVector::default());
+        // All the weight rests on the right edge.
+        let center = BoardVector::center_of_pressure([3000, 3000, 0, 0]).unwrap();
+        assert_eq!(center, BoardVector { x: 1.0, y: 0.0 });
+        // Nobody stands on the board.
+        assert_eq!(BoardVector::center_of_pressure([100, 50, 0, 80]), None);
+    }
+
+    #[test]
+    fn first_reading_is_exact() {
+        let mut tracker = BalanceTracker::default();
+        assert_eq!(tracker.motion(), None);
+        let motion = tracker.update([1000, 2000, 3000, 4000], SystemTime::now());
+        assert_eq!(motion.weight, 100.0);
+        assert_eq!(motion.weight_rate, None);
+        assert!(motion.center.is_some());
+        assert_eq!(motion.center_velocity, None);
+    }
+
+    #[test]
+    fn estimates_rates_of_change() {
+        let mut tracker = BalanceTracker::default();
+        let start = SystemTime::UNIX_EPOCH;
+        // The weight grows by 8 kg per second, and it shifts to the right.
+        for tick in 0..=100 {
+            let (load, shift) = (1000 + 2 * tick, 5 * tick);
+            let weights = [load + shift, load + shift, load - shift, load - shift];
+            tracker.update(weights, start + Duration::from_millis(10 * tick as u64));
+        }
+        let motion = tracker.motion().unwrap();
+        let rate = motion.weight_rate.unwrap();
+        assert!((rate - 8.0).abs() < 0.1, "weight rate is {rate}");
+        let velocity = motion.center_velocity.unwrap();
+        assert!(velocity.x > 0.0 && velocity.y.abs() < 1e-3);
+    }
+
+    #[test]
+    fn stops_tracking_center_when_empty() {
+        let mut tracker = BalanceTracker::default();
+        let start = SystemTime::UNIX_EPOCH;
+        tracker.update([2000; 4], start);
+        tracker.update([2000; 4], start + Duration::from_millis(10));
+        assert!(tracker.motion().unwrap().center_velocity.is_some());
+        let motion = tracker.update([0; 4], start + Duration::from_millis(20));
+        assert_eq!(motion.center, None);
+        assert_eq!(motion.center_velocity, None);
+    }
+
+    #[test]
+    fn ignores_stale_readings() {
+        let mut tracker = BalanceTracker::default();
+        let start = SystemTime::UNIX_EPOCH;
+        tracker.update([1000; 4], start + Duration::from_secs(1));
+        assert_eq!(tracker.update([0; 4], start).weight, 40.0);
+    }
+}
@@ -0,0 +1,51 @@
+//! Building blocks of the filters of the other modules, which applications
+//! can use to post-process the sensor data.
+
+use std::time::Duration;
+
+/// Computes the weight of a new reading in an exponential moving average,
+/// given the time elapsed since the previous reading.
+///
+/// The weight of a reading decays by a factor of _e_ every `time_constant`,
+/// independently of the rate at which the readings arrive. A zero time
+/// constant gives the whole weight to the new reading.
+pub fn smoothing_factor(elapsed: Duration, time_constant: Duration) -> f32 {
+    if time_constant.is_zero() {
+        return 1.0;
+    }
+    1.0 - (-elapsed.as_secs_f32() / time_constant.as_secs_f32()).exp()
+}
+
+/// Moves `value` towards zero by `size`, so that values within the dead
+/// zone around zero become zero and the output grows continuously
+/// beyond it.
+///
+/// Dead zones hide the small, unintended deviations of a user holding
+/// a device still, e.g. a slight tilt that should not scroll a page.
+pub fn dead_zone(value: f32, size: f32) -> f32 {
+    (value.abs() - size).max(0.0).copysign(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::{dead_zone, smoothing_factor};
+    use std::time::Duration;
+
+    #[test]
+    fn smoothing_decays_exponentially() {
+        let tc = Duration::from_millis(100);
+        assert_eq!(smoothing_factor(Duration::ZERO, tc), 0.0);
+        let alpha = smoothing_factor(tc, tc);
+        assert!((alpha - (1.0 - (-1f32).exp())).abs() < 1e-6);
+        assert!(smoothing_factor(Duration::from_secs(10), tc) > 0.999);
+        assert_eq!(smoothing_factor(tc, Duration::ZERO), 1.0);
+    }
+
+    #[test]
+    fn dead_zone_is_continuous() {
+        assert_eq!(dead_zone(5.0, 10.0), 0.0);
+        assert_eq!(dead_zone(-10.0, 10.0), 0.0);
+        assert_eq!(dead_zone(12.5, 10.0), 2.5);
+        assert_eq!(dead_zone(-12.5, 10.0), -2.5);
+    }
+}
@@ -0,0 +1,43 @@
+//! Signal processing for the sensor data of Wii Remotes and their
+//! extensions: smoothing filters, dead zones and orientation estimation,
+//! along with the ranges of the raw sensor values.
+//!
+//! This crate does not depend on the `xwiimote` library, so it builds
+//! on any platform. The [`xwiimote`](https://docs.rs/xwiimote) crate
+//! re-exports its modules, which process the events of connected
+//! devices; the same code can process recorded sensor data, e.g. in
+//! the unit tests of an application.
+//!
+//! # Examples
+//! Estimate the tilt of a device from recorded accelerometer readings.
+//! ```
+//! use std::time::{Duration, SystemTime};
+//! use xwiimote_util::orientation::TiltFilter;
+//! use xwiimote_util::Acceleration;
+//!
+//! let start = SystemTime::UNIX_EPOCH;
+//! let readings = [(0, 0, 100), (0, 50, 90), (0, 70, 70)];
+//! let mut filter = TiltFilter::default();
+//! for (ix, (x, y, z)) in readings.into_iter().enumerate() {
+//!     let at = start + Duration::from_millis(10 * ix as u64);
+//!     filter.update(Acceleration { x, y, z }, at);
+//! }
+//! assert!(filter.tilt().unwrap().pitch > 0.0);
+//! ```
+
+#[cfg(feature = "balance")]
+pub mod balance;
+pub mod filter;
+pub mod orientation;
+pub mod ranges;
+
+/// The acceleration reported by the accelerometer.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Acceleration {
+    /// The x-axis acceleration.
+    pub x: i32,
+    /// The y-axis acceleration.
+    pub y: i32,
+    /// The z-axis acceleration.
+    pub z: i32,
+}
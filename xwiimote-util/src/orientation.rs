@@ -0,0 +1,249 @@
+//! Estimation of the orientation of a device from its accelerometer.
+//!
+//! While a device is held still, the accelerometer only measures the
+//! gravitational acceleration, whose direction tells how the device is
+//! tilted. The rotation around the vertical axis (the yaw) cannot be
+//! determined this way; see the Motion Plus events instead.
+//!
+//! # Examples
+//! Print the tilt of a device, in degrees, as it changes.
+//! ```
+//! use std::time::SystemTime;
+//! use xwiimote_util::orientation::TiltFilter;
+//! use xwiimote_util::Acceleration;
+//!
+//! # let readings: Vec<(Acceleration, SystemTime)> = Vec::new();
+//! // The readings of the `Accelerometer` channel of a device.
+//! let mut filter = TiltFilter::default();
+//! for (acc, time) in readings {
+//!     if let Some(tilt) = filter.update(acc, time) {
+//!         println!("pitch {:.0}°, roll {:.0}°", tilt.pitch_degrees(), tilt.roll_degrees());
+//!     }
+//! }
+//! ```
+
+use crate::filter::smoothing_factor;
+use crate::ranges::ACCELERATION_PER_G;
+use crate::Acceleration;
+use std::time::{Duration, SystemTime};
+
+/// The accelerometer reading that corresponds to the standard gravity,
+/// approximately.
+pub const GRAVITY: f32 = ACCELERATION_PER_G as f32;
+
+/// The inclination of a device with respect to the ground, in radians.
+///
+/// Both angles are zero when the device lies flat with its buttons facing up.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Tilt {
+    /// The rotation around the x-axis, which is positive when
+    /// the front of the device (where the IR camera is) points up.
+    /// Ranges from -π/2 to π/2.
+    pub pitch: f32,
+    /// The rotation around the y-axis, which is positive when
+    /// the device is rolled to the right. Ranges from -π to π.
+    pub roll: f32,
+}
+
+impl Tilt {
+    /// Estimates the tilt of a device from an accelerometer reading.
+    ///
+    /// Returns [`None`] if the reading is too weak to determine
+    /// the direction of gravity, e.g. while the device is falling.
+    pub fn from_acceleration(acc: Acceleration) -> Option<Self> {
+        Self::from_components([acc.x as f32, acc.y as f32, acc.z as f32])
+    }
+
+    fn from_components([x, y, z]: [f32; 3]) -> Option<Self> {
+        if (x * x + y * y + z * z).sqrt() < GRAVITY / 4.0 {
+            return None;
+        }
+        Some(Self {
+            pitch: y.atan2(x.hypot(z)),
+            roll: (-x).atan2(z),
+        })
+    }
+
+    /// Returns the pitch in degrees.
+    pub fn pitch_degrees(&self) -> f32 {
+        self.pitch.to_degrees()
+    }
+
+    /// Returns the roll in degrees.
+    pub fn roll_degrees(&self) -> f32 {
+        self.roll.to_degrees()
+    }
+}
+
+/// Returns the pitch and roll of a device in degrees, from the average
+/// of a few recent accelerometer readings, which cancels out the jitter
+/// of the sensor and the tremor of the hand.
+///
+/// Returns [`None`] if no readings are given, or if their average is too
+/// weak to determine the direction of gravity. Use a [`TiltFilter`] to
+/// smooth a continuous stream of readings instead.
+pub fn tilt_angles(readings: impl IntoIterator<Item = Acceleration>) -> Option<(f32, f32)> {
+    let mut sum = [0.0; 3];
+    let mut count = 0;
+    for acc in readings {
+        sum[0] += acc.x as f32;
+        sum[1] += acc.y as f32;
+        sum[2] += acc.z as f32;
+        count += 1;
+    }
+    if count == 0 {
+        return None;
+    }
+    let tilt = Tilt::from_components(sum.map(|c| c / count as f32))?;
+    Some((tilt.pitch_degrees(), tilt.roll_degrees()))
+}
+
+/// Estimates the tilt of a device from the exponential moving average
+/// of its accelerometer readings.
+///
+/// This is enough for simple tilt-controlled applications on devices
+/// without a Motion Plus, although the estimate lags behind fast
+/// rotations and is disturbed while the device accelerates.
+#[derive(Clone, Debug)]
+pub struct TiltFilter {
+    /// The time over which the weight of a reading decays by a factor of _e_.
+    time_constant: Duration,
+    /// The average acceleration and the time of the last reading, if any.
+    state: Option<([f32; 3], SystemTime)>,
+}
+
+impl Default for TiltFilter {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_TIME_CONSTANT)
+    }
+}
+
+impl TiltFilter {
+    /// The default smoothing time constant.
+    pub const DEFAULT_TIME_CONSTANT: Duration = Duration::from_millis(100);
+
+    /// Creates a filter that smooths the readings over
+    /// the given time constant.
+    pub fn new(time_constant: Duration) -> Self {
+        Self {
+            time_constant,
+            state: None,
+        }
+    }
+
+    /// Adds an accelerometer reading taken at the given time, and returns
+    /// the estimated tilt, as [`Tilt::from_acceleration`] does.
+    ///
+    /// Readings older than the previous one are ignored.
+    pub fn update(&mut self, acc: Acceleration, at: SystemTime) -> Option<Tilt> {
+        let reading = [acc.x as f32, acc.y as f32, acc.z as f32];
+        let average = match self.state {
+            None => reading,
+            Some((prev, prev_at)) => {
+                let Ok(elapsed) = at.duration_since(prev_at) else {
+                    return Tilt::from_components(prev);
+                };
+                let alpha = smoothing_factor(elapsed, self.time_constant);
+                [0, 1, 2].map(|i| prev[i] + alpha * (reading[i] - prev[i]))
+            }
+        };
+        self.state = Some((average, at));
+        Tilt::from_components(average)
+    }
+
+    /// Returns the current estimate, if any reading was added and
+    /// their average determines the direction of gravity.
+    pub fn tilt(&self) -> Option<Tilt> {
+        self.state
+            .and_then(|(average, _)| Tilt::from_components(average))
+    }
+
+    /// Discards all readings.
+    pub fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::orientation::{tilt_angles, Tilt, TiltFilter};
+    use crate::Acceleration;
+    use std::f32::consts::FRAC_PI_2;
+    use std::time::{Duration, SystemTime};
+
+    fn tilt(x: i32, y: i32, z: i32) -> Tilt {
+        Tilt::from_acceleration(Acceleration { x, y, z }).unwrap()
+    }
+
+    #[test]
+    fn flat_device_is_level() {
+        assert_eq!(tilt(0, 0, 100), Tilt::default());
+    }
+
+    #[test]
+    fn detects_pitch_and_roll() {
+        assert!((tilt(0, 100, 0).pitch - FRAC_PI_2).abs() < 1e-6);
+        assert!((tilt(0, -100, 0).pitch + FRAC_PI_2).abs() < 1e-6);
+        assert!((tilt(-100, 0, 100).roll_degrees() - 45.0).abs() < 1e-4);
+        assert!((tilt(100, 0, 100).roll_degrees() + 45.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn rejects_free_fall() {
+        assert_eq!(
+            Tilt::from_acceleration(Acceleration { x: 3, y: -2, z: 5 }),
+            None
+        );
+    }
+
+    #[test]
+    fn averages_readings_into_angles() {
+        let readings = [
+            Acceleration {
+                x: -90,
+                y: 0,
+                z: 100,
+            },
+            Acceleration {
+                x: -110,
+                y: 0,
+                z: 100,
+            },
+        ];
+        let (pitch, roll) = tilt_angles(readings).unwrap();
+        assert!(pitch.abs() < 1e-4);
+        assert!((roll - 45.0).abs() < 1e-4);
+        assert_eq!(tilt_angles([]), None);
+    }
+
+    #[test]
+    fn filter_smooths_jitter() {
+        let mut filter = TiltFilter::default();
+        let start = SystemTime::UNIX_EPOCH;
+        assert_eq!(
+            filter.update(Acceleration { x: 0, y: 0, z: 100 }, start),
+            Some(Tilt::default())
+        );
+        // A single spike barely moves the estimate.
+        let tilt = filter
+            .update(
+                Acceleration { x: 0, y: 100, z: 0 },
+                start + Duration::from_millis(10),
+            )
+            .unwrap();
+        assert!(tilt.pitch_degrees() > 0.0 && tilt.pitch_degrees() < 10.0);
+        // Stale readings are ignored.
+        assert_eq!(
+            filter.update(
+                Acceleration {
+                    x: 0,
+                    y: -100,
+                    z: 0
+                },
+                start
+            ),
+            Some(tilt)
+        );
+        assert_eq!(filter.tilt(), Some(tilt));
+    }
+}
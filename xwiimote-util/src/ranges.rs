@@ -0,0 +1,33 @@
+//! The ranges and units of the raw sensor values reported by the devices.
+//!
+//! The events of the `xwiimote` crate carry the values read from the
+//! devices without scaling them. Code that normalizes these values
+//! should refer to the constants below rather than repeat them.
+
+/// The largest magnitude of an accelerometer axis, which the device
+/// reports with 10 bits centered on zero.
+pub const ACCELERATION_MAX: i32 = 512;
+
+/// The accelerometer reading that corresponds to the standard gravity,
+/// approximately.
+pub const ACCELERATION_PER_G: i32 = 100;
+
+/// The largest magnitude of a Motion Plus axis, which the device
+/// reports with 14 bits once normalized.
+pub const ROTATION_MAX: i32 = 1 << 14;
+
+/// The horizontal resolution of the IR camera. The x-coordinate of
+/// a source ranges from 0 to `IR_WIDTH - 1`.
+pub const IR_WIDTH: i32 = 1024;
+
+/// The vertical resolution of the IR camera. The y-coordinate of
+/// a source ranges from 0 to `IR_HEIGHT - 1`.
+pub const IR_HEIGHT: i32 = 768;
+
+/// The position of a fully pressed analog trigger of a Classic
+/// controller. Released triggers are at 0.
+pub const TRIGGER_MAX: u8 = 63;
+
+/// The number of units in a kilogram of the weights measured by the
+/// sensors of a Balance Board.
+pub const BALANCE_UNITS_PER_KG: i32 = 100;